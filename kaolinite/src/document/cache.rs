@@ -0,0 +1,137 @@
+/// cache.rs - remembers recently read lines (and their character maps) from the rope
+use crate::map::{form_map, DblUsize};
+use crate::searching::SearchText;
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How many lines are remembered at once
+const CAPACITY: usize = 1024;
+
+/// A line of the document, along with the positions of characters with unusual widths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedLine {
+    /// The text on this line (without the line ending)
+    pub text: String,
+    /// The locations of double width characters
+    pub dbl: DblUsize,
+    /// The locations of tab characters
+    pub tab: DblUsize,
+}
+
+/// Remembers lines that have been read recently, so that they aren't read from the rope
+/// (and scanned for double width and tab characters) each time they are needed.
+/// Edits only forget the lines they touched (moving the lines below them up or down),
+/// everything is forgotten when the document changes in some other way, or the tab width changes.
+#[derive(Debug, Clone, Default)]
+pub struct LineCache {
+    state: RefCell<CacheState>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CacheState {
+    /// The revision of the document the cached lines are up to date with
+    revision: usize,
+    /// The tab width the character maps were worked out with
+    tab_width: usize,
+    /// Counts up each time a line is read, to tell which lines were used least recently
+    clock: usize,
+    /// The cached lines, along with when they were last read
+    lines: HashMap<usize, (Arc<CachedLine>, usize)>,
+}
+
+impl LineCache {
+    /// Get a line from the cache, reading it in with `read` if it isn't there
+    pub fn get(
+        &self,
+        revision: usize,
+        tab_width: usize,
+        y: usize,
+        read: impl FnOnce() -> Option<String>,
+    ) -> Option<Arc<CachedLine>> {
+        let mut state = self.state.borrow_mut();
+        if state.revision != revision || state.tab_width != tab_width {
+            state.lines.clear();
+            state.revision = revision;
+            state.tab_width = tab_width;
+        }
+        state.clock += 1;
+        let now = state.clock;
+        if let Some((line, used)) = state.lines.get_mut(&y) {
+            *used = now;
+            return Some(line.clone());
+        }
+        let text = read()?;
+        let (dbl, tab) = form_map(&text, tab_width);
+        let line = Arc::new(CachedLine { text, dbl, tab });
+        if state.lines.len() >= CAPACITY {
+            let oldest = state.lines.iter().min_by_key(|(_, (_, used))| *used);
+            if let Some(&oldest) = oldest.map(|(y, _)| y) {
+                state.lines.remove(&oldest);
+            }
+        }
+        state.lines.insert(y, (line.clone(), now));
+        Some(line)
+    }
+
+    /// Keep the cache up to date with an edit that took the document from revision `from` to `to`,
+    /// replacing `old` lines (starting at `y`) with `new` lines.
+    /// Lines the edit touched are forgotten and the lines below them are moved along.
+    pub fn edit(&self, from: usize, to: usize, y: usize, old: usize, new: usize) {
+        let mut state = self.state.borrow_mut();
+        if state.revision != from {
+            state.lines.clear();
+        }
+        let lines = std::mem::take(&mut state.lines);
+        state.lines = lines
+            .into_iter()
+            .filter_map(|(line, entry)| match line {
+                _ if line < y => Some((line, entry)),
+                _ if line < y + old => None,
+                _ => Some((line + new - old, entry)),
+            })
+            .collect();
+        state.revision = to;
+    }
+}
+
+/// Remembers the whole text of the document (read out of the rope to be searched),
+/// so that it is only read out again once the document changes
+#[derive(Debug, Clone, Default)]
+pub struct TextCache {
+    state: RefCell<Option<(usize, SearchText)>>,
+}
+
+impl TextCache {
+    /// Get the text of the rope at a revision, reading it out if it isn't there
+    pub fn get(&self, revision: usize, rope: &Rope) -> SearchText {
+        let mut state = self.state.borrow_mut();
+        match &*state {
+            Some((at, text)) if *at == revision => text.clone(),
+            _ => {
+                let text = SearchText::new(rope);
+                *state = Some((revision, text.clone()));
+                text
+            }
+        }
+    }
+}
+
+/// The cache doesn't change what a document contains, so it is ignored when comparing them
+impl PartialEq for LineCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for LineCache {}
+
+/// The cache doesn't change what a document contains, so it is ignored when comparing them
+impl PartialEq for TextCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for TextCache {}
@@ -20,6 +20,10 @@ impl Document {
 
     /// Select with the cursor up
     pub fn select_up(&mut self) -> Status {
+        // Wrapped lines are moved through a row at a time, and folded lines are skipped
+        if !self.one_row_per_line() {
+            return self.select_row_up();
+        }
         // Return if already at start of document
         if self.loc().y == 0 {
             return Status::StartOfFile;
@@ -45,6 +49,9 @@ impl Document {
 
     /// Select with the cursor down
     pub fn select_down(&mut self) -> Status {
+        if !self.one_row_per_line() {
+            return self.select_row_down();
+        }
         // Return if already on end of document
         if self.len_lines() < self.loc().y + 1 {
             return Status::EndOfFile;
@@ -163,12 +170,15 @@ impl Document {
     /// Move to the top of the document
     pub fn move_top(&mut self) {
         self.move_to(&Loc::at(0, 0));
+        self.old_cursor = self.loc().x;
     }
 
     /// Move to the bottom of the document
     pub fn move_bottom(&mut self) {
+        self.index_to(usize::MAX);
         let last = self.len_lines();
         self.move_to(&Loc::at(0, last));
+        self.old_cursor = self.loc().x;
     }
 
     /// Select to the top of the document
@@ -179,6 +189,7 @@ impl Document {
 
     /// Select to the bottom of the document
     pub fn select_bottom(&mut self) {
+        self.index_to(usize::MAX);
         let last = self.len_lines();
         self.select_to(&Loc::at(0, last));
         self.old_cursor = self.loc().x;
@@ -191,6 +202,16 @@ impl Document {
         self.cursor.loc.x = 0;
         self.char_ptr = 0;
         self.old_cursor = 0;
+        // Lines don't line up with rows when wrapped or folded, so move a page worth of rows
+        if !self.one_row_per_line() {
+            for _ in 0..self.size.h {
+                if self.select_row_up() == Status::StartOfFile {
+                    break;
+                }
+            }
+            self.cancel_selection();
+            return;
+        }
         // Calculate where to move the cursor
         let new_cursor_y = self.cursor.loc.y.saturating_sub(self.size.h);
         // Move to the new location and shift down offset proportionally
@@ -207,6 +228,15 @@ impl Document {
         self.cursor.loc.x = 0;
         self.char_ptr = 0;
         self.old_cursor = 0;
+        if !self.one_row_per_line() {
+            for _ in 0..self.size.h {
+                if self.select_row_down() == Status::EndOfFile {
+                    break;
+                }
+            }
+            self.cancel_selection();
+            return;
+        }
         // Calculate where to move the cursor
         let new_cursor_y = self.cursor.loc.y + self.size.h;
         if new_cursor_y <= self.len_lines() {
@@ -222,7 +252,6 @@ impl Document {
             self.offset.y = self.len_lines().saturating_sub(self.size.h);
         }
         // Clean up
-        self.load_to(self.offset.y + self.size.h);
         self.cancel_selection();
     }
 
@@ -271,6 +300,11 @@ impl Document {
 
     /// Function to select to a specific y position
     pub fn select_to_y(&mut self, y: usize) {
+        self.index_to(y);
+        // Open up any fold the cursor is moving into
+        if self.is_hidden(y) {
+            self.unfold(y);
+        }
         // Bounds checking
         if self.loc().y != y && y <= self.len_lines() {
             self.cursor.loc.y = y;
@@ -284,20 +318,16 @@ impl Document {
         // Correct the character pointer
         self.update_char_ptr();
         self.bring_cursor_in_viewport();
-        // Load any lines necessary
-        self.load_to(self.offset.y + self.size.h);
     }
 
     /// Move the view down
     pub fn scroll_down(&mut self) {
         self.offset.y += 1;
-        self.load_to(self.offset.y + self.size.h);
     }
 
     /// Move the view up
     pub fn scroll_up(&mut self) {
         self.offset.y = self.offset.y.saturating_sub(1);
-        self.load_to(self.offset.y + self.size.h);
     }
 
     /// Get the current position within the document, including offset
@@ -321,6 +351,9 @@ impl Document {
     /// If the cursor is within the viewport, this will return where it is relatively
     #[must_use]
     pub fn cursor_loc_in_screen(&self) -> Option<Loc> {
+        if !self.one_row_per_line() {
+            return self.cursor_row_in_screen();
+        }
         if self.cursor.loc.x < self.offset.x {
             return None;
         }
@@ -403,14 +436,35 @@ impl Document {
     /// Will return the text contained within the current selection
     #[must_use]
     pub fn selection_text(&self) -> String {
-        self.file.slice(self.selection_range()).to_string()
+        if self.pager.is_none() {
+            return self.file.slice(self.selection_range()).to_string();
+        }
+        // Paged documents aren't held in the rope, so gather the text line by line
+        let (start, end) = self.selection_loc_bound();
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            let line = self.line(y).unwrap_or_default();
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y {
+                end.x
+            } else {
+                line.chars().count()
+            };
+            text.extend(line.chars().skip(from).take(to.saturating_sub(from)));
+            if y != end.y {
+                text.push_str(self.info.line_ending.as_str());
+            }
+        }
+        text
     }
 
     /// Delete the currently selected text
     pub fn remove_selection(&mut self) {
-        self.file.remove(self.selection_range());
-        self.reload_lines();
-        let mut goto = self.selection_loc_bound().0;
+        let start = self.selection_loc_bound().0;
+        let text = self.selection_text();
+        // Going through delete_text ensures that this can be undone
+        let _ = self.delete_text(&start, &text);
+        let mut goto = start;
         goto.x = self.display_idx(&goto);
         self.cursor.loc = goto;
         self.char_ptr = self.character_idx(&self.cursor.loc);
@@ -1,11 +1,16 @@
+use crate::document::cache::{LineCache, TextCache};
 use crate::document::Cursor;
+use crate::encoding::Encoding;
 use crate::event::{Error, EventMgmt, Result};
-use crate::map::{form_map, CharMap};
+use crate::history::{hash, hash_rope, HistoryStore};
+use crate::pager::Pager;
 use crate::utils::get_absolute_path;
 use crate::{Document, Loc, Size};
 use ropey::Rope;
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// A document info struct to store information about the file it represents
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -14,8 +19,138 @@ pub struct DocumentInfo {
     pub read_only: bool,
     /// Flag for an EOL
     pub eol: bool,
-    /// Contains the number of lines buffered into the document
-    pub loaded_to: usize,
+    /// The style of line endings this document uses
+    pub line_ending: LineEnding,
+    /// The character encoding the file is stored in
+    pub encoding: Encoding,
+    /// Whether information was lost when decoding the file (such files are read only)
+    pub lossy: bool,
+    /// What the file looked like on the disk when it was last opened or saved
+    pub disk: Option<DiskState>,
+}
+
+/// A record of a file on the disk, used to notice when other programs change it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskState {
+    /// When the file was last modified (where the platform supports it)
+    pub modified: Option<SystemTime>,
+    /// The size of the file in bytes
+    pub len: u64,
+    /// A hash of the contents of the file
+    pub hash: u64,
+}
+
+impl DiskState {
+    /// Take a record of a file on the disk, returning None if it can't be read
+    #[must_use]
+    pub fn read(path: &Path) -> Option<Self> {
+        let bytes = std::fs::read(path).ok()?;
+        Some(Self::of(path, &bytes))
+    }
+
+    /// Take a record of a file without reading it (for files too large to hash)
+    #[must_use]
+    pub fn stat(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: 0,
+        })
+    }
+
+    /// Take a record of a file, given its contents
+    fn of(path: &Path, bytes: &[u8]) -> Self {
+        Self {
+            modified: std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            len: bytes.len() as u64,
+            hash: hash(bytes.iter().copied()),
+        }
+    }
+
+    /// Quickly check whether a file still has the same size and modification time
+    #[must_use]
+    pub fn unchanged(&self, path: &Path) -> bool {
+        std::fs::metadata(path)
+            .is_ok_and(|m| m.len() == self.len && m.modified().ok() == self.modified)
+    }
+}
+
+/// The different styles of line ending a document can use
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineEnding {
+    /// Line feed, used on Unix-like systems
+    #[default]
+    Lf,
+    /// Carriage return followed by a line feed, used on Windows
+    CrLf,
+    /// Carriage return, used on classic Mac OS
+    Cr,
+}
+
+impl LineEnding {
+    /// The characters that make up this line ending
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+
+    /// Find a line ending style from its name (e.g. "lf", "crlf" or "cr")
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "lf" | "unix" => Some(Self::Lf),
+            "crlf" | "dos" | "windows" => Some(Self::CrLf),
+            "cr" | "mac" => Some(Self::Cr),
+            _ => None,
+        }
+    }
+
+    /// Work out which line ending is most common in the start of a rope (LF if there are none)
+    #[must_use]
+    pub fn detect(rope: &Rope) -> Self {
+        let mut counts = [0_usize; 3];
+        for line in rope.lines().take(1000) {
+            let len = line.len_chars();
+            let last = (len > 0).then(|| line.char(len - 1));
+            let second_last = (len > 1).then(|| line.char(len - 2));
+            match (second_last, last) {
+                (Some('\r'), Some('\n')) => counts[1] += 1,
+                (_, Some('\n')) => counts[0] += 1,
+                (_, Some('\r')) => counts[2] += 1,
+                _ => (),
+            }
+        }
+        let styles = [Self::Lf, Self::CrLf, Self::Cr];
+        // Ties go to the first style (preferring LF)
+        let most = counts.iter().max().copied().unwrap_or(0);
+        styles[counts.iter().position(|c| *c == most).unwrap_or(0)]
+    }
+
+    /// Convert every line ending within a piece of text to this style
+    #[must_use]
+    pub fn normalise(self, st: &str) -> String {
+        let unix = st.replace("\r\n", "\n").replace('\r', "\n");
+        match self {
+            Self::Lf => unix,
+            _ => unix.replace('\n', self.as_str()),
+        }
+    }
+}
+
+impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+            Self::Cr => "CR",
+        };
+        write!(f, "{name}")
+    }
 }
 
 impl Document {
@@ -25,9 +160,9 @@ impl Document {
     pub fn new(size: Size) -> Self {
         Self {
             file: Rope::from_str("\n"),
-            lines: vec![String::new()],
-            dbl_map: CharMap::default(),
-            tab_map: CharMap::default(),
+            revision: 0,
+            line_cache: LineCache::default(),
+            text_cache: TextCache::default(),
             file_name: None,
             cursor: Cursor::default(),
             offset: Loc::default(),
@@ -35,43 +170,68 @@ impl Document {
             char_ptr: 0,
             event_mgmt: EventMgmt::default(),
             tab_width: 4,
+            wrap: false,
+            folds: vec![],
+            edit_log: None,
             old_cursor: 0,
             in_redo: false,
             info: DocumentInfo {
-                loaded_to: 1,
                 eol: false,
                 read_only: false,
+                line_ending: LineEnding::default(),
+                encoding: Encoding::default(),
+                lossy: false,
+                disk: None,
             },
             secondary_cursors: vec![],
+            history_store: None,
+            backup: Backup::default(),
+            pager: None,
         }
     }
 
     /// Open a document from a file name.
+    /// The character encoding of the file is detected automatically.
     /// # Errors
     /// Returns an error when file doesn't exist, or has incorrect permissions.
     /// Also returns an error if the rope fails to initialise due to character set issues or
     /// disk errors.
     #[cfg(not(tarpaulin_include))]
     pub fn open<S: Into<String>>(size: Size, file_name: S) -> Result<Self> {
-        // Try to find the absolute path and load it into the reader
+        Self::open_with_encoding(size, file_name, None)
+    }
+
+    /// Open a document from a file name, using a specific encoding (or None to detect it).
+    /// If the file can't be decoded without losing information, it is opened as read only.
+    /// # Errors
+    /// Returns an error when file doesn't exist, or has incorrect permissions.
+    /// Also returns an error if the rope fails to initialise due to character set issues or
+    /// disk errors.
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_with_encoding<S: Into<String>>(
+        size: Size,
+        file_name: S,
+        encoding: Option<Encoding>,
+    ) -> Result<Self> {
+        // Try to find the absolute path and load it into the rope
         let file_name = file_name.into();
         let full_path = std::fs::canonicalize(&file_name)?;
-        let file = load_rope_from_reader(BufReader::new(File::open(&full_path)?));
+        let (file, encoding, lossy, disk) = load_rope_from_file(&full_path, encoding)?;
         // Find the string representation of the absolute path
         let file_name = get_absolute_path(&file_name);
         Ok(Self {
             info: DocumentInfo {
-                loaded_to: 0,
-                eol: !file
-                    .line(file.len_lines().saturating_sub(1))
-                    .to_string()
-                    .is_empty(),
-                read_only: false,
+                line_ending: LineEnding::detect(&file),
+                eol: has_eol(&file),
+                read_only: lossy,
+                encoding,
+                lossy,
+                disk: Some(disk),
             },
             file,
-            lines: vec![],
-            dbl_map: CharMap::default(),
-            tab_map: CharMap::default(),
+            revision: 0,
+            line_cache: LineCache::default(),
+            text_cache: TextCache::default(),
             file_name,
             cursor: Cursor::default(),
             offset: Loc::default(),
@@ -79,13 +239,229 @@ impl Document {
             char_ptr: 0,
             event_mgmt: EventMgmt::default(),
             tab_width: 4,
+            wrap: false,
+            folds: vec![],
+            edit_log: None,
             old_cursor: 0,
             in_redo: false,
             secondary_cursors: vec![],
+            history_store: None,
+            backup: Backup::default(),
+            pager: None,
         })
     }
 
+    /// Open a document from a file name in large file mode.
+    /// Rather than reading the whole file into memory, lines are read from the disk as needed.
+    /// Documents opened this way are read only, and aren't syntax highlighted by ox.
+    /// Files that can't be paged (such as UTF-16 files) are opened normally instead.
+    /// # Errors
+    /// Returns an error when file doesn't exist, or has incorrect permissions.
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_paged<S: Into<String>>(size: Size, file_name: S) -> Result<Self> {
+        Self::open_paged_with_encoding(size, file_name, None)
+    }
+
+    /// Open a document from a file name in large file mode (see `Document::open_paged`),
+    /// using a specific encoding (or None to detect it).
+    /// # Errors
+    /// Returns an error when file doesn't exist, or has incorrect permissions.
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_paged_with_encoding<S: Into<String>>(
+        size: Size,
+        file_name: S,
+        encoding: Option<Encoding>,
+    ) -> Result<Self> {
+        let file_name = file_name.into();
+        let full_path = std::fs::canonicalize(&file_name)?;
+        let Some(pager) = Pager::open(&full_path, encoding)? else {
+            return Self::open_with_encoding(size, file_name, encoding);
+        };
+        let mut doc = Self::new(size);
+        doc.file = Rope::new();
+        doc.changed();
+        doc.file_name = get_absolute_path(&file_name);
+        doc.info = DocumentInfo {
+            read_only: true,
+            eol: false,
+            line_ending: pager.line_ending,
+            encoding: pager.encoding,
+            lossy: false,
+            disk: DiskState::stat(&full_path),
+        };
+        doc.pager = Some(pager);
+        Ok(doc)
+    }
+
+    /// Whether this document is in large file mode (see `Document::open_paged`)
+    #[must_use]
+    pub fn is_paged(&self) -> bool {
+        self.pager.is_some()
+    }
+
+    /// Read the whole of a paged document into memory, so it can be edited.
+    /// # Errors
+    /// Returns an error if the file couldn't be read.
+    pub fn load_fully(&mut self) -> Result<()> {
+        let Some(pager) = self.pager.take() else {
+            return Ok(());
+        };
+        self.info.read_only = false;
+        self.info.lossy = false;
+        let result = self.reload_with_encoding(pager.encoding);
+        if result.is_err() {
+            self.pager = Some(pager);
+            self.info.read_only = true;
+        }
+        result
+    }
+
+    /// Read the file this document was opened from again, interpreting it with a specific encoding.
+    /// Any unsaved changes and undo history are discarded.
+    /// # Errors
+    /// Returns an error if the document has no file name, or the file couldn't be read.
+    pub fn reload_with_encoding(&mut self, encoding: Encoding) -> Result<()> {
+        let file_name = self.file_name.clone().ok_or(Error::NoFileName)?;
+        if self.pager.is_some() {
+            let path = Path::new(&file_name);
+            if let Some(pager) = Pager::open(path, Some(encoding))? {
+                self.info.encoding = pager.encoding;
+                self.info.line_ending = pager.line_ending;
+                self.info.disk = DiskState::stat(path);
+                self.pager = Some(pager);
+                self.changed();
+                self.folds.clear();
+                self.move_to(&Loc::default());
+                self.offset = Loc::default();
+                return Ok(());
+            }
+            // This encoding can't be paged, so fall back to loading the whole file
+            self.pager = None;
+        }
+        let (file, encoding, lossy, disk) =
+            load_rope_from_file(Path::new(&file_name), Some(encoding))?;
+        // Files that were read only because of a bad decode can be edited now it is fixed
+        self.info.read_only = lossy || (self.info.read_only && !self.info.lossy);
+        self.info.lossy = lossy;
+        self.info.encoding = encoding;
+        self.info.line_ending = LineEnding::detect(&file);
+        self.info.eol = has_eol(&file);
+        self.info.disk = Some(disk);
+        self.file = file;
+        self.changed();
+        self.folds.clear();
+        self.event_mgmt = EventMgmt::default();
+        self.secondary_cursors.clear();
+        self.move_to(&Loc::default());
+        self.offset = Loc::default();
+        Ok(())
+    }
+
+    /// Read the file this document was opened from again, after another program changed it.
+    /// The cursor stays where it was (as far as the new contents allow).
+    /// The new contents replace the old as a single edit, so any unsaved changes
+    /// can be brought back by undoing the reload.
+    /// # Errors
+    /// Returns an error if the document has no file name, or the file couldn't be read.
+    pub fn reload(&mut self) -> Result<()> {
+        let (loc, offset) = (self.loc(), self.offset);
+        if self.pager.is_some() {
+            self.reload_with_encoding(self.info.encoding)?;
+        } else {
+            let file_name = self.file_name.clone().ok_or(Error::NoFileName)?;
+            let (file, encoding, lossy, disk) =
+                load_rope_from_file(Path::new(&file_name), Some(self.info.encoding))?;
+            self.commit();
+            let line_ending = LineEnding::detect(&file);
+            if line_ending != self.info.line_ending {
+                self.event_mgmt.line_ending = Some((self.info.line_ending, line_ending));
+                self.info.line_ending = line_ending;
+            }
+            self.info.eol = has_eol(&file);
+            self.replace_text(file);
+            self.info.read_only = lossy || (self.info.read_only && !self.info.lossy);
+            self.info.lossy = lossy;
+            self.info.encoding = encoding;
+            self.info.disk = Some(disk);
+            self.folds.clear();
+            self.secondary_cursors.clear();
+        }
+        let y = loc.y.min(self.len_lines().saturating_sub(1));
+        let x = loc.x.min(self.line(y).map_or(0, |l| l.chars().count()));
+        self.offset.y = offset.y.min(y);
+        self.move_to(&Loc::at(x, y));
+        // The document now matches the file on the disk
+        self.event_mgmt.disk_write(self.cursor);
+        Ok(())
+    }
+
+    /// Check whether another program has changed the file since it was last opened or saved.
+    /// Returns what the file now looks like if its contents are different.
+    /// (If only the modification time changed, that is noted, so the file isn't read again)
+    pub fn external_change(&mut self) -> Option<DiskState> {
+        let recorded = self.info.disk?;
+        let path = Path::new(self.file_name.as_ref()?);
+        if recorded.unchanged(path) {
+            return None;
+        } else if self.pager.is_some() {
+            return DiskState::stat(path);
+        }
+        let current = DiskState::read(path)?;
+        if current.hash == recorded.hash {
+            self.info.disk = Some(current);
+            None
+        } else {
+            Some(current)
+        }
+    }
+
+    /// Open a document from a file name, restoring its undo history from a history store
+    /// (provided the file hasn't changed since the history was saved).
+    /// Any history will be written back to the store whenever the document is saved.
+    /// # Errors
+    /// Returns an error when file doesn't exist, or has incorrect permissions.
+    /// Also returns an error if the rope fails to initialise due to character set issues or
+    /// disk errors.
+    #[cfg(not(tarpaulin_include))]
+    pub fn open_with_history<S: Into<String>>(
+        size: Size,
+        file_name: S,
+        store: HistoryStore,
+    ) -> Result<Self> {
+        let mut doc = Self::open(size, file_name)?;
+        doc.attach_history(store);
+        Ok(doc)
+    }
+
+    /// Persist the undo history of this document in a history store.
+    /// If the store holds history for this file and its contents still match, it is restored.
+    pub fn attach_history(&mut self, store: HistoryStore) {
+        if let Some(file_name) = self.history_key() {
+            if let Some(mgmt) = store.load(&file_name, hash_rope(&self.file)) {
+                self.event_mgmt = mgmt;
+            }
+        }
+        self.history_store = Some(store);
+    }
+
+    /// Write the undo history of this document to its history store (if it has one).
+    /// # Errors
+    /// Returns an error if the history failed to write to the disk
+    pub fn save_history(&self) -> Result<()> {
+        if let (Some(store), Some(file_name)) = (&self.history_store, self.history_key()) {
+            store.save(&file_name, hash_rope(&self.file), &self.event_mgmt)?;
+        }
+        Ok(())
+    }
+
+    /// The name history is stored under (the absolute path, where it can be found)
+    fn history_key(&self) -> Option<String> {
+        let file_name = self.file_name.as_ref()?;
+        Some(get_absolute_path(file_name).unwrap_or_else(|| file_name.clone()))
+    }
+
     /// Save back to the file the document was opened from.
+    /// Saving is atomic: the file on the disk is either fully updated or left as it was.
     /// # Errors
     /// Returns an error if the file fails to write, due to permissions
     /// or character set issues.
@@ -93,9 +469,10 @@ impl Document {
         if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else if let Some(file_name) = &self.file_name {
-            self.file
-                .write_to(BufWriter::new(File::create(file_name)?))?;
-            self.event_mgmt.disk_write(&self.take_snapshot());
+            self.info.disk = Some(self.write_file(file_name)?);
+            self.event_mgmt.disk_write(self.cursor);
+            // Undo history is a convenience, so failing to store it shouldn't fail the save
+            let _ = self.save_history();
             Ok(())
         } else {
             Err(Error::NoFileName)
@@ -110,40 +487,248 @@ impl Document {
         if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else {
-            self.file
-                .write_to(BufWriter::new(File::create(file_name)?))?;
-            Ok(())
+            self.write_file(file_name).map(|_| ())
         }
     }
 
-    /// Load lines in this document up to a specified index.
-    /// This must be called before starting to edit the document as
-    /// this is the function that actually load and processes the text.
-    pub fn load_to(&mut self, mut to: usize) {
-        // Make sure to doesn't go over the number of lines in the buffer
-        let len_lines = self.file.len_lines();
-        if to >= len_lines {
-            to = len_lines;
-        }
-        // Only act if there are lines we haven't loaded yet
-        if to > self.info.loaded_to {
-            // For each line, run through each character and make note of any double width characters
-            for i in self.info.loaded_to..to {
-                let line: String = self.file.line(i).chars().collect();
-                // Add to char maps
-                let (dbl_map, tab_map) = form_map(&line, self.tab_width);
-                self.dbl_map.insert(i, dbl_map);
-                self.tab_map.insert(i, tab_map);
-                // Cache this line
-                self.lines
-                    .push(line.trim_end_matches(['\n', '\r']).to_string());
+    /// Write the contents of this document to a file, in the document's encoding.
+    /// The contents go to a temporary file alongside the target first, which then replaces
+    /// the target in a single step, so a failure part way through never leaves a truncated file.
+    /// Returns a record of the newly written file.
+    fn write_file(&self, file_name: &str) -> Result<DiskState> {
+        let bytes = if self.info.encoding == Encoding::Utf8 {
+            None
+        } else {
+            let bytes = self.info.encoding.encode(&self.file.to_string());
+            Some(bytes.ok_or_else(|| Error::Unencodable {
+                encoding: self.info.encoding.to_string(),
+            })?)
+        };
+        // Write through symbolic links rather than replacing them
+        let target = std::fs::canonicalize(file_name).unwrap_or_else(|_| PathBuf::from(file_name));
+        let original = std::fs::metadata(&target).ok();
+        let temp = temp_path(&target);
+        let result = self
+            .write_temp(&temp, bytes.as_deref(), original.as_ref())
+            .and_then(|()| {
+                if original.is_some() {
+                    self.backup.make(&target)?;
+                }
+                std::fs::rename(&temp, &target).map_err(|e| Error::Rename {
+                    reason: e.to_string(),
+                })
+            });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        result?;
+        sync_parent(&target);
+        Ok(DiskState {
+            modified: std::fs::metadata(&target).and_then(|m| m.modified()).ok(),
+            len: bytes.as_ref().map_or(self.file.len_bytes(), Vec::len) as u64,
+            hash: bytes
+                .as_ref()
+                .map_or_else(|| hash_rope(&self.file), |b| hash(b.iter().copied())),
+        })
+    }
+
+    /// Write the document into a temporary file, matching the original file's metadata
+    fn write_temp(
+        &self,
+        temp: &Path,
+        bytes: Option<&[u8]>,
+        original: Option<&std::fs::Metadata>,
+    ) -> Result<()> {
+        let temp_error = |e: std::io::Error| Error::TempFile {
+            reason: e.to_string(),
+        };
+        let _ = std::fs::remove_file(temp);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp)
+            .map_err(temp_error)?;
+        let mut writer = BufWriter::new(file);
+        if let Some(bytes) = bytes {
+            writer.write_all(bytes).map_err(temp_error)?;
+        } else {
+            self.file.write_to(&mut writer).map_err(temp_error)?;
+        }
+        let file = writer
+            .into_inner()
+            .map_err(|e| temp_error(e.into_error()))?;
+        if let Some(original) = original {
+            file.set_permissions(original.permissions())
+                .map_err(temp_error)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only privileged users can give files away, so this is best effort
+                let _ =
+                    std::os::unix::fs::fchown(&file, Some(original.uid()), Some(original.gid()));
             }
-            // Store new loaded point
-            self.info.loaded_to = to;
         }
+        file.sync_all().map_err(|e| Error::Sync {
+            reason: e.to_string(),
+        })
+    }
+
+    /// Convert every line ending in this document to a different style.
+    /// Future edits will use this style too.
+    /// The conversion is a single edit, so it can be undone.
+    /// # Errors
+    /// Returns an error if the document is read only.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) -> Result<()> {
+        if self.info.read_only {
+            return Err(Error::ReadOnlyFile);
+        } else if self.info.line_ending == line_ending {
+            return Ok(());
+        }
+        let cursor = self.char_loc();
+        self.commit();
+        // The change of style is kept in the history, so undoing it switches back
+        let before = std::mem::replace(&mut self.info.line_ending, line_ending);
+        self.event_mgmt.line_ending = Some((before, line_ending));
+        let original = self.file.to_string();
+        let converted = line_ending.normalise(&original);
+        if converted != original {
+            self.delete_text(&Loc::at(0, 0), &original)?;
+            self.insert(&Loc::at(0, 0), &converted)?;
+        }
+        // Only the line endings have changed, so the cursor can stay where it was
+        self.move_to(&cursor);
+        self.commit();
+        Ok(())
+    }
+
+    /// Make sure the document ends (or doesn't end) with a line ending.
+    /// The lines of the document stay the same, only what follows the last one changes.
+    /// Empty documents are left alone.
+    /// # Errors
+    /// Returns an error if the document is read only.
+    pub fn set_final_newline(&mut self, newline: bool) -> Result<()> {
+        if self.info.read_only {
+            return Err(Error::ReadOnlyFile);
+        }
+        let len = self.file.len_chars();
+        let ends = len > 0 && matches!(self.file.char(len - 1), '\n' | '\r');
+        if len == 0 || ends == newline {
+            return Ok(());
+        }
+        let cursor = self.char_loc();
+        self.commit();
+        if newline {
+            let end = self.file_pos_to_loc(len);
+            self.insert(&end, self.info.line_ending.as_str())?;
+        } else {
+            let crlf =
+                len > 1 && self.file.char(len - 2) == '\r' && self.file.char(len - 1) == '\n';
+            let start = len - 1 - usize::from(crlf);
+            let removed = self.file.slice(start..len).to_string();
+            self.delete_text(&self.file_pos_to_loc(start), &removed)?;
+        }
+        // Documents without a final line ending show their last line as it is
+        self.info.eol = !newline;
+        self.move_to(&cursor);
+        self.commit();
+        Ok(())
     }
 }
 
+/// How the previous contents of a file are kept when a document is saved over it
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub enum Backup {
+    /// Don't keep any backups
+    #[default]
+    None,
+    /// Keep the previous version alongside the file, with a `~` on the end of its name
+    Tilde,
+    /// Keep a number of previous versions of each file in a directory
+    Directory { dir: PathBuf, keep: usize },
+}
+
+impl Backup {
+    /// Back up a file before it is replaced
+    fn make(&self, target: &Path) -> Result<()> {
+        let result = match self {
+            Self::None => Ok(()),
+            Self::Tilde => {
+                let mut backup = target.as_os_str().to_owned();
+                backup.push("~");
+                std::fs::copy(target, backup).map(|_| ())
+            }
+            Self::Directory { dir, keep } => Self::rolling(target, dir, *keep),
+        };
+        result.map_err(|e| Error::Backup {
+            reason: e.to_string(),
+        })
+    }
+
+    /// Add a backup to a directory, removing the oldest backups of the file beyond a limit
+    fn rolling(target: &Path, dir: &Path, keep: usize) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        // Name backups after the full path of the file, so files with the same name don't clash
+        let prefix = format!(
+            "{}~",
+            target.to_string_lossy().replace(['/', '\\', ':'], "%")
+        );
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        std::fs::copy(target, dir.join(format!("{prefix}{time:020}")))?;
+        let mut backups: Vec<PathBuf> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+            })
+            .collect();
+        backups.sort();
+        for old in backups.iter().take(backups.len().saturating_sub(keep)) {
+            std::fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find a place to write a file before it replaces the target (in the same directory,
+/// so that it can be renamed over the target)
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    target.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Make sure a rename within a directory has made it to the disk (best effort)
+fn sync_parent(target: &Path) {
+    #[cfg(unix)]
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = target;
+}
+
+/// Read a file into a rope, along with the encoding used, whether decoding was lossy
+/// and a record of the file
+fn load_rope_from_file(
+    path: &Path,
+    encoding: Option<Encoding>,
+) -> Result<(Rope, Encoding, bool, DiskState)> {
+    let bytes = std::fs::read(path)?;
+    let encoding = encoding.unwrap_or_else(|| Encoding::detect(&bytes));
+    let (text, lossless) = encoding.decode(&bytes);
+    let disk = DiskState::of(path, &bytes);
+    Ok((Rope::from_str(&text), encoding, !lossless, disk))
+}
+
+/// Determine if a rope ends with a line ending
+pub(crate) fn has_eol(file: &Rope) -> bool {
+    !file
+        .line(file.len_lines().saturating_sub(1))
+        .to_string()
+        .is_empty()
+}
+
 pub fn load_rope_from_reader<T: Read + BufRead>(mut reader: T) -> Rope {
     let mut buffer = [0u8; 2048]; // Buffer to read chunks
     let mut valid_string = String::new();
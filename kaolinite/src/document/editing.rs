@@ -1,7 +1,7 @@
 use crate::event::{Error, Event, Result};
-use crate::map::form_map;
 use crate::utils::{get_range, tab_boundaries_backward};
 use crate::{Document, Loc};
+use ropey::Rope;
 use std::ops::RangeBounds;
 
 impl Document {
@@ -9,36 +9,116 @@ impl Document {
     /// # Errors
     /// Returns an error if location is out of range.
     pub fn insert(&mut self, loc: &Loc, st: &str) -> Result<()> {
-        self.out_of_range(loc.x, loc.y)?;
+        // Text that spans multiple lines can fill an empty document (as converting line endings does)
+        let filling = self.file.len_chars() == 0 && *loc == Loc::default() && line_breaks(st) > 0;
+        if !filling {
+            self.out_of_range(loc.x, loc.y)?;
+        }
+        // Text that spans multiple lines is handled separately
+        // (history is replayed as it was, in case the line endings were changed since)
+        if line_breaks(st) > 0 {
+            let st = if self.in_redo {
+                st.to_string()
+            } else {
+                self.info.line_ending.normalise(st)
+            };
+            self.insert_lines(loc, &st);
+            return Ok(());
+        }
         // Move cursor to location
         self.move_to(loc);
         // Update rope
         let idx = self.loc_to_file_pos(loc);
         self.file.insert(idx, st);
-        // Update cache
-        let line: String = self.file.line(loc.y).chars().collect();
-        self.lines[loc.y] = line.trim_end_matches(['\n', '\r']).to_string();
-        // Update unicode map
-        let dbl_start = self.dbl_map.shift_insertion(loc, st, self.tab_width);
-        let tab_start = self.tab_map.shift_insertion(loc, st, self.tab_width);
-        // Register new double widths and tabs
-        let (mut dbls, mut tabs) = form_map(st, self.tab_width);
-        // Shift up to match insertion position in the document
-        let tab_shift = self.tab_width.saturating_sub(1) * tab_start;
-        for e in &mut dbls {
-            *e = (e.0 + loc.x + dbl_start + tab_shift, e.1 + loc.x);
-        }
-        for e in &mut tabs {
-            *e = (e.0 + loc.x + tab_shift + dbl_start, e.1 + loc.x);
-        }
-        self.dbl_map.splice(loc, dbl_start, dbls);
-        self.tab_map.splice(loc, tab_start, tabs);
+        self.edited(loc.y, 1, 1);
+        self.register(Event::Insert(*loc, st.to_string()));
         // Go to end x position
         self.move_to_x(loc.x + st.chars().count());
         self.old_cursor = self.loc().x;
         Ok(())
     }
 
+    /// Replace the whole text of the document as a single edit.
+    /// The text is kept exactly as it is, without converting its line endings.
+    pub(crate) fn replace_text(&mut self, file: Rope) {
+        let (old, new) = (self.file.to_string(), file.to_string());
+        if old == new {
+            return;
+        }
+        let lines = self.file.len_lines();
+        self.file = file;
+        self.edited(0, lines, self.file.len_lines());
+        self.register(Event::Delete(Loc::default(), old));
+        self.register(Event::Insert(Loc::default(), new));
+    }
+
+    /// Inserts a string that contains line breaks into this document
+    fn insert_lines(&mut self, loc: &Loc, st: &str) {
+        // Update rope
+        let idx = self.loc_to_file_pos(loc);
+        self.file.insert(idx, st);
+        self.edited(loc.y, 1, 1 + line_breaks(st));
+        self.register(Event::Insert(*loc, st.to_string()));
+        // Go to the end of the inserted text
+        let end = idx + st.chars().count();
+        let y = self.file.char_to_line(end);
+        self.move_to(&Loc::at(end - self.file.line_to_char(y), y));
+        self.old_cursor = self.loc().x;
+    }
+
+    /// Remove the whitespace from the end of every line, returning whether any was removed.
+    /// Each removal is an ordinary edit, so it can be undone.
+    /// # Errors
+    /// Returns an error if the document is read only.
+    pub fn trim_trailing_whitespace(&mut self) -> Result<bool> {
+        if self.info.read_only {
+            return Err(Error::ReadOnlyFile);
+        }
+        let cursor = self.char_loc();
+        let mut trimmed = false;
+        for y in 0..self.len_lines() {
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+            let kept = line.trim_end().chars().count();
+            if kept < line.chars().count() {
+                let removed: String = line.chars().skip(kept).collect();
+                self.exe(Event::Delete(Loc::at(kept, y), removed))?;
+                trimmed = true;
+            }
+        }
+        self.move_to(&cursor);
+        Ok(trimmed)
+    }
+
+    /// Deletes a piece of text (which may span multiple lines) at a location.
+    /// # Errors
+    /// Returns an error if location is out of range.
+    pub fn delete_text(&mut self, loc: &Loc, st: &str) -> Result<()> {
+        let breaks = line_breaks(st);
+        if breaks == 0 {
+            return self.delete(loc.x..=loc.x + st.chars().count(), loc.y);
+        }
+        self.out_of_range(loc.x, loc.y)?;
+        // Update rope (finding the end by lines, in case the line endings have since changed)
+        let start = self.loc_to_file_pos(loc);
+        let last = st.rsplit(['\n', '\r']).next().unwrap_or_default();
+        if loc.y + breaks >= self.file.len_lines() {
+            return Err(Error::OutOfRange);
+        }
+        let end = self.file.line_to_char(loc.y + breaks) + last.chars().count();
+        if end > self.file.len_chars() {
+            return Err(Error::OutOfRange);
+        }
+        let removed = self.file.slice(start..end).to_string();
+        self.file.remove(start..end);
+        self.edited(loc.y, 1 + breaks, 1);
+        self.register(Event::Delete(*loc, removed));
+        self.move_to(loc);
+        self.old_cursor = self.loc().x;
+        Ok(())
+    }
+
     /// Deletes a character at a location whilst checking for tab spaces
     ///
     /// # Errors
@@ -78,24 +158,10 @@ impl Document {
         start += line_start;
         end += line_start;
         let removed = self.file.slice(start..end).to_string();
-        // Update unicode and tab map
-        self.dbl_map.shift_deletion(
-            &Loc::at(line_start, y),
-            (start, end),
-            &removed,
-            self.tab_width,
-        );
-        self.tab_map.shift_deletion(
-            &Loc::at(line_start, y),
-            (start, end),
-            &removed,
-            self.tab_width,
-        );
         // Update rope
         self.file.remove(start..end);
-        // Update cache
-        let line: String = self.file.line(y).chars().collect();
-        self.lines[y] = line.trim_end_matches(['\n', '\r']).to_string();
+        self.edited(y, 1, 1);
+        self.register(Event::Delete(Loc::at(start - line_start, y), removed));
         self.old_cursor = self.loc().x;
         Ok(())
     }
@@ -104,22 +170,17 @@ impl Document {
     /// # Errors
     /// Returns an error if location is out of range.
     pub fn insert_line(&mut self, loc: usize, contents: String) -> Result<()> {
-        if !(self.lines.is_empty() || self.len_lines() == 0 && loc == 0) {
+        if !(self.len_lines() == 0 && loc == 0) {
             self.out_of_range(0, loc.saturating_sub(1))?;
         }
-        // Update unicode and tab map
-        self.dbl_map.shift_down(loc);
-        self.tab_map.shift_down(loc);
-        // Calculate the unicode map and tab map of this line
-        let (dbl_map, tab_map) = form_map(&contents, self.tab_width);
-        self.dbl_map.insert(loc, dbl_map);
-        self.tab_map.insert(loc, tab_map);
-        // Update cache
-        self.lines.insert(loc, contents.to_string());
         // Update rope
         let char_idx = self.file.line_to_char(loc);
-        self.file.insert(char_idx, &(contents + "\n"));
-        self.info.loaded_to += 1;
+        self.file.insert(
+            char_idx,
+            &(contents.clone() + self.info.line_ending.as_str()),
+        );
+        self.edited(loc, 0, 1);
+        self.register(Event::InsertLine(loc, contents));
         // Goto line
         self.move_to_y(loc);
         self.old_cursor = self.loc().x;
@@ -131,19 +192,13 @@ impl Document {
     /// Returns an error if location is out of range.
     pub fn delete_line(&mut self, loc: usize) -> Result<()> {
         self.out_of_range(0, loc)?;
-        // Update tab & unicode map
-        self.dbl_map.delete(loc);
-        self.tab_map.delete(loc);
-        // Shift down other line numbers in the hashmap
-        self.dbl_map.shift_up(loc);
-        self.tab_map.shift_up(loc);
-        // Update cache
-        self.lines.remove(loc);
+        let removed = self.line(loc).unwrap_or_default();
+        self.register(Event::DeleteLine(loc, removed));
         // Update rope
         let idx_start = self.file.line_to_char(loc);
         let idx_end = self.file.line_to_char(loc + 1);
         self.file.remove(idx_start..idx_end);
-        self.info.loaded_to = self.info.loaded_to.saturating_sub(1);
+        self.edited(loc, 1, 0);
         // Goto line
         self.move_to_y(loc);
         self.old_cursor = self.loc().x;
@@ -182,3 +237,36 @@ impl Document {
         Ok(())
     }
 }
+
+impl Document {
+    /// Record an event that has taken place, so that it can be undone later
+    /// (and passed on to anything following the edits made to this document)
+    fn register(&mut self, ev: Event) {
+        if let Some(log) = &mut self.edit_log {
+            log.push(ev.clone());
+        }
+        if !self.in_redo {
+            self.event_mgmt.register(ev);
+        }
+    }
+
+    /// Start keeping a log of the edits made to this document.
+    /// Every edit bumps the revision, so edits were missed (by changing the rope directly)
+    /// if the revision has moved on by more than the number of edits taken.
+    pub fn follow_edits(&mut self) {
+        self.edit_log.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the edits made since they were last taken (in the order they were made)
+    pub fn take_edits(&mut self) -> Vec<Event> {
+        self.edit_log
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+/// Count the number of line breaks within a string
+fn line_breaks(st: &str) -> usize {
+    ropey::str_utils::byte_to_line_idx(st, st.len())
+}
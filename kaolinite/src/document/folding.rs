@@ -0,0 +1,83 @@
+/// folding.rs - hides away regions of a document (code folding)
+use crate::{Document, Loc};
+use std::ops::Range;
+
+impl Document {
+    /// Fold away a range of lines, leaving the line above it visible to represent it.
+    /// Any folds inside the range are swallowed up by it.
+    /// The cursor is moved out of the range if it was inside it.
+    pub fn fold(&mut self, hidden: Range<usize>) {
+        let hidden = hidden.start..hidden.end.min(self.len_lines());
+        let covered = self
+            .folds
+            .iter()
+            .any(|f| f.start <= hidden.start && hidden.end <= f.end);
+        if hidden.is_empty() || hidden.start == 0 || covered {
+            return;
+        }
+        self.folds
+            .retain(|f| f.end <= hidden.start || hidden.end <= f.start);
+        let at = self.folds.partition_point(|f| f.start < hidden.start);
+        let header = hidden.start - 1;
+        self.folds.insert(at, hidden);
+        // Make sure no cursors are left on hidden lines
+        if self.is_hidden(self.loc().y) {
+            self.move_to(&Loc::at(self.char_loc().x, header));
+        }
+        let folds = &self.folds;
+        self.secondary_cursors
+            .retain(|c| !folds.iter().any(|f| f.contains(&c.y)));
+        self.bring_cursor_in_viewport();
+    }
+
+    /// Unfold the fold represented by a line (or the fold the line is hidden within).
+    /// Returns true if a fold was opened.
+    pub fn unfold(&mut self, y: usize) -> bool {
+        let before = self.folds.len();
+        self.folds.retain(|f| f.start != y + 1 && !f.contains(&y));
+        let unfolded = self.folds.len() != before;
+        if unfolded {
+            self.bring_cursor_in_viewport();
+        }
+        unfolded
+    }
+
+    /// Open up every fold in the document
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+        self.bring_cursor_in_viewport();
+    }
+
+    /// Determine if a line is hidden away by a fold
+    #[must_use]
+    pub fn is_hidden(&self, y: usize) -> bool {
+        self.fold_containing(y).is_some()
+    }
+
+    /// Find the fold a line is hidden within
+    #[must_use]
+    pub fn fold_containing(&self, y: usize) -> Option<Range<usize>> {
+        let at = self.folds.partition_point(|f| f.end <= y);
+        self.folds.get(at).filter(|f| f.contains(&y)).cloned()
+    }
+
+    /// Find the fold that a line represents (the fold just below it)
+    #[must_use]
+    pub fn fold_below(&self, y: usize) -> Option<Range<usize>> {
+        self.fold_containing(y + 1).filter(|f| f.start == y + 1)
+    }
+
+    /// Find the nearest line above a line that isn't hidden by a fold
+    #[must_use]
+    pub fn line_above(&self, y: usize) -> Option<usize> {
+        let above = y.checked_sub(1)?;
+        Some(self.fold_containing(above).map_or(above, |f| f.start - 1))
+    }
+
+    /// Find the nearest line below a line that isn't hidden by a fold
+    /// (this can be past the end of the document)
+    #[must_use]
+    pub fn line_below(&self, y: usize) -> usize {
+        self.fold_containing(y + 1).map_or(y + 1, |f| f.end)
+    }
+}
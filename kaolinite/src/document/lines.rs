@@ -6,7 +6,7 @@ impl Document {
     /// Get the line at a specified index
     #[must_use]
     pub fn line(&self, line: usize) -> Option<String> {
-        Some(self.lines.get(line)?.to_string())
+        Some(self.line_info(line)?.text.clone())
     }
 
     /// Get the line at a specified index and trim it
@@ -19,9 +19,33 @@ impl Document {
     /// Returns the number of lines in the document
     #[must_use]
     pub fn len_lines(&self) -> usize {
+        if let Some(pager) = &self.pager {
+            return pager.len_lines();
+        }
         self.file.len_lines().saturating_sub(1) + usize::from(self.info.eol)
     }
 
+    /// Collect the lines from the start of the document up to (but not including) an index
+    #[must_use]
+    pub fn lines_to(&self, to: usize) -> Vec<String> {
+        (0..to).map_while(|y| self.line(y)).collect()
+    }
+
+    /// Collect the lines from the start of the document down to the bottom of the viewport
+    /// (syntax highlighters need to see everything above the lines they highlight)
+    #[must_use]
+    pub fn lines_to_view(&self) -> Vec<String> {
+        self.lines_to(self.offset.y + self.size.h + 1)
+    }
+
+    /// Make sure lines up to a certain index have been found in paged documents
+    /// (use `usize::MAX` to find every line)
+    pub fn index_to(&self, line: usize) {
+        if let Some(pager) = &self.pager {
+            pager.index_to(line);
+        }
+    }
+
     /// Evaluate the line number text for a specific line
     #[must_use]
     pub fn line_number(&self, request: usize) -> String {
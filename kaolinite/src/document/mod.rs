@@ -1,19 +1,30 @@
 /// document.rs - has Document, for opening, editing and saving documents
 use crate::event::{Error, Event, EventMgmt, Result};
-use crate::map::CharMap;
-use crate::searching::{Match, Searcher};
+use crate::history::HistoryStore;
+use crate::map::{count, inside, DblUsize};
+use crate::pager::Pager;
+use crate::searching::{Match, SearchText, Searcher};
 use crate::utils::{modeline, width, Loc, Size};
+use cache::{CachedLine, LineCache, TextCache};
+use disk::has_eol;
 use ropey::Rope;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+mod cache;
 pub mod cursor;
 pub mod disk;
 pub mod editing;
+pub mod folding;
 pub mod lines;
 pub mod words;
+pub mod wrapping;
 
 pub use cursor::Cursor;
-pub use disk::DocumentInfo;
+pub use disk::{Backup, DiskState, DocumentInfo, LineEnding};
+pub use wrapping::VisualRow;
 
 /// A document struct manages a file.
 /// It has tools to read, write and traverse a document.
@@ -26,14 +37,14 @@ pub struct Document {
     pub file_name: Option<String>,
     /// The rope of the document to facilitate reading and writing to disk
     pub file: Rope,
-    /// Cache of all the loaded lines in this document
-    pub lines: Vec<String>,
+    /// Goes up each time the rope is changed (if you edit the rope directly, bump this too)
+    pub revision: usize,
+    /// Recently read lines, along with where their double width and tab characters are
+    line_cache: LineCache,
+    /// The whole text of the document, as it was last searched
+    text_cache: TextCache,
     /// Stores information about the underlying file
     pub info: DocumentInfo,
-    /// Stores the locations of double width characters
-    pub dbl_map: CharMap,
-    /// Stores the locations of tab characters
-    pub tab_map: CharMap,
     /// Contains the size of this document for purposes of offset
     pub size: Size,
     /// Contains the cursor data structure
@@ -46,12 +57,24 @@ pub struct Document {
     pub event_mgmt: EventMgmt,
     /// Storage of the old cursor x position (to snap back to)
     pub old_cursor: usize,
-    /// Flag for if the editor is currently in a redo action
+    /// Flag for if the editor is currently in an undo or redo action
     pub in_redo: bool,
     /// The number of spaces a tab should be rendered as
     pub tab_width: usize,
+    /// Whether long lines are wrapped onto several rows instead of scrolling sideways
+    pub wrap: bool,
+    /// Ranges of lines hidden by folds, in order (the line above each range stays visible)
+    pub folds: Vec<Range<usize>>,
+    /// Edits made since they were last taken (None until something asks to follow them)
+    pub edit_log: Option<Vec<Event>>,
     /// Secondary cursor (for multi-cursors)
     pub secondary_cursors: Vec<Loc>,
+    /// Where to persist undo history between sessions (None if it shouldn't be persisted)
+    pub history_store: Option<HistoryStore>,
+    /// How to back up the file on the disk when saving over it
+    pub backup: Backup,
+    /// Reads lines straight from the disk for files too large to load (these are read only)
+    pub pager: Option<Pager>,
 }
 
 impl Document {
@@ -61,8 +84,8 @@ impl Document {
     pub fn get_file_type(&self) -> Option<&str> {
         let mut result = None;
         // Try to use modeline first off
-        if let Some(first_line) = self.lines.first() {
-            result = modeline(first_line);
+        if let Some(first_line) = self.line(0) {
+            result = modeline(&first_line);
         }
         // If an extension is available, use that instead
         if let Some(file_name) = &self.file_name {
@@ -95,8 +118,13 @@ impl Document {
     /// # Errors
     /// Will return an error if any of the events failed to be reversed.
     pub fn undo(&mut self) -> Result<()> {
-        if let Some(s) = self.event_mgmt.undo(self.take_snapshot()) {
-            self.apply_snapshot(s);
+        if let Some(t) = self.event_mgmt.undo(self.cursor) {
+            let events = t.events.into_iter().rev().map(Event::reverse).collect();
+            self.replay(events)?;
+            if let Some((before, _)) = t.line_ending {
+                self.info.line_ending = before;
+            }
+            self.restore_cursor(t.before);
         }
         Ok(())
     }
@@ -105,12 +133,141 @@ impl Document {
     /// # Errors
     /// Will return an error if any of the events failed to be re-executed.
     pub fn redo(&mut self) -> Result<()> {
-        if let Some(s) = self.event_mgmt.redo(&self.take_snapshot()) {
-            self.apply_snapshot(s);
+        if let Some(t) = self.event_mgmt.redo(self.cursor) {
+            self.replay(t.events)?;
+            if let Some((_, after)) = t.line_ending {
+                self.info.line_ending = after;
+            }
+            self.restore_cursor(t.after);
+        }
+        Ok(())
+    }
+
+    /// Move through the undo tree to a specific revision, undoing and redoing as required.
+    /// Revisions are numbered in the order they were made, with 0 being the original document.
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn goto_revision(&mut self, target: usize) -> Result<()> {
+        self.commit();
+        let target = target.min(self.event_mgmt.history.len().saturating_sub(1));
+        // Undo back to a common ancestor, then redo down the branch the target is on
+        while !self.event_mgmt.is_ancestor(self.event_mgmt.ptr, target) {
+            self.undo()?;
+        }
+        self.event_mgmt.route_to(target);
+        while self.event_mgmt.ptr != target {
+            self.redo()?;
         }
         Ok(())
     }
 
+    /// Move back a number of revisions in the order they were made (regardless of branch)
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn earlier(&mut self, steps: usize) -> Result<()> {
+        self.commit();
+        self.goto_revision(self.event_mgmt.ptr.saturating_sub(steps))
+    }
+
+    /// Move forward a number of revisions in the order they were made (regardless of branch)
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn later(&mut self, steps: usize) -> Result<()> {
+        self.commit();
+        self.goto_revision(self.event_mgmt.ptr.saturating_add(steps))
+    }
+
+    /// Move to the state the document was in a certain amount of time before the current revision
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn earlier_by(&mut self, duration: Duration) -> Result<()> {
+        self.commit();
+        let time = self.event_mgmt.history[self.event_mgmt.ptr].time;
+        let target = self
+            .event_mgmt
+            .revision_at(time.saturating_sub(duration.as_secs()));
+        self.goto_revision(target)
+    }
+
+    /// Move to the state the document was in a certain amount of time after the current revision
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn later_by(&mut self, duration: Duration) -> Result<()> {
+        self.commit();
+        let time = self.event_mgmt.history[self.event_mgmt.ptr].time;
+        let target = self
+            .event_mgmt
+            .revision_at(time.saturating_add(duration.as_secs()));
+        self.goto_revision(target.max(self.event_mgmt.ptr))
+    }
+
+    /// Switch to the next branch alongside the current revision in the undo tree
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn next_branch(&mut self) -> Result<()> {
+        self.switch_branch(true)
+    }
+
+    /// Switch to the previous branch alongside the current revision in the undo tree
+    /// # Errors
+    /// Will return an error if any of the events failed to be reversed or re-executed.
+    pub fn prev_branch(&mut self) -> Result<()> {
+        self.switch_branch(false)
+    }
+
+    /// Move to a sibling of the current revision, wrapping around at either end
+    fn switch_branch(&mut self, forward: bool) -> Result<()> {
+        self.commit();
+        let siblings = self.event_mgmt.siblings();
+        let here = siblings
+            .iter()
+            .position(|idx| *idx == self.event_mgmt.ptr)
+            .unwrap_or(0);
+        let there = if forward {
+            (here + 1) % siblings.len()
+        } else {
+            (here + siblings.len() - 1) % siblings.len()
+        };
+        self.goto_revision(siblings[there])
+    }
+
+    /// Execute events from the history without registering them as new events
+    fn replay(&mut self, events: Vec<Event>) -> Result<()> {
+        // Adding or removing the final line ending changes how the last line is shown
+        // (as can replacing the whole document, such as when it is reloaded)
+        let start = Loc::default();
+        let final_newline = matches!(
+            events.as_slice(),
+            [Event::Insert(_, st) | Event::Delete(_, st)] if matches!(st.as_str(), "\n" | "\r\n" | "\r")
+        ) || matches!(
+            events.as_slice(),
+            [Event::Delete(a, _), Event::Insert(b, _)] if *a == start && *b == start
+        );
+        let eol = has_eol(&self.file);
+        self.in_redo = true;
+        let result = events.into_iter().try_for_each(|ev| match ev {
+            Event::Insert(loc, st) => self.insert(&loc, &st),
+            Event::Delete(loc, st) => self.delete_text(&loc, &st),
+            Event::InsertLine(loc, st) => self.insert_line(loc, st),
+            Event::DeleteLine(loc, _) => self.delete_line(loc),
+            Event::SplitDown(loc) => self.split_down(&loc),
+            Event::SpliceUp(loc) => self.splice_up(loc.y),
+        });
+        self.in_redo = false;
+        if final_newline && has_eol(&self.file) != eol {
+            self.info.eol = !eol;
+        }
+        result
+    }
+
+    /// Place the cursor back to where it was at a certain point in the history
+    fn restore_cursor(&mut self, cursor: Cursor) {
+        self.cursor = cursor;
+        self.char_ptr = self.character_idx(&cursor.loc);
+        self.old_cursor = cursor.loc.x;
+        self.bring_cursor_in_viewport();
+    }
+
     /// Handle an editing event, use the method `exe` for executing events.
     /// # Errors
     /// Returns an error if there is a problem with the specified operation.
@@ -132,89 +289,192 @@ impl Document {
         self.file.line_to_char(loc.y) + loc.x
     }
 
-    /// Function to search the document to find the next occurance of a regex
-    pub fn next_match(&mut self, regex: &str, inc: usize) -> Option<Match> {
-        // Prepare
-        let mut srch = Searcher::new(regex);
-        // Check current line for matches
-        let current: String = self
-            .line(self.loc().y)?
-            .chars()
-            .skip(self.char_ptr + inc)
-            .collect();
-        if let Some(mut mtch) = srch.lfind(&current) {
-            mtch.loc.y = self.loc().y;
-            mtch.loc.x += self.char_ptr + inc;
-            return Some(mtch);
+    /// Takes a char index for ropey and converts it into a loc
+    #[must_use]
+    pub fn file_pos_to_loc(&self, idx: usize) -> Loc {
+        let y = self.file.char_to_line(idx);
+        Loc::at(idx - self.file.line_to_char(y), y)
+    }
+
+    /// Replace a specific part of the document with another string.
+    /// # Errors
+    /// Will error if the replacement failed to be executed.
+    pub fn replace(&mut self, loc: Loc, target: &str, into: &str) -> Result<()> {
+        self.exe(Event::Delete(loc, target.to_string()))?;
+        self.exe(Event::Insert(loc, into.to_string()))?;
+        Ok(())
+    }
+
+    /// Get the whole text of the document to search through
+    /// (this is kept until the document changes, so searching again doesn't have to read it out again)
+    #[must_use]
+    pub fn search_text(&self) -> SearchText {
+        self.text_cache.get(self.revision, &self.file)
+    }
+
+    /// Find the next match of a searcher across the whole document (matches can span lines),
+    /// starting `inc` characters after the cursor
+    #[must_use]
+    pub fn find_next(&self, searcher: &Searcher, inc: usize) -> Option<Match> {
+        if self.is_paged() {
+            return self.find_next_line_by_line(searcher, inc);
+        }
+        let text = self.search_text();
+        let mut from = self.loc_to_file_pos(&self.char_loc()) + inc;
+        loop {
+            let mtch = searcher.find_in(&text, from.min(self.file.len_chars()))?;
+            // Matches on lines hidden by folds are skipped
+            let Some(fold) = self.fold_containing(mtch.loc.y) else {
+                return Some(mtch);
+            };
+            from = self.file.try_line_to_char(fold.end).ok()?;
+        }
+    }
+
+    /// Find the previous match of a searcher across the whole document, before the cursor
+    #[must_use]
+    pub fn find_prev(&self, searcher: &Searcher) -> Option<Match> {
+        if self.is_paged() {
+            return self.find_prev_line_by_line(searcher);
         }
-        // Check subsequent lines for matches
-        let mut line_no = self.loc().y + 1;
-        self.load_to(line_no + 1);
+        let text = self.search_text();
+        let mut before = self.loc_to_file_pos(&self.char_loc());
+        loop {
+            let mtch = searcher.rfind_in(&text, before)?;
+            let Some(fold) = self.fold_containing(mtch.loc.y) else {
+                return Some(mtch);
+            };
+            before = self.file.line_to_char(fold.start);
+        }
+    }
+
+    /// Paged documents aren't held in a rope, so they are searched a line at a time
+    fn find_next_line_by_line(&self, searcher: &Searcher, inc: usize) -> Option<Match> {
+        let Loc { x, y } = self.char_loc();
+        let mut line_no = y;
         while let Some(line) = self.line(line_no) {
-            if let Some(mut mtch) = srch.lfind(&line) {
+            let found = searcher
+                .finds_in_line(&line)
+                .into_iter()
+                .find(|m| line_no > y || m.loc.x >= x + inc);
+            if let Some(mut mtch) = found {
                 mtch.loc.y = line_no;
                 return Some(mtch);
             }
             line_no += 1;
-            self.load_to(line_no + 1);
         }
         None
     }
 
-    /// Function to search the document to find the previous occurance of a regex
-    pub fn prev_match(&mut self, regex: &str) -> Option<Match> {
-        // Prepare
-        let mut srch = Searcher::new(regex);
-        // Check current line for matches
-        let current: String = self
-            .line(self.loc().y)?
-            .chars()
-            .take(self.char_ptr)
-            .collect();
-        if let Some(mut mtch) = srch.rfind(&current) {
-            mtch.loc.y = self.loc().y;
-            return Some(mtch);
-        }
-        // Check antecedent lines for matches
-        self.load_to(self.loc().y + 1);
-        let mut line_no = self.loc().y.saturating_sub(1);
-        while let Some(line) = self.line(line_no) {
-            if let Some(mut mtch) = srch.rfind(&line) {
+    /// Paged documents aren't held in a rope, so they are searched a line at a time
+    fn find_prev_line_by_line(&self, searcher: &Searcher) -> Option<Match> {
+        let Loc { x, y } = self.char_loc();
+        for line_no in (0..=y).rev() {
+            let line = self.line(line_no)?;
+            let found = searcher
+                .finds_in_line(&line)
+                .into_iter()
+                .rfind(|m| line_no < y || m.loc.x < x);
+            if let Some(mut mtch) = found {
                 mtch.loc.y = line_no;
                 return Some(mtch);
             }
-            if line_no == 0 {
-                break;
-            }
-            line_no = line_no.saturating_sub(1);
         }
         None
     }
 
-    /// Replace a specific part of the document with another string.
+    /// Find every match of a searcher within a range of lines, a line at a time
+    /// (this works in paged documents, where the whole document can't be searched at once)
+    #[must_use]
+    pub fn find_in_lines(&self, searcher: &Searcher, lines: Range<usize>) -> Vec<Match> {
+        let mut result = vec![];
+        for y in lines {
+            let Some(line) = self.line(y) else {
+                break;
+            };
+            result.extend(searcher.finds_in_line(&line).into_iter().map(|mut mtch| {
+                mtch.loc.y = y;
+                mtch
+            }));
+        }
+        result
+    }
+
+    /// Find every match of a searcher across the whole document
+    #[must_use]
+    pub fn find_all(&self, searcher: &Searcher) -> Vec<Match> {
+        searcher.finds_in(&self.search_text())
+    }
+
+    /// Find the first `limit` matches of a searcher across the whole document
+    #[must_use]
+    pub fn find_upto(&self, searcher: &Searcher, limit: usize) -> Vec<Match> {
+        searcher.finds_in_upto(&self.search_text(), limit)
+    }
+
+    /// Replace a match found with a searcher, filling in any capture groups in the replacement.
+    /// The replacement is committed as its own undoable transaction.
     /// # Errors
-    /// Will error if the replacement failed to be executed.
-    pub fn replace(&mut self, loc: Loc, target: &str, into: &str) -> Result<()> {
-        self.exe(Event::Delete(loc, target.to_string()))?;
-        self.exe(Event::Insert(loc, into.to_string()))?;
+    /// Will error if the document is read only, or the match is no longer in the document.
+    pub fn replace_match(&mut self, searcher: &Searcher, mtch: &Match, into: &str) -> Result<()> {
+        if self.info.read_only {
+            return Err(Error::ReadOnlyFile);
+        }
+        let into = searcher.expand_in(&self.search_text(), mtch, into);
+        self.commit();
+        self.delete_text(&mtch.loc, &mtch.text)?;
+        self.insert(&mtch.loc, &into)?;
+        self.commit();
         Ok(())
     }
 
-    /// Replace all instances of a regex with another string
-    pub fn replace_all(&mut self, target: &str, into: &str) {
-        self.move_to(&Loc::at(0, 0));
-        while let Some(mtch) = self.next_match(target, 1) {
-            drop(self.replace(mtch.loc, &mtch.text, into));
+    /// Replace every match of a searcher across the whole document,
+    /// filling in any capture groups in the replacement (`$1`, `${name}`).
+    /// All the replacements are committed together, so they can be undone in one go.
+    /// Returns the number of replacements made.
+    /// # Errors
+    /// Will error if the document is read only, or a replacement failed to be executed.
+    pub fn replace_all_matches(&mut self, searcher: &Searcher, into: &str) -> Result<usize> {
+        let replacements = searcher.replacements_in(&self.search_text(), into);
+        self.replace_matches(&replacements)
+    }
+
+    /// Replace matches that have already been found (in order, e.g. by `Searcher::replacements_in`)
+    /// with what each should be replaced with.
+    /// All the replacements are committed together, so they can be undone in one go.
+    /// Returns the number of replacements made.
+    /// # Errors
+    /// Will error if the document is read only, or a replacement failed to be executed.
+    pub fn replace_matches(&mut self, replacements: &[(Match, String)]) -> Result<usize> {
+        if self.info.read_only {
+            return Err(Error::ReadOnlyFile);
+        }
+        self.commit();
+        // Work from the bottom up, so the locations of earlier matches aren't disturbed
+        for (mtch, into) in replacements.iter().rev() {
+            self.delete_text(&mtch.loc, &mtch.text)?;
+            self.insert(&mtch.loc, into)?;
         }
+        self.commit();
+        self.cancel_selection();
+        Ok(replacements.len())
     }
 
     /// Brings the cursor into the viewport so it can be seen
     pub fn bring_cursor_in_viewport(&mut self) {
-        if self.offset.y > self.cursor.loc.y {
-            self.offset.y = self.cursor.loc.y;
-        }
-        if self.offset.y + self.size.h <= self.cursor.loc.y {
-            self.offset.y = self.cursor.loc.y.saturating_sub(self.size.h) + 1;
+        if self.one_row_per_line() {
+            if self.offset.y > self.cursor.loc.y {
+                self.offset.y = self.cursor.loc.y;
+            }
+            if self.offset.y + self.size.h <= self.cursor.loc.y {
+                self.offset.y = self.cursor.loc.y.saturating_sub(self.size.h) + 1;
+            }
+        } else {
+            // Wrapped lines never need scrolling sideways
+            self.bring_row_in_viewport();
+            if self.wrap {
+                return;
+            }
         }
         if self.offset.x > self.cursor.loc.x {
             self.offset.x = self.cursor.loc.x;
@@ -222,19 +482,13 @@ impl Document {
         if self.offset.x + self.size.w <= self.cursor.loc.x {
             self.offset.x = self.cursor.loc.x.saturating_sub(self.size.w) + 1;
         }
-        self.load_to(self.offset.y + self.size.h);
     }
 
     /// Determines if specified coordinates are out of range of the document.
     /// # Errors
     /// Returns an error when the given coordinates are out of range.
-    /// # Panics
-    /// When you try using this function on a location that has not yet been loaded into buffer
-    /// If you see this error, you should double check that you have used `Document::load_to`
-    /// enough
     pub fn out_of_range(&self, x: usize, y: usize) -> Result<()> {
-        let msg = "Did you forget to use load_to?";
-        if y >= self.len_lines() || x > self.line(y).expect(msg).chars().count() {
+        if y >= self.len_lines() || x > self.line(y).map_or(0, |l| l.chars().count()) {
             return Err(Error::OutOfRange);
         }
         Ok(())
@@ -255,12 +509,15 @@ impl Document {
     /// Calculate the character index from the display index on a certain line
     #[must_use]
     pub fn character_idx(&self, loc: &Loc) -> usize {
+        let Some(line) = self.line_info(loc.y) else {
+            return loc.x;
+        };
         let mut idx = loc.x;
         // Account for double width characters
-        idx = idx.saturating_sub(self.dbl_map.count(loc, true).unwrap_or(0));
+        idx = idx.saturating_sub(count(&line.dbl, loc.x, true));
         // Account for tab characters
-        let tabs_behind = self.tab_map.count(loc, true).unwrap_or(0);
-        idx = if let Some(inner_idx) = self.tab_map.inside(self.tab_width, loc.x, loc.y) {
+        let tabs_behind = count(&line.tab, loc.x, true);
+        idx = if let Some(inner_idx) = inside(&line.tab, self.tab_width, loc.x) {
             // Display index is within a tab, account for it properly
             let existing_tabs = tabs_behind.saturating_sub(1) * self.tab_width.saturating_sub(1);
             idx.saturating_sub(existing_tabs + inner_idx)
@@ -273,21 +530,25 @@ impl Document {
 
     /// Calculate the display index from the character index on a certain line
     fn display_idx(&self, loc: &Loc) -> usize {
+        let Some(line) = self.line_info(loc.y) else {
+            return loc.x;
+        };
         let mut idx = loc.x;
         // Account for double width characters
-        idx += self.dbl_map.count(loc, false).unwrap_or(0);
+        idx += count(&line.dbl, loc.x, false);
         // Account for tab characters
-        idx += self.tab_map.count(loc, false).unwrap_or(0) * self.tab_width.saturating_sub(1);
+        idx += count(&line.tab, loc.x, false) * self.tab_width.saturating_sub(1);
         idx
     }
 
     /// A utility function to update the character pointer when moving up or down
     fn update_char_ptr(&mut self) {
         let mut idx = self.loc().x;
-        let dbl_count = self.dbl_map.count(&self.loc(), true).unwrap_or(0);
-        idx = idx.saturating_sub(dbl_count);
-        let tab_count = self.tab_map.count(&self.loc(), true).unwrap_or(0);
-        idx = idx.saturating_sub(tab_count * self.tab_width.saturating_sub(1));
+        if let Some(line) = self.line_info(self.loc().y) {
+            idx = idx.saturating_sub(count(&line.dbl, idx, true));
+            let tab_count = count(&line.tab, self.loc().x, true);
+            idx = idx.saturating_sub(tab_count * self.tab_width.saturating_sub(1));
+        }
         self.char_ptr = idx;
     }
 
@@ -306,24 +567,19 @@ impl Document {
     fn fix_split(&mut self) {
         let mut magnitude = 0;
         let Loc { x, y } = self.loc();
-        if let Some(map) = self.dbl_map.get(y) {
-            let last_dbl = self
-                .dbl_map
-                .count(&self.loc(), true)
-                .unwrap()
-                .saturating_sub(1);
-            let start = map[last_dbl].0;
+        let Some(line) = self.line_info(y) else {
+            return;
+        };
+        if !line.dbl.is_empty() {
+            let last_dbl = count(&line.dbl, x, true).saturating_sub(1);
+            let start = line.dbl[last_dbl].0;
             if x == start + 1 {
                 magnitude += 1;
             }
         }
-        if let Some(map) = self.tab_map.get(y) {
-            let last_tab = self
-                .tab_map
-                .count(&self.loc(), true)
-                .unwrap()
-                .saturating_sub(1);
-            let start = map[last_tab].0;
+        if !line.tab.is_empty() {
+            let last_tab = count(&line.tab, x, true).saturating_sub(1);
+            let start = line.tab[last_tab].0;
             let range = start..start + self.tab_width;
             if range.contains(&x) {
                 magnitude += x.saturating_sub(start);
@@ -336,22 +592,16 @@ impl Document {
     /// x is the display index.
     #[must_use]
     pub fn is_dbl_width(&self, y: usize, x: usize) -> bool {
-        if let Some(line) = self.dbl_map.get(y) {
-            line.iter().any(|i| x == i.1)
-        } else {
-            false
-        }
+        self.line_info(y)
+            .is_some_and(|line| line.dbl.iter().any(|i| x == i.1))
     }
 
     /// Determine if a character at a certain location is a tab character.
     /// x is the display index.
     #[must_use]
     pub fn is_tab(&self, y: usize, x: usize) -> bool {
-        if let Some(line) = self.tab_map.get(y) {
-            line.iter().any(|i| x == i.1)
-        } else {
-            false
-        }
+        self.line_info(y)
+            .is_some_and(|line| line.tab.iter().any(|i| x == i.1))
     }
 
     /// Determine the width of a character at a certain location
@@ -368,14 +618,65 @@ impl Document {
 
     /// Commit a change to the undo management system
     pub fn commit(&mut self) {
-        let s = self.take_snapshot();
-        self.event_mgmt.commit(s);
+        self.event_mgmt.commit(self.cursor);
     }
 
-    /// Completely reload the file
-    pub fn reload_lines(&mut self) {
-        let to = std::mem::take(&mut self.info.loaded_to);
-        self.lines.clear();
-        self.load_to(to);
+    /// Get the locations of double width characters on a line,
+    /// as (display index, character index) pairs
+    #[must_use]
+    pub fn dbl_map(&self, y: usize) -> DblUsize {
+        self.line_info(y).map(|l| l.dbl.clone()).unwrap_or_default()
+    }
+
+    /// Get the locations of tab characters on a line,
+    /// as (display index, character index) pairs
+    #[must_use]
+    pub fn tab_map(&self, y: usize) -> DblUsize {
+        self.line_info(y).map(|l| l.tab.clone()).unwrap_or_default()
+    }
+
+    /// Read a line (and where its double width and tab characters are) through the cache
+    fn line_info(&self, y: usize) -> Option<Arc<CachedLine>> {
+        self.line_cache.get(self.revision, self.tab_width, y, || {
+            if let Some(pager) = &self.pager {
+                return pager.line(y);
+            }
+            if y >= self.file.len_lines() {
+                return None;
+            }
+            let line = self.file.line(y).to_string();
+            Some(line.trim_end_matches(['\n', '\r']).to_string())
+        })
+    }
+
+    /// Make a note that the rope has changed, so stale lines aren't read from the cache
+    fn changed(&mut self) {
+        self.revision = self.revision.wrapping_add(1);
+    }
+
+    /// Make a note that an edit replaced `old` lines (starting at `y`) with `new` lines,
+    /// keeping the cache and folds up to date with it
+    fn edited(&mut self, y: usize, old: usize, new: usize) {
+        let from = self.revision;
+        self.changed();
+        self.line_cache.edit(from, self.revision, y, old, new);
+        self.shift_folds(y, old, new);
+    }
+
+    /// Keep folds in the right place after an edit replaced `old` lines (starting at `y`)
+    /// with `new` lines. Folds that the edit touched are unfolded.
+    fn shift_folds(&mut self, y: usize, old: usize, new: usize) {
+        if self.folds.is_empty() {
+            return;
+        }
+        let touched = y..y + old.max(1);
+        self.folds
+            .retain(|f| f.end <= touched.start || touched.end <= f.start);
+        for fold in &mut self.folds {
+            if fold.start >= touched.end {
+                fold.start = fold.start + new - old;
+                fold.end = fold.end + new - old;
+            }
+        }
     }
 }
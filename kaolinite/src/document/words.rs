@@ -19,8 +19,9 @@ impl Document {
         let mut searcher = Searcher::new(re);
         let starts: Vec<Match> = searcher.lfinds_raw(line);
         let mut ends: Vec<Match> = starts.clone();
-        ends.iter_mut()
-            .for_each(|m| m.loc.x += m.text.chars().count());
+        for m in &mut ends {
+            m.loc.x += m.text.chars().count();
+        }
         let starts: Vec<usize> = starts.iter().map(|m| m.loc.x).collect();
         let ends: Vec<usize> = ends.iter().map(|m| m.loc.x).collect();
         starts.into_iter().zip(ends).collect()
@@ -278,7 +279,7 @@ impl Document {
         let y = loc.y;
         let x = self.character_idx(loc);
         let re = format!("(\t| {{{}}}|^|\\W| )", self.tab_width);
-        let start = if let Some(mut mtch) = self.prev_match(&re) {
+        let start = if let Some(mut mtch) = self.match_before_cursor(&re) {
             let len = mtch.text.chars().count();
             let same = mtch.loc.x + len == x;
             if !same {
@@ -293,7 +294,7 @@ impl Document {
             0
         };
         let re = format!("(\t| {{{}}}|\\W|$|^ +| )", self.tab_width);
-        let end = if let Some(mtch) = self.next_match(&re, 0) {
+        let end = if let Some(mtch) = self.match_after_cursor(&re) {
             mtch.loc.x
         } else {
             self.line(y).unwrap_or_default().chars().count()
@@ -302,4 +303,22 @@ impl Document {
         self.select_to(&Loc { x: end, y });
         self.old_cursor = self.loc().x;
     }
+
+    /// Find the last match of a regex on the cursor's line, before the cursor
+    fn match_before_cursor(&self, regex: &str) -> Option<Match> {
+        let y = self.loc().y;
+        let before: String = self.line(y)?.chars().take(self.char_ptr).collect();
+        let mut mtch = Searcher::new(regex).rfind(&before)?;
+        mtch.loc.y = y;
+        Some(mtch)
+    }
+
+    /// Find the first match of a regex on the cursor's line, from the cursor onwards
+    fn match_after_cursor(&self, regex: &str) -> Option<Match> {
+        let y = self.loc().y;
+        let after: String = self.line(y)?.chars().skip(self.char_ptr).collect();
+        let mut mtch = Searcher::new(regex).lfind(&after)?;
+        mtch.loc = Loc::at(mtch.loc.x + self.char_ptr, y);
+        Some(mtch)
+    }
 }
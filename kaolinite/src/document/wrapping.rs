@@ -0,0 +1,267 @@
+/// wrapping.rs - lays lines out on the rows of the viewport (for soft wrapping and folding)
+use crate::event::Status;
+use crate::utils::width_char;
+use crate::{Document, Loc};
+
+/// A row of the viewport (when lines are wrapped or folded, lines and rows don't line up)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualRow {
+    /// The line this row is a part of
+    pub y: usize,
+    /// The display index on the line that this row starts at
+    pub start: usize,
+    /// The display index on the line that this row stops at (exclusive)
+    pub end: usize,
+    /// Whether this is the first row of its line (the one the line number is shown on)
+    pub first: bool,
+}
+
+impl Document {
+    /// Turn soft wrapping on or off
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+        if wrap {
+            self.offset.x = 0;
+        }
+        self.bring_cursor_in_viewport();
+    }
+
+    /// Whether every line takes up exactly one row of the viewport
+    /// (false when lines are wrapped or folded away)
+    #[must_use]
+    pub fn one_row_per_line(&self) -> bool {
+        !self.wrap && self.folds.is_empty()
+    }
+
+    /// Find the display indices that each row of a line starts at
+    /// (lines that aren't wrapped only have the one row)
+    #[must_use]
+    pub fn row_starts(&self, y: usize) -> Vec<usize> {
+        if !self.wrap {
+            return vec![0];
+        }
+        let line = self.line(y).unwrap_or_default();
+        row_starts(&line, self.size.w, self.tab_width)
+    }
+
+    /// Find which row of a wrapped line a display index is on,
+    /// along with the display index that row starts at
+    #[must_use]
+    pub fn row_of(&self, loc: &Loc) -> (usize, usize) {
+        let starts = self.row_starts(loc.y);
+        let row = starts.iter().rposition(|s| *s <= loc.x).unwrap_or(0);
+        (row, starts[row])
+    }
+
+    /// Count the rows taken up by the lines in a range (lines hidden by folds take up none)
+    #[must_use]
+    pub fn rows_between(&self, from: usize, to: usize) -> usize {
+        let mut rows = 0;
+        let mut y = self.fold_containing(from).map_or(from, |f| f.end);
+        while y < to {
+            rows += self.row_starts(y).len();
+            y = self.line_below(y);
+        }
+        rows
+    }
+
+    /// Work out which part of which line each row of the viewport shows
+    #[must_use]
+    pub fn rows_in_view(&self) -> Vec<VisualRow> {
+        let mut rows = vec![];
+        let mut y = self
+            .fold_containing(self.offset.y)
+            .map_or(self.offset.y, |f| f.end);
+        while rows.len() < self.size.h && y < self.len_lines() {
+            if self.wrap {
+                let line = self.line(y).unwrap_or_default();
+                let starts = row_starts(&line, self.size.w, self.tab_width);
+                let line_width = line.chars().map(|c| width_char(&c, self.tab_width)).sum();
+                for (n, start) in starts.iter().enumerate() {
+                    let end = starts.get(n + 1).copied().unwrap_or(line_width);
+                    rows.push(VisualRow {
+                        y,
+                        start: *start,
+                        end,
+                        first: n == 0,
+                    });
+                }
+            } else {
+                rows.push(VisualRow {
+                    y,
+                    start: self.offset.x,
+                    end: self.offset.x + self.size.w,
+                    first: true,
+                });
+            }
+            y = self.line_below(y);
+        }
+        rows.truncate(self.size.h);
+        rows
+    }
+
+    /// Find the location (with a display index) shown at a position in the viewport
+    #[must_use]
+    pub fn screen_to_loc(&self, at: &Loc) -> Loc {
+        if self.one_row_per_line() {
+            return Loc::at(at.x + self.offset.x, at.y + self.offset.y);
+        }
+        let rows = self.rows_in_view();
+        match rows.get(at.y) {
+            Some(row) => {
+                // The end of a row that doesn't finish its line is the start of the next row
+                let continues = rows.get(at.y + 1).is_some_and(|next| next.y == row.y);
+                let end = row.end.saturating_sub(usize::from(continues));
+                Loc::at((row.start + at.x).min(end), row.y)
+            }
+            // Below the end of the document
+            None => Loc::at(at.x, self.len_lines()),
+        }
+    }
+
+    /// Work out where the cursor is within the viewport when lines are wrapped or folded
+    #[must_use]
+    pub fn cursor_row_in_screen(&self) -> Option<Loc> {
+        let Loc { x, y } = self.loc();
+        // Every line takes up at least one row (unless it is folded away)
+        let too_far = self.folds.is_empty() && y >= self.offset.y + self.size.h;
+        if y < self.offset.y || too_far || self.is_hidden(y) {
+            return None;
+        }
+        let above = self.rows_between(self.offset.y, y);
+        let (row, start) = self.row_of(&self.loc());
+        let result = if self.wrap {
+            Loc::at(x.saturating_sub(start), above + row)
+        } else if x < self.offset.x || x > self.offset.x + self.size.w {
+            return None;
+        } else {
+            Loc::at(x - self.offset.x, above)
+        };
+        (result.y < self.size.h).then_some(result)
+    }
+
+    /// Scroll so that the row the cursor is on can be seen
+    pub fn bring_row_in_viewport(&mut self) {
+        if self.wrap {
+            self.offset.x = 0;
+        }
+        let y = self.loc().y;
+        if self.offset.y > y {
+            self.offset.y = y;
+        }
+        // Each line that can be seen takes up at least one row,
+        // so lines too far above can be skipped straight away
+        let mut top = y;
+        for _ in 1..self.size.h {
+            match self.line_above(top) {
+                Some(above) => top = above,
+                None => break,
+            }
+        }
+        self.offset.y = self.offset.y.max(top).min(y);
+        // Count up the rows between the top of the viewport and the cursor
+        let mut rows = self.row_of(&self.loc()).0 + 1 + self.rows_between(self.offset.y, y);
+        while rows > self.size.h && self.offset.y < y {
+            let next = self.line_below(self.offset.y);
+            rows -= self.rows_between(self.offset.y, next);
+            self.offset.y = next;
+        }
+    }
+
+    /// Move the cursor up a row of a wrapped line (or on to the line above, skipping folds)
+    pub fn select_row_up(&mut self) -> Status {
+        let loc = self.loc();
+        let (row, start) = self.row_of(&loc);
+        let column = self.old_cursor.saturating_sub(start);
+        if row > 0 {
+            let starts = self.row_starts(loc.y);
+            self.cursor.loc.x = (starts[row - 1] + column).min(start.saturating_sub(1));
+            self.old_cursor = starts[row - 1] + column;
+        } else if let Some(above) = self.line_above(loc.y) {
+            let last = *self.row_starts(above).last().unwrap_or(&0);
+            self.cursor.loc.y = above;
+            self.cursor.loc.x = last + column;
+            self.old_cursor = last + column;
+        } else {
+            return Status::StartOfFile;
+        }
+        self.fix_row_move();
+        Status::None
+    }
+
+    /// Move the cursor down a row of a wrapped line (or on to the line below, skipping folds)
+    pub fn select_row_down(&mut self) -> Status {
+        let loc = self.loc();
+        let (row, start) = self.row_of(&loc);
+        let column = self.old_cursor.saturating_sub(start);
+        let starts = self.row_starts(loc.y);
+        let below = self.line_below(loc.y);
+        if row + 1 < starts.len() {
+            self.cursor.loc.x = starts[row + 1] + column;
+            self.old_cursor = starts[row + 1] + column;
+            if let Some(next) = starts.get(row + 2) {
+                self.cursor.loc.x = self.cursor.loc.x.min(next.saturating_sub(1));
+            }
+        } else if self.len_lines() < below {
+            return Status::EndOfFile;
+        } else {
+            self.cursor.loc.y = below;
+            self.cursor.loc.x = column;
+            self.old_cursor = column;
+            if let Some(next) = self.row_starts(below).get(1) {
+                self.cursor.loc.x = self.cursor.loc.x.min(next.saturating_sub(1));
+            }
+        }
+        self.fix_row_move();
+        Status::None
+    }
+
+    /// Tidy up the cursor after it moves between rows
+    fn fix_row_move(&mut self) {
+        let old_cursor = self.old_cursor;
+        // Snap to end of line
+        self.fix_dangling_cursor();
+        // Move back if in the middle of a longer character
+        self.fix_split();
+        // Update the character pointer
+        self.update_char_ptr();
+        self.bring_cursor_in_viewport();
+        self.old_cursor = old_cursor;
+    }
+}
+
+/// Find the display indices that each row of a line starts at, when wrapped to a certain width.
+/// Lines are broken after whitespace where possible, and in the middle of words otherwise.
+/// A line that exactly fills its last row is given an empty row after it,
+/// so that there is somewhere to show the cursor at the end of the line.
+#[must_use]
+pub fn row_starts(line: &str, wrap_width: usize, tab_width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    if wrap_width == 0 {
+        return starts;
+    }
+    let mut row_start = 0;
+    let mut x = 0;
+    // Where the row could be broken (just after some whitespace)
+    let mut last_break = None;
+    for c in line.chars() {
+        let c_width = width_char(&c, tab_width);
+        while x + c_width - row_start > wrap_width && x > row_start {
+            let at = match last_break {
+                Some(at) if at > row_start => at,
+                _ => x,
+            };
+            starts.push(at);
+            row_start = at;
+            last_break = None;
+        }
+        x += c_width;
+        if c.is_whitespace() {
+            last_break = Some(x);
+        }
+    }
+    if x > 0 && x - row_start >= wrap_width {
+        starts.push(x);
+    }
+    starts
+}
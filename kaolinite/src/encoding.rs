@@ -0,0 +1,159 @@
+/// encoding.rs - detects, decodes and encodes the character sets that files are stored in
+use std::fmt;
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// The characters that Windows-1252 places in the 0x80 to 0x9F range
+/// (bytes that are left undefined map to the matching control characters)
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// The character encodings a document can be read from and written back to
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Encoding {
+    /// UTF-8 without a byte order mark
+    #[default]
+    Utf8,
+    /// UTF-8 with a byte order mark
+    Utf8Bom,
+    /// Little endian UTF-16 (with a byte order mark)
+    Utf16Le,
+    /// Big endian UTF-16 (with a byte order mark)
+    Utf16Be,
+    /// ISO-8859-1, where every byte is the unicode code point of the same value
+    Latin1,
+    /// Windows-1252, a superset of Latin-1 with printable characters from 0x80 to 0x9F
+    Windows1252,
+}
+
+impl Encoding {
+    /// Find an encoding from its name (e.g. "utf-8", "utf-16le" or "latin1")
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase().replace(['-', '_', ' '], "");
+        match name.as_str() {
+            "utf8" => Some(Self::Utf8),
+            "utf8bom" => Some(Self::Utf8Bom),
+            "utf16" | "utf16le" => Some(Self::Utf16Le),
+            "utf16be" => Some(Self::Utf16Be),
+            "latin1" | "iso88591" => Some(Self::Latin1),
+            "windows1252" | "cp1252" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Work out the encoding of some bytes.
+    /// Byte order marks are checked first, then UTF-8, falling back to a legacy encoding.
+    #[must_use]
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(UTF8_BOM) {
+            Self::Utf8Bom
+        } else if bytes.starts_with(UTF16LE_BOM) {
+            Self::Utf16Le
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Self::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Self::Utf8
+        } else if bytes.iter().any(|b| (0x80..=0x9F).contains(b)) {
+            Self::Windows1252
+        } else {
+            Self::Latin1
+        }
+    }
+
+    /// Decode bytes in this encoding into a string.
+    /// Also returns whether the bytes could be decoded without losing any information.
+    #[must_use]
+    pub fn decode(self, bytes: &[u8]) -> (String, bool) {
+        match self {
+            Self::Utf8 | Self::Utf8Bom => {
+                let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+                match std::str::from_utf8(bytes) {
+                    Ok(st) => (st.to_string(), true),
+                    Err(_) => (String::from_utf8_lossy(bytes).to_string(), false),
+                }
+            }
+            Self::Utf16Le | Self::Utf16Be => {
+                let (bom, from_bytes): (_, fn([u8; 2]) -> u16) = if self == Self::Utf16Le {
+                    (UTF16LE_BOM, u16::from_le_bytes)
+                } else {
+                    (UTF16BE_BOM, u16::from_be_bytes)
+                };
+                let bytes = bytes.strip_prefix(bom).unwrap_or(bytes);
+                let units = bytes.chunks_exact(2).map(|c| from_bytes([c[0], c[1]]));
+                let mut lossless = bytes.len() % 2 == 0;
+                let st = char::decode_utf16(units)
+                    .map(|c| {
+                        c.unwrap_or_else(|_| {
+                            lossless = false;
+                            char::REPLACEMENT_CHARACTER
+                        })
+                    })
+                    .collect();
+                (st, lossless)
+            }
+            Self::Latin1 => (bytes.iter().map(|b| char::from(*b)).collect(), true),
+            Self::Windows1252 => {
+                let st = bytes
+                    .iter()
+                    .map(|b| match b {
+                        0x80..=0x9F => WINDOWS_1252[usize::from(b - 0x80)],
+                        _ => char::from(*b),
+                    })
+                    .collect();
+                (st, true)
+            }
+        }
+    }
+
+    /// Encode a string into bytes in this encoding.
+    /// Returns None if the string contains characters this encoding can't represent.
+    #[must_use]
+    pub fn encode(self, st: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Utf8 => Some(st.as_bytes().to_vec()),
+            Self::Utf8Bom => Some([UTF8_BOM, st.as_bytes()].concat()),
+            Self::Utf16Le => {
+                let mut bytes = UTF16LE_BOM.to_vec();
+                bytes.extend(st.encode_utf16().flat_map(u16::to_le_bytes));
+                Some(bytes)
+            }
+            Self::Utf16Be => {
+                let mut bytes = UTF16BE_BOM.to_vec();
+                bytes.extend(st.encode_utf16().flat_map(u16::to_be_bytes));
+                Some(bytes)
+            }
+            Self::Latin1 => st.chars().map(|c| u8::try_from(c).ok()).collect(),
+            Self::Windows1252 => st
+                .chars()
+                .map(|c| match u8::try_from(c) {
+                    Ok(b) if !(0x80..=0x9F).contains(&b) => Some(b),
+                    _ => WINDOWS_1252
+                        .iter()
+                        .position(|w| *w == c)
+                        .and_then(|i| u8::try_from(i + 0x80).ok()),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf8Bom => "UTF-8 BOM",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Latin1 => "ISO-8859-1",
+            Self::Windows1252 => "Windows-1252",
+        };
+        write!(f, "{name}")
+    }
+}
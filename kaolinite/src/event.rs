@@ -1,13 +1,54 @@
 /// event.rs - manages editing events and provides tools for error handling
-use crate::{document::Cursor, utils::Loc, Document};
+use crate::{
+    document::{Cursor, LineEnding},
+    utils::Loc,
+};
 use error_set::error_set;
-use ropey::Rope;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// A snapshot stores the state of a document at a certain time
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Snapshot {
-    pub content: Rope,
-    pub cursor: Cursor,
+/// A transaction is a group of events that are undone and redone together
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// The events in this transaction, in the order they were executed
+    pub events: Vec<Event>,
+    /// Where the cursor was before the transaction took place
+    pub before: Cursor,
+    /// Where the cursor was once the transaction was committed
+    pub after: Cursor,
+    /// The line ending style before and after the transaction, if the transaction changed it
+    pub line_ending: Option<(LineEnding, LineEnding)>,
+}
+
+/// A revision is a state of the document, forming a node in the undo tree
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Revision {
+    /// The revision this one was made from (the root revision is its own parent)
+    pub parent: usize,
+    /// The child revision that redo will move to (the most recently made or visited)
+    pub last_child: Option<usize>,
+    /// The changes that take the parent revision to this revision
+    pub transaction: Transaction,
+    /// When this revision was made, in seconds since the unix epoch
+    pub time: u64,
+}
+
+impl Revision {
+    /// Create the root revision of an undo tree (the state of the document when opened)
+    #[must_use]
+    pub fn root() -> Self {
+        Self {
+            time: now(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Get the current time in seconds since the unix epoch
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
 }
 
 /// Represents an editing event.
@@ -83,120 +124,227 @@ error_set! {
         Io(std::io::Error),
         #[display("Rope error: {0}")]
         Rope(ropey::Error),
+        #[display("Document can't be saved as {} without losing characters", encoding)]
+        Unencodable {
+            encoding: String
+        },
+        #[display("Failed to write to a temporary file: {}", reason)]
+        TempFile {
+            reason: String
+        },
+        #[display("Failed to flush the file to the disk: {}", reason)]
+        Sync {
+            reason: String
+        },
+        #[display("Failed to replace the file with the saved version: {}", reason)]
+        Rename {
+            reason: String
+        },
+        #[display("Failed to back up the file: {}", reason)]
+        Backup {
+            reason: String
+        },
         NoFileName,
         OutOfRange,
         ReadOnlyFile
     };
 }
 
-/// For managing events for purposes of undo and redo
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+/// For managing events for purposes of undo and redo.
+/// History is kept as a tree of revisions, so making changes after undoing
+/// starts a new branch rather than throwing away the changes that were undone.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EventMgmt {
-    /// Contains all the snapshots in the current timeline
-    pub history: Vec<Snapshot>,
-    /// Stores where the document currently is
-    pub ptr: Option<usize>,
-    /// Store where the file on the disk is currently at
+    /// Contains every revision of the document, in the order they were made (root first)
+    pub history: Vec<Revision>,
+    /// Events that have been executed since the last commit
+    pub patch: Vec<Event>,
+    /// A change of line ending style since the last commit (the style before and after)
+    pub line_ending: Option<(LineEnding, LineEnding)>,
+    /// The revision the document is currently at
+    pub ptr: usize,
+    /// Store the revision the file on the disk is at (None if it can no longer be reached)
     pub on_disk: Option<usize>,
+    /// Where the cursor was at the time of the last commit
+    pub cursor: Cursor,
     /// Store the last event to occur (so that we can see if there is a change)
     pub last_event: Option<Event>,
     /// Flag to force the file not to be with disk (i.e. file only exists in memory)
     pub force_not_with_disk: bool,
 }
 
-impl Document {
-    #[must_use]
-    pub fn take_snapshot(&self) -> Snapshot {
-        Snapshot {
-            content: self.file.clone(),
-            cursor: self.cursor,
+impl Default for EventMgmt {
+    fn default() -> Self {
+        Self {
+            history: vec![Revision::root()],
+            patch: vec![],
+            line_ending: None,
+            ptr: 0,
+            on_disk: Some(0),
+            cursor: Cursor::default(),
+            last_event: None,
+            force_not_with_disk: false,
         }
     }
-
-    pub fn apply_snapshot(&mut self, snapshot: Snapshot) {
-        self.file = snapshot.content;
-        self.cursor = snapshot.cursor;
-        self.char_ptr = self.character_idx(&snapshot.cursor.loc);
-        self.old_cursor = snapshot.cursor.loc.x;
-        self.reload_lines();
-        self.bring_cursor_in_viewport();
-    }
 }
 
 impl EventMgmt {
-    /// In the event of some changes, redo should be cleared
-    pub fn clear_redo(&mut self) {
-        if let Some(ptr) = self.ptr {
-            self.history.drain(ptr + 1..);
+    /// Register an event that has just been executed on the document
+    pub fn register(&mut self, ev: Event) {
+        // Events that don't change anything aren't worth keeping
+        if matches!(&ev, Event::Insert(_, st) | Event::Delete(_, st) if st.is_empty()) {
+            return;
+        }
+        // Merge runs of typing and deleting on a line into a single event to save on memory
+        if let Some(last) = self.patch.last_mut() {
+            match (last, &ev) {
+                (Event::Insert(loc, st), Event::Insert(new_loc, new_st))
+                    if !st.contains(['\n', '\r'])
+                        && !new_st.contains(['\n', '\r'])
+                        && *new_loc == Loc::at(loc.x + st.chars().count(), loc.y) =>
+                {
+                    st.push_str(new_st);
+                    return;
+                }
+                (Event::Delete(loc, st), Event::Delete(new_loc, new_st))
+                    if !st.contains(['\n', '\r'])
+                        && !new_st.contains(['\n', '\r'])
+                        && new_loc.y == loc.y
+                        && new_loc.x + new_st.chars().count() == loc.x =>
+                {
+                    *st = format!("{new_st}{st}");
+                    *loc = *new_loc;
+                    return;
+                }
+                (Event::Delete(loc, st), Event::Delete(new_loc, new_st))
+                    if !st.contains(['\n', '\r'])
+                        && !new_st.contains(['\n', '\r'])
+                        && new_loc == loc =>
+                {
+                    st.push_str(new_st);
+                    return;
+                }
+                _ => (),
+            }
         }
+        self.patch.push(ev);
     }
 
-    /// To be called when a snapshot needs to be registered
-    pub fn commit(&mut self, snapshot: Snapshot) {
-        // Only commit when previous snapshot differs
-        let ptr = self.ptr.unwrap_or(0);
-        if self.history.get(ptr).map(|s| &s.content) != Some(&snapshot.content) {
-            self.clear_redo();
-            self.history.push(snapshot);
-            self.ptr = Some(self.history.len().saturating_sub(1));
+    /// To be called when the events executed so far should be grouped into a transaction
+    pub fn commit(&mut self, cursor: Cursor) {
+        // Only commit when there have been changes since the last commit
+        if !self.patch.is_empty() || self.line_ending.is_some() {
+            let idx = self.history.len();
+            self.history.push(Revision {
+                parent: self.ptr,
+                last_child: None,
+                transaction: Transaction {
+                    events: std::mem::take(&mut self.patch),
+                    before: self.cursor,
+                    after: cursor,
+                    line_ending: self.line_ending.take(),
+                },
+                time: now(),
+            });
+            self.history[self.ptr].last_child = Some(idx);
+            self.ptr = idx;
         }
+        self.cursor = cursor;
     }
 
     /// To be called when writing to disk
-    pub fn disk_write(&mut self, snapshot: &Snapshot) {
+    pub fn disk_write(&mut self, cursor: Cursor) {
         self.force_not_with_disk = false;
-        self.commit(snapshot.clone());
-        self.on_disk = self.ptr;
+        self.commit(cursor);
+        self.on_disk = Some(self.ptr);
     }
 
     /// A way to query whether we're currently up to date with the disk
     #[must_use]
-    pub fn with_disk(&self, snapshot: &Snapshot) -> bool {
-        if self.force_not_with_disk {
-            false
-        } else if let Some(disk) = self.on_disk {
-            self.history.get(disk).map(|s| &s.content) == Some(&snapshot.content)
-        } else if self.history.is_empty() {
-            true
+    pub fn with_disk(&self) -> bool {
+        !self.force_not_with_disk && self.patch.is_empty() && self.on_disk == Some(self.ptr)
+    }
+
+    /// Get the transaction to reverse in order to undo
+    pub fn undo(&mut self, cursor: Cursor) -> Option<Transaction> {
+        // Make sure any outstanding events can be undone too
+        self.commit(cursor);
+        if self.ptr == 0 {
+            None
         } else {
-            self.history.first().map(|s| &s.content) == Some(&snapshot.content)
+            let idx = self.ptr;
+            let revision = &self.history[idx];
+            let transaction = revision.transaction.clone();
+            self.ptr = revision.parent;
+            // Redo should bring us back to where we just were
+            self.history[self.ptr].last_child = Some(idx);
+            self.cursor = transaction.before;
+            Some(transaction)
         }
     }
 
-    /// Get previous snapshot to restore to
-    pub fn undo(&mut self, snapshot: Snapshot) -> Option<Snapshot> {
-        // Push cursor back by 1
-        self.commit(snapshot);
-        if let Some(ptr) = self.ptr {
-            if ptr != 0 {
-                let new_ptr = ptr.saturating_sub(1);
-                self.ptr = Some(new_ptr);
-                self.history.get(new_ptr).cloned()
-            } else {
-                None
+    /// Get the transaction to execute again in order to redo
+    pub fn redo(&mut self, cursor: Cursor) -> Option<Transaction> {
+        // If the user has edited since the undo, this will start a new branch with no children
+        self.commit(cursor);
+        let idx = self.history[self.ptr].last_child?;
+        self.ptr = idx;
+        let transaction = self.history[idx].transaction.clone();
+        self.cursor = transaction.after;
+        Some(transaction)
+    }
+
+    /// Determine if a revision is the same as, or an ancestor of, another revision
+    #[must_use]
+    pub fn is_ancestor(&self, ancestor: usize, mut of: usize) -> bool {
+        loop {
+            if of == ancestor {
+                return true;
+            } else if of == 0 {
+                return false;
             }
-        } else {
-            None
+            of = self.history[of].parent;
         }
     }
 
-    /// Get snapshot that used to be in place
-    pub fn redo(&mut self, snapshot: &Snapshot) -> Option<Snapshot> {
-        if let Some(ptr) = self.ptr {
-            // If the user has edited since the undo, wipe the redo stack
-            if self.history.get(ptr).map(|s| &s.content) != Some(&snapshot.content) {
-                self.clear_redo();
-            }
-            // Perform the redo
-            let new_ptr = if ptr + 1 < self.history.len() {
-                ptr + 1
-            } else {
-                return None;
-            };
-            self.ptr = Some(new_ptr);
-            self.history.get(new_ptr).cloned()
-        } else {
-            None
+    /// Point redo along the path from the root to a certain revision
+    pub fn route_to(&mut self, mut target: usize) {
+        while target != 0 {
+            let parent = self.history[target].parent;
+            self.history[parent].last_child = Some(target);
+            target = parent;
+        }
+    }
+
+    /// Find the revision the document was at a certain point in time
+    /// (the most recent revision made at or before the time given)
+    #[must_use]
+    pub fn revision_at(&self, time: u64) -> usize {
+        self.history
+            .iter()
+            .rposition(|r| r.time <= time)
+            .unwrap_or(0)
+    }
+
+    /// Find the branches that sit alongside the current revision (including itself)
+    #[must_use]
+    pub fn siblings(&self) -> Vec<usize> {
+        if self.ptr == 0 {
+            return vec![0];
+        }
+        let parent = self.history[self.ptr].parent;
+        (1..self.history.len())
+            .filter(|idx| self.history[*idx].parent == parent)
+            .collect()
+    }
+
+    /// Find the number of branches (leaf revisions) in the undo tree
+    #[must_use]
+    pub fn branches(&self) -> usize {
+        let mut has_children = vec![false; self.history.len()];
+        for revision in self.history.iter().skip(1) {
+            has_children[revision.parent] = true;
         }
+        has_children.iter().filter(|c| !**c).count()
     }
 }
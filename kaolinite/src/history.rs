@@ -0,0 +1,367 @@
+/// history.rs - persists undo history to the disk so that it survives a document being closed
+use crate::document::{Cursor, LineEnding};
+use crate::event::{Event, EventMgmt, Result, Revision, Transaction};
+use crate::utils::Loc;
+use ropey::Rope;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+/// Identifies a file as a kaolinite undo history file
+const MAGIC: &[u8; 8] = b"KAOUNDO\0";
+/// The version of the on-disk format, bumped whenever the layout changes
+pub const HISTORY_VERSION: u32 = 3;
+
+/// Describes where undo histories are stored and how large they are allowed to get.
+/// Each file gets its own history file in `dir`, named after a hash of its absolute path.
+/// Histories are only restored when the file's contents match what they were when saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryStore {
+    /// The directory to store history files in
+    pub dir: PathBuf,
+    /// The maximum size of a single history file in bytes
+    pub max_size: usize,
+}
+
+impl HistoryStore {
+    /// Create a new history store in a directory, with a size cap (in bytes) per file
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(dir: P, max_size: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_size,
+        }
+    }
+
+    /// Work out where the history of a file (given its absolute path) would be stored
+    #[must_use]
+    pub fn path_for(&self, file_name: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.undo", hash(file_name.bytes())))
+    }
+
+    /// Write the history of a file to the disk.
+    /// The oldest branches, followed by the oldest revisions leading up to the current one,
+    /// are dropped to keep the file within the size cap.
+    /// # Errors
+    /// Returns an error if the history file or its directory couldn't be written to
+    pub fn save(&self, file_name: &str, content: u64, mgmt: &EventMgmt) -> Result<()> {
+        let path = self.path_for(file_name);
+        if let Some(data) = self.encode(file_name, content, mgmt) {
+            std::fs::create_dir_all(&self.dir)?;
+            std::fs::write(path, data)?;
+        } else if path.exists() {
+            // Nothing fits within the cap, so don't leave a stale history lying around
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Read the history of a file back from the disk.
+    /// Returns None if there is no history, the format is not understood,
+    /// or if the file has changed since the history was saved.
+    #[must_use]
+    pub fn load(&self, file_name: &str, content: u64) -> Option<EventMgmt> {
+        let data = std::fs::read(self.path_for(file_name)).ok()?;
+        decode(&data, file_name, content)
+    }
+
+    /// Serialise history into the on-disk format, respecting the size cap
+    fn encode(&self, file_name: &str, content: u64, mgmt: &EventMgmt) -> Option<Vec<u8>> {
+        let mut data = vec![];
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&HISTORY_VERSION.to_le_bytes());
+        write_u64(&mut data, content);
+        write_str(&mut data, file_name);
+        let (history, ptr) = prune(mgmt, self.max_size.checked_sub(data.len() + 7 * 8)?)?;
+        write_u64(&mut data, ptr as u64);
+        write_cursor(&mut data, &mgmt.cursor);
+        write_u64(&mut data, history.len() as u64);
+        for revision in history {
+            data.extend_from_slice(&revision);
+        }
+        Some(data)
+    }
+}
+
+/// Encode the revisions of an undo tree, dropping revisions until they fit within a budget.
+/// Returns the encoded revisions and the new position of the current revision,
+/// or None if there is nothing that is worth keeping.
+fn prune(mgmt: &EventMgmt, budget: usize) -> Option<(Vec<Vec<u8>>, usize)> {
+    let len = mgmt.history.len();
+    let ptr = mgmt.ptr.min(len.saturating_sub(1));
+    let sizes: Vec<usize> = mgmt
+        .history
+        .iter()
+        .map(|r| encode_revision(r, None, None).len())
+        .collect();
+    let mut total: usize = sizes.iter().sum();
+    let mut keep = vec![true; len];
+    // Work out the path from the root to the current revision, which is dropped last
+    let mut path = vec![ptr];
+    while let Some(&idx) = path.last().filter(|idx| **idx != 0) {
+        path.push(mgmt.history[idx].parent);
+    }
+    path.reverse();
+    let mut on_path = vec![false; len];
+    for idx in &path {
+        on_path[*idx] = true;
+    }
+    // Leaves off the path can be dropped without disconnecting the tree
+    let mut children = vec![0_usize; len];
+    for revision in mgmt.history.iter().skip(1) {
+        children[revision.parent] += 1;
+    }
+    let mut leaves: BTreeSet<usize> = (0..len)
+        .filter(|idx| children[*idx] == 0 && !on_path[*idx])
+        .collect();
+    let mut root = 0;
+    while total > budget {
+        if let Some(leaf) = leaves.pop_first() {
+            // Drop the oldest branch first
+            keep[leaf] = false;
+            total -= sizes[leaf];
+            let parent = mgmt.history[leaf].parent;
+            children[parent] -= 1;
+            if children[parent] == 0 && !on_path[parent] {
+                leaves.insert(parent);
+            }
+        } else if root + 1 < path.len() {
+            // Only the path remains, so forget the oldest revision on it
+            keep[path[root]] = false;
+            total -= sizes[path[root]];
+            root += 1;
+        } else {
+            return None;
+        }
+    }
+    // Renumber the remaining revisions (parents always come before their children)
+    let mut new_idx = vec![None; len];
+    let mut count = 0;
+    for idx in (0..len).filter(|idx| keep[*idx]) {
+        new_idx[idx] = Some(count);
+        count += 1;
+    }
+    if count < 2 {
+        return None;
+    }
+    let history = (0..len)
+        .filter(|idx| keep[*idx])
+        .map(|idx| {
+            let revision = &mgmt.history[idx];
+            if idx == path[root] {
+                // The new root can't be undone past, so its changes don't need to be kept
+                let root = Revision {
+                    time: revision.time,
+                    ..Revision::default()
+                };
+                encode_revision(&root, Some(0), revision.last_child.and_then(|c| new_idx[c]))
+            } else {
+                let parent = new_idx[revision.parent];
+                encode_revision(
+                    revision,
+                    parent,
+                    revision.last_child.and_then(|c| new_idx[c]),
+                )
+            }
+        })
+        .collect();
+    Some((history, new_idx[ptr]?))
+}
+
+/// A stable FNV-1a hash, used for naming history files and detecting changes to files
+pub fn hash<I: IntoIterator<Item = u8>>(bytes: I) -> u64 {
+    bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |acc, b| {
+        (acc ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hash the contents of a rope (without collecting it into a string)
+#[must_use]
+pub fn hash_rope(rope: &Rope) -> u64 {
+    hash(rope.bytes())
+}
+
+fn write_u64(data: &mut Vec<u8>, n: u64) {
+    data.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_str(data: &mut Vec<u8>, st: &str) {
+    write_u64(data, st.len() as u64);
+    data.extend_from_slice(st.as_bytes());
+}
+
+fn write_loc(data: &mut Vec<u8>, loc: &Loc) {
+    write_u64(data, loc.x as u64);
+    write_u64(data, loc.y as u64);
+}
+
+fn write_cursor(data: &mut Vec<u8>, cursor: &Cursor) {
+    write_loc(data, &cursor.loc);
+    write_loc(data, &cursor.selection_end);
+}
+
+/// Encode a revision, with its parent and last child given as their renumbered positions
+fn encode_revision(
+    revision: &Revision,
+    parent: Option<usize>,
+    last_child: Option<usize>,
+) -> Vec<u8> {
+    let mut data = vec![];
+    write_u64(&mut data, parent.unwrap_or(revision.parent) as u64);
+    write_u64(&mut data, last_child.map_or(u64::MAX, |c| c as u64));
+    write_u64(&mut data, revision.time);
+    let transaction = &revision.transaction;
+    write_cursor(&mut data, &transaction.before);
+    write_cursor(&mut data, &transaction.after);
+    // Line ending styles are stored as a byte each (0 when the style wasn't changed)
+    let (before, after) = transaction.line_ending.map_or((0, 0), |(before, after)| {
+        (line_ending_tag(before), line_ending_tag(after))
+    });
+    data.extend_from_slice(&[before, after]);
+    write_u64(&mut data, transaction.events.len() as u64);
+    for ev in &transaction.events {
+        let (tag, loc, st) = match ev {
+            Event::Insert(loc, st) => (0, *loc, st.as_str()),
+            Event::Delete(loc, st) => (1, *loc, st.as_str()),
+            Event::InsertLine(y, st) => (2, Loc::at(0, *y), st.as_str()),
+            Event::DeleteLine(y, st) => (3, Loc::at(0, *y), st.as_str()),
+            Event::SplitDown(loc) => (4, *loc, ""),
+            Event::SpliceUp(loc) => (5, *loc, ""),
+        };
+        data.push(tag);
+        write_loc(&mut data, &loc);
+        write_str(&mut data, st);
+    }
+    data
+}
+
+fn line_ending_tag(line_ending: LineEnding) -> u8 {
+    match line_ending {
+        LineEnding::Lf => 1,
+        LineEnding::CrLf => 2,
+        LineEnding::Cr => 3,
+    }
+}
+
+fn line_ending(tag: u8) -> Option<LineEnding> {
+    match tag {
+        1 => Some(LineEnding::Lf),
+        2 => Some(LineEnding::CrLf),
+        3 => Some(LineEnding::Cr),
+        _ => None,
+    }
+}
+
+/// Reads values out of the on-disk format, stopping at the first sign of corruption
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        if n > self.data.len() {
+            return None;
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(taken)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        usize::try_from(self.u64()?).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn loc(&mut self) -> Option<Loc> {
+        Some(Loc::at(self.usize()?, self.usize()?))
+    }
+
+    fn cursor(&mut self) -> Option<Cursor> {
+        Some(Cursor {
+            loc: self.loc()?,
+            selection_end: self.loc()?,
+        })
+    }
+
+    fn event(&mut self) -> Option<Event> {
+        let tag = self.take(1)?[0];
+        let loc = self.loc()?;
+        let st = self.string()?;
+        Some(match tag {
+            0 => Event::Insert(loc, st),
+            1 => Event::Delete(loc, st),
+            2 => Event::InsertLine(loc.y, st),
+            3 => Event::DeleteLine(loc.y, st),
+            4 => Event::SplitDown(loc),
+            5 => Event::SpliceUp(loc),
+            _ => return None,
+        })
+    }
+
+    fn revision(&mut self) -> Option<Revision> {
+        let parent = self.usize()?;
+        let last_child = self.u64()?;
+        let time = self.u64()?;
+        let before = self.cursor()?;
+        let after = self.cursor()?;
+        let line_ending = match *self.take(2)? {
+            [0, 0] => None,
+            [before, after] => Some((line_ending(before)?, line_ending(after)?)),
+            _ => return None,
+        };
+        let len = self.usize()?;
+        let events = (0..len).map(|_| self.event()).collect::<Option<Vec<_>>>()?;
+        Some(Revision {
+            parent,
+            last_child: usize::try_from(last_child)
+                .ok()
+                .filter(|_| last_child != u64::MAX),
+            transaction: Transaction {
+                events,
+                before,
+                after,
+                line_ending,
+            },
+            time,
+        })
+    }
+}
+
+fn decode(data: &[u8], file_name: &str, content: u64) -> Option<EventMgmt> {
+    let mut reader = Reader { data };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return None;
+    }
+    let version = u32::from_le_bytes(reader.take(4)?.try_into().ok()?);
+    if version != HISTORY_VERSION || reader.u64()? != content || reader.string()? != file_name {
+        return None;
+    }
+    let ptr = reader.usize()?;
+    let cursor = reader.cursor()?;
+    let len = reader.usize()?;
+    let history = (0..len)
+        .map(|_| reader.revision())
+        .collect::<Option<Vec<_>>>()?;
+    // Make sure the tree is well formed before trusting it
+    let well_formed = history.iter().enumerate().all(|(idx, r)| {
+        (idx == 0 || r.parent < idx) && r.last_child.is_none_or(|c| c > idx && c < len)
+    });
+    if !well_formed || ptr >= len {
+        return None;
+    }
+    Some(EventMgmt {
+        history,
+        ptr,
+        on_disk: Some(ptr),
+        cursor,
+        ..EventMgmt::default()
+    })
+}
@@ -9,11 +9,12 @@
 //! - Rendering line numbers
 //! - Insertion and deletion from the document
 //! - File type detection
-//! - Undo & Redo
+//! - Undo & Redo (which can be persisted between sessions)
 //! - Moving around the document, by word, page, character or other means
 //! - Searching & Replacing
 //! - Handles tabs, different line endings and double width characters perfectly
-//! - File buffering for larger files
+//! - Reads and writes files in UTF-8, UTF-16 and legacy encodings
+//! - Reads lines from the rope as they are needed, and pages in files too large to fit in memory
 //!
 //! It removes a lot of complexity from your text editor and allows the creation of an advanced
 //! text editor in very few lines of idiomatic code.
@@ -27,8 +28,11 @@
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
 pub mod document;
+pub mod encoding;
 pub mod event;
+pub mod history;
 pub mod map;
+pub mod pager;
 pub mod searching;
 pub mod utils;
 
@@ -128,28 +128,38 @@ impl CharMap {
     }
 
     /// Shift lines in the character map up one
-    #[allow(clippy::missing_panics_doc)]
     pub fn shift_up(&mut self, loc: usize) {
+        self.shift_up_by(loc, 1);
+    }
+
+    /// Shift lines in the character map down one
+    pub fn shift_down(&mut self, loc: usize) {
+        self.shift_down_by(loc, 1);
+    }
+
+    /// Shift lines in the character map up by a number of lines
+    #[allow(clippy::missing_panics_doc)]
+    pub fn shift_up_by(&mut self, loc: usize, by: usize) {
         let mut keys: Vec<usize> = self.map.keys().copied().collect();
         keys.sort_unstable();
         for k in keys {
             if k >= loc {
                 let v = self.map.remove(&k).unwrap();
-                self.map.insert(k.saturating_sub(1), v);
+                self.map.insert(k.saturating_sub(by), v);
             }
         }
     }
 
-    /// Shift lines in the character map down one
+    /// Shift lines in the character map down by a number of lines
     #[allow(clippy::missing_panics_doc)]
-    pub fn shift_down(&mut self, loc: usize) {
+    pub fn shift_down_by(&mut self, loc: usize, by: usize) {
         let mut keys: Vec<usize> = self.map.keys().copied().collect();
         keys.sort_unstable();
         keys.reverse();
         for k in keys {
             if k >= loc {
                 let v = self.map.remove(&k).unwrap();
-                self.map.insert(k + 1, v);
+                self.map.insert(k + by, v);
             }
         }
     }
@@ -157,30 +167,35 @@ impl CharMap {
     /// Count the number of characters before an index, useful for conversion of indices
     #[must_use]
     pub fn count(&self, loc: &Loc, display: bool) -> Option<usize> {
-        let mut ctr = 0;
-        for i in self.get(loc.y)? {
-            let i = if display { i.0 } else { i.1 };
-            if i >= loc.x {
-                break;
-            }
-            ctr += 1;
-        }
-        Some(ctr)
+        Some(count(self.get(loc.y)?, loc.x, display))
     }
 
     /// If all character maps are of size n, then determine if x would be within one,
     /// and return their index inside the mapped char
     #[must_use]
     pub fn inside(&self, n: usize, x: usize, y: usize) -> Option<usize> {
-        for (disp, _) in self.get(y)? {
-            if ((disp + 1)..(disp + n)).contains(&x) {
-                return Some(x.saturating_sub(*disp));
-            }
-        }
-        None
+        inside(self.get(y)?, n, x)
     }
 }
 
+/// Count the number of characters in a line's map before an index
+/// (a display index if `display` is true, otherwise a character index)
+#[must_use]
+pub fn count(map: &[(usize, usize)], x: usize, display: bool) -> usize {
+    map.iter()
+        .take_while(|(disp, ch)| if display { *disp } else { *ch } < x)
+        .count()
+}
+
+/// If all characters in a line's map are of size n, then determine if display index x
+/// would be within one, and return its index inside the mapped char
+#[must_use]
+pub fn inside(map: &[(usize, usize)], n: usize, x: usize) -> Option<usize> {
+    map.iter()
+        .find(|(disp, _)| ((disp + 1)..(disp + n)).contains(&x))
+        .map(|(disp, _)| x.saturating_sub(*disp))
+}
+
 /// Vector that takes two usize values
 pub type DblUsize = Vec<(usize, usize)>;
 
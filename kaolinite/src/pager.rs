@@ -0,0 +1,193 @@
+/// pager.rs - reads lines out of files that are too large to load into memory at once
+use crate::document::LineEnding;
+use crate::encoding::Encoding;
+use crate::event::Result;
+use ropey::Rope;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// The number of lines between each position recorded in the line index
+pub const BLOCK: usize = 256;
+/// How many blocks of lines are kept in memory at once
+const CACHED_BLOCKS: usize = 16;
+/// How many bytes are read at a time when indexing lines
+const CHUNK: usize = 1 << 20;
+/// How many bytes at the start of a file are used to detect its encoding and line endings
+const SAMPLE: usize = 1 << 16;
+
+/// Provides access to the lines of a file without reading the whole file into memory.
+/// Lines are indexed lazily as they are requested, and read from the disk in blocks,
+/// of which only a few recently used blocks are kept around.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pager {
+    /// The path of the file being paged
+    pub path: PathBuf,
+    /// The size of the file in bytes
+    pub len: u64,
+    /// The encoding used to decode each line
+    pub encoding: Encoding,
+    /// The style of line endings the file uses
+    pub line_ending: LineEnding,
+    state: RefCell<PagerState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct PagerState {
+    /// Byte offsets of the start of every `BLOCK`th line
+    checkpoints: Vec<u64>,
+    /// How many bytes of the file have been indexed
+    scanned: u64,
+    /// How many line breaks have been found so far
+    breaks: usize,
+    /// Whether the last byte indexed was a line break
+    ends_in_break: bool,
+    /// Whether the whole file has been indexed
+    complete: bool,
+    /// Recently used blocks of lines, with the most recently used last
+    blocks: Vec<(usize, Vec<String>)>,
+}
+
+impl Pager {
+    /// Start paging a file, detecting the encoding from the start of the file if none is given.
+    /// Returns None if the file can't be paged a line at a time
+    /// (because it is in UTF-16, or only uses carriage returns to end lines).
+    /// # Errors
+    /// Returns an error if the file couldn't be read
+    pub fn open(path: &Path, encoding: Option<Encoding>) -> Result<Option<Self>> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut sample = vec![];
+        file.by_ref().take(SAMPLE as u64).read_to_end(&mut sample)?;
+        // Only detect using whole lines, so that characters aren't cut in half
+        let whole = sample
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(sample.as_slice(), |end| &sample[..=end]);
+        let encoding = encoding.unwrap_or_else(|| Encoding::detect(whole));
+        if matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be) {
+            return Ok(None);
+        }
+        let line_ending = LineEnding::detect(&Rope::from_str(&encoding.decode(whole).0));
+        if line_ending == LineEnding::Cr {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            path: path.to_path_buf(),
+            len,
+            encoding,
+            line_ending,
+            state: RefCell::new(PagerState {
+                checkpoints: vec![0],
+                complete: len == 0,
+                ..PagerState::default()
+            }),
+        }))
+    }
+
+    /// Get the number of lines in the file.
+    /// Until the whole file has been indexed, this is the number of lines found so far.
+    #[must_use]
+    pub fn len_lines(&self) -> usize {
+        let state = self.state.borrow();
+        if state.complete {
+            state.breaks + usize::from(self.len > 0 && !state.ends_in_break)
+        } else {
+            state.breaks + 1
+        }
+    }
+
+    /// Whether every line in the file has been found
+    #[must_use]
+    pub fn is_indexed(&self) -> bool {
+        self.state.borrow().complete
+    }
+
+    /// Get the line at a specified index
+    #[must_use]
+    pub fn line(&self, y: usize) -> Option<String> {
+        self.index_to(y);
+        let block = y / BLOCK;
+        let mut state = self.state.borrow_mut();
+        let cached = state.blocks.iter().position(|(b, _)| *b == block);
+        let lines = if let Some(idx) = cached {
+            // Move this block to the back, as it is the most recently used
+            let lines = state.blocks.remove(idx);
+            state.blocks.push(lines);
+            &state.blocks.last()?.1
+        } else {
+            let start = *state.checkpoints.get(block)?;
+            let lines = self.read_block(start).ok()?;
+            if state.blocks.len() >= CACHED_BLOCKS {
+                state.blocks.remove(0);
+            }
+            state.blocks.push((block, lines));
+            &state.blocks.last()?.1
+        };
+        let line = lines.get(y % BLOCK).cloned();
+        // Like a rope, a file ending in a line break has an empty line at the end
+        let trailing =
+            state.complete && y == state.breaks && (state.ends_in_break || self.len == 0);
+        line.or_else(|| trailing.then(String::new))
+    }
+
+    /// Index the file up to (and a little past) a certain line.
+    /// Use `usize::MAX` to index the whole file.
+    pub fn index_to(&self, y: usize) {
+        let mut state = self.state.borrow_mut();
+        let wanted = y.saturating_add(BLOCK) / BLOCK + 1;
+        if state.complete || state.checkpoints.len() > wanted {
+            return;
+        }
+        let Ok(mut file) = File::open(&self.path) else {
+            return;
+        };
+        if file.seek(SeekFrom::Start(state.scanned)).is_err() {
+            return;
+        }
+        let mut buffer = vec![0; CHUNK];
+        while !state.complete && state.checkpoints.len() <= wanted {
+            let Ok(read) = file.read(&mut buffer) else {
+                return;
+            };
+            if read == 0 || state.scanned >= self.len {
+                state.complete = true;
+                break;
+            }
+            // Don't read past the size the file had when it was opened
+            let read = read.min(usize::try_from(self.len - state.scanned).unwrap_or(read));
+            for (idx, _) in buffer[..read]
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+            {
+                state.breaks += 1;
+                if state.breaks.is_multiple_of(BLOCK) {
+                    let offset = state.scanned + idx as u64 + 1;
+                    state.checkpoints.push(offset);
+                }
+            }
+            state.ends_in_break = buffer[read - 1] == b'\n';
+            state.scanned += read as u64;
+        }
+    }
+
+    /// Read and decode a block of lines, starting at a byte offset
+    fn read_block(&self, start: u64) -> Result<Vec<String>> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut reader = BufReader::new(file.take(self.len.saturating_sub(start)));
+        let mut lines = Vec::with_capacity(BLOCK);
+        let mut bytes = vec![];
+        while lines.len() < BLOCK {
+            bytes.clear();
+            if reader.read_until(b'\n', &mut bytes)? == 0 {
+                break;
+            }
+            let (line, _) = self.encoding.decode(&bytes);
+            lines.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        Ok(lines)
+    }
+}
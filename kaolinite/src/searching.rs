@@ -1,7 +1,10 @@
 /// searching.rs - utilities to assist with searching a document
 use crate::regex;
 use crate::utils::Loc;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
+use ropey::str_utils::{byte_to_line_idx, line_to_byte_idx};
+use ropey::Rope;
+use std::sync::Arc;
 
 /// Stores information about a match in a document
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -10,6 +13,57 @@ pub struct Match {
     pub text: String,
 }
 
+impl Match {
+    /// Work out where this match ends (as matches can span several lines)
+    #[must_use]
+    pub fn end(&self) -> Loc {
+        let breaks = byte_to_line_idx(&self.text, self.text.len());
+        if breaks == 0 {
+            Loc::at(self.loc.x + self.text.chars().count(), self.loc.y)
+        } else {
+            let last = &self.text[line_to_byte_idx(&self.text, breaks)..];
+            Loc::at(last.chars().count(), self.loc.y + breaks)
+        }
+    }
+}
+
+/// Changes how a whole document is searched
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SearchOptions {
+    /// Whether letters should match regardless of their case
+    pub case_insensitive: bool,
+    /// Whether matches have to start and end on word boundaries
+    pub whole_word: bool,
+    /// Whether the target is plain text rather than a regular expression
+    pub literal: bool,
+}
+
+/// The text of a rope read out as one string, as regular expressions can only search through
+/// text that is all in one place. Reading it out is the slow part of searching a large document,
+/// so it can be kept and searched many times (`Document::search_text` keeps it until the document changes).
+#[derive(Debug, Clone)]
+pub struct SearchText {
+    rope: Rope,
+    text: Arc<str>,
+}
+
+impl SearchText {
+    /// Read out the text of a rope
+    #[must_use]
+    pub fn new(rope: &Rope) -> Self {
+        Self {
+            rope: rope.clone(),
+            text: rope.to_string().into(),
+        }
+    }
+
+    /// The text that was read out
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
 /// Struct to abstract searching
 pub struct Searcher {
     pub re: Regex,
@@ -22,6 +76,132 @@ impl Searcher {
         Self { re: regex!(re) }
     }
 
+    /// Create a searcher for searching across whole documents (see `Searcher::find_in`).
+    /// `^` and `$` match at the start and end of each line,
+    /// and line breaks are matched as they appear in the document (e.g. `\r\n` in CRLF files).
+    /// Invalid expressions won't match anything.
+    #[must_use]
+    pub fn with_options(re: &str, options: SearchOptions) -> Self {
+        Self::try_with_options(re, options).unwrap_or_else(|_| Self { re: regex!("a^") })
+    }
+
+    /// Create a searcher for searching across whole documents (see `Searcher::with_options`)
+    /// # Errors
+    /// Returns an error if the expression is invalid
+    pub fn try_with_options(re: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        let re = if options.literal {
+            regex::escape(re)
+        } else {
+            re.to_string()
+        };
+        let pattern = if options.whole_word {
+            format!(r"\b(?:{re})\b")
+        } else {
+            re
+        };
+        let re = RegexBuilder::new(&pattern)
+            .case_insensitive(options.case_insensitive)
+            .multi_line(true)
+            .crlf(true)
+            .build()?;
+        Ok(Self { re })
+    }
+
+    /// Find the first match in some text that starts at or after a character index.
+    /// Unlike `Searcher::lfind`, the whole text is searched at once, so matches can span lines.
+    /// Empty matches are skipped, as there is nothing to select.
+    #[must_use]
+    pub fn find_in(&self, haystack: &SearchText, from: usize) -> Option<Match> {
+        let SearchText { rope, text } = haystack;
+        let start = rope.try_char_to_byte(from).ok()?;
+        let mut at = start;
+        while let Some(m) = self.re.find_at(text, at) {
+            if !m.is_empty() {
+                return Some(rope_match(rope, m.start(), m.as_str()));
+            }
+            // Step over the empty match onto the next character
+            at = m.end() + text[m.end()..].chars().next()?.len_utf8();
+        }
+        None
+    }
+
+    /// Find the last (non-empty) match in some text that starts before a character index
+    #[must_use]
+    pub fn rfind_in(&self, haystack: &SearchText, before: usize) -> Option<Match> {
+        let SearchText { rope, text } = haystack;
+        let end = rope.try_char_to_byte(before.min(rope.len_chars())).ok()?;
+        let m = self
+            .re
+            .find_iter(text)
+            .filter(|m| !m.is_empty())
+            .take_while(|m| m.start() < end)
+            .last()?;
+        Some(rope_match(rope, m.start(), m.as_str()))
+    }
+
+    /// Find every (non-empty) match in some text, where matches can span lines
+    #[must_use]
+    pub fn finds_in(&self, haystack: &SearchText) -> Vec<Match> {
+        self.finds_in_upto(haystack, usize::MAX)
+    }
+
+    /// Find the first `limit` (non-empty) matches in some text, where matches can span lines
+    #[must_use]
+    pub fn finds_in_upto(&self, haystack: &SearchText, limit: usize) -> Vec<Match> {
+        let SearchText { rope, text } = haystack;
+        self.re
+            .find_iter(text)
+            .filter(|m| !m.is_empty())
+            .take(limit)
+            .map(|m| rope_match(rope, m.start(), m.as_str()))
+            .collect()
+    }
+
+    /// Find every (non-empty) match within a single line, for documents searched a line at a time
+    #[must_use]
+    pub fn finds_in_line(&self, line: &str) -> Vec<Match> {
+        self.re
+            .find_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| Match {
+                loc: Loc::at(Self::raw_to_char(m.start(), line), 0),
+                text: m.as_str().to_string(),
+            })
+            .collect()
+    }
+
+    /// Work out what a match in some text would be replaced with,
+    /// filling in any capture groups the replacement refers to (`$1`, `${name}`)
+    #[must_use]
+    pub fn expand_in(&self, haystack: &SearchText, mtch: &Match, into: &str) -> String {
+        let SearchText { rope, text } = haystack;
+        let start = rope
+            .try_line_to_char(mtch.loc.y)
+            .and_then(|c| rope.try_char_to_byte(c + mtch.loc.x));
+        let mut result = String::new();
+        match start.ok().and_then(|s| self.re.captures_at(text, s)) {
+            Some(caps) => caps.expand(into, &mut result),
+            None => result.push_str(into),
+        }
+        result
+    }
+
+    /// Find every match in some text, along with what each would be replaced with.
+    /// Empty matches are included, so that e.g. `^` can be used to add text to each line.
+    #[must_use]
+    pub fn replacements_in(&self, haystack: &SearchText, into: &str) -> Vec<(Match, String)> {
+        let SearchText { rope, text } = haystack;
+        self.re
+            .captures_iter(text)
+            .filter_map(|caps| {
+                let whole = caps.get(0)?;
+                let mut replacement = String::new();
+                caps.expand(into, &mut replacement);
+                Some((rope_match(rope, whole.start(), whole.as_str()), replacement))
+            })
+            .collect()
+    }
+
     /// Find the next match, starting from the left hand side of the string
     pub fn lfind(&mut self, st: &str) -> Option<Match> {
         for cap in self.re.captures_iter(st) {
@@ -115,3 +295,13 @@ impl Searcher {
         st.char_indices().nth(x).map_or(st.len(), |(byte, _)| byte)
     }
 }
+
+/// Create a match from a piece of a rope's text, given the byte index it starts at
+fn rope_match(rope: &Rope, byte: usize, text: &str) -> Match {
+    let idx = rope.byte_to_char(byte);
+    let y = rope.char_to_line(idx);
+    Match {
+        loc: Loc::at(idx - rope.line_to_char(y), y),
+        text: text.to_string(),
+    }
+}
@@ -442,7 +442,7 @@ pub fn icon(language: &str) -> String {
 
 /// Determine the file extension based off the magic modeline (if present)
 #[must_use]
-pub fn modeline(first_line: &str) -> Option<&str> {
+pub fn modeline(first_line: &str) -> Option<&'static str> {
     // Create a regex to handle leading/trailing whitespaces and spaces between '#!' and path
     let re = regex!(r"^#!\s*/\s*(\S+)(\s+\S+)?");
 
@@ -1,3 +1,5 @@
+#![allow(unused_must_use, clippy::invalid_regex, clippy::useless_vec)]
+
 use kaolinite::regex;
 #[cfg(test)]
 use kaolinite::{document::*, event::*, map::*, searching::*, utils::*};
@@ -300,7 +302,6 @@ fn errors() {
 fn document_disks() {
     // Standard test
     let mut doc = Document::open(Size::is(100, 10), "tests/data/saving.txt").unwrap();
-    doc.load_to(100);
     doc.delete_line(0);
     doc.insert_line(0, st!("this document is modified"));
     doc.save();
@@ -330,16 +331,12 @@ fn document_disks() {
 #[test]
 fn document_insertion() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(100);
     doc.exe(Event::Insert(Loc { x: 5, y: 0 }, st!("hello")));
     assert_eq!(doc.line(0), Some(st!("    你hello好")));
-    assert_eq!(doc.dbl_map.get(3), Some(&vec![(4, 1), (6, 2)]));
+    assert_eq!(doc.dbl_map(3), vec![(4, 1), (6, 2)]);
     doc.exe(Event::Insert(Loc { x: 3, y: 3 }, st!("\t你你")));
     assert_eq!(doc.line(3), Some(st!("\t你好\t你你")));
-    assert_eq!(
-        doc.dbl_map.get(3),
-        Some(&vec![(4, 1), (6, 2), (12, 4), (14, 5)])
-    );
+    assert_eq!(doc.dbl_map(3), vec![(4, 1), (6, 2), (12, 4), (14, 5)]);
     doc.exe(Event::Insert(
         Loc { x: 0, y: 6 },
         st!("\thello, world: 你好"),
@@ -352,13 +349,12 @@ fn document_insertion() {
 #[test]
 fn document_deletion() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(100);
     doc.exe(Event::Delete(Loc { x: 4, y: 0 }, st!("你")));
     assert_eq!(doc.line(0), Some(st!("    好")));
-    assert_eq!(doc.dbl_map.get(3), Some(&vec![(4, 1), (6, 2)]));
+    assert_eq!(doc.dbl_map(3), vec![(4, 1), (6, 2)]);
     doc.exe(Event::Delete(Loc { x: 1, y: 3 }, st!("你")));
     assert_eq!(doc.line(3), Some(st!("\t好")));
-    assert_eq!(doc.dbl_map.get(3), Some(&vec![(4, 1)]));
+    assert_eq!(doc.dbl_map(3), vec![(4, 1)]);
     doc.exe(Event::Delete(
         Loc { x: 0, y: 6 },
         st!("\thello, world: 你好"),
@@ -404,54 +400,602 @@ fn document_deletion() {
 #[test]
 fn document_undo_redo() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(100);
-    assert!(doc.event_mgmt.undo(doc.take_snapshot()).is_none());
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(doc.event_mgmt.undo(doc.cursor).is_none());
+    assert!(doc.event_mgmt.with_disk());
     doc.event_mgmt.force_not_with_disk = true;
-    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
     doc.event_mgmt.force_not_with_disk = false;
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
-    assert!(doc.event_mgmt.undo(doc.take_snapshot()).is_none());
+    assert!(doc.event_mgmt.with_disk());
+    assert!(doc.event_mgmt.undo(doc.cursor).is_none());
     assert!(doc.redo().is_ok());
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(doc.event_mgmt.with_disk());
     doc.exe(Event::InsertLine(0, st!("hello你bye好hello")));
     doc.exe(Event::Delete(Loc { x: 0, y: 2 }, st!("\t")));
     doc.exe(Event::Insert(Loc { x: 3, y: 2 }, st!("a")));
-    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
     doc.commit();
-    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
     assert!(doc.undo().is_ok());
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(doc.event_mgmt.with_disk());
     assert_eq!(doc.line(0), Some(st!("    你好")));
     assert_eq!(doc.line(1), Some(st!("\thello")));
     assert_eq!(doc.line(2), Some(st!("    hello")));
     assert!(doc.redo().is_ok());
-    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
     assert_eq!(doc.line(0), Some(st!("hello你bye好hello")));
     assert_eq!(doc.line(2), Some(st!("helalo")));
-    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
-    doc.event_mgmt.disk_write(&doc.take_snapshot());
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
+    doc.event_mgmt.disk_write(doc.cursor);
+    assert!(doc.event_mgmt.with_disk());
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(100);
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(doc.event_mgmt.with_disk());
     doc.exe(Event::InsertLine(0, st!("hello你bye好hello")));
-    assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
+    assert!(!doc.event_mgmt.with_disk());
+    assert!(doc.undo().is_ok());
+    assert!(doc.event_mgmt.with_disk());
+}
+
+
+#[test]
+fn document_undo_redo_deltas() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    let original = doc.file.to_string();
+    let original_lines = doc.lines_to(usize::MAX);
+    // Typing on a line is merged into a single event
+    doc.exe(Event::Insert(Loc { x: 1, y: 1 }, st!("a")));
+    doc.exe(Event::Insert(Loc { x: 2, y: 1 }, st!("b")));
+    assert_eq!(
+        doc.event_mgmt.patch,
+        vec![Event::Insert(Loc { x: 1, y: 1 }, st!("ab"))]
+    );
+    doc.commit();
+    // Multi-line insertion only touches the lines it spans
+    doc.exe(Event::Insert(Loc { x: 0, y: 2 }, st!("one\n\ttwo\n")));
+    assert_eq!(doc.line(2), Some(st!("one")));
+    assert_eq!(doc.line(3), Some(st!("\ttwo")));
+    assert_eq!(doc.line(4), Some(st!("    hello")));
+    assert_eq!(doc.tab_map(3), vec![(0, 0)]);
+    assert_eq!(doc.tab_map(5), vec![(0, 0)]);
+    assert_eq!(doc.char_loc(), Loc { x: 0, y: 4 });
+    doc.commit();
+    let edited = doc.file.to_string();
+    // Removing a selection spanning several lines is recorded as one event
+    doc.move_to(&Loc { x: 1, y: 0 });
+    doc.select_to(&Loc { x: 2, y: 3 });
+    doc.remove_selection();
+    assert_eq!(doc.line(0), Some(st!(" wo")));
+    assert_eq!(doc.tab_map(2), vec![(0, 0)]);
+    assert_eq!(doc.event_mgmt.patch.len(), 1);
+    // Undo everything
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), edited);
+    assert!(doc.undo().is_ok());
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), original);
+    assert_eq!(doc.lines_to(usize::MAX), original_lines);
+    assert_eq!(doc.tab_map(1), vec![(0, 0)]);
+    assert_eq!(doc.dbl_map(4), vec![(5, 5), (12, 11)]);
+    assert!(doc.event_mgmt.with_disk());
+    // Redo everything
+    assert!(doc.redo().is_ok());
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.file.to_string(), edited);
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.line(0), Some(st!(" wo")));
+    // Editing after an undo starts a new branch, which redo can't leave
+    assert!(doc.undo().is_ok());
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("x")));
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.line(0), Some(st!("x    你好")));
+    assert_eq!(doc.event_mgmt.history.len(), 5);
+    // The branch that was undone is still there to switch back to
+    assert!(doc.prev_branch().is_ok());
+    assert_eq!(doc.line(0), Some(st!(" wo")));
+    assert!(doc.next_branch().is_ok());
+    assert_eq!(doc.line(0), Some(st!("x    你好")));
+}
+
+#[test]
+fn document_line_endings() {
+    let dir = std::env::temp_dir().join(format!("kaolinite-endings-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("crlf.txt");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "one\r\ntwo\r\nthree\r\n").unwrap();
+    // Detection
+    let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert_eq!(doc.line(1), Some(st!("two")));
+    assert_eq!(
+        LineEnding::detect(&ropey::Rope::from_str("a\rb\rc")),
+        LineEnding::Cr
+    );
+    assert_eq!(LineEnding::detect(&ropey::Rope::from_str("a")), LineEnding::Lf);
+    // Editing preserves the line ending
+    doc.exe(Event::SplitDown(Loc { x: 1, y: 1 }));
+    doc.exe(Event::InsertLine(0, st!("zero")));
+    doc.exe(Event::Insert(Loc { x: 0, y: 4 }, st!("pasted\nlines\n")));
+    assert_eq!(
+        doc.file.to_string(),
+        "zero\r\none\r\nt\r\nwo\r\npasted\r\nlines\r\nthree\r\n"
+    );
+    assert_eq!(doc.line(5), Some(st!("lines")));
+    doc.save().unwrap();
+    assert!(!std::fs::read_to_string(path).unwrap().replace("\r\n", "").contains('\n'));
+    // Converting between styles is undone in one go, before the edits made before it
+    doc.move_to(&Loc { x: 2, y: 5 });
+    assert!(doc.set_line_ending(LineEnding::Lf).is_ok());
+    assert_eq!(
+        doc.file.to_string(),
+        "zero\none\nt\nwo\npasted\nlines\nthree\n"
+    );
+    assert_eq!(doc.char_loc(), Loc { x: 2, y: 5 });
+    assert!(!doc.event_mgmt.with_disk());
+    assert!(doc.undo().is_ok());
+    assert_eq!(
+        doc.file.to_string(),
+        "zero\r\none\r\nt\r\nwo\r\npasted\r\nlines\r\nthree\r\n"
+    );
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert!(doc.event_mgmt.with_disk());
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "one\r\ntwo\r\nthree\r\n");
+    assert!(doc.redo().is_ok());
+    assert!(doc.redo().is_ok());
+    assert_eq!(
+        doc.file.to_string(),
+        "zero\none\nt\nwo\npasted\nlines\nthree\n"
+    );
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+    assert!(doc.undo().is_ok());
+    assert!(doc.undo().is_ok());
+    assert!(doc.set_line_ending(LineEnding::Cr).is_ok());
+    assert_eq!(doc.file.to_string(), "one\rtwo\rthree\r");
+    assert_eq!(doc.lines_to(usize::MAX), vec![st!("one"), st!("two"), st!("three"), st!("")]);
+    // Undoing a conversion goes back to the old style for later edits too
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    doc.exe(Event::Insert(Loc { x: 3, y: 0 }, st!("\n"))).unwrap();
+    assert_eq!(doc.file.to_string(), "one\r\n\r\ntwo\r\nthree\r\n");
+    // Changing the style of a document without any line endings can be undone too
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("single"))).unwrap();
+    assert!(doc.set_final_newline(false).is_ok());
+    assert!(doc.set_line_ending(LineEnding::CrLf).is_ok());
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+    assert_eq!(doc.file.to_string(), "single");
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    // Names
+    assert_eq!(LineEnding::from_name("CRLF"), Some(LineEnding::CrLf));
+    assert_eq!(LineEnding::from_name("nope"), None);
+    assert_eq!(LineEnding::Cr.to_string(), "CR");
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_tidying() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("one  \ntwo\n\t\nthree\t")));
+    doc.commit();
+    doc.move_to(&Loc { x: 2, y: 3 });
+    // Trailing whitespace is removed as edits that can be undone
+    assert_eq!(doc.trim_trailing_whitespace().ok(), Some(true));
+    assert_eq!(doc.file.to_string(), "one\ntwo\n\nthree\n");
+    assert_eq!(doc.char_loc(), Loc { x: 2, y: 3 });
+    assert_eq!(doc.trim_trailing_whitespace().ok(), Some(false));
+    doc.commit();
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "one  \ntwo\n\t\nthree\t\n");
+    // The final line ending can be removed and added back without changing the lines
+    assert!(doc.set_final_newline(false).is_ok());
+    assert_eq!(doc.file.to_string(), "one  \ntwo\n\t\nthree\t");
+    assert_eq!(doc.len_lines(), 4);
+    assert!(doc.set_final_newline(true).is_ok());
+    assert!(doc.set_final_newline(true).is_ok());
+    assert_eq!(doc.file.to_string(), "one  \ntwo\n\t\nthree\t\n");
+    assert_eq!(doc.len_lines(), 4);
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "one  \ntwo\n\t\nthree\t");
+    assert_eq!(doc.len_lines(), 4);
+    assert!(doc.undo().is_ok());
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.len_lines(), 4);
+    doc.info.read_only = true;
+    assert!(doc.trim_trailing_whitespace().is_err());
 }
 
+#[test]
+fn document_encodings() {
+    use kaolinite::encoding::Encoding;
+    let dir = std::env::temp_dir().join(format!("kaolinite-encodings-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+    let path = path.to_str().unwrap();
+    // Legacy encodings are detected and written back the same way
+    std::fs::write(path, b"caf\xe9\n").unwrap();
+    let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+    assert_eq!(doc.info.encoding, Encoding::Latin1);
+    assert_eq!(doc.line(0), Some(st!("café")));
+    doc.exe(Event::Insert(Loc { x: 4, y: 0 }, st!("!")));
+    doc.save().unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), b"caf\xe9!\n");
+    // Characters the encoding can't represent stop the save rather than being lost
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("你")));
+    assert!(matches!(doc.save(), Err(Error::Unencodable { .. })));
+    assert_eq!(std::fs::read(path).unwrap(), b"caf\xe9!\n");
+    std::fs::write(path, b"\x80 \x93hi\x94\n").unwrap();
+    let doc = Document::open(Size::is(100, 10), path).unwrap();
+    assert_eq!(doc.info.encoding, Encoding::Windows1252);
+    assert_eq!(doc.file.to_string(), "€ \u{201C}hi\u{201D}\n");
+    // Byte order marks are detected, hidden from the document, and kept when saving
+    for (encoding, bytes) in [
+        (Encoding::Utf8Bom, b"\xef\xbb\xbfhi\n".to_vec()),
+        (Encoding::Utf16Le, b"\xff\xfeh\x00i\x00\n\x00".to_vec()),
+        (Encoding::Utf16Be, b"\xfe\xff\x00h\x00i\x00\n".to_vec()),
+    ] {
+        std::fs::write(path, &bytes).unwrap();
+        let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+        assert_eq!(doc.info.encoding, encoding);
+        assert_eq!(doc.file.to_string(), "hi\n");
+        assert!(!doc.info.read_only);
+        doc.save().unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), bytes);
+    }
+    // Files that can't be decoded losslessly are read only, until reopened correctly
+    std::fs::write(path, b"caf\xe9\n").unwrap();
+    let mut doc = Document::open_with_encoding(Size::is(100, 10), path, Some(Encoding::Utf8)).unwrap();
+    assert!(doc.info.lossy);
+    assert!(doc.info.read_only);
+    assert!(matches!(doc.save(), Err(Error::ReadOnlyFile)));
+    assert!(doc.reload_with_encoding(Encoding::Latin1).is_ok());
+    assert!(!doc.info.lossy);
+    assert!(!doc.info.read_only);
+    assert_eq!(doc.line(0), Some(st!("café")));
+    // Names
+    assert_eq!(Encoding::from_name("UTF-16"), Some(Encoding::Utf16Le));
+    assert_eq!(Encoding::from_name("iso-8859-1"), Some(Encoding::Latin1));
+    assert_eq!(Encoding::from_name("ebcdic"), None);
+    assert_eq!(Encoding::Windows1252.encode("€"), Some(vec![0x80]));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_atomic_save() {
+    let dir = std::env::temp_dir().join(format!("kaolinite-atomic-{}", std::process::id()));
+    let backups = dir.join("backups");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "one\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o640)).unwrap();
+    }
+    let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+    // Saves replace the file, keeping its permissions and leaving no temporary files behind
+    doc.exe(Event::Insert(Loc { x: 3, y: 0 }, st!("!")));
+    doc.save().unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "one!\n");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    // Tilde backups hold the previous version of the file
+    doc.backup = Backup::Tilde;
+    doc.exe(Event::Insert(Loc { x: 4, y: 0 }, st!("!")));
+    doc.save().unwrap();
+    assert_eq!(std::fs::read_to_string(format!("{path}~")).unwrap(), "one!\n");
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "one!!\n");
+    // Backup directories only keep the most recent versions
+    doc.backup = Backup::Directory { dir: backups.clone(), keep: 2 };
+    for _ in 0..3 {
+        doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!(">")));
+        doc.save().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    let mut kept: Vec<String> = std::fs::read_dir(&backups)
+        .unwrap()
+        .map(|e| std::fs::read_to_string(e.unwrap().path()).unwrap())
+        .collect();
+    kept.sort();
+    assert_eq!(kept, vec![st!(">>one!!\n"), st!(">one!!\n")]);
+    // Failures are reported distinctly, and the temporary file is cleaned up
+    let target = dir.join("folder");
+    std::fs::create_dir_all(&target).unwrap();
+    let target = target.to_str().unwrap();
+    assert!(matches!(doc.save_as(target), Err(Error::Backup { .. })));
+    doc.backup = Backup::None;
+    assert!(matches!(doc.save_as(target), Err(Error::Rename { .. })));
+    let leftovers = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".tmp"))
+        .count();
+    assert_eq!(leftovers, 0);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_external_change() {
+    let dir = std::env::temp_dir().join(format!("kaolinite-external-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "one\ntwo\n").unwrap();
+    let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+    assert_eq!(doc.info.disk, DiskState::read(std::path::Path::new(path)));
+    assert!(doc.external_change().is_none());
+    // Rewriting a file with the same contents isn't a change
+    std::fs::write(path, "one\ntwo\n").unwrap();
+    assert!(doc.external_change().is_none());
+    // Other programs changing the file are noticed, and the file can be reloaded
+    std::fs::write(path, "one\n2\nthree\n").unwrap();
+    assert!(doc.external_change().is_some());
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("unsaved ")));
+    doc.move_to(&Loc { x: 2, y: 1 });
+    doc.reload().unwrap();
+    assert_eq!(doc.file.to_string(), "one\n2\nthree\n");
+    assert_eq!(doc.loc(), Loc { x: 1, y: 1 });
+    assert!(doc.event_mgmt.with_disk());
+    assert!(doc.external_change().is_none());
+    // Reloading is an edit, so the unsaved changes can be brought back
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "unsaved one\ntwo\n");
+    assert!(!doc.event_mgmt.with_disk());
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "one\ntwo\n");
+    assert!(doc.redo().is_ok());
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.file.to_string(), "one\n2\nthree\n");
+    assert!(doc.event_mgmt.with_disk());
+    // The new contents are kept as they are on the disk (along with their style of line ending)
+    std::fs::write(path, "crlf\r\nno final newline").unwrap();
+    doc.reload().unwrap();
+    assert_eq!(doc.file.to_string(), "crlf\r\nno final newline");
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert_eq!(doc.len_lines(), 2);
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "one\n2\nthree\n");
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+    assert_eq!(doc.len_lines(), 3);
+    assert!(doc.redo().is_ok());
+    assert_eq!(doc.len_lines(), 2);
+    assert_eq!(doc.line(1), Some(st!("no final newline")));
+    // Saving records the new state of the file
+    std::fs::write(path, "changed").unwrap();
+    assert!(doc.external_change().is_some());
+    doc.save().unwrap();
+    assert!(doc.external_change().is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_paged() {
+    let dir = std::env::temp_dir().join(format!("kaolinite-paged-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.log");
+    let path = path.to_str().unwrap();
+    let contents: String = (0..1000).map(|n| format!("line {n}\r\n")).collect();
+    std::fs::write(path, &contents).unwrap();
+    let mut doc = Document::open_paged(Size::is(100, 10), path).unwrap();
+    assert!(doc.is_paged());
+    assert!(doc.info.read_only);
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert_eq!(doc.file.len_chars(), 0);
+    // Lines can be jumped to
+    assert_eq!(doc.line(0), Some(st!("line 0")));
+    assert_eq!(doc.line(0), Some(st!("line 0")));
+    doc.move_to(&Loc { x: 0, y: 900 });
+    assert_eq!(doc.loc().y, 900);
+    assert_eq!(doc.line(899), Some(st!("line 899")));
+    doc.move_bottom();
+    assert_eq!(doc.len_lines(), 1000);
+    assert_eq!(doc.line(999), Some(st!("line 999")));
+    assert_eq!(doc.line(1000), Some(st!("")));
+    assert_eq!(doc.line(1001), None);
+    // Searching works without the file in memory
+    doc.move_to(&Loc { x: 0, y: 0 });
+    let searcher = Searcher::with_options("line 7[0-9]{2}", SearchOptions::default());
+    assert_eq!(doc.find_next(&searcher, 0).map(|m| m.loc), Some(Loc { x: 0, y: 700 }));
+    doc.move_to(&Loc { x: 0, y: 600 });
+    let searcher = Searcher::with_options("line 3[0-9]{2}", SearchOptions::default());
+    assert_eq!(doc.find_prev(&searcher).map(|m| m.loc), Some(Loc { x: 0, y: 399 }));
+    // (search options are followed too)
+    let options = SearchOptions {
+        case_insensitive: true,
+        whole_word: true,
+        literal: false,
+    };
+    let searcher = Searcher::with_options("LINE 5", options);
+    assert_eq!(doc.find_prev(&searcher).map(|m| m.loc), Some(Loc { x: 0, y: 5 }));
+    assert_eq!(doc.find_next(&searcher, 1).map(|m| m.loc), None);
+    doc.move_to(&Loc { x: 0, y: 5 });
+    assert_eq!(doc.find_next(&searcher, 0).map(|m| m.text), Some(st!("line 5")));
+    assert_eq!(doc.find_next(&searcher, 1).map(|m| m.loc), None);
+    // Ranges of lines can be searched a line at a time
+    let searcher = Searcher::new("e [0-9]");
+    let found: Vec<Loc> = doc.find_in_lines(&searcher, 8..11).iter().map(|m| m.loc).collect();
+    assert_eq!(found, vec![Loc::at(3, 8), Loc::at(3, 9), Loc::at(3, 10)]);
+    assert_eq!(doc.find_in_lines(&searcher, 999..1005).len(), 1);
+    // Text can be copied, but not changed
+    doc.move_to(&Loc { x: 5, y: 10 });
+    doc.select_to(&Loc { x: 4, y: 11 });
+    assert_eq!(doc.selection_text(), "10\r\nline");
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("edit")));
+    assert_eq!(doc.line(0), Some(st!("line 0")));
+    assert!(matches!(doc.save(), Err(Error::ReadOnlyFile)));
+    // Changes on the disk are noticed without hashing the file
+    std::fs::write(path, "short\nfile\n").unwrap();
+    assert!(doc.external_change().is_some());
+    doc.reload().unwrap();
+    assert_eq!(doc.len_lines(), 2);
+    assert_eq!(doc.line(1), Some(st!("file")));
+    // Paged documents can be loaded into memory to be edited
+    doc.load_fully().unwrap();
+    assert!(!doc.is_paged());
+    assert!(!doc.info.read_only);
+    assert_eq!(doc.file.to_string(), "short\nfile\n");
+    // Only as much of the file as is needed gets indexed
+    let contents = "x".repeat(99) + "\n";
+    std::fs::write(path, contents.repeat(30_000)).unwrap();
+    let doc = Document::open_paged(Size::is(100, 10), path).unwrap();
+    assert_eq!(doc.line(5), Some("x".repeat(99)));
+    assert!(!doc.pager.as_ref().unwrap().is_indexed());
+    doc.index_to(usize::MAX);
+    assert_eq!(doc.len_lines(), 30_000);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn document_undo_tree() {
+    use std::time::Duration;
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/no_eol.txt").unwrap();
+    let original = doc.file.to_string();
+    // Build a tree: root -> 1 -> 2, and root -> 1 -> 3
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("a")));
+    doc.commit();
+    doc.exe(Event::Insert(Loc { x: 1, y: 0 }, st!("b")));
+    doc.commit();
+    let ab = doc.file.to_string();
+    doc.undo();
+    doc.exe(Event::Insert(Loc { x: 1, y: 0 }, st!("c")));
+    doc.commit();
+    let ac = doc.file.to_string();
+    assert_eq!(doc.event_mgmt.history.len(), 4);
+    assert_eq!(doc.event_mgmt.branches(), 2);
+    assert_eq!(doc.event_mgmt.siblings(), vec![2, 3]);
+    // Branch switching
+    assert!(doc.prev_branch().is_ok());
+    assert_eq!(doc.event_mgmt.ptr, 2);
+    assert_eq!(doc.file.to_string(), ab);
+    assert!(doc.prev_branch().is_ok());
+    assert_eq!(doc.file.to_string(), ac);
+    // Redo follows the most recently visited branch
+    doc.undo();
+    doc.redo();
+    assert_eq!(doc.file.to_string(), ac);
+    // Saved revision is tracked across branches
+    doc.event_mgmt.disk_write(doc.cursor);
+    assert!(doc.event_mgmt.with_disk());
+    assert!(doc.goto_revision(2).is_ok());
+    assert_eq!(doc.file.to_string(), ab);
+    assert!(!doc.event_mgmt.with_disk());
+    assert!(doc.goto_revision(3).is_ok());
+    assert!(doc.event_mgmt.with_disk());
+    // Moving by number of revisions crosses branches in the order they were made
+    assert!(doc.earlier(1).is_ok());
+    assert_eq!(doc.file.to_string(), ab);
+    assert!(doc.earlier(10).is_ok());
+    assert_eq!(doc.file.to_string(), original);
+    assert!(doc.later(3).is_ok());
+    assert_eq!(doc.file.to_string(), ac);
+    // Moving by time
+    for (idx, revision) in doc.event_mgmt.history.iter_mut().enumerate() {
+        revision.time = 1000 + idx as u64 * 60;
+    }
+    assert!(doc.earlier_by(Duration::from_secs(90)).is_ok());
+    assert_eq!(doc.event_mgmt.ptr, 1);
+    assert!(doc.later_by(Duration::from_secs(60)).is_ok());
+    assert_eq!(doc.file.to_string(), ab);
+    assert!(doc.earlier_by(Duration::from_secs(3600)).is_ok());
+    assert_eq!(doc.file.to_string(), original);
+    assert!(doc.later_by(Duration::from_secs(3600)).is_ok());
+    assert_eq!(doc.file.to_string(), ac);
+}
+
+#[test]
+fn document_persistent_history() {
+    use kaolinite::history::HistoryStore;
+    let dir = std::env::temp_dir().join(format!("kaolinite-history-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file.txt");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "hello\nworld\n").unwrap();
+    let store = HistoryStore::new(dir.join("undo"), 1024 * 1024);
+    // Make some edits and save them
+    let mut doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    doc.exe(Event::Insert(Loc { x: 5, y: 0 }, st!(" there")));
+    doc.commit();
+    doc.exe(Event::DeleteLine(1, st!("world")));
+    doc.save().unwrap();
+    assert!(store.path_for(&get_absolute_path(path).unwrap()).exists());
+    // History is restored when the file is reopened
+    let mut doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    assert_eq!(doc.event_mgmt.history.len(), 3);
+    assert!(doc.event_mgmt.with_disk());
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "hello there\nworld\n");
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), "hello\nworld\n");
+    assert!(!doc.event_mgmt.with_disk());
+    assert!(doc.redo().is_ok());
+    assert!(doc.redo().is_ok());
+    assert!(doc.event_mgmt.with_disk());
+    // Branches survive being persisted
+    doc.undo();
+    doc.exe(Event::Insert(Loc { x: 0, y: 1 }, st!("new ")));
+    doc.save().unwrap();
+    let mut doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    assert_eq!(doc.event_mgmt.branches(), 2);
+    assert!(doc.prev_branch().is_ok());
+    assert_eq!(doc.file.to_string(), "hello there\n");
+    assert!(doc.next_branch().is_ok());
+    assert_eq!(doc.file.to_string(), "hello there\nnew world\n");
+    // Changes of line ending style survive being persisted
+    doc.set_line_ending(LineEnding::CrLf).unwrap();
+    doc.save().unwrap();
+    let mut doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    assert_eq!(doc.info.line_ending, LineEnding::CrLf);
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+    assert_eq!(doc.file.to_string(), "hello there\nnew world\n");
+    // History is discarded when the file was changed elsewhere
+    std::fs::write(path, "changed\n").unwrap();
+    let doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    assert_eq!(doc.event_mgmt.history.len(), 1);
+    // Oldest transactions are dropped to respect the size cap
+    let mut doc = Document::open(Size::is(100, 10), path).unwrap();
+    for i in 0..50 {
+        doc.exe(Event::InsertLine(0, format!("line number {i}")));
+        doc.commit();
+    }
+    doc.attach_history(HistoryStore::new(dir.join("undo"), 1024));
+    doc.save().unwrap();
+    let size = std::fs::metadata(store.path_for(&get_absolute_path(path).unwrap()))
+        .unwrap()
+        .len();
+    assert!(size <= 1024);
+    let mut doc = Document::open_with_history(Size::is(100, 10), path, store.clone()).unwrap();
+    let kept = doc.event_mgmt.history.len() - 1;
+    assert!(kept > 0 && kept < 50);
+    while doc.event_mgmt.ptr > 0 {
+        doc.undo().unwrap();
+    }
+    assert_eq!(doc.line(0), Some(format!("line number {}", 49 - kept)));
+    // Corrupt history files are ignored
+    std::fs::write(store.path_for(&get_absolute_path(path).unwrap()), b"KAOUNDO\0garbage").unwrap();
+    let doc = Document::open_with_history(Size::is(100, 10), path, store).unwrap();
+    assert_eq!(doc.event_mgmt.history.len(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
 #[test]
 fn cursor_state_after_undo_redo() {
     // Create a simple document with known content
     let mut doc = Document::new(Size::is(100, 10));
     doc.insert_line(0, st!("hello world")).unwrap();
-    doc.load_to(100);
     
     // Move cursor to position 5 (after "hello")
     doc.move_to(&Loc::at(5, 0));
     let initial_cursor = doc.cursor.loc;
     let initial_char_ptr = doc.char_ptr;
-    let initial_old_cursor = doc.old_cursor;
+    let _initial_old_cursor = doc.old_cursor;
     
     // Take initial snapshot for undo history (with cursor at position 5)
     doc.commit();
@@ -499,14 +1043,13 @@ fn cursor_state_with_unicode() {
     // Create a document with Unicode
     let mut doc = Document::new(Size::is(100, 10));
     doc.insert_line(0, st!("你好world")).unwrap();
-    doc.load_to(100);
     
     // Test with Unicode characters
     // "你好world" - each Unicode char takes 2 display width
     doc.move_to(&Loc::at(4, 0)); // Position after "你好" (2 chars * 2 width = 4 display)
     let unicode_cursor = doc.cursor.loc;
     let unicode_char_ptr = doc.char_ptr;
-    let unicode_old_cursor = doc.old_cursor;
+    let _unicode_old_cursor = doc.old_cursor;
     
     // Save state with cursor at this position
     doc.commit();
@@ -535,7 +1078,6 @@ fn cursor_state_with_tabs() {
     // Create a document with tabs
     let mut doc = Document::new(Size::is(100, 10));
     doc.insert_line(0, st!("\thello")).unwrap();
-    doc.load_to(100);
     
     // Test with tabs
     doc.move_to(&Loc::at(0, 0)); // Start of line with tab
@@ -543,7 +1085,7 @@ fn cursor_state_with_tabs() {
     
     let tab_cursor = doc.cursor.loc;
     let tab_char_ptr = doc.char_ptr;
-    let tab_old_cursor = doc.old_cursor;
+    let _tab_old_cursor = doc.old_cursor;
     
     // Save state with cursor after the tab
     doc.commit();
@@ -572,20 +1114,18 @@ fn cursor_state_with_tabs() {
 #[test]
 fn document_moving() {
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
-    doc.load_to(10);
     // Check moving down
-    for loaded in 0..100 {
+    for moved in 0..100 {
         assert_eq!(doc.move_down(), Status::None);
-        assert_eq!(doc.cursor.loc.y, 1 + loaded);
+        assert_eq!(doc.cursor.loc.y, 1 + moved);
         assert_eq!(
             doc.offset.y,
-            if loaded < 9 {
+            if moved < 9 {
                 0
             } else {
-                (1 + loaded).saturating_sub(9)
+                (1 + moved).saturating_sub(9)
             }
         );
-        assert!(doc.info.loaded_to >= loaded);
     }
     assert_eq!(doc.move_down(), Status::EndOfFile);
     // Check moving up
@@ -597,7 +1137,6 @@ fn document_moving() {
     assert_eq!(doc.move_up(), Status::StartOfFile);
     // Check cursor "stickiness" & goto & double width straddling attempts
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(10);
     doc.exe(Event::InsertLine(5, st!("hello你bye")));
     doc.move_to(&Loc { x: 4, y: 1 });
     doc.old_cursor = 7;
@@ -619,7 +1158,6 @@ fn document_moving() {
     assert_eq!(doc.loc(), Loc { x: 19, y: 4 });
     // Moving left and right & cursor "stickiness"
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
-    doc.load_to(10);
     for _ in 0..9 {
         doc.move_right();
     }
@@ -657,7 +1195,6 @@ fn document_moving() {
     assert_eq!(doc.char_loc(), Loc { x: 0, y: 2 });
     // Test unicode
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(10);
     doc.move_right();
     assert_eq!(doc.loc(), Loc { x: 4, y: 0 });
     assert_eq!(doc.char_loc(), Loc { x: 4, y: 0 });
@@ -678,7 +1215,6 @@ fn document_moving() {
     assert_eq!(doc.char_loc(), Loc { x: 0, y: 0 });
     assert_eq!(doc.old_cursor, 0);
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
-    doc.load_to(10);
     doc.move_right();
     assert_eq!(doc.char_loc(), Loc { x: 1, y: 0 });
     assert_eq!(doc.old_cursor, 1);
@@ -691,7 +1227,6 @@ fn document_moving() {
         }
     );
     assert_eq!(doc.old_cursor, 0);
-    assert_eq!(doc.info.loaded_to, doc.len_lines() + 1);
     doc.move_top();
     assert_eq!(doc.char_loc(), Loc { x: 0, y: 0 });
     assert_eq!(doc.old_cursor, 0);
@@ -704,7 +1239,6 @@ fn document_moving() {
         }
     );
     assert_eq!(doc.old_cursor, 0);
-    assert_eq!(doc.info.loaded_to, doc.len_lines() + 1);
     doc.select_top();
     assert_eq!(doc.char_loc(), Loc { x: 0, y: 0 });
     assert_eq!(doc.old_cursor, 0);
@@ -794,7 +1328,6 @@ fn document_moving() {
 #[test]
 fn document_selection() {
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
-    doc.load_to(10);
     assert!(doc.is_selection_empty());
     doc.select_to(&Loc { x: 1, y: 1 });
     assert!(!doc.is_selection_empty());
@@ -853,23 +1386,133 @@ fn document_selection() {
 #[test]
 fn document_scrolling() {
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
-    doc.load_to(10);
     // Scrolling down
     assert_eq!(doc.offset.y, 0);
     doc.scroll_down();
     assert_eq!(doc.offset.y, 1);
-    assert_eq!(doc.info.loaded_to, 11);
     // Scrolling up
     assert_eq!(doc.offset.y, 1);
     doc.scroll_up();
     assert_eq!(doc.offset.y, 0);
-    assert_eq!(doc.info.loaded_to, 11);
+}
+
+#[test]
+fn document_wrapping() {
+    // Lines are broken after whitespace, or mid-word when a word is too long for a row
+    assert_eq!(wrapping::row_starts("hello there world", 10, 4), vec![0, 6, 12]);
+    assert_eq!(wrapping::row_starts("abcdefghijklmno", 5, 4), vec![0, 5, 10, 15]);
+    assert_eq!(wrapping::row_starts("a\tb", 4, 4), vec![0, 1, 5]);
+    assert_eq!(wrapping::row_starts("你好你好", 3, 4), vec![0, 2, 4, 6]);
+    assert_eq!(wrapping::row_starts("", 10, 4), vec![0]);
+    // Rows in view, and moving between them
+    let mut doc = Document::new(Size::is(12, 3));
+    doc.exe(Event::Insert(Loc::at(0, 0), st!("hello there world\nshort\nlast line")));
+    doc.set_wrap(true);
+    doc.move_to(&Loc::at(2, 0));
+    assert_eq!(
+        doc.rows_in_view(),
+        vec![
+            VisualRow { y: 0, start: 0, end: 12, first: true },
+            VisualRow { y: 0, start: 12, end: 17, first: false },
+            VisualRow { y: 1, start: 0, end: 5, first: true },
+        ]
+    );
+    doc.old_cursor = 2;
+    assert_eq!(doc.move_down(), Status::None);
+    assert_eq!(doc.loc(), Loc::at(14, 0));
+    assert_eq!(doc.cursor_loc_in_screen(), Some(Loc::at(2, 1)));
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc::at(2, 1));
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc::at(2, 2));
+    // The view scrolls a line at a time to keep the cursor's row in view
+    assert_eq!(doc.offset.y, 1);
+    assert_eq!(doc.cursor_loc_in_screen(), Some(Loc::at(2, 1)));
+    doc.move_up();
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc::at(14, 0));
+    assert_eq!(doc.offset.y, 0);
+    assert_eq!(doc.move_up(), Status::None);
+    assert_eq!(doc.move_up(), Status::StartOfFile);
+    // Finding where positions on the screen are within the document
+    assert_eq!(doc.screen_to_loc(&Loc::at(3, 1)), Loc::at(15, 0));
+    assert_eq!(doc.screen_to_loc(&Loc::at(8, 1)), Loc::at(17, 0));
+    doc.set_wrap(false);
+    assert_eq!(doc.screen_to_loc(&Loc::at(3, 1)), Loc::at(3, 1));
+}
+
+#[test]
+fn document_edit_log() {
+    let mut doc = Document::new(Size::is(10, 3));
+    // Edits aren't kept until something asks to follow them
+    doc.exe(Event::Insert(Loc::at(0, 0), st!("hello"))).unwrap();
+    assert!(doc.take_edits().is_empty());
+    doc.follow_edits();
+    let revision = doc.revision;
+    doc.exe(Event::Insert(Loc::at(5, 0), st!(" world"))).unwrap();
+    doc.exe(Event::InsertLine(1, st!("there"))).unwrap();
+    doc.undo().unwrap();
+    let edits = doc.take_edits();
+    assert_eq!(doc.revision - revision, edits.len());
+    assert_eq!(edits[0], Event::Insert(Loc::at(5, 0), st!(" world")));
+    assert!(doc.take_edits().is_empty());
+}
+
+#[test]
+fn document_folding() {
+    let mut doc = Document::new(Size::is(10, 3));
+    doc.exe(Event::Insert(Loc::at(0, 0), st!("fn a() {\n    one\n    two\n}\nfn b() {\n    three\n}")))
+        .unwrap();
+    // Folding away lines moves the cursor out of them
+    doc.move_to(&Loc::at(4, 2));
+    doc.fold(1..3);
+    assert_eq!(doc.folds, vec![1..3]);
+    assert_eq!(doc.loc(), Loc::at(4, 0));
+    assert!(doc.is_hidden(2));
+    assert!(!doc.is_hidden(3));
+    assert_eq!(doc.fold_below(0), Some(1..3));
+    assert!(!doc.one_row_per_line());
+    // Folds within folds and folds of the first line are ignored
+    doc.fold(2..3);
+    doc.fold(0..1);
+    assert_eq!(doc.folds, vec![1..3]);
+    // Hidden lines are skipped over when moving and rendering
+    let shown: Vec<usize> = doc.rows_in_view().iter().map(|r| r.y).collect();
+    assert_eq!(shown, vec![0, 3, 4]);
+    assert_eq!(doc.screen_to_loc(&Loc::at(0, 1)), Loc::at(0, 3));
+    doc.old_cursor = 4;
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc::at(1, 3));
+    assert_eq!(doc.cursor_loc_in_screen(), Some(Loc::at(1, 1)));
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc::at(4, 0));
+    assert_eq!(doc.move_up(), Status::StartOfFile);
+    doc.move_to(&Loc::at(0, 6));
+    assert_eq!(doc.offset.y, 4);
+    assert_eq!(doc.cursor_loc_in_screen(), Some(Loc::at(0, 2)));
+    // Searches skip over hidden lines
+    doc.move_to(&Loc::at(0, 0));
+    let searcher = Searcher::new("t[wh]");
+    assert_eq!(doc.find_next(&searcher, 0).unwrap().loc, Loc::at(4, 5));
+    // Folds move along with edits above them and are opened up by edits inside them
+    doc.exe(Event::InsertLine(0, st!("// top"))).unwrap();
+    assert_eq!(doc.folds, vec![2..4]);
+    doc.exe(Event::Insert(Loc::at(0, 3), st!("x"))).unwrap();
+    assert!(doc.folds.is_empty());
+    assert!(doc.one_row_per_line());
+    // Unfolding
+    doc.fold(2..4);
+    doc.fold(6..7);
+    assert!(doc.unfold(3));
+    assert!(!doc.unfold(3));
+    assert_eq!(doc.folds, vec![6..7]);
+    doc.unfold_all();
+    assert!(doc.folds.is_empty());
 }
 
 #[test]
 fn document_utilities() {
     let mut doc = Document::open(Size::is(100, 2), "tests/data/big.txt").unwrap();
-    doc.load_to(1000);
     // File type
     assert_eq!(doc.get_file_type(), Some("txt"));
     // Cursor location
@@ -919,12 +1562,11 @@ fn document_utilities() {
 #[test]
 fn document_line_editing() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
     // Basics
     doc.exe(Event::InsertLine(2, st!("hello你bye好hello")));
     assert_eq!(doc.line(2), Some(st!("hello你bye好hello")));
     assert_eq!(doc.len_lines(), 6);
-    assert_eq!(doc.dbl_map.get(2), Some(&vec![(5, 5), (10, 9)]));
+    assert_eq!(doc.dbl_map(2), vec![(5, 5), (10, 9)]);
     doc.exe(Event::DeleteLine(4, st!("hello你world好hello")));
     assert_ne!(doc.line(4), Some(st!("hello你bye好hello")));
     assert_eq!(doc.len_lines(), 5);
@@ -940,7 +1582,6 @@ fn document_line_editing() {
     assert_eq!(doc.line(5), Some(st!("")));
     // Line swapping
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
     doc.swap_line_down().unwrap();
     assert_eq!(doc.line(0), Some(st!("\thello")));
     assert_eq!(doc.line(1), Some(st!("    你好")));
@@ -952,13 +1593,12 @@ fn document_line_editing() {
 #[test]
 fn document_splitting_splicing() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
     // Splitting
-    assert_eq!(doc.dbl_map.get(4), Some(&vec![(5, 5), (12, 11)]));
-    assert_eq!(doc.dbl_map.get(5), None);
+    assert_eq!(doc.dbl_map(4), vec![(5, 5), (12, 11)]);
+    assert_eq!(doc.dbl_map(5), vec![]);
     doc.exe(Event::SplitDown(Loc { x: 9, y: 4 }));
-    assert_eq!(doc.dbl_map.get(4), Some(&vec![(5, 5)]));
-    assert_eq!(doc.dbl_map.get(5), Some(&vec![(2, 2)]));
+    assert_eq!(doc.dbl_map(4), vec![(5, 5)]);
+    assert_eq!(doc.dbl_map(5), vec![(2, 2)]);
     assert_eq!(doc.line(4), Some(st!("hello你wor")));
     assert_eq!(doc.line(5), Some(st!("ld好hello")));
     assert_eq!(doc.len_lines(), 6);
@@ -966,54 +1606,59 @@ fn document_splitting_splicing() {
     doc.exe(Event::SpliceUp(Loc { x: 9, y: 4 }));
     assert_eq!(doc.line(4), Some(st!("hello你world好hello")));
     assert_eq!(doc.len_lines(), 5);
-    assert_eq!(doc.dbl_map.get(4), Some(&vec![(5, 5), (12, 11)]));
-    assert_eq!(doc.dbl_map.get(5), None);
+    assert_eq!(doc.dbl_map(4), vec![(5, 5), (12, 11)]);
+    assert_eq!(doc.dbl_map(5), vec![]);
 }
 
 #[test]
 fn document_searching() {
+    let find = |re: &str| Searcher::with_options(re, SearchOptions::default());
     let mut doc = Document::open(Size::is(100, 1), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1);
     assert_eq!(
-        doc.next_match("hello", 0),
+        doc.find_next(&find("hello"), 0),
         Some(Match {
             loc: Loc { x: 1, y: 1 },
             text: st!("hello")
         })
     );
     assert_eq!(
-        doc.next_match("world", 0),
+        doc.find_next(&find("world"), 0),
         Some(Match {
             loc: Loc { x: 6, y: 4 },
             text: st!("world")
         })
     );
-    assert_eq!(doc.info.loaded_to, 5);
     doc.move_to(&Loc { x: 2, y: 2 });
     assert_eq!(
-        doc.next_match("hello", 0),
+        doc.find_next(&find("hello"), 0),
         Some(Match {
             loc: Loc { x: 4, y: 2 },
             text: st!("hello")
         })
     );
-    assert_eq!(doc.next_match("random", 0), None);
+    assert_eq!(doc.find_next(&find("random"), 0), None);
     doc.move_to(&Loc { x: 9, y: 4 });
     assert_eq!(
-        doc.prev_match("你"),
+        doc.find_prev(&find("你")),
         Some(Match {
             loc: Loc { x: 5, y: 4 },
             text: st!("你")
         })
     );
-    assert_eq!(doc.prev_match("random"), None);
+    assert_eq!(doc.find_prev(&find("random")), None);
     assert_eq!(
-        doc.prev_match("\\s+hello"),
+        doc.find_prev(&find(" +hello")),
         Some(Match {
             loc: Loc { x: 0, y: 2 },
             text: st!("    hello")
         })
     );
+    // The text is only read out of the rope again once the document has changed
+    let text = doc.search_text();
+    assert!(std::ptr::eq(text.as_str(), doc.search_text().as_str()));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("random")));
+    assert!(doc.search_text().as_str().starts_with("random"));
+    assert!(doc.find_prev(&find("random")).is_some());
     // General searching stuff
     let mut searcher = Searcher::new("[0-9]+");
     assert_eq!(
@@ -1034,8 +1679,8 @@ fn document_searching() {
 #[test]
 fn document_replacing() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
-    doc.replace_all("hello", "你好");
+    let searcher = Searcher::new("hello");
+    assert_eq!(doc.replace_all_matches(&searcher, "你好").unwrap(), 4);
     assert_eq!(doc.line(0), Some(st!("    你好")));
     assert_eq!(doc.line(1), Some(st!("\t你好")));
     assert_eq!(doc.line(2), Some(st!("    你好")));
@@ -1043,10 +1688,56 @@ fn document_replacing() {
     assert_eq!(doc.line(4), Some(st!("你好你world好你好")));
 }
 
+#[test]
+fn document_multiline_replacing() {
+    let mut doc = Document::new(Size::is(100, 10));
+    let text = st!("let a = foo(\n    1);\nlet b = Foo(2);\nfood");
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, text));
+    doc.commit();
+    doc.move_to(&Loc { x: 0, y: 0 });
+    let original = doc.file.to_string();
+    // Matches can span several lines
+    let call = Searcher::with_options(r"(?<name>\w+)\(\n\s*(\d)\)", SearchOptions::default());
+    let mtch = doc.find_next(&call, 0).unwrap();
+    assert_eq!(mtch.loc, Loc { x: 8, y: 0 });
+    assert_eq!(mtch.end(), Loc { x: 6, y: 1 });
+    assert_eq!(doc.find_prev(&call), None);
+    // Case insensitive and whole word searching
+    let mut options = SearchOptions::default();
+    assert_eq!(doc.find_all(&Searcher::with_options("foo", options)).len(), 2);
+    options.case_insensitive = true;
+    assert_eq!(doc.find_all(&Searcher::with_options("foo", options)).len(), 3);
+    options.whole_word = true;
+    let words = doc.find_all(&Searcher::with_options("foo", options));
+    assert_eq!(words.len(), 2);
+    assert_eq!(words[1].loc, Loc { x: 8, y: 2 });
+    // Literal searching treats regular expression syntax as plain text
+    let options = SearchOptions { literal: true, ..SearchOptions::default() };
+    assert_eq!(doc.find_all(&Searcher::with_options("foo(", options)).len(), 1);
+    assert!(Searcher::try_with_options("foo(", SearchOptions::default()).is_err());
+    assert!(doc.find_all(&Searcher::with_options("foo(", SearchOptions::default())).is_empty());
+    // Replacing a single match fills in capture groups
+    doc.replace_match(&call, &mtch, "${name}($2)").unwrap();
+    assert_eq!(doc.line(0), Some(st!("let a = foo(1);")));
+    assert_eq!(doc.len_lines(), 3);
+    // Replacing everything can split lines, and is undone in one go
+    let arg = Searcher::with_options(r"\((\d)\)", SearchOptions::default());
+    assert_eq!(doc.replace_all_matches(&arg, "(\n    $1\n)").unwrap(), 2);
+    assert_eq!(doc.len_lines(), 7);
+    assert_eq!(doc.line(4), Some(st!("    2")));
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.line(0), Some(st!("let a = foo(1);")));
+    assert_eq!(doc.line(1), Some(st!("let b = Foo(2);")));
+    assert!(doc.undo().is_ok());
+    assert_eq!(doc.file.to_string(), original);
+    // Read only documents can't be replaced in
+    doc.info.read_only = true;
+    assert!(doc.replace_all_matches(&arg, "").is_err());
+}
+
 #[test]
 fn document_validation() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
     doc.move_to(&Loc { x: 2, y: 1 });
     doc.old_cursor = 5;
     doc.move_up();
@@ -1054,10 +1745,53 @@ fn document_validation() {
     assert_eq!(doc.loc(), Loc { x: 4, y: 0 });
 }
 
+#[test]
+fn document_line_cache() {
+    let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
+    // Lines far outside the viewport can be read and edited straight away
+    let last = doc.line(99).unwrap();
+    doc.exe(Event::Insert(Loc { x: 0, y: 99 }, st!("\t你")));
+    assert_eq!(doc.line(99), Some(format!("\t你{last}")));
+    assert_eq!(doc.tab_map(99), vec![(0, 0)]);
+    assert_eq!(doc.dbl_map(99), vec![(4, 1)]);
+    assert_eq!(doc.character_idx(&Loc { x: 6, y: 99 }), 2);
+    // Reading more lines than the cache holds doesn't lose track of any of them
+    let text = doc.file.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    for _ in 0..20 {
+        for (y, line) in lines.iter().enumerate() {
+            assert_eq!(doc.line(y).as_deref(), Some(*line));
+        }
+    }
+    // Changes made to the rope directly are picked up once the revision is bumped
+    doc.file.insert(0, "new ");
+    doc.revision += 1;
+    assert_eq!(doc.line(0), Some(format!("new {}", lines[0])));
+    // Edits only forget the lines they touch, so lines outside of them are still cached
+    // (a change to the rope that the cache wasn't told about shows which lines are)
+    assert_eq!(doc.line(5).as_deref(), Some(lines[5]));
+    assert_eq!(doc.line(9).as_deref(), Some(lines[9]));
+    let idx = doc.file.line_to_char(9);
+    doc.file.insert(idx, "unseen ");
+    doc.exe(Event::Insert(Loc { x: 0, y: 5 }, st!("seen ")));
+    assert_eq!(doc.line(5), Some(format!("seen {}", lines[5])));
+    assert_eq!(doc.line(9).as_deref(), Some(lines[9]));
+    // Lines below an edit that adds or removes lines are moved along with it
+    doc.exe(Event::InsertLine(2, st!("inserted")));
+    assert_eq!(doc.line(2), Some(st!("inserted")));
+    assert_eq!(doc.line(10).as_deref(), Some(lines[9]));
+    doc.exe(Event::DeleteLine(3, st!("")));
+    assert_eq!(doc.line(9).as_deref(), Some(lines[9]));
+    doc.revision += 1;
+    assert_eq!(doc.line(9), Some(format!("unseen {}", lines[9])));
+    // Character maps follow the tab width
+    doc.set_tab_width(2);
+    assert_eq!(doc.dbl_map(99), vec![(2, 1)]);
+}
+
 #[test]
 fn document_indices() {
-    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
-    doc.load_to(1000);
+    let doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
     assert_eq!(doc.character_idx(&Loc { x: 6, y: 0 }), 5);
     assert_eq!(doc.character_idx(&Loc { x: 5, y: 1 }), 2);
 }
@@ -1087,7 +1821,7 @@ fn fuzz() {
         println!("--");
         let size = Size { w: 10, h: 8 };
         let mut doc = Document::open(size, "tests/data/unicode.txt").unwrap();
-        doc.load_to(100);
+        let original = doc.file.to_string();
         println!("{} | {}", doc.loc().x, doc.char_ptr);
         for _ in 0..300 {
             let e = rand::random::<u8>() % 25;
@@ -1159,7 +1893,7 @@ fn fuzz() {
                     Ok(())
                 }
                 20 => {
-                    doc.replace_all("a", "c");
+                    drop(doc.replace_all_matches(&Searcher::new("a"), "c"));
                     Ok(())
                 }
                 21 => {
@@ -1175,8 +1909,21 @@ fn fuzz() {
                 _ => Ok(()),
             };
             println!("{} | {}", doc.loc().x, doc.char_ptr);
-            doc.load_to(doc.len_lines() + 10);
         }
+        // Undoing everything should always lead back to the original document
+        while doc.event_mgmt.ptr > 0 || !doc.event_mgmt.patch.is_empty() {
+            doc.undo().unwrap();
+        }
+        assert_eq!(doc.file.to_string(), original);
+        // Lines read through the cache should match what is in the rope
+        let read = |doc: &Document| -> Vec<_> {
+            (0..=doc.len_lines())
+                .map(|y| (doc.line(y), doc.dbl_map(y), doc.tab_map(y)))
+                .collect()
+        };
+        let cached = read(&doc);
+        doc.revision += 1;
+        assert_eq!(read(&doc), cached);
     }
 }
 
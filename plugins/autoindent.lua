@@ -7,6 +7,11 @@ and then automatically applying these guesses as you program
 
 autoindent = {}
 
+-- Work out how many spaces make up a level of indentation in the current document
+function autoindent:indent_size()
+    return editor.indent_size or document.tab_width
+end
+
 -- Determine if a line starts with a certain string
 function autoindent:starts(y, starting)
     local line = editor:get_line_at(y)
@@ -91,8 +96,8 @@ function autoindent:set_indent(y, new_indent)
             new_line = string.rep("\t", indent_change) .. line
         else
             -- Insert Spaces
-            x = x + indent_change * document.tab_width
-            new_line = string.rep(" ", indent_change * document.tab_width) .. line
+            x = x + indent_change * autoindent:indent_size()
+            new_line = string.rep(" ", indent_change * autoindent:indent_size()) .. line
         end
     elseif indent_change < 0 then
         -- Remove indentation
@@ -102,8 +107,8 @@ function autoindent:set_indent(y, new_indent)
             new_line = line:gsub("\t", "", -indent_change)
         else
             -- Remove Spaces
-            x = x - -indent_change * document.tab_width
-            new_line = line:gsub(string.rep(" ", document.tab_width), "", -indent_change)
+            x = x - -indent_change * autoindent:indent_size()
+            new_line = line:gsub(string.rep(" ", autoindent:indent_size()), "", -indent_change)
         end
     else
         return
@@ -121,7 +126,7 @@ end
 function autoindent:get_indent(y)
     if y == nil then return nil end
     local line = editor:get_line_at(y)
-    return #(line:match("^\t+") or "") + #(line:match("^ +") or "") / document.tab_width
+    return #(line:match("^\t+") or "") + #(line:match("^ +") or "") / autoindent:indent_size()
 end
 
 -- Utilties for when moving lines around
@@ -206,7 +211,7 @@ function dedent_amount(y)
     if tabs then
         return 1
     else
-        return document.tab_width
+        return autoindent:indent_size()
     end
 end
 
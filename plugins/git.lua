@@ -142,6 +142,7 @@ event_mapping["ctrl_s"] = function()
 end
 
 -- Export the git command
+palette:describe("git", "Use git", "<commit|push|pull|add|reset|stat|diff|checkout> [all|file]")
 commands["git"] = function(args)
     -- Check if git is installed
     if not git:ready() then
@@ -51,6 +51,7 @@ function live_html_refresh()
     end
 end
 
+palette:describe("html", "Preview HTML live in a browser", "<start|stop|track <file>>")
 commands["html"] = function(args)
     -- Check dependencies
     if live_html:ready() then
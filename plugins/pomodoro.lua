@@ -56,6 +56,7 @@ function pomodoro_show()
 end
 
 -- Add the pomodoro command to interface with the user
+palette:describe("pomodoro", "Start or stop the pomodoro timer", "<start|stop>")
 commands["pomodoro"] = function(arguments)
     subcmd = arguments[1]
     if subcmd == "start" then
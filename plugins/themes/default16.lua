@@ -53,6 +53,17 @@ colors.file_tree_fg = white
 colors.file_tree_selection_bg = darkgrey
 colors.file_tree_selection_fg = cyan
 
+colors.completion_fg = white
+colors.completion_bg = darkblue
+colors.completion_selection_fg = cyan
+colors.completion_selection_bg = darkgrey
+
+colors.search_match_fg = black
+colors.search_match_bg = darkyellow
+
+colors.bracket_match_fg = white
+colors.bracket_match_bg = darkgrey
+
 colors.file_tree_red = red
 colors.file_tree_orange = darkyellow
 colors.file_tree_yellow = yellow
@@ -49,6 +49,17 @@ colors.file_tree_fg = white
 colors.file_tree_selection_bg = purple
 colors.file_tree_selection_fg = black
 
+colors.completion_fg = white
+colors.completion_bg = grey1
+colors.completion_selection_fg = black
+colors.completion_selection_bg = purple
+
+colors.search_match_fg = black
+colors.search_match_bg = yellow
+
+colors.bracket_match_fg = white
+colors.bracket_match_bg = grey2
+
 colors.file_tree_red = {247, 156, 156}
 colors.file_tree_orange = {247, 165, 156}
 colors.file_tree_yellow = {247, 226, 156}
@@ -49,6 +49,17 @@ colors.file_tree_fg = foreground
 colors.file_tree_selection_bg = pink
 colors.file_tree_selection_fg = background
 
+colors.completion_fg = foreground
+colors.completion_bg = background3
+colors.completion_selection_fg = background
+colors.completion_selection_bg = pink
+
+colors.search_match_fg = background
+colors.search_match_bg = yellow
+
+colors.bracket_match_fg = foreground
+colors.bracket_match_bg = selection
+
 colors.file_tree_red = {255, 128, 128}
 colors.file_tree_orange = {255, 155, 128}
 colors.file_tree_yellow = {255, 204, 128}
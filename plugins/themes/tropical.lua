@@ -49,6 +49,17 @@ colors.file_tree_fg = white
 colors.file_tree_selection_bg = lightblue
 colors.file_tree_selection_fg = black
 
+colors.completion_fg = white
+colors.completion_bg = grey1
+colors.completion_selection_fg = black
+colors.completion_selection_bg = lightblue
+
+colors.search_match_fg = black
+colors.search_match_bg = yellow
+
+colors.bracket_match_fg = white
+colors.bracket_match_bg = grey2
+
 colors.file_tree_red = {245, 127, 127}
 colors.file_tree_orange = {245, 169, 127}
 colors.file_tree_yellow = {245, 217, 127}
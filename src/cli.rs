@@ -18,7 +18,9 @@ OPTIONS:
   --config [path], -c [path]   : Specify the configuration file
   --readonly, -r               : Prevent opened files from writing
   --filetype [name], -f [name] : Set the file type of files opened
+  --encoding [name], -e [name] : Set the encoding of files opened (e.g. utf-8, latin1)
   --stdin                      : Reads file from the stdin
+  --session [name], -s [name]  : Restore a session, saving it again when ox is closed
   --config-assist              : Activate the configuration assistant
   --debug, -d                  : Enable debug output for errors
 
@@ -29,6 +31,8 @@ EXAMPLES:
   ox /home/user/docs/test.txt
   ox -c config.lua test.txt
   ox -r -c ~/.config/.oxrc -f Lua my_file.lua
+  ox -e utf-16le notes.txt
+  ox --session work
   tree | ox -r --stdin
   ox --config-assist
   ox --debug test.txt         # Enable debug output\
@@ -56,6 +60,8 @@ pub struct CommandLineInterfaceFlags {
 pub struct CommandLineInterface {
     pub flags: CommandLineInterfaceFlags,
     pub file_type: Option<String>,
+    pub encoding: Option<String>,
+    pub session: Option<String>,
     pub config_path: String,
     pub to_open: Vec<String>,
 }
@@ -68,7 +74,9 @@ impl CommandLineInterface {
 
         // Define keys
         let filetype: Key = ["-f", "--filetype"].into();
+        let encoding: Key = ["-e", "--encoding"].into();
         let config: Key = ["-c", "--config"].into();
+        let session: Key = ["-s", "--session"].into();
 
         Self {
             flags: CommandLineInterfaceFlags {
@@ -80,6 +88,8 @@ impl CommandLineInterface {
                 debug: j.contains(["-d", "--debug"]),
             },
             file_type: j.option_arg::<String, Key>(filetype.clone()),
+            encoding: j.option_arg::<String, Key>(encoding.clone()),
+            session: j.option_arg::<String, Key>(session.clone()),
             config_path: j
                 .option_arg::<String, Key>(config.clone())
                 .unwrap_or_else(|| "~/.oxrc".to_string()),
@@ -97,14 +97,14 @@ mod windows_clipboard {
         
         unsafe {
             if OpenClipboard(ptr::null()) == 0 {
-                return Err(Error::new(ErrorKind::Other, "Failed to open clipboard"));
+                return Err(Error::other("Failed to open clipboard"));
             }
             
             // Ensure clipboard is closed on all error paths
             let _guard = ClipboardGuard;
             
             if EmptyClipboard() == 0 {
-                return Err(Error::new(ErrorKind::Other, "Failed to empty clipboard"));
+                return Err(Error::other("Failed to empty clipboard"));
             }
             
             let wide: Vec<u16> = OsStr::new(text)
@@ -122,12 +122,12 @@ mod windows_clipboard {
             let handle = GlobalAlloc(GMEM_MOVEABLE, size);
             
             if handle.is_null() {
-                return Err(Error::new(ErrorKind::Other, "Failed to allocate memory"));
+                return Err(Error::other("Failed to allocate memory"));
             }
             
             let locked = GlobalLock(handle);
             if locked.is_null() {
-                return Err(Error::new(ErrorKind::Other, "Failed to lock memory"));
+                return Err(Error::other("Failed to lock memory"));
             }
             
             // Use unlock guard to ensure memory is unlocked even if copy fails
@@ -136,7 +136,7 @@ mod windows_clipboard {
                 
                 // Verify that we have valid pointers before copying
                 if wide.as_ptr().is_null() || locked.is_null() {
-                    return Err(Error::new(ErrorKind::Other, "Invalid memory pointers"));
+                    return Err(Error::other("Invalid memory pointers"));
                 }
                 
                 std::ptr::copy_nonoverlapping(
@@ -147,17 +147,18 @@ mod windows_clipboard {
             }
             
             if SetClipboardData(CF_UNICODETEXT, handle).is_null() {
-                return Err(Error::new(ErrorKind::Other, "Failed to set clipboard data"));
+                return Err(Error::other("Failed to set clipboard data"));
             }
             
             Ok(())
         }
     }
     
+    #[allow(dead_code)]
     pub fn get_clipboard_text() -> Result<String> {
         unsafe {
             if OpenClipboard(ptr::null()) == 0 {
-                return Err(Error::new(ErrorKind::Other, "Failed to open clipboard"));
+                return Err(Error::other("Failed to open clipboard"));
             }
             
             // Ensure clipboard is closed on all error paths
@@ -165,12 +166,12 @@ mod windows_clipboard {
             
             let handle = GetClipboardData(CF_UNICODETEXT);
             if handle.is_null() {
-                return Err(Error::new(ErrorKind::Other, "No text data in clipboard"));
+                return Err(Error::other("No text data in clipboard"));
             }
             
             let locked = GlobalLock(handle as *mut u8);
             if locked.is_null() {
-                return Err(Error::new(ErrorKind::Other, "Failed to lock clipboard data"));
+                return Err(Error::other("Failed to lock clipboard data"));
             }
             
             // Create unlock guard to ensure memory is unlocked on all paths
@@ -203,17 +204,18 @@ mod macos_clipboard {
         
         match output {
             Ok(status) if status.success() => Ok(()),
-            _ => Err(Error::new(ErrorKind::Other, "Failed to copy to clipboard"))
+            _ => Err(Error::other("Failed to copy to clipboard"))
         }
     }
     
+    #[allow(dead_code)]
     pub fn get_clipboard_text() -> Result<String> {
         let output = Command::new("pbpaste").output()?;
         
         if output.status.success() {
             Ok(String::from_utf8_lossy(&output.stdout).to_string())
         } else {
-            Err(Error::new(ErrorKind::Other, "Failed to paste from clipboard"))
+            Err(Error::other("Failed to paste from clipboard"))
         }
     }
 }
@@ -240,18 +242,13 @@ mod linux_clipboard {
         Xsel,         // xsel for X11
     }
     
-    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Default)]
     pub enum Selection {
+        #[default]
         Clipboard,
         Primary,
     }
     
-    impl Default for Selection {
-        fn default() -> Self {
-            Selection::Clipboard
-        }
-    }
-    
     // Cache for detected clipboard tool
     static CLIPBOARD_TOOL: OnceLock<Option<ClipboardTool>> = OnceLock::new();
     static SESSION_TYPE: OnceLock<SessionType> = OnceLock::new();
@@ -287,8 +284,7 @@ mod linux_clipboard {
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
-            .map(|status| status.success())
-            .unwrap_or(false)
+            .is_ok_and(|status| status.success())
     }
     
     pub(super) fn detect_clipboard_tool() -> Option<ClipboardTool> {
@@ -341,13 +337,13 @@ mod linux_clipboard {
             .stderr(Stdio::piped());
         
         let mut child = cmd.spawn()
-            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to spawn process: {}", e)))?;
+            .map_err(|e| Error::other(format!("Failed to spawn process: {e}")))?;
         
         // Write input if provided
         if let Some(data) = input {
             if let Some(stdin) = child.stdin.as_mut() {
                 stdin.write_all(data)
-                    .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to write to stdin: {}", e)))?;
+                    .map_err(|e| Error::other(format!("Failed to write to stdin: {e}")))?;
             }
         }
         
@@ -366,11 +362,10 @@ mod linux_clipboard {
                 Ok(Some(status)) => {
                     if status.success() {
                         let output = child.wait_with_output()
-                            .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to get output: {}", e)))?;
+                            .map_err(|e| Error::other(format!("Failed to get output: {e}")))?;
                         return Ok(output.stdout);
-                    } else {
-                        return Err(Error::new(ErrorKind::Other, "Command failed"));
                     }
+                    return Err(Error::other("Command failed"));
                 }
                 Ok(None) => {
                     // Still running, check for timeout
@@ -382,7 +377,7 @@ mod linux_clipboard {
                     thread::sleep(Duration::from_millis(10));
                 }
                 Err(e) => {
-                    return Err(Error::new(ErrorKind::Other, format!("Failed to wait for process: {}", e)));
+                    return Err(Error::other(format!("Failed to wait for process: {e}")));
                 }
             }
         }
@@ -467,10 +462,12 @@ mod linux_clipboard {
     }
     
     // Public API maintaining backward compatibility
+    #[allow(dead_code)]
     pub fn set_clipboard_text(text: &str) -> Result<()> {
         set_clipboard_text_with_selection(text, Selection::default())
     }
     
+    #[allow(dead_code)]
     pub fn get_clipboard_text() -> Result<String> {
         get_clipboard_text_with_selection(Selection::default())
     }
@@ -503,26 +500,26 @@ impl fmt::Display for ClipboardError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ClipboardError::NativeClipboardFailed(msg) => {
-                write!(f, "Native clipboard operation failed: {}", msg)
+                write!(f, "Native clipboard operation failed: {msg}")
             }
             ClipboardError::ToolNotFound(msg) => {
-                write!(f, "Clipboard tool not found: {}", msg)
+                write!(f, "Clipboard tool not found: {msg}")
             }
             ClipboardError::Timeout => write!(f, "Clipboard operation timed out"),
             ClipboardError::Locked => write!(f, "Clipboard is locked by another process"),
             ClipboardError::TextTooLarge(size) => {
-                write!(f, "Text too large for clipboard: {} bytes", size)
+                write!(f, "Text too large for clipboard: {size} bytes")
             }
             ClipboardError::InvalidFormat(msg) => {
-                write!(f, "Invalid clipboard data format: {}", msg)
+                write!(f, "Invalid clipboard data format: {msg}")
             }
             ClipboardError::PlatformError(msg) => {
-                write!(f, "Platform-specific clipboard error: {}", msg)
+                write!(f, "Platform-specific clipboard error: {msg}")
             }
             ClipboardError::OSC52Failed(msg) => {
-                write!(f, "OSC52 clipboard operation failed: {}", msg)
+                write!(f, "OSC52 clipboard operation failed: {msg}")
             }
-            ClipboardError::IoError(err) => write!(f, "IO error: {}", err),
+            ClipboardError::IoError(err) => write!(f, "IO error: {err}"),
         }
     }
 }
@@ -542,7 +539,7 @@ impl From<ClipboardError> for IoError {
             ClipboardError::Timeout => IoError::new(ErrorKind::TimedOut, error.to_string()),
             ClipboardError::Locked => IoError::new(ErrorKind::WouldBlock, error.to_string()),
             ClipboardError::TextTooLarge(_) => IoError::new(ErrorKind::InvalidInput, error.to_string()),
-            _ => IoError::new(ErrorKind::Other, error.to_string()),
+            _ => IoError::other(error.to_string()),
         }
     }
 }
@@ -649,7 +646,7 @@ impl Clipboard {
             };
             
             match result {
-                Ok(_) => {
+                Ok(()) => {
                     self.current_method = ClipboardMethod::Native;
                     self.last_error = None;
                     if self.verbose_logging {
@@ -666,14 +663,14 @@ impl Clipboard {
                     }
                     
                     // Don't retry for certain errors
-                    if let Some(err_str) = last_native_error.as_ref().map(|e| e.to_string()) {
+                    if let Some(err_str) = last_native_error.as_ref().map(std::string::ToString::to_string) {
                         if err_str.contains("too large") || err_str.contains("No clipboard tool found") {
                             break;
                         }
                     }
                     
                     if attempts < self.max_retries {
-                        std::thread::sleep(std::time::Duration::from_millis(50 * attempts as u64));
+                        std::thread::sleep(std::time::Duration::from_millis(50 * u64::from(attempts)));
                     }
                 }
             }
@@ -685,7 +682,7 @@ impl Clipboard {
             warn!("Native clipboard failed after {} attempts: {}. Falling back to OSC 52.", attempts, native_err);
             
             match self.set_text_osc52(text) {
-                Ok(_) => {
+                Ok(()) => {
                     self.current_method = ClipboardMethod::OSC52;
                     self.last_error = Some(ClipboardError::NativeClipboardFailed(native_err.to_string()));
                     Ok(())
@@ -738,7 +735,7 @@ impl Clipboard {
                     }
                     
                     if attempts < self.max_retries {
-                        std::thread::sleep(std::time::Duration::from_millis(50 * attempts as u64));
+                        std::thread::sleep(std::time::Duration::from_millis(50 * u64::from(attempts)));
                     }
                 }
             }
@@ -759,6 +756,7 @@ impl Clipboard {
     }
     
     /// Copy using OSC 52 escape sequence (terminal clipboard)
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
     pub fn set_text_osc52(&self, text: &str) -> Result<()> {
         use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64_STANDARD};
         // Use both OSC 52 formats for better compatibility
@@ -781,12 +779,13 @@ impl Clipboard {
             method: self.current_method,
             native_available: self.check_native_available(),
             osc52_enabled: self.use_osc52,
-            last_error: self.last_error.as_ref().map(|e| e.to_string()),
+            last_error: self.last_error.as_ref().map(std::string::ToString::to_string),
             platform_info,
         }
     }
     
     /// Check if native clipboard is available
+    #[allow(clippy::unused_self)]
     fn check_native_available(&self) -> bool {
         #[cfg(all(not(target_os = "windows"), not(target_os = "macos")))]
         {
@@ -837,7 +836,7 @@ impl Default for Clipboard {
 }
 
 // Re-export Selection for Linux users
-#[cfg(all(not(target_os = "windows"), not(target_os = "macos")))]
+#[allow(unused_imports)]
 pub use self::linux_clipboard::Selection;
 
 #[cfg(test)]
@@ -851,7 +850,7 @@ mod tests {
         // Test setting and getting text
         let test_text = "Hello, World!";
         match clipboard.set_text(test_text) {
-            Ok(_) => {
+            Ok(()) => {
                 // Only test get if set succeeded (may fail in CI environments)
                 if let Ok(retrieved) = clipboard.get_text() {
                     assert_eq!(retrieved, test_text);
@@ -880,7 +879,7 @@ mod tests {
         // Test with moderately large text (1MB)
         let large_text = "a".repeat(1024 * 1024);
         match clipboard.set_text(&large_text) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(clipboard.last_copied(), large_text);
             }
             Err(_) => {
@@ -905,7 +904,7 @@ mod tests {
         
         for test_text in unicode_tests {
             match clipboard.set_text(test_text) {
-                Ok(_) => {
+                Ok(()) => {
                     assert_eq!(clipboard.last_copied(), test_text);
                     if let Ok(retrieved) = clipboard.get_text() {
                         assert_eq!(retrieved, test_text);
@@ -924,7 +923,7 @@ mod tests {
         
         let multiline = "Line 1\nLine 2\rLine 3\r\nLine 4";
         match clipboard.set_text(multiline) {
-            Ok(_) => {
+            Ok(()) => {
                 assert_eq!(clipboard.last_copied(), multiline);
             }
             Err(_) => {
@@ -935,7 +934,7 @@ mod tests {
     
     #[test]
     fn test_osc52_fallback() {
-        let mut clipboard = Clipboard::new().with_osc52_fallback();
+        let clipboard = Clipboard::new().with_osc52_fallback();
         
         // OSC52 should always succeed as it just prints escape sequences
         let result = clipboard.set_text_osc52("Test OSC52");
@@ -272,9 +272,7 @@ impl Assistant {
             if !because_no_config {
                 let yellow = Fg(Color::Ansi(220).to_color()?);
                 let reset = Fg(Color::Transparent.to_color()?);
-                let backup_path = dirs::config_dir()
-                    .map(|mut p| { p.push(".oxrc-backup"); p })
-                    .unwrap_or_else(|| std::path::PathBuf::from("~/.oxrc-backup"));
+                let backup_path = dirs::config_dir().map_or_else(|| std::path::PathBuf::from("~/.oxrc-backup"), |mut p| { p.push(".oxrc-backup"); p });
                 println!("{yellow}WARNING{reset}: config file already exists, it will be backed-up to {} if you write", backup_path.display());
             }
             let contents = result.to_config();
@@ -42,6 +42,17 @@ pub struct Colors {
     pub file_tree_selection_fg: Color,
     pub file_tree_selection_bg: Color,
 
+    pub completion_fg: Color,
+    pub completion_bg: Color,
+    pub completion_selection_fg: Color,
+    pub completion_selection_bg: Color,
+
+    pub search_match_fg: Color,
+    pub search_match_bg: Color,
+
+    pub bracket_match_fg: Color,
+    pub bracket_match_bg: Color,
+
     pub file_tree_red: Color,
     pub file_tree_orange: Color,
     pub file_tree_yellow: Color,
@@ -91,6 +102,17 @@ impl Default for Colors {
             file_tree_selection_bg: Color::Rgb(59, 59, 130),
             file_tree_selection_fg: Color::Rgb(255, 255, 255),
 
+            completion_fg: Color::Rgb(255, 255, 255),
+            completion_bg: Color::Rgb(59, 59, 84),
+            completion_selection_fg: Color::Rgb(255, 255, 255),
+            completion_selection_bg: Color::Rgb(59, 59, 130),
+
+            search_match_fg: Color::Rgb(255, 255, 255),
+            search_match_bg: Color::Rgb(105, 80, 40),
+
+            bracket_match_fg: Color::Rgb(255, 255, 255),
+            bracket_match_bg: Color::Rgb(80, 80, 110),
+
             file_tree_red: Color::Rgb(240, 56, 36),
             file_tree_orange: Color::Rgb(240, 107, 36),
             file_tree_yellow: Color::Rgb(240, 236, 36),
@@ -145,6 +167,30 @@ impl LuaUserData for Colors {
         fields.add_field_method_get("selection_bg", |env, this| {
             Ok(this.selection_bg.to_lua(env))
         });
+        fields.add_field_method_get("completion_fg", |env, this| {
+            Ok(this.completion_fg.to_lua(env))
+        });
+        fields.add_field_method_get("completion_bg", |env, this| {
+            Ok(this.completion_bg.to_lua(env))
+        });
+        fields.add_field_method_get("completion_selection_fg", |env, this| {
+            Ok(this.completion_selection_fg.to_lua(env))
+        });
+        fields.add_field_method_get("completion_selection_bg", |env, this| {
+            Ok(this.completion_selection_bg.to_lua(env))
+        });
+        fields.add_field_method_get("search_match_fg", |env, this| {
+            Ok(this.search_match_fg.to_lua(env))
+        });
+        fields.add_field_method_get("search_match_bg", |env, this| {
+            Ok(this.search_match_bg.to_lua(env))
+        });
+        fields.add_field_method_get("bracket_match_fg", |env, this| {
+            Ok(this.bracket_match_fg.to_lua(env))
+        });
+        fields.add_field_method_get("bracket_match_bg", |env, this| {
+            Ok(this.bracket_match_bg.to_lua(env))
+        });
         fields.add_field_method_set("editor_bg", |_, this, value| {
             this.editor_bg = Color::from_lua(value);
             Ok(())
@@ -245,6 +291,38 @@ impl LuaUserData for Colors {
             this.file_tree_selection_fg = Color::from_lua(value);
             Ok(())
         });
+        fields.add_field_method_set("completion_fg", |_, this, value| {
+            this.completion_fg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("completion_bg", |_, this, value| {
+            this.completion_bg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("completion_selection_fg", |_, this, value| {
+            this.completion_selection_fg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("completion_selection_bg", |_, this, value| {
+            this.completion_selection_bg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("search_match_fg", |_, this, value| {
+            this.search_match_fg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("search_match_bg", |_, this, value| {
+            this.search_match_bg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("bracket_match_fg", |_, this, value| {
+            this.bracket_match_fg = Color::from_lua(value);
+            Ok(())
+        });
+        fields.add_field_method_set("bracket_match_bg", |_, this, value| {
+            this.bracket_match_bg = Color::from_lua(value);
+            Ok(())
+        });
         fields.add_field_method_set("file_tree_red", |_, this, value| {
             this.file_tree_red = Color::from_lua(value);
             Ok(())
@@ -0,0 +1,94 @@
+/// For configuring the completion popup and the sources of its suggestions
+use mlua::prelude::*;
+use std::collections::HashMap;
+
+/// The file type name that snippets available in every file type are kept under
+pub const ANY_FILE_TYPE: &str = "*";
+
+#[derive(Debug)]
+pub struct Completion {
+    /// Whether the popup opens by itself while typing
+    pub auto: bool,
+    /// How much of a word needs typing before the popup opens by itself
+    pub min_length: usize,
+    /// The most suggestions shown at once
+    pub max_items: usize,
+    /// Whether words from the document are suggested
+    pub buffer_words: bool,
+    /// Whether file paths are suggested
+    pub file_paths: bool,
+    /// Snippets (as their trigger and body) by the name of the file type they're for
+    pub snippets: HashMap<String, Vec<(String, String)>>,
+    /// Functions plug-ins have registered to give suggestions
+    pub providers: Vec<LuaFunction>,
+}
+
+impl Default for Completion {
+    fn default() -> Self {
+        Self {
+            auto: true,
+            min_length: 2,
+            max_items: 8,
+            buffer_words: true,
+            file_paths: true,
+            snippets: HashMap::new(),
+            providers: vec![],
+        }
+    }
+}
+
+impl Completion {
+    /// Find the snippets available in a file type
+    pub fn snippets_for(&self, file_type: Option<&str>) -> Vec<(String, String)> {
+        let mut result = self.snippets.get(ANY_FILE_TYPE).cloned().unwrap_or_default();
+        if let Some(snippets) = file_type.and_then(|t| self.snippets.get(t)) {
+            result.extend(snippets.iter().cloned());
+        }
+        result
+    }
+}
+
+impl LuaUserData for Completion {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("auto", |_, this| Ok(this.auto));
+        fields.add_field_method_set("auto", |_, this, value| {
+            this.auto = value;
+            Ok(())
+        });
+        fields.add_field_method_get("min_length", |_, this| Ok(this.min_length));
+        fields.add_field_method_set("min_length", |_, this, value| {
+            this.min_length = value;
+            Ok(())
+        });
+        fields.add_field_method_get("max_items", |_, this| Ok(this.max_items));
+        fields.add_field_method_set("max_items", |_, this, value: usize| {
+            this.max_items = value.max(1);
+            Ok(())
+        });
+        fields.add_field_method_get("buffer_words", |_, this| Ok(this.buffer_words));
+        fields.add_field_method_set("buffer_words", |_, this, value| {
+            this.buffer_words = value;
+            Ok(())
+        });
+        fields.add_field_method_get("file_paths", |_, this| Ok(this.file_paths));
+        fields.add_field_method_set("file_paths", |_, this, value| {
+            this.file_paths = value;
+            Ok(())
+        });
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut("register", |_, this, provider: LuaFunction| {
+            this.providers.push(provider);
+            Ok(())
+        });
+        methods.add_method_mut(
+            "add_snippet",
+            |_, this, (trigger, body, file_type): (String, String, Option<String>)| {
+                let file_type = file_type.unwrap_or_else(|| ANY_FILE_TYPE.to_string());
+                this.snippets.entry(file_type).or_default().push((trigger, body));
+                Ok(())
+            },
+        );
+    }
+}
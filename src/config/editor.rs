@@ -2,15 +2,20 @@
 use crate::cli::VERSION;
 use crate::config::runner::RunCommand;
 use crate::editor::{Editor, FileContainer, FileLayout};
-use crate::pty_cross::Pty;
+use crate::error::OxError;
+use crate::lsp::protocol::uri_to_path;
 use crate::ui::Feedback;
 use crate::{config, fatal_error, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN};
+use kaolinite::document::LineEnding;
+use kaolinite::encoding::Encoding;
 use kaolinite::utils::{get_absolute_path, get_cwd, get_file_ext, get_file_name};
 use kaolinite::Loc;
 use mlua::prelude::*;
+use serde_json::{Map, Number, Value};
 use std::collections::HashMap;
 
 impl LuaUserData for Editor {
+    #[allow(clippy::too_many_lines)]
     fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
         fields.add_field_method_get("cursor", |_, editor| {
             if let Some(doc) = editor.try_doc() {
@@ -48,6 +53,19 @@ impl LuaUserData for Editor {
                 Ok(None)
             }
         });
+        fields.add_field_method_get("diagnostics", |lua, editor| {
+            let result = lua.create_table()?;
+            for diagnostic in editor.current_diagnostics() {
+                let entry = lua.create_table()?;
+                entry.set("x", diagnostic.range.start.character)?;
+                entry.set("y", diagnostic.range.start.line + 1)?;
+                entry.set("severity", diagnostic.severity.name())?;
+                entry.set("message", diagnostic.message.clone())?;
+                entry.set("source", diagnostic.source.clone())?;
+                result.push(entry)?;
+            }
+            Ok(result)
+        });
         fields.add_field_method_get("version", |_, _| Ok(VERSION));
         fields.add_field_method_get("current_document_id", |_, editor| {
             Ok(editor.files.get_atom(editor.ptr.clone()).map(|a| a.1))
@@ -96,6 +114,66 @@ impl LuaUserData for Editor {
             Ok(editor.macro_man.recording)
         });
         fields.add_field_method_get("macro_playing", |_, editor| Ok(editor.macro_man.playing));
+        fields.add_field_method_get("encoding", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.info.encoding.to_string()))
+        });
+        fields.add_field_method_get("line_ending", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.info.line_ending.to_string()))
+        });
+        fields.add_field_method_get("soft_wrap", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.wrap))
+        });
+        fields.add_field_method_set("soft_wrap", |_, editor, value: bool| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.set_wrap(value);
+            }
+            Ok(())
+        });
+        // Indentation of the current document (which can come from an .editorconfig file)
+        fields.add_field_method_get("tab_width", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.tab_width))
+        });
+        fields.add_field_method_set("tab_width", |_, editor, value: usize| {
+            if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                file.doc.set_tab_width(value);
+                file.highlighter.tab_width = value;
+            }
+            Ok(())
+        });
+        fields.add_field_method_get("indentation", |_, editor| {
+            Ok(editor
+                .files
+                .get(editor.ptr.clone())
+                .map(|file| file.format.indentation.to_string()))
+        });
+        fields.add_field_method_set("indentation", |_, editor, value: String| {
+            if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                file.format.indentation = value.into();
+            }
+            Ok(())
+        });
+        fields.add_field_method_get("indent_size", |_, editor| {
+            Ok(editor.files.get(editor.ptr.clone()).map(|file| file.format.indent_size))
+        });
+        fields.add_field_method_set("indent_size", |_, editor, value: usize| {
+            if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                file.format.indent_size = value;
+            }
+            Ok(())
+        });
+        fields.add_field_method_set("line_ending", |_, editor, value: String| {
+            let Some(line_ending) = LineEnding::from_name(&value) else {
+                return Err(LuaError::runtime(format!(
+                    "'{value}' is not a valid line ending (expected lf, crlf or cr)"
+                )));
+            };
+            if let Some(doc) = editor.try_doc_mut() {
+                if let Err(err) = doc.set_line_ending(line_ending) {
+                    editor.feedback = Feedback::Error(OxError::from(err).to_string());
+                }
+            }
+            Ok(())
+        });
     }
 
     #[allow(clippy::too_many_lines)]
@@ -281,6 +359,11 @@ impl LuaUserData for Editor {
             editor.update_highlighter();
             Ok(())
         });
+        methods.add_method_mut("move_to_matching_bracket", |_, editor, ()| {
+            let found = editor.move_to_matching_bracket();
+            editor.update_highlighter();
+            Ok(found)
+        });
         methods.add_method_mut("cursor_snap", |_, editor, ()| {
             if let Some(doc) = editor.try_doc_mut() {
                 doc.old_cursor = doc.loc().x;
@@ -333,6 +416,16 @@ impl LuaUserData for Editor {
             editor.update_highlighter();
             Ok(())
         });
+        methods.add_method_mut("select_inside_brackets", |_, editor, ()| {
+            let found = editor.select_brackets(false);
+            editor.update_highlighter();
+            Ok(found)
+        });
+        methods.add_method_mut("select_around_brackets", |_, editor, ()| {
+            let found = editor.select_brackets(true);
+            editor.update_highlighter();
+            Ok(found)
+        });
         methods.add_method_mut("select_to", |_, editor, (x, y): (usize, usize)| {
             if let Some(doc) = editor.try_doc_mut() {
                 let y = y.saturating_sub(1);
@@ -475,9 +568,7 @@ impl LuaUserData for Editor {
         });
         methods.add_method_mut("get", |_, editor, ()| {
             if let Some(doc) = editor.try_doc_mut() {
-                let lines = doc.len_lines();
-                doc.load_to(lines);
-                let contents = doc.lines.join("\n");
+                let contents = doc.lines_to(doc.len_lines()).join("\n");
                 Ok(Some(contents))
             } else {
                 Ok(None)
@@ -500,7 +591,6 @@ impl LuaUserData for Editor {
         });
         methods.add_method_mut("get_character_at", |_, editor, (x, y): (usize, usize)| {
             if let Some(doc) = editor.try_doc_mut() {
-                doc.load_to(y);
                 let y = y.saturating_sub(1);
                 let ch = doc
                     .line(y)
@@ -525,7 +615,6 @@ impl LuaUserData for Editor {
         });
         methods.add_method_mut("get_line_at", |_, editor, y: usize| {
             if let Some(doc) = editor.try_doc_mut() {
-                doc.load_to(y);
                 let y = y.saturating_sub(1);
                 let line = doc.line(y).unwrap_or_default();
                 editor.update_highlighter();
@@ -597,6 +686,94 @@ impl LuaUserData for Editor {
             editor.update_highlighter();
             Ok(())
         });
+        methods.add_method_mut("earlier", |_, editor, spec: String| {
+            let result = editor.earlier(&spec);
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method_mut("later", |_, editor, spec: String| {
+            let result = editor.later(&spec);
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method_mut("goto_revision", |_, editor, revision: usize| {
+            let result = editor.goto_revision(revision);
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method_mut("next_branch", |_, editor, ()| {
+            let result = editor.next_branch();
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method_mut("previous_branch", |_, editor, ()| {
+            let result = editor.previous_branch();
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method("undo_tree", |lua, editor, ()| {
+            let Some(doc) = editor.try_doc() else {
+                return Ok(None);
+            };
+            let mgmt = &doc.event_mgmt;
+            let siblings = mgmt.siblings();
+            let tree = lua.create_table()?;
+            tree.set("revision", mgmt.ptr)?;
+            tree.set("revisions", mgmt.history.len())?;
+            tree.set(
+                "branch",
+                siblings.iter().position(|r| *r == mgmt.ptr).unwrap_or(0) + 1,
+            )?;
+            tree.set("siblings", siblings.len())?;
+            tree.set("branches", mgmt.branches())?;
+            tree.set("saved", mgmt.on_disk)?;
+            tree.set("age", kaolinite::event::now().saturating_sub(mgmt.history[mgmt.ptr].time))?;
+            Ok(Some(tree))
+        });
+        methods.add_method_mut("reopen_with_encoding", |_, editor, name: String| {
+            let Some(encoding) = Encoding::from_name(&name) else {
+                editor.feedback = Feedback::Error(format!("'{name}' is not a supported encoding"));
+                return Ok(false);
+            };
+            let Some(idx) = editor.files.get_atom(editor.ptr.clone()).map(|(_, idx)| idx) else {
+                return Ok(false);
+            };
+            let result = editor.reopen_with_encoding(idx, encoding);
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            } else if editor.try_doc().is_some_and(|doc| !doc.info.lossy) {
+                editor.feedback = Feedback::Info(format!("Document is now using {encoding}"));
+            }
+            editor.update_highlighter();
+            Ok(result.is_ok())
+        });
+        methods.add_method_mut("reload", |_, editor, ()| {
+            let Some(idx) = editor.files.get_atom(editor.ptr.clone()).map(|(_, idx)| idx) else {
+                return Ok(false);
+            };
+            let result = editor.reload_file(&editor.ptr.clone(), idx);
+            if let Err(err) = &result {
+                editor.feedback = Feedback::Error(err.to_string());
+            } else {
+                editor.feedback = Feedback::Info("Document reloaded from disk".to_string());
+            }
+            Ok(result.is_ok())
+        });
         methods.add_method_mut("commit", |_, editor, ()| {
             if let Some(doc) = editor.try_doc_mut() {
                 doc.commit();
@@ -730,6 +907,68 @@ impl LuaUserData for Editor {
             let _ = editor.render(lua);
             Ok(())
         });
+        methods.add_method_mut("project_search", |lua, editor, target: Option<String>| {
+            let result = match target {
+                Some(target) => {
+                    editor.run_project_search(&target, None);
+                    Ok(())
+                }
+                None => editor.project_search(),
+            };
+            if let Err(err) = result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut(
+            "project_replace",
+            |lua, editor, (target, into): (Option<String>, Option<String>)| {
+                let result = match (target, into) {
+                    (Some(target), Some(into)) => {
+                        editor.run_project_search(&target, Some(into));
+                        Ok(())
+                    }
+                    _ => editor.project_replace(),
+                };
+                if let Err(err) = result {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
+                editor.needs_rerender = true;
+                let _ = editor.render(lua);
+                Ok(())
+            },
+        );
+        methods.add_method_mut("close_project_search", |_, editor, ()| {
+            editor.close_project_search();
+            editor.needs_rerender = true;
+            Ok(())
+        });
+        methods.add_method_mut("find_file", |lua, editor, ()| {
+            if let Err(err) = editor.find_file(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("find_buffer", |lua, editor, ()| {
+            if let Err(err) = editor.find_buffer(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("find_recent", |lua, editor, ()| {
+            if let Err(err) = editor.find_recent(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
         methods.add_method_mut("move_next_match", |_, editor, query: String| {
             editor.next_match(&query);
             if let Some(doc) = editor.try_doc_mut() {
@@ -746,6 +985,141 @@ impl LuaUserData for Editor {
             editor.update_highlighter();
             Ok(())
         });
+        // Folding
+        methods.add_method_mut("fold_at", |_, editor, y: Option<usize>| {
+            let y = fold_line(editor, y);
+            let folded = editor.fold_at(y);
+            if !folded {
+                editor.feedback = Feedback::Error(format!("Nothing to fold at line {}", y + 1));
+            }
+            Ok(folded)
+        });
+        methods.add_method_mut("unfold_at", |_, editor, y: Option<usize>| {
+            let y = fold_line(editor, y);
+            Ok(editor.unfold_at(y))
+        });
+        methods.add_method_mut("toggle_fold", |_, editor, y: Option<usize>| {
+            let y = fold_line(editor, y);
+            let toggled = editor.toggle_fold(y);
+            if !toggled {
+                editor.feedback = Feedback::Error(format!("Nothing to fold at line {}", y + 1));
+            }
+            Ok(toggled)
+        });
+        methods.add_method_mut("fold_all", |_, editor, ()| {
+            editor.fold_all();
+            Ok(())
+        });
+        methods.add_method_mut("unfold_all", |_, editor, ()| {
+            editor.unfold_all();
+            Ok(())
+        });
+        // Language servers
+        methods.add_method_mut("lsp_hover", |_, editor, ()| match editor.lsp_hover() {
+            Ok(hover) => Ok(hover),
+            Err(err) => {
+                editor.feedback = Feedback::Error(err.to_string());
+                Ok(None)
+            }
+        });
+        methods.add_method_mut("lsp_definition", |_, editor, ()| {
+            match editor.lsp_definition() {
+                Ok(true) => (),
+                Ok(false) => editor.feedback = Feedback::Warning("No definition found".to_string()),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("lsp_references", |lua, editor, ()| {
+            let references = match editor.lsp_references() {
+                Ok(references) => references,
+                Err(err) => {
+                    editor.feedback = Feedback::Error(err.to_string());
+                    vec![]
+                }
+            };
+            let result = lua.create_table()?;
+            for reference in references {
+                let Some(file) = uri_to_path(&reference.uri) else {
+                    continue;
+                };
+                let entry = lua.create_table()?;
+                entry.set("file", file)?;
+                entry.set("x", reference.range.start.character)?;
+                entry.set("y", reference.range.start.line + 1)?;
+                result.push(entry)?;
+            }
+            Ok(result)
+        });
+        methods.add_method_mut("lsp_next_reference", |_, editor, ()| {
+            if let Err(err) = editor.lsp_next_reference() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("lsp_rename", |_, editor, new_name: String| {
+            match editor.lsp_rename(&new_name) {
+                Ok(0) => editor.feedback = Feedback::Warning("Nothing to rename".to_string()),
+                Ok(n) => editor.feedback = Feedback::Info(format!("Made {n} edits")),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("lsp_completion", |lua, editor, ()| {
+            let items = match editor.lsp_completion() {
+                Ok(items) => items,
+                Err(err) => {
+                    editor.feedback = Feedback::Error(err.to_string());
+                    vec![]
+                }
+            };
+            let result = lua.create_table()?;
+            for item in items {
+                let entry = lua.create_table()?;
+                entry.set("label", item.label)?;
+                entry.set("detail", item.detail)?;
+                entry.set("insert_text", item.insert_text)?;
+                result.push(entry)?;
+            }
+            Ok(result)
+        });
+        methods.add_method_mut(
+            "lsp_request",
+            |lua, editor, (method, params): (String, LuaValue)| {
+                let params = lua_to_json(&params)?;
+                match editor.lsp_request(&method, params) {
+                    Ok(result) => json_to_lua(lua, &result),
+                    Err(err) => {
+                        editor.feedback = Feedback::Error(err.to_string());
+                        Ok(LuaNil)
+                    }
+                }
+            },
+        );
+        methods.add_method_mut(
+            "lsp_notify",
+            |_, editor, (method, params): (String, LuaValue)| {
+                let params = lua_to_json(&params)?;
+                if let Err(err) = editor.lsp_notify(&method, params) {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
+                Ok(())
+            },
+        );
+        methods.add_method_mut("lsp_restart", |_, editor, ()| {
+            editor.restart_language_server();
+            Ok(())
+        });
+        // Completion
+        methods.add_method_mut("complete", |_, editor, ()| {
+            editor.completion.requested = true;
+            Ok(())
+        });
+        methods.add_method_mut("cancel_completion", |_, editor, ()| {
+            editor.close_completion();
+            editor.needs_rerender = true;
+            Ok(())
+        });
         // Document state modification
         methods.add_method_mut("set_read_only", |_, editor, status: bool| {
             if let Some(doc) = editor.try_doc_mut() {
@@ -758,8 +1132,10 @@ impl LuaUserData for Editor {
                 let doc = config!(editor.config, document);
                 if let Some(file_type) = doc.file_types.get_name(&name) {
                     let mut highlighter = file_type.get_highlighter(&editor.config, 4);
-                    highlighter.run(&actual_doc.lines);
+                    highlighter.run(&actual_doc.lines_to_view());
+                    let wrap = config!(editor.config, document).soft_wrap_for(Some(&file_type));
                     if let Some(file) = editor.files.get_mut(editor.ptr.clone()) {
+                        file.doc.set_wrap(wrap);
                         file.highlighter = highlighter;
                         file.file_type = Some(file_type);
                     }
@@ -798,12 +1174,12 @@ impl LuaUserData for Editor {
         });
         // Terminal
         methods.add_method_mut("open_terminal_up", |_, editor, cmd: Option<String>| {
-            if let Ok(term) = Pty::new(config!(editor.config, terminal).shell) {
+            if let Ok(term) = editor.new_terminal() {
                 if let Some(cmd) = cmd {
                     match term.lock() {
-                        Ok(mut pty) => pty.silent_run_command(&format!("{cmd}\n"))?,
+                        Ok(mut pty) => pty.run_command(&format!("{cmd}\n"))?,
                         Err(e) => {
-                            eprintln!("Failed to lock PTY: {}", e);
+                            eprintln!("Failed to lock PTY: {e}");
                             return Ok(false);
                         }
                     }
@@ -818,12 +1194,12 @@ impl LuaUserData for Editor {
             }
         });
         methods.add_method_mut("open_terminal_down", |_, editor, cmd: Option<String>| {
-            if let Ok(term) = Pty::new(config!(editor.config, terminal).shell) {
+            if let Ok(term) = editor.new_terminal() {
                 if let Some(cmd) = cmd {
                     match term.lock() {
-                        Ok(mut pty) => pty.silent_run_command(&format!("{cmd}\n"))?,
+                        Ok(mut pty) => pty.run_command(&format!("{cmd}\n"))?,
                         Err(e) => {
-                            eprintln!("Failed to lock PTY: {}", e);
+                            eprintln!("Failed to lock PTY: {e}");
                             return Ok(false);
                         }
                     }
@@ -838,12 +1214,12 @@ impl LuaUserData for Editor {
             }
         });
         methods.add_method_mut("open_terminal_left", |_, editor, cmd: Option<String>| {
-            if let Ok(term) = Pty::new(config!(editor.config, terminal).shell) {
+            if let Ok(term) = editor.new_terminal() {
                 if let Some(cmd) = cmd {
                     match term.lock() {
-                        Ok(mut pty) => pty.silent_run_command(&format!("{cmd}\n"))?,
+                        Ok(mut pty) => pty.run_command(&format!("{cmd}\n"))?,
                         Err(e) => {
-                            eprintln!("Failed to lock PTY: {}", e);
+                            eprintln!("Failed to lock PTY: {e}");
                             return Ok(false);
                         }
                     }
@@ -858,12 +1234,12 @@ impl LuaUserData for Editor {
             }
         });
         methods.add_method_mut("open_terminal_right", |_, editor, cmd: Option<String>| {
-            if let Ok(term) = Pty::new(config!(editor.config, terminal).shell) {
+            if let Ok(term) = editor.new_terminal() {
                 if let Some(cmd) = cmd {
                     match term.lock() {
-                        Ok(mut pty) => pty.silent_run_command(&format!("{cmd}\n"))?,
+                        Ok(mut pty) => pty.run_command(&format!("{cmd}\n"))?,
                         Err(e) => {
-                            eprintln!("Failed to lock PTY: {}", e);
+                            eprintln!("Failed to lock PTY: {e}");
                             return Ok(false);
                         }
                     }
@@ -896,7 +1272,7 @@ impl LuaUserData for Editor {
                     if let Some(cmds) = runcmds.get(&kind) {
                         let RunCommand { compile, run } = cmds;
                         // ...open a terminal...
-                        if let Ok(term) = Pty::new(config!(editor.config, terminal).shell) {
+                        if let Ok(term) = editor.new_terminal() {
                             editor.ptr = editor
                                 .files
                                 .open_right(editor.ptr.clone(), FileLayout::Terminal(term));
@@ -911,7 +1287,7 @@ impl LuaUserData for Editor {
                                     match term.lock() {
                                         Ok(mut pty) => pty.run_command(&format!("{compile_cmd}\n"))?,
                                         Err(e) => {
-                                            eprintln!("Failed to lock PTY: {}", e);
+                                            eprintln!("Failed to lock PTY: {e}");
                                             return Ok(false);
                                         }
                                     }
@@ -921,7 +1297,7 @@ impl LuaUserData for Editor {
                                     match term.lock() {
                                         Ok(mut pty) => pty.run_command(&format!("{run_cmd}\n"))?,
                                         Err(e) => {
-                                            eprintln!("Failed to lock PTY: {}", e);
+                                            eprintln!("Failed to lock PTY: {e}");
                                             return Ok(false);
                                         }
                                     }
@@ -933,6 +1309,68 @@ impl LuaUserData for Editor {
             }
             Ok(true)
         });
+        // Tasks
+        methods.add_method_mut("run_task", |lua, editor, name: Option<String>| {
+            let name = match name {
+                Some(name) => Ok(name),
+                None => editor.prompt("Task"),
+            };
+            let result = name.and_then(|name| editor.run_task(lua, &name));
+            if let Err(err) = result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("next_problem", |lua, editor, ()| {
+            if let Err(err) = editor.next_problem() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("previous_problem", |lua, editor, ()| {
+            if let Err(err) = editor.previous_problem() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("close_problems", |_, editor, ()| {
+            editor.close_problems();
+            editor.needs_rerender = true;
+            Ok(())
+        });
+        // Sessions
+        methods.add_method_mut("save_session", |lua, editor, name: Option<String>| {
+            let name = match name {
+                Some(name) => Ok(name),
+                None => editor.prompt("Session name"),
+            };
+            let result = name.and_then(|name| editor.save_session(&name));
+            if let Err(err) = result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
+        methods.add_method_mut("load_session", |lua, editor, name: Option<String>| {
+            let name = match name {
+                Some(name) => Ok(name),
+                None => editor.prompt("Session name"),
+            };
+            let result = name.and_then(|name| editor.load_session(&name));
+            if let Err(err) = result {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
         // Miscellaneous
         methods.add_method_mut("open_command_line", |_, editor, ()| {
             match editor.prompt("Command") {
@@ -945,6 +1383,14 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        methods.add_method_mut("command_palette", |lua, editor, ()| {
+            if let Err(err) = editor.command_palette(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            editor.needs_rerender = true;
+            let _ = editor.render(lua);
+            Ok(())
+        });
         // Macro
         methods.add_method_mut("macro_record_start", |_, editor, ()| {
             editor.macro_man.record();
@@ -965,6 +1411,80 @@ impl LuaUserData for Editor {
     }
 }
 
+/// Work out which line a folding method refers to (the cursor's line if none was given)
+fn fold_line(editor: &Editor, y: Option<usize>) -> usize {
+    y.map_or_else(
+        || editor.try_doc().map_or(0, |doc| doc.loc().y),
+        |y| y.saturating_sub(1),
+    )
+}
+
+/// Convert JSON (from a language server) into a lua value
+fn json_to_lua(lua: &Lua, value: &Value) -> LuaResult<LuaValue> {
+    Ok(match value {
+        Value::Null => LuaNil,
+        Value::Bool(b) => LuaValue::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => LuaValue::Integer(i),
+            None => LuaValue::Number(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(st) => LuaValue::String(lua.create_string(st)?),
+        Value::Array(items) => {
+            let table = lua.create_table()?;
+            for item in items {
+                table.push(json_to_lua(lua, item)?)?;
+            }
+            LuaValue::Table(table)
+        }
+        Value::Object(fields) => {
+            let table = lua.create_table()?;
+            for (key, item) in fields {
+                table.set(key.as_str(), json_to_lua(lua, item)?)?;
+            }
+            LuaValue::Table(table)
+        }
+    })
+}
+
+/// Convert a lua value into JSON (to send to a language server).
+/// Tables with only the keys 1 to n become arrays (and empty tables become empty objects).
+fn lua_to_json(value: &LuaValue) -> LuaResult<Value> {
+    Ok(match value {
+        LuaValue::Nil => Value::Null,
+        LuaValue::Boolean(b) => Value::Bool(*b),
+        LuaValue::Integer(i) => Value::from(*i),
+        LuaValue::Number(n) => Number::from_f64(*n).map_or(Value::Null, Value::Number),
+        LuaValue::String(st) => Value::String(st.to_str()?.to_string()),
+        LuaValue::Table(table) => {
+            let length = table.raw_len();
+            let pairs = table.clone().pairs::<LuaValue, LuaValue>().count();
+            if length > 0 && length == pairs {
+                let items = table.clone();
+                let items = items.sequence_values::<LuaValue>();
+                Value::Array(items.map(|item| lua_to_json(&item?)).collect::<LuaResult<_>>()?)
+            } else {
+                let mut fields = Map::new();
+                for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+                    let (key, item) = pair?;
+                    let key = match key {
+                        LuaValue::String(st) => st.to_str()?.to_string(),
+                        LuaValue::Integer(i) => i.to_string(),
+                        _ => continue,
+                    };
+                    fields.insert(key, lua_to_json(&item)?);
+                }
+                Value::Object(fields)
+            }
+        }
+        _ => {
+            return Err(LuaError::runtime(format!(
+                "a {} can't be sent to a language server",
+                value.type_name()
+            )))
+        }
+    })
+}
+
 /// For representing a cursor location object within lua
 pub struct LuaLoc {
     x: usize,
@@ -0,0 +1,42 @@
+/// For configuring the fuzzy finder
+use mlua::prelude::*;
+
+#[derive(Debug)]
+pub struct Finder {
+    /// Patterns (like those in `.gitignore`) of files and folders to leave out of the index
+    pub exclude: Vec<String>,
+    /// Whether the file that is selected is previewed
+    pub preview: bool,
+    /// The most files that are indexed
+    pub max_files: usize,
+}
+
+impl Default for Finder {
+    fn default() -> Self {
+        Self {
+            exclude: vec![],
+            preview: true,
+            max_files: 50_000,
+        }
+    }
+}
+
+impl LuaUserData for Finder {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("exclude", |_, this| Ok(this.exclude.clone()));
+        fields.add_field_method_set("exclude", |_, this, value| {
+            this.exclude = value;
+            Ok(())
+        });
+        fields.add_field_method_get("preview", |_, this| Ok(this.preview));
+        fields.add_field_method_set("preview", |_, this, value| {
+            this.preview = value;
+            Ok(())
+        });
+        fields.add_field_method_get("max_files", |_, this| Ok(this.max_files));
+        fields.add_field_method_set("max_files", |_, this, value| {
+            this.max_files = value;
+            Ok(())
+        });
+    }
+}
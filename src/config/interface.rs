@@ -22,6 +22,10 @@ pub struct Terminal {
     #[cfg(target_os = "windows")]
     #[allow(dead_code)]
     pub shell: (),
+    /// The key that moves focus out of a terminal split (all other keys go to the terminal)
+    pub escape_key: String,
+    /// How many lines of output each terminal split keeps to scroll back through
+    pub scrollback: usize,
     // Clipboard configuration
     pub clipboard_use_osc52: bool,
     pub clipboard_max_retries: usize,
@@ -37,6 +41,8 @@ impl Default for Terminal {
             shell: Shell::Bash,
             #[cfg(target_os = "windows")]
             shell: (),
+            escape_key: "ctrl_alt_q".to_string(),
+            scrollback: crate::vt::MAX_SCROLLBACK,
             clipboard_use_osc52: true,
             clipboard_max_retries: 3,
             clipboard_verbose_logging: false,
@@ -67,6 +73,16 @@ impl LuaUserData for Terminal {
         fields.add_field_method_get("shell", |_, _| Ok("windows not supported"));
         #[cfg(target_os = "windows")]
         fields.add_field_method_set("shell", |_, _, _: String| Ok(()));
+        fields.add_field_method_get("escape_key", |_, this| Ok(this.escape_key.clone()));
+        fields.add_field_method_set("escape_key", |_, this, value| {
+            this.escape_key = value;
+            Ok(())
+        });
+        fields.add_field_method_get("scrollback", |_, this| Ok(this.scrollback));
+        fields.add_field_method_set("scrollback", |_, this, value| {
+            this.scrollback = value;
+            Ok(())
+        });
         
         // Clipboard configuration fields
         fields.add_field_method_get("clipboard_use_osc52", |_, this| Ok(this.clipboard_use_osc52));
@@ -146,8 +162,8 @@ impl GreetingMessage {
     pub fn render(&self, lua: &Lua) -> (String, Vec<usize>) {
         let mut result = self.format.clone();
         // Substitute in simple values
-        result = result.replace("{version}", VERSION).to_string();
-        result = result.replace('\t', "    ").to_string();
+        result = result.replace("{version}", VERSION).clone();
+        result = result.replace('\t', "    ").clone();
         // Handle highlighted part
         let start = result.find("{highlight_start}");
         let end = result.find("{highlight_end}");
@@ -158,8 +174,8 @@ impl GreetingMessage {
         } else {
             vec![]
         };
-        result = result.replace("{highlight_start}", "").to_string();
-        result = result.replace("{highlight_end}", "").to_string();
+        result = result.replace("{highlight_start}", "").clone();
+        result = result.replace("{highlight_end}", "").clone();
         // Find functions to call and substitute in
         let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
         while let Some(m) = searcher.lfind(&result) {
@@ -220,7 +236,7 @@ impl HelpMessage {
         let mut message = self.format.clone();
         //result = result.replace("{highlight_start}", &highlight).to_string();
         //result = result.replace("{highlight_end}", &editor_fg).to_string();
-        message = message.replace("{version}", VERSION).to_string();
+        message = message.replace("{version}", VERSION).clone();
         // Find functions to call and substitute in
         let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
         while let Some(m) = searcher.lfind(&message) {
@@ -302,22 +318,20 @@ impl TabLine {
         let absolute_path = get_absolute_path(&path).unwrap_or_else(|| "[No Name]".to_string());
         let file_name = get_file_name(&path).unwrap_or_else(|| "[No Name]".to_string());
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |t| t.icon);
-        let modified = if fc.doc.event_mgmt.with_disk(&fc.doc.take_snapshot()) {
+        let modified = if fc.doc.event_mgmt.with_disk() {
             ""
         } else {
             "[+]"
         };
         let mut result = self.format.clone();
         result = result
-            .replace("{file_extension}", &file_extension)
-            .to_string();
-        result = result.replace("{file_name}", &file_name).to_string();
+            .replace("{file_extension}", &file_extension).clone();
+        result = result.replace("{file_name}", &file_name).clone();
         result = result
-            .replace("{absolute_path}", &absolute_path)
-            .to_string();
-        result = result.replace("{path}", &path).to_string();
-        result = result.replace("{modified}", modified).to_string();
-        result = result.replace("{icon}", &icon).to_string();
+            .replace("{absolute_path}", &absolute_path).clone();
+        result = result.replace("{path}", &path).clone();
+        result = result.replace("{modified}", modified).clone();
+        result = result.replace("{icon}", &icon).clone();
         // Find functions to call and substitute in
         let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
         while let Some(m) = searcher.lfind(&result) {
@@ -399,7 +413,7 @@ impl StatusLine {
             .clone()
             .map_or("Unknown".to_string(), |ft| ft.name);
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |ft| ft.icon);
-        let modified = if doc.event_mgmt.with_disk(&doc.take_snapshot()) {
+        let modified = if doc.event_mgmt.with_disk() {
             ""
         } else {
             "[+]"
@@ -407,21 +421,24 @@ impl StatusLine {
         let cursor_y = (doc.loc().y + 1).to_string();
         let cursor_x = doc.char_ptr.to_string();
         let line_count = doc.len_lines().to_string();
+        let line_ending = doc.info.line_ending.to_string();
+        let encoding = doc.info.encoding.to_string();
 
         for part in &self.parts {
             let mut part = part.clone();
-            part = part.replace("{file_name}", &file_name).to_string();
+            part = part.replace("{file_name}", &file_name).clone();
             part = part
-                .replace("{file_extension}", &file_extension)
-                .to_string();
-            part = part.replace("{icon}", &icon).to_string();
-            part = part.replace("{path}", &path).to_string();
-            part = part.replace("{absolute_path}", &absolute_path).to_string();
-            part = part.replace("{modified}", modified).to_string();
-            part = part.replace("{file_type}", &file_type).to_string();
-            part = part.replace("{cursor_y}", &cursor_y).to_string();
-            part = part.replace("{cursor_x}", &cursor_x).to_string();
-            part = part.replace("{line_count}", &line_count).to_string();
+                .replace("{file_extension}", &file_extension).clone();
+            part = part.replace("{icon}", &icon).clone();
+            part = part.replace("{path}", &path).clone();
+            part = part.replace("{absolute_path}", &absolute_path).clone();
+            part = part.replace("{modified}", modified).clone();
+            part = part.replace("{file_type}", &file_type).clone();
+            part = part.replace("{cursor_y}", &cursor_y).clone();
+            part = part.replace("{cursor_x}", &cursor_x).clone();
+            part = part.replace("{line_count}", &line_count).clone();
+            part = part.replace("{line_ending}", &line_ending).clone();
+            part = part.replace("{encoding}", &encoding).clone();
             // Find functions to call and substitute in
             let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
             while let Some(m) = searcher.lfind(&part) {
@@ -8,21 +8,28 @@ use std::sync::{Arc, Mutex};
 
 mod assistant;
 mod colors;
+mod completion;
 mod editor;
 mod filetree;
+mod finder;
 mod highlighting;
 mod interface;
 mod keys;
+mod palette;
 pub mod path_utils;
 mod runner;
 mod tasks;
 
 pub use assistant::Assistant;
 pub use colors::{Color, Colors};
+pub use completion::Completion;
 pub use filetree::FileTree;
+pub use finder::Finder;
 pub use highlighting::SyntaxHighlighting;
 pub use interface::{GreetingMessage, HelpMessage, LineNumbers, StatusLine, TabLine, Terminal};
 pub use keys::{get_listeners, key_to_string, run_key, run_key_before};
+pub use palette::{Description, Palette};
+pub use runner::TaskCommand;
 pub use tasks::TaskManager;
 
 /// Issue a warning to the user
@@ -98,6 +105,18 @@ macro_rules! config {
         $cfg.terminal.borrow::<$crate::config::Terminal>()
             .expect("Failed to borrow terminal config")
     };
+    ($cfg:expr, completion) => {
+        $cfg.completion.borrow::<$crate::config::Completion>()
+            .expect("Failed to borrow completion config")
+    };
+    ($cfg:expr, finder) => {
+        $cfg.finder.borrow::<$crate::config::Finder>()
+            .expect("Failed to borrow finder config")
+    };
+    ($cfg:expr, palette) => {
+        $cfg.palette.borrow::<$crate::config::Palette>()
+            .expect("Failed to borrow palette config")
+    };
 }
 
 /// The struct that holds all the configuration information
@@ -113,6 +132,9 @@ pub struct Config {
     pub file_tree: LuaAnyUserData,
     pub terminal: LuaAnyUserData,
     pub document: LuaAnyUserData,
+    pub completion: LuaAnyUserData,
+    pub finder: LuaAnyUserData,
+    pub palette: LuaAnyUserData,
     pub task_manager: Arc<Mutex<TaskManager>>,
 }
 
@@ -130,6 +152,9 @@ impl Config {
         let file_tree = lua.create_userdata(FileTree::default())?;
         let terminal = lua.create_userdata(Terminal::default())?;
         let document = lua.create_userdata(Document::default())?;
+        let completion = lua.create_userdata(Completion::default())?;
+        let finder = lua.create_userdata(Finder::default())?;
+        let palette = lua.create_userdata(Palette::default())?;
 
         // Set up the task manager
         let task_manager = Arc::new(Mutex::new(TaskManager::default()));
@@ -153,6 +178,9 @@ impl Config {
         lua.globals().set("colors", colors.clone())?;
         lua.globals().set("terminal", terminal.clone())?;
         lua.globals().set("document", document.clone())?;
+        lua.globals().set("completion", completion.clone())?;
+        lua.globals().set("finder", finder.clone())?;
+        lua.globals().set("palette", palette.clone())?;
 
         // Define task list
         let task_manager_clone = Arc::clone(&task_manager);
@@ -201,6 +229,9 @@ impl Config {
             file_tree,
             terminal,
             document,
+            completion,
+            finder,
+            palette,
             task_manager,
         })
     }
@@ -245,9 +276,9 @@ impl Config {
 
     /// Read the user-provided config
     pub fn get_user_provided_config(path: &str) -> Option<String> {
-        let path = dirs::expand_tilde(&path).to_string_lossy().to_string();
+        let path = dirs::expand_tilde(path).to_string_lossy().to_string();
         {
-            if let Ok(config) = std::fs::read_to_string(path.to_string()) {
+            if let Ok(config) = std::fs::read_to_string(&path) {
                 return Some(config);
             }
         }
@@ -287,7 +318,7 @@ impl Config {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Indentation {
     Tabs,
     Spaces,
@@ -316,12 +347,56 @@ impl From<String> for Indentation {
     }
 }
 
+#[derive(Debug, PartialEq)]
+pub enum BackupMode {
+    None,
+    Tilde,
+    Directory,
+}
+
+impl Display for BackupMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::result::Result<(), Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::None => "none",
+                Self::Tilde => "tilde",
+                Self::Directory => "directory",
+            }
+        )
+    }
+}
+
+impl From<String> for BackupMode {
+    /// Interpret a backup setting from a string format
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "tilde" => Self::Tilde,
+            "directory" => Self::Directory,
+            _ => Self::None,
+        }
+    }
+}
+
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Document {
     pub tab_width: usize,
     pub indentation: Indentation,
     pub undo_period: usize,
     pub wrap_cursor: bool,
+    pub persistent_undo: bool,
+    pub undo_history_size: usize,
+    pub backup: BackupMode,
+    pub backup_count: usize,
+    pub large_file_size: u64,
+    pub highlight_size_limit: u64,
+    pub soft_wrap: bool,
+    pub match_brackets: bool,
+    pub editorconfig: bool,
+    pub language_servers: bool,
+    pub auto_session: bool,
     pub file_types: FileTypes,
 }
 
@@ -332,11 +407,29 @@ impl Default for Document {
             indentation: Indentation::Tabs,
             undo_period: 10,
             wrap_cursor: true,
+            persistent_undo: false,
+            undo_history_size: 1024,
+            backup: BackupMode::None,
+            backup_count: 10,
+            large_file_size: 64,
+            highlight_size_limit: 8,
+            soft_wrap: false,
+            match_brackets: true,
+            editorconfig: true,
+            language_servers: true,
+            auto_session: false,
             file_types: FileTypes::default(),
         }
     }
 }
 
+impl Document {
+    /// Work out whether files of a certain type should have their long lines soft wrapped
+    pub fn soft_wrap_for(&self, file_type: Option<&FileType>) -> bool {
+        file_type.and_then(|t| t.wrap).unwrap_or(self.soft_wrap)
+    }
+}
+
 impl LuaUserData for Document {
     fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
         fields.add_field_method_get("tab_width", |_, document| Ok(document.tab_width));
@@ -361,6 +454,73 @@ impl LuaUserData for Document {
             this.wrap_cursor = value;
             Ok(())
         });
+        fields.add_field_method_get("persistent_undo", |_, document| {
+            Ok(document.persistent_undo)
+        });
+        fields.add_field_method_set("persistent_undo", |_, this, value| {
+            this.persistent_undo = value;
+            Ok(())
+        });
+        fields.add_field_method_get("undo_history_size", |_, document| {
+            Ok(document.undo_history_size)
+        });
+        fields.add_field_method_set("undo_history_size", |_, this, value| {
+            this.undo_history_size = value;
+            Ok(())
+        });
+        fields.add_field_method_get("backup", |_, document| Ok(document.backup.to_string()));
+        fields.add_field_method_set("backup", |_, this, value: String| {
+            this.backup = value.into();
+            Ok(())
+        });
+        fields.add_field_method_get("backup_count", |_, document| Ok(document.backup_count));
+        fields.add_field_method_set("backup_count", |_, this, value| {
+            this.backup_count = value;
+            Ok(())
+        });
+        fields.add_field_method_get("large_file_size", |_, document| {
+            Ok(document.large_file_size)
+        });
+        fields.add_field_method_set("large_file_size", |_, this, value| {
+            this.large_file_size = value;
+            Ok(())
+        });
+        fields.add_field_method_get("highlight_size_limit", |_, document| {
+            Ok(document.highlight_size_limit)
+        });
+        fields.add_field_method_set("highlight_size_limit", |_, this, value| {
+            this.highlight_size_limit = value;
+            Ok(())
+        });
+        fields.add_field_method_get("soft_wrap", |_, document| Ok(document.soft_wrap));
+        fields.add_field_method_set("soft_wrap", |_, this, value| {
+            this.soft_wrap = value;
+            Ok(())
+        });
+        fields.add_field_method_get("match_brackets", |_, document| {
+            Ok(document.match_brackets)
+        });
+        fields.add_field_method_set("match_brackets", |_, this, value| {
+            this.match_brackets = value;
+            Ok(())
+        });
+        fields.add_field_method_get("editorconfig", |_, document| Ok(document.editorconfig));
+        fields.add_field_method_set("editorconfig", |_, this, value| {
+            this.editorconfig = value;
+            Ok(())
+        });
+        fields.add_field_method_get("language_servers", |_, document| {
+            Ok(document.language_servers)
+        });
+        fields.add_field_method_set("language_servers", |_, this, value| {
+            this.language_servers = value;
+            Ok(())
+        });
+        fields.add_field_method_get("auto_session", |_, document| Ok(document.auto_session));
+        fields.add_field_method_set("auto_session", |_, this, value| {
+            this.auto_session = value;
+            Ok(())
+        });
     }
 }
 
@@ -390,6 +550,8 @@ impl FromLua for FileTypes {
                     .filter_map(|val| if let Ok((_, v)) = val { Some(v) } else { None })
                     .collect::<Vec<String>>();
                 let color = info.get::<String>("color")?;
+                let wrap = info.get::<Option<bool>>("wrap")?;
+                let lsp = info.get::<Option<String>>("lsp")?;
                 result.push(FileType {
                     name,
                     icon,
@@ -397,6 +559,8 @@ impl FromLua for FileTypes {
                     extensions,
                     modelines,
                     color,
+                    wrap,
+                    lsp,
                 });
             }
         }
@@ -0,0 +1,51 @@
+/// For configuring the command palette and describing what can be run from it
+use mlua::prelude::*;
+use std::collections::HashMap;
+
+/// What is shown in the palette beside a command, action or key binding
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Description {
+    /// What it does
+    pub text: String,
+    /// What arguments it takes, e.g. `<start|stop>`
+    pub hint: String,
+}
+
+#[derive(Debug)]
+pub struct Palette {
+    /// Descriptions by the name of what they describe
+    /// (a command from `commands`, a key binding, or an editor action like `editor:save`)
+    pub descriptions: HashMap<String, Description>,
+    /// The most recently used commands remembered
+    pub max_recent: usize,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            descriptions: HashMap::new(),
+            max_recent: 20,
+        }
+    }
+}
+
+impl LuaUserData for Palette {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("max_recent", |_, this| Ok(this.max_recent));
+        fields.add_field_method_set("max_recent", |_, this, value| {
+            this.max_recent = value;
+            Ok(())
+        });
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "describe",
+            |_, this, (name, text, hint): (String, String, Option<String>)| {
+                let hint = hint.unwrap_or_default();
+                this.descriptions.insert(name, Description { text, hint });
+                Ok(())
+            },
+        );
+    }
+}
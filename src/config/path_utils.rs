@@ -37,17 +37,13 @@ impl LuaUserData for PathUtils {
         // Get the directory part of a path
         methods.add_function("dirname", |_, path: String| {
             let path = Path::new(&path);
-            Ok(path.parent()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|| ".".to_string()))
+            Ok(path.parent().map_or_else(|| ".".to_string(), |p| p.to_string_lossy().to_string()))
         });
 
         // Get the filename part of a path
         methods.add_function("basename", |_, path: String| {
             let path = Path::new(&path);
-            Ok(path.file_name()
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|| path.to_string_lossy().to_string()))
+            Ok(path.file_name().map_or_else(|| path.to_string_lossy().to_string(), |p| p.to_string_lossy().to_string()))
         });
 
         // Expand home directory in paths
@@ -92,7 +88,7 @@ impl LuaUserData for PathUtils {
             let path = Path::new(&path);
             Ok(path.extension()
                 .and_then(|ext| ext.to_str())
-                .map(|s| s.to_string()))
+                .map(std::string::ToString::to_string))
         });
 
         // Remove file extension
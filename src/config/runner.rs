@@ -22,3 +22,34 @@ impl FromLua for RunCommand {
         }
     }
 }
+
+/// A task that can be run in the background (like building, testing or linting a project),
+/// along with the expressions that find problems in its output
+#[derive(Debug, Default)]
+pub struct TaskCommand {
+    pub command: String,
+    /// Expressions with `file`, `line` and (optionally) `col`, `severity` and `message` groups,
+    /// tried before the built-in one
+    pub error_format: Vec<String>,
+}
+
+impl FromLua for TaskCommand {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        if let LuaValue::Table(table) = val {
+            // Error formats can be given as a single expression or as a list of them
+            let error_format = match table.get::<LuaValue>("error_format")? {
+                LuaValue::String(format) => vec![format.to_str()?.to_string()],
+                LuaValue::Table(formats) => formats
+                    .sequence_values::<String>()
+                    .collect::<LuaResult<Vec<String>>>()?,
+                _ => vec![],
+            };
+            Ok(Self {
+                command: table.get("command")?,
+                error_format,
+            })
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
@@ -211,7 +211,6 @@ pub fn normalize_path(path: &Path) -> PathBuf {
                 }
                 std::path::Component::CurDir => {
                     // Skip current directory markers
-                    continue;
                 }
                 _ => components.push(component),
             }
@@ -297,12 +296,12 @@ pub fn validate_path(path: &str) -> bool {
     }
     
     // Additional check: if path starts with multiple parent dirs, it's likely malicious
-    let mut components = path_buf.components();
+    let components = path_buf.components();
     let mut parent_count = 0;
-    while let Some(component) = components.next() {
+    for component in components {
         match component {
             std::path::Component::ParentDir => parent_count += 1,
-            std::path::Component::CurDir => continue,
+            std::path::Component::CurDir => (),
             _ => break,
         }
     }
@@ -352,7 +351,7 @@ pub fn is_absolute_path(path: &str) -> bool {
 
 /// Make a path relative to a base path
 pub fn make_relative(path: &Path, base: &Path) -> Option<PathBuf> {
-    path.strip_prefix(base).ok().map(|p| p.to_path_buf())
+    path.strip_prefix(base).ok().map(std::path::Path::to_path_buf)
 }
 
 #[cfg(test)]
@@ -362,7 +361,7 @@ mod tests {
     #[test]
     fn test_expand_tilde() {
         let expanded = expand_tilde("~/test/file.txt");
-        assert!(!expanded.to_string_lossy().contains("~"));
+        assert!(!expanded.to_string_lossy().contains('~'));
         
         let expanded_home = expand_tilde("~");
         assert_eq!(expanded_home, home_dir().unwrap());
@@ -373,14 +372,14 @@ mod tests {
     
     #[test]
     fn test_join_paths() {
-        let path = join_paths(&["home", "user", "file.txt"]);
+        let path = join_paths(["home", "user", "file.txt"]);
         let path_str = path.to_string_lossy();
         
         #[cfg(target_os = "windows")]
         assert!(path_str.contains("\\"));
         
         #[cfg(not(target_os = "windows"))]
-        assert!(path_str.contains("/"));
+        assert!(path_str.contains('/'));
     }
     
     #[test]
@@ -407,7 +406,7 @@ mod tests {
         {
             // Unix-like should use .config
             assert!(path_str.contains(".config") || path_str.contains("ox"));
-            assert!(path_str.contains("/"));
+            assert!(path_str.contains('/'));
         }
     }
     
@@ -423,7 +422,7 @@ mod tests {
         assert!(path_str.contains("\\"));
         
         #[cfg(not(target_os = "windows"))]
-        assert!(path_str.contains("/"));
+        assert!(path_str.contains('/'));
     }
     
     #[test]
@@ -455,7 +454,7 @@ mod tests {
         
         // Test tilde expansion
         let sanitized = sanitize_path("~/test.txt");
-        assert!(!sanitized.to_string_lossy().starts_with("~"));
+        assert!(!sanitized.to_string_lossy().starts_with('~'));
         
         // Test normalization
         #[cfg(not(target_os = "windows"))]
@@ -469,8 +468,8 @@ mod tests {
     fn test_forward_slash_conversion() {
         let path = PathBuf::from("home/user/file.txt");
         let forward = to_forward_slashes(&path);
-        assert!(forward.contains("/"));
-        assert!(!forward.contains("\\"));
+        assert!(forward.contains('/'));
+        assert!(!forward.contains('\\'));
         
         #[cfg(target_os = "windows")]
         {
@@ -493,7 +492,7 @@ mod tests {
         
         #[cfg(not(target_os = "windows"))]
         {
-            assert!(path.to_string_lossy().contains("/"));
+            assert!(path.to_string_lossy().contains('/'));
         }
     }
     
@@ -532,7 +531,7 @@ mod tests {
     
     #[test]
     fn test_ensure_parent_dirs() {
-        use std::fs;
+        
         use tempfile::tempdir;
         
         let temp_dir = tempdir().unwrap();
@@ -0,0 +1,286 @@
+/// Functions for pairing up brackets, to highlight, jump between and select within them
+use kaolinite::{Document, Loc};
+use synoptic::{Highlighter, TokOpt};
+
+use super::Editor;
+
+/// The most lines looked through (in each direction) when pairing up brackets
+const BRACKET_SEARCH_LINES: usize = 5000;
+
+/// Find the bracket that pairs with a bracket, and whether it comes after it
+fn partner(c: char) -> Option<(char, bool)> {
+    match c {
+        '(' => Some((')', true)),
+        '[' => Some((']', true)),
+        '{' => Some(('}', true)),
+        ')' => Some(('(', false)),
+        ']' => Some(('[', false)),
+        '}' => Some(('{', false)),
+        _ => None,
+    }
+}
+
+/// Determine if a kind of token holds text rather than code (brackets in it don't count)
+fn is_text_token(kind: &str) -> bool {
+    ["string", "comment", "character"]
+        .iter()
+        .any(|k| kind.contains(k))
+}
+
+/// Work out which characters of a line are code, rather than being in a string or comment
+/// (lines that haven't been highlighted yet are taken to be all code)
+fn code_mask(line: &str, y: usize, highlighter: &Highlighter) -> Vec<bool> {
+    if y >= highlighter.line_ref.len() {
+        return vec![true; line.chars().count()];
+    }
+    // The highlighter works on lines with tabs turned into spaces
+    let mut expanded = vec![];
+    for token in highlighter.line(y, line) {
+        let (text, code) = match token {
+            TokOpt::Some(text, kind) => (text, !is_text_token(&kind)),
+            TokOpt::None(text) => (text, true),
+        };
+        expanded.extend(std::iter::repeat_n(code, text.chars().count()));
+    }
+    let mut at = 0;
+    line.chars()
+        .map(|c| {
+            let code = expanded.get(at).copied().unwrap_or(true);
+            at += if c == '\t' { highlighter.tab_width } else { 1 };
+            code
+        })
+        .collect()
+}
+
+/// The characters of a line, along with whether each one is code
+fn code_chars(doc: &Document, highlighter: &Highlighter, y: usize) -> Option<Vec<(char, bool)>> {
+    let line = doc.line(y)?;
+    let mask = code_mask(&line, y, highlighter);
+    Some(line.chars().zip(mask).collect())
+}
+
+/// Find the bracket that pairs with the bracket at a location (None if there isn't a bracket there,
+/// or it isn't paired up). Brackets in strings and comments are ignored.
+pub fn matching_bracket(doc: &Document, highlighter: &Highlighter, at: Loc) -> Option<Loc> {
+    let chars = code_chars(doc, highlighter, at.y)?;
+    let (bracket, code) = *chars.get(at.x)?;
+    let (other, forwards) = partner(bracket)?;
+    if !code {
+        return None;
+    }
+    let mut depth = 0;
+    if forwards {
+        let last = doc.len_lines().min(at.y + BRACKET_SEARCH_LINES);
+        for y in at.y..last {
+            let line = if y == at.y {
+                chars.clone()
+            } else {
+                code_chars(doc, highlighter, y)?
+            };
+            let skip = if y == at.y { at.x + 1 } else { 0 };
+            for (x, (c, code)) in line.into_iter().enumerate().skip(skip) {
+                if !code {
+                    continue;
+                }
+                if c == bracket {
+                    depth += 1;
+                } else if c == other {
+                    if depth == 0 {
+                        return Some(Loc::at(x, y));
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+    } else {
+        let first = at.y.saturating_sub(BRACKET_SEARCH_LINES);
+        for y in (first..=at.y).rev() {
+            let mut line = if y == at.y {
+                chars.clone()
+            } else {
+                code_chars(doc, highlighter, y)?
+            };
+            if y == at.y {
+                line.truncate(at.x);
+            }
+            for (x, (c, code)) in line.into_iter().enumerate().rev() {
+                if !code {
+                    continue;
+                }
+                if c == bracket {
+                    depth += 1;
+                } else if c == other {
+                    if depth == 0 {
+                        return Some(Loc::at(x, y));
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Find the bracket at the cursor (or just before it) and the bracket it pairs with
+pub fn bracket_pair_at(doc: &Document, highlighter: &Highlighter, at: Loc) -> Option<(Loc, Loc)> {
+    let mut candidates = vec![at];
+    if at.x > 0 {
+        candidates.push(Loc::at(at.x - 1, at.y));
+    }
+    candidates
+        .into_iter()
+        .find_map(|loc| Some((loc, matching_bracket(doc, highlighter, loc)?)))
+}
+
+/// Find the innermost pair of brackets around a location (the opening and closing bracket).
+/// A location on an opening bracket counts as being within it.
+pub fn enclosing_brackets(
+    doc: &Document,
+    highlighter: &Highlighter,
+    at: Loc,
+) -> Option<(Loc, Loc)> {
+    let first = at.y.saturating_sub(BRACKET_SEARCH_LINES);
+    // Closing brackets found on the way back, which need their opening brackets skipping over
+    let mut closed = vec![];
+    for y in (first..=at.y).rev() {
+        let mut line = code_chars(doc, highlighter, y)?;
+        if y == at.y {
+            line.truncate(at.x + 1);
+        }
+        for (x, (c, code)) in line.into_iter().enumerate().rev() {
+            let loc = Loc::at(x, y);
+            match partner(c) {
+                Some(_) if !code => (),
+                // Closing brackets (other than one at the location itself) are skipped over
+                Some((opener, false)) if loc != at => closed.push(opener),
+                Some((_, true)) if closed.last() == Some(&c) => {
+                    closed.pop();
+                }
+                Some((_, true)) => return Some((loc, matching_bracket(doc, highlighter, loc)?)),
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+impl Editor {
+    /// Work out which brackets to highlight in the document that is focused
+    pub fn focused_bracket_pair(&self) -> Option<(Loc, Loc)> {
+        let fc = self.files.get(self.ptr.clone())?;
+        if fc.doc.is_paged() {
+            return None;
+        }
+        bracket_pair_at(&fc.doc, &fc.highlighter, fc.doc.char_loc())
+    }
+
+    /// Move the cursor to the bracket that pairs with the one at the cursor
+    pub fn move_to_matching_bracket(&mut self) -> bool {
+        let Some((_, other)) = self.focused_bracket_pair() else {
+            return false;
+        };
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+            doc.move_to(&other);
+        }
+        true
+    }
+
+    /// Select within the innermost brackets around the cursor,
+    /// leaving out the brackets themselves unless `around` is true
+    pub fn select_brackets(&mut self, around: bool) -> bool {
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return false;
+        };
+        let Some((open, close)) = enclosing_brackets(&fc.doc, &fc.highlighter, fc.doc.char_loc())
+        else {
+            return false;
+        };
+        let (start, end) = if around {
+            (open, Loc::at(close.x + 1, close.y))
+        } else {
+            (Loc::at(open.x + 1, open.y), close)
+        };
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+            doc.move_to(&end);
+            doc.select_to(&start);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaolinite::event::Event;
+    use kaolinite::utils::Size;
+
+    fn document(text: &str) -> Document {
+        let mut doc = Document::new(Size { w: 80, h: 20 });
+        doc.exe(Event::Insert(Loc::at(0, 0), text.to_string()))
+            .unwrap();
+        doc
+    }
+
+    fn highlighter(doc: &Document) -> Highlighter {
+        let mut highlighter = Highlighter::new(4);
+        highlighter.bounded("string", "\"", "\"", true);
+        highlighter.keyword("comment", "//.*$");
+        let lines: Vec<String> = (0..doc.len_lines()).map(|y| doc.line(y).unwrap()).collect();
+        highlighter.run(&lines);
+        highlighter
+    }
+
+    #[test]
+    fn bracket_matching() {
+        let doc = document("fn f(a: [u8]) {\n\tg(\")\", b); // )\n}");
+        let hl = highlighter(&doc);
+        assert_eq!(
+            matching_bracket(&doc, &hl, Loc::at(4, 0)),
+            Some(Loc::at(12, 0))
+        );
+        assert_eq!(
+            matching_bracket(&doc, &hl, Loc::at(12, 0)),
+            Some(Loc::at(4, 0))
+        );
+        // Brackets in strings and comments are skipped (even after a tab)
+        assert_eq!(
+            matching_bracket(&doc, &hl, Loc::at(2, 1)),
+            Some(Loc::at(9, 1))
+        );
+        assert_eq!(matching_bracket(&doc, &hl, Loc::at(4, 1)), None);
+        assert_eq!(
+            matching_bracket(&doc, &hl, Loc::at(14, 0)),
+            Some(Loc::at(0, 2))
+        );
+        assert_eq!(
+            matching_bracket(&doc, &hl, Loc::at(0, 2)),
+            Some(Loc::at(14, 0))
+        );
+        assert_eq!(matching_bracket(&doc, &hl, Loc::at(0, 0)), None);
+        // The bracket just before the cursor counts too
+        assert_eq!(
+            bracket_pair_at(&doc, &hl, Loc::at(13, 0)),
+            Some((Loc::at(12, 0), Loc::at(4, 0)))
+        );
+        // The innermost brackets around a location
+        assert_eq!(
+            enclosing_brackets(&doc, &hl, Loc::at(6, 0)),
+            Some((Loc::at(4, 0), Loc::at(12, 0)))
+        );
+        assert_eq!(
+            enclosing_brackets(&doc, &hl, Loc::at(9, 0)),
+            Some((Loc::at(8, 0), Loc::at(11, 0)))
+        );
+        assert_eq!(
+            enclosing_brackets(&doc, &hl, Loc::at(8, 1)),
+            Some((Loc::at(2, 1), Loc::at(9, 1)))
+        );
+        assert_eq!(
+            enclosing_brackets(&doc, &hl, Loc::at(12, 1)),
+            Some((Loc::at(14, 0), Loc::at(0, 2)))
+        );
+        assert_eq!(enclosing_brackets(&doc, &hl, Loc::at(1, 0)), None);
+    }
+}
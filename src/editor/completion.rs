@@ -0,0 +1,648 @@
+/// Functions for suggesting how to finish what is being typed, in a popup by the cursor
+use crate::config;
+use crate::dirs::expand_tilde;
+use crate::error::Result;
+use crate::ui::{size, Feedback};
+use crate::{display, ged};
+use crossterm::event::{Event as CEvent, KeyCode as KCode, KeyModifiers as KMod};
+use crossterm::style::{
+    Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+};
+use kaolinite::event::Event;
+use kaolinite::utils::{width, width_char};
+use kaolinite::{Document, Loc};
+use mlua::prelude::*;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::Editor;
+
+/// How many lines either side of the cursor are looked through for words to suggest
+const WORD_SEARCH_LINES: usize = 1000;
+/// The widest the popup can be
+const MAX_WIDTH: usize = 60;
+/// Characters that end a file path being typed (along with whitespace)
+const PATH_DELIMITERS: &str = "\"'`()[]{}<>,;=";
+
+/// Where a suggestion came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// A word found in the document
+    Word,
+    /// A file or folder
+    Path,
+    /// A snippet from the configuration file
+    Snippet,
+    /// A function registered by a plug-in
+    Plugin,
+    /// The language server of the document
+    Server,
+}
+
+/// A suggestion of what could be typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// What to show in the popup
+    pub label: String,
+    /// The text to put in place of what has been typed (`$0` marks where the cursor goes)
+    pub insert: String,
+    /// Extra information shown alongside the label
+    pub detail: Option<String>,
+    /// Where the suggestion came from
+    pub source: Source,
+}
+
+impl Candidate {
+    /// Create a suggestion that inserts its label
+    pub fn new(label: &str, detail: Option<String>, source: Source) -> Self {
+        Self {
+            label: label.to_string(),
+            insert: label.to_string(),
+            detail,
+            source,
+        }
+    }
+}
+
+/// What is being completed at the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    /// The character index on the line where the text being completed starts
+    pub start: usize,
+    /// The text typed so far
+    pub prefix: String,
+    /// The folder part of a file path being typed (None if a path isn't being typed)
+    pub path: Option<String>,
+}
+
+/// The state of the completion popup
+#[derive(Debug, Default)]
+pub struct CompletionPopup {
+    /// Whether the popup is open
+    pub active: bool,
+    /// Set when the popup is asked for (it is filled once the current event has been handled)
+    pub requested: bool,
+    /// Where the text being completed starts (as a character location)
+    pub start: Loc,
+    /// Every suggestion gathered when the popup was opened
+    pub candidates: Vec<Candidate>,
+    /// The indices of the suggestions that match what has been typed (best first)
+    pub shown: Vec<usize>,
+    /// The index (into shown) of the selected suggestion
+    pub selected: usize,
+    /// The index (into shown) of the suggestion at the top of the popup
+    pub scroll: usize,
+}
+
+impl CompletionPopup {
+    /// Find the suggestion that is selected
+    pub fn current(&self) -> Option<&Candidate> {
+        self.candidates.get(*self.shown.get(self.selected)?)
+    }
+
+    /// Move the selection, wrapping around at either end
+    fn select_by(&mut self, by: isize) {
+        let len = self.shown.len();
+        if len > 0 {
+            let selected = self.selected.cast_signed() + by;
+            self.selected = selected.rem_euclid(len.cast_signed()).cast_unsigned();
+        }
+    }
+
+    /// Scroll so the selected suggestion is visible (when a certain number of them are shown)
+    fn scroll_to_selected(&mut self, rows: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+}
+
+impl Editor {
+    /// Handle a key while the popup is open.
+    /// Returns true if the key was used up by the popup.
+    pub fn completion_key(&mut self, modifiers: KMod, code: KCode) -> Result<bool> {
+        if !self.completion.active {
+            return Ok(false);
+        }
+        let page = config!(self.config, completion).max_items.cast_signed();
+        match (modifiers, code) {
+            (KMod::NONE, KCode::Up) => self.completion.select_by(-1),
+            (KMod::NONE, KCode::Down) => self.completion.select_by(1),
+            (KMod::NONE, KCode::PageUp) => self.completion.select_by(-page),
+            (KMod::NONE, KCode::PageDown) => self.completion.select_by(page),
+            (KMod::NONE, KCode::Tab | KCode::Enter) => self.accept_completion()?,
+            (KMod::NONE, KCode::Esc) => self.close_completion(),
+            _ => return Ok(false),
+        }
+        self.needs_rerender = true;
+        Ok(true)
+    }
+
+    /// Work out what is being completed at the cursor
+    pub fn completion_context(&self) -> Option<Context> {
+        let doc = self.try_doc()?;
+        let loc = doc.char_loc();
+        Some(context_at(&doc.line(loc.y)?, loc.x))
+    }
+
+    /// Gather the suggestions that don't come from plug-ins
+    /// (asking the language server too, if there is one and it is wanted)
+    pub fn builtin_candidates(&mut self, context: &Context, ask_server: bool) -> Vec<Candidate> {
+        let mut result = vec![];
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return result;
+        };
+        let config = config!(self.config, completion);
+        if let Some(folder) = &context.path {
+            if config.file_paths {
+                let base = fc.doc.file_name.as_deref().and_then(|f| Path::new(f).parent());
+                result.extend(path_candidates(folder, &context.prefix, base));
+            }
+            return result;
+        }
+        let file_type = fc.file_type.as_ref().map(|t| t.name.as_str());
+        for (trigger, body) in config.snippets_for(file_type) {
+            result.push(Candidate {
+                label: trigger,
+                insert: body,
+                detail: Some("snippet".to_string()),
+                source: Source::Snippet,
+            });
+        }
+        if config.buffer_words {
+            let words = buffer_words(&fc.doc, &fc.doc.char_loc());
+            result.extend(words.iter().map(|w| Candidate::new(w, None, Source::Word)));
+        }
+        drop(config);
+        if ask_server && fc.file_type.as_ref().is_some_and(|t| t.lsp.is_some()) {
+            if let Ok(items) = self.lsp_completion() {
+                result.extend(items.into_iter().map(|item| Candidate {
+                    label: item.label,
+                    insert: item.insert_text,
+                    detail: item.detail,
+                    source: Source::Server,
+                }));
+            }
+        }
+        result
+    }
+
+    /// Open the popup with some suggestions for the text starting at a location.
+    /// Returns true if any of the suggestions match what has been typed.
+    pub fn open_completion(&mut self, start: Loc, candidates: Vec<Candidate>) -> bool {
+        self.completion = CompletionPopup {
+            active: true,
+            start,
+            candidates,
+            ..CompletionPopup::default()
+        };
+        self.refresh_completion();
+        self.completion.active
+    }
+
+    /// Filter the suggestions by what has been typed, closing the popup if the cursor
+    /// has left what is being completed or nothing matches any more
+    pub fn refresh_completion(&mut self) {
+        let context = self.completion_context();
+        let y = self.try_doc().map(|doc| doc.loc().y);
+        let start = self.completion.start;
+        match context {
+            Some(context) if context.start == start.x && y == Some(start.y) => {
+                self.completion.shown = filter(&self.completion.candidates, &context.prefix);
+                self.completion.selected = 0;
+                self.completion.scroll = 0;
+                if self.completion.shown.is_empty() {
+                    self.close_completion();
+                }
+            }
+            _ => self.close_completion(),
+        }
+    }
+
+    /// Close the popup
+    pub fn close_completion(&mut self) {
+        self.completion = CompletionPopup::default();
+    }
+
+    /// Put the selected suggestion in place of what has been typed, and close the popup
+    pub fn accept_completion(&mut self) -> Result<()> {
+        let candidate = self.completion.current().cloned();
+        let start = self.completion.start;
+        self.close_completion();
+        let (Some(candidate), Some(doc)) = (candidate, self.try_doc()) else {
+            return Ok(());
+        };
+        let loc = doc.char_loc();
+        let line = doc.line(loc.y).unwrap_or_default();
+        let typed: String = line.chars().skip(start.x).take(loc.x - start.x).collect();
+        let indent: String = line.chars().take_while(|c| c.is_whitespace()).collect();
+        // Lines after the first (of snippets) line up with the line they are put on
+        let insert = candidate.insert.replace('\n', &format!("\n{indent}"));
+        let (insert, cursor) = match insert.split_once("$0") {
+            Some((before, after)) => (format!("{before}{after}"), Some(before.to_string())),
+            None => (insert, None),
+        };
+        // The suggestion is one change to undo
+        if let Some(doc) = self.try_doc_mut() {
+            doc.commit();
+        }
+        if let Some(rest) = insert.strip_prefix(&typed) {
+            if !rest.is_empty() {
+                self.exe(Event::Insert(loc, rest.to_string()))?;
+            }
+        } else {
+            if !typed.is_empty() {
+                self.exe(Event::Delete(start, typed))?;
+            }
+            self.exe(Event::Insert(start, insert))?;
+        }
+        if let Some(doc) = self.try_doc_mut() {
+            doc.commit();
+            if let Some(before) = cursor {
+                doc.move_to(&advance(start, &before));
+            }
+        }
+        self.reload_highlight();
+        Ok(())
+    }
+
+    /// Render the popup over the document, by the cursor
+    pub fn render_completion(&mut self) -> Result<()> {
+        if !self.completion.active {
+            return Ok(());
+        }
+        let (Some(at), Some(doc)) = (self.cursor_position(), self.try_doc()) else {
+            return Ok(());
+        };
+        let tab_width = config!(self.config, document).tab_width;
+        let loc = doc.char_loc();
+        let line = doc.line(loc.y).unwrap_or_default();
+        let typed = width(
+            &line.chars().skip(self.completion.start.x).take(loc.x.saturating_sub(self.completion.start.x)).collect::<String>(),
+            tab_width,
+        );
+        let colors = config!(self.config, colors);
+        let normal = (
+            Fg(colors.completion_fg.to_color()?),
+            Bg(colors.completion_bg.to_color()?),
+        );
+        let highlighted = (
+            Fg(colors.completion_selection_fg.to_color()?),
+            Bg(colors.completion_selection_bg.to_color()?),
+        );
+        drop(colors);
+        let max_items = config!(self.config, completion).max_items;
+        let screen = size()?;
+        let popup = &mut self.completion;
+        popup.scroll_to_selected(max_items);
+        let end = (popup.scroll + max_items).min(popup.shown.len());
+        let rows: Vec<(bool, &Candidate)> = (popup.scroll..end)
+            .map(|i| (i == popup.selected, &popup.candidates[popup.shown[i]]))
+            .collect();
+        // Work out how big the popup is, and where it goes
+        let label_width = rows.iter().map(|(_, c)| width(&c.label, tab_width)).max().unwrap_or(0);
+        let detail_width = rows
+            .iter()
+            .map(|(_, c)| c.detail.as_ref().map_or(0, |d| width(d, tab_width) + 2))
+            .max()
+            .unwrap_or(0);
+        let w = (label_width + detail_width + 2).min(MAX_WIDTH).min(screen.w);
+        // The popup lines up with the start of what is being completed
+        let x = at.x.saturating_sub(typed).min(screen.w.saturating_sub(w));
+        // Below the cursor if there is room (above the feedback line), otherwise above it
+        let room_below = screen.h.saturating_sub(at.y + 1);
+        let top = if rows.len() <= room_below {
+            at.y + 1
+        } else {
+            at.y.saturating_sub(rows.len())
+        };
+        let mut output = vec![];
+        for (selected, candidate) in rows {
+            let detail = candidate.detail.clone().unwrap_or_default();
+            let detail_room = w.saturating_sub(label_width + 2).min(width(&detail, tab_width));
+            let label = fit(&candidate.label, w.saturating_sub(2 + detail_room), tab_width);
+            let detail = fit(&detail, detail_room, tab_width);
+            let gap = w.saturating_sub(2 + width(&label, tab_width) + width(&detail, tab_width));
+            let (fg, bg) = if selected { highlighted } else { normal };
+            output.push(format!("{bg}{fg} {label}{} {detail}", " ".repeat(gap)));
+        }
+        for (i, row) in output.into_iter().enumerate() {
+            self.terminal.goto(x, top + i);
+            display!(self, row);
+        }
+        Ok(())
+    }
+}
+
+/// Open, refresh or close the completion popup after an event has been handled.
+/// This sits outside of the editor, as plug-ins that give suggestions may use the editor too.
+pub fn update_completion(editor: &LuaAnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
+    let (typed, requested) = {
+        let mut ed = ged!(mut &editor);
+        let requested = std::mem::take(&mut ed.completion.requested);
+        // Multiple cursors would each want their own popup
+        if ed.try_doc().is_none_or(|doc| !doc.secondary_cursors.is_empty()) {
+            ed.close_completion();
+            return Ok(());
+        }
+        let typed = match event {
+            CEvent::Key(key) => match (key.modifiers, key.code) {
+                (KMod::NONE | KMod::SHIFT, KCode::Char(ch)) => Some(Some(ch)),
+                (KMod::NONE, KCode::Backspace) => Some(None),
+                _ => None,
+            },
+            CEvent::Resize(..) => return Ok(()),
+            _ => None,
+        };
+        // Typing changes what the suggestions are filtered by, anything else closes the popup
+        if ed.completion.active {
+            if typed.is_some() && !requested {
+                ed.refresh_completion();
+                if ed.completion.active {
+                    return Ok(());
+                }
+            } else {
+                ed.close_completion();
+            }
+        }
+        (typed.flatten(), requested)
+    };
+    let Some(context) = ged!(&editor).completion_context() else {
+        return Ok(());
+    };
+    // Decide whether to open the popup
+    if !requested {
+        let ed = ged!(&editor);
+        let config = config!(ed.config, completion);
+        let long_enough = context.prefix.chars().count() >= config.min_length
+            && context.prefix.starts_with(|c: char| !c.is_ascii_digit());
+        let word_typed = typed.is_some_and(is_word_char) && context.path.is_none() && long_enough;
+        let path_typed = typed == Some('/') && context.path.is_some();
+        if !config.auto || !(word_typed || path_typed) {
+            return Ok(());
+        }
+    }
+    // Gather suggestions from everywhere
+    let mut candidates = ged!(mut &editor).builtin_candidates(&context, requested);
+    let providers = config!(ged!(&editor).config, completion).providers.clone();
+    if !providers.is_empty() && context.path.is_none() {
+        let info = lua.create_table()?;
+        info.set("prefix", context.prefix.clone())?;
+        if let Some(doc) = ged!(&editor).try_doc() {
+            let loc = doc.char_loc();
+            info.set("line", doc.line(loc.y).unwrap_or_default())?;
+            info.set("x", loc.x)?;
+            info.set("y", loc.y + 1)?;
+        }
+        for provider in providers {
+            match provider.call::<LuaValue>(info.clone()) {
+                Ok(items) => candidates.extend(lua_candidates(&items)),
+                Err(err) => ged!(mut &editor).feedback = Feedback::Error(err.to_string()),
+            }
+        }
+    }
+    let mut ed = ged!(mut &editor);
+    let start = Loc::at(context.start, ed.try_doc().map_or(0, |doc| doc.loc().y));
+    if !ed.open_completion(start, candidates) && requested {
+        ed.feedback = Feedback::Info("No suggestions found".to_string());
+    }
+    ed.needs_rerender = true;
+    Ok(())
+}
+
+/// Read the suggestions given by a plug-in (a list of labels or of tables with a label,
+/// with the text to insert and a detail optional)
+fn lua_candidates(items: &LuaValue) -> Vec<Candidate> {
+    let LuaValue::Table(items) = items else {
+        return vec![];
+    };
+    let mut result = vec![];
+    for item in items.clone().sequence_values::<LuaValue>().filter_map(LuaResult::ok) {
+        match item {
+            LuaValue::String(label) => {
+                result.push(Candidate::new(&label.to_string_lossy(), None, Source::Plugin));
+            }
+            LuaValue::Table(item) => {
+                let Ok(label) = item.get::<String>("label") else {
+                    continue;
+                };
+                let insert = item.get::<Option<String>>("insert").ok().flatten();
+                result.push(Candidate {
+                    insert: insert.unwrap_or_else(|| label.clone()),
+                    detail: item.get::<Option<String>>("detail").ok().flatten(),
+                    label,
+                    source: Source::Plugin,
+                });
+            }
+            _ => (),
+        }
+    }
+    result
+}
+
+/// Determine if a character can be part of a word being completed
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Work out what is being completed at a character index on a line
+pub fn context_at(line: &str, x: usize) -> Context {
+    let before: Vec<char> = line.chars().take(x).collect();
+    // File paths run back to whitespace or a delimiter, and are completed from their last slash
+    let token_start = before
+        .iter()
+        .rposition(|c| c.is_whitespace() || PATH_DELIMITERS.contains(*c))
+        .map_or(0, |i| i + 1);
+    if let Some(slash) = before[token_start..].iter().rposition(|c| *c == '/') {
+        let start = token_start + slash + 1;
+        return Context {
+            start,
+            prefix: before[start..].iter().collect(),
+            path: Some(before[token_start..start].iter().collect()),
+        };
+    }
+    let start = before
+        .iter()
+        .rposition(|c| !is_word_char(*c))
+        .map_or(0, |i| i + 1);
+    Context {
+        start,
+        prefix: before[start..].iter().collect(),
+        path: None,
+    }
+}
+
+/// Find the suggestions that match what has been typed, best first: those that start with it
+/// (matching case first), then those that contain its characters in order
+pub fn filter(candidates: &[Candidate], prefix: &str) -> Vec<usize> {
+    let lower = prefix.to_lowercase();
+    let mut ranked: Vec<(usize, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !(c.source == Source::Word && c.label == prefix))
+        .filter_map(|(i, c)| {
+            let label = c.label.to_lowercase();
+            let rank = if c.label.starts_with(prefix) {
+                0
+            } else if label.starts_with(&lower) {
+                1
+            } else if is_subsequence(&lower, &label) {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, i))
+        })
+        .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    // The same suggestion from different places only needs showing once
+    let mut seen = HashSet::new();
+    ranked
+        .into_iter()
+        .map(|(_, i)| i)
+        .filter(|i| seen.insert((&candidates[*i].label, &candidates[*i].insert)))
+        .collect()
+}
+
+/// Determine if the characters of one string appear in another (in order)
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|n| haystack.any(|h| h == n))
+}
+
+/// Find the words in the lines around a location (apart from the word at the location)
+pub fn buffer_words(doc: &Document, at: &Loc) -> Vec<String> {
+    let first = at.y.saturating_sub(WORD_SEARCH_LINES);
+    let last = (at.y + WORD_SEARCH_LINES).min(doc.len_lines());
+    let mut text = String::new();
+    let mut cursor = 0;
+    for y in first..last {
+        let line = doc.line(y).unwrap_or_default();
+        if y == at.y {
+            cursor = text.len() + line.chars().take(at.x).map(char::len_utf8).sum::<usize>();
+        }
+        text.push_str(&line);
+        text.push('\n');
+    }
+    // Word boundaries are given as byte indices
+    let mut seen = HashSet::new();
+    doc.word_boundaries(&text)
+        .into_iter()
+        .filter(|(start, end)| !(*start <= cursor && cursor <= *end))
+        // Boundaries only cover ASCII, so leave out parts of words with other letters in
+        .filter(|(start, end)| {
+            !text[..*start].ends_with(is_word_char) && !text[*end..].starts_with(is_word_char)
+        })
+        .map(|(start, end)| &text[start..end])
+        .filter(|w| w.chars().count() > 1 && w.starts_with(|c: char| c.is_alphabetic() || c == '_'))
+        .filter(|w| seen.insert(*w))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Find the files and folders within a folder (relative to another folder if need be)
+fn path_candidates(folder: &str, prefix: &str, base: Option<&Path>) -> Vec<Candidate> {
+    let mut dir = expand_tilde(folder);
+    if dir.is_relative() {
+        if let Some(base) = base {
+            dir = base.join(dir);
+        }
+    }
+    if dir.as_os_str().is_empty() {
+        dir = PathBuf::from(".");
+    }
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return vec![];
+    };
+    let mut result: Vec<Candidate> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Hidden files are only suggested once a dot is typed
+            if name.starts_with('.') && !prefix.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let (label, detail) = if is_dir {
+                (format!("{name}/"), "folder")
+            } else {
+                (name, "file")
+            };
+            Some(Candidate::new(&label, Some(detail.to_string()), Source::Path))
+        })
+        .collect();
+    result.sort_by(|a, b| a.label.cmp(&b.label));
+    result
+}
+
+/// Find where a location ends up after some text is inserted at it
+fn advance(at: Loc, text: &str) -> Loc {
+    match text.rsplit_once('\n') {
+        Some((before, last)) => Loc::at(last.chars().count(), at.y + before.matches('\n').count() + 1),
+        None => Loc::at(at.x + text.chars().count(), at.y),
+    }
+}
+
+/// Cut some text down to fit within a width
+fn fit(text: &str, max: usize, tab_width: usize) -> String {
+    let mut result = String::new();
+    let mut total = 0;
+    for c in text.chars() {
+        total += width_char(&c, tab_width);
+        if total > max {
+            break;
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaolinite::utils::Size;
+
+    fn words(list: &[&str]) -> Vec<Candidate> {
+        list.iter().map(|w| Candidate::new(w, None, Source::Word)).collect()
+    }
+
+    #[test]
+    fn completion_context() {
+        let context = context_at("let value = some_thi", 20);
+        assert_eq!(context.start, 12);
+        assert_eq!(context.prefix, "some_thi");
+        assert_eq!(context.path, None);
+        let context = context_at("open(\"src/edi", 13);
+        assert_eq!(context.start, 10);
+        assert_eq!(context.prefix, "edi");
+        assert_eq!(context.path, Some("src/".to_string()));
+        assert_eq!(context_at("x = ", 4).prefix, "");
+    }
+
+    #[test]
+    fn completion_filtering() {
+        let candidates = words(&["Print", "println", "sprint", "print", "other"]);
+        let names = |prefix| -> Vec<&str> {
+            filter(&candidates, prefix).iter().map(|i| candidates[*i].label.as_str()).collect()
+        };
+        // Matching case first, then any case, then anything containing the letters in order
+        assert_eq!(names("pri"), vec!["println", "print", "Print", "sprint"]);
+        // The word being typed isn't suggested back
+        assert_eq!(names("print"), vec!["println", "Print", "sprint"]);
+        assert_eq!(names("").len(), 5);
+        assert!(names("xyz").is_empty());
+    }
+
+    #[test]
+    fn completion_buffer_words() {
+        let mut doc = Document::new(Size::is(80, 10));
+        doc.exe(Event::Insert(Loc::at(0, 0), "let über = 1;\nlet total = über + 2;\nto".to_string()))
+            .unwrap();
+        let found = buffer_words(&doc, &Loc::at(2, 2));
+        assert_eq!(found, vec!["let", "total"]);
+        assert_eq!(advance(Loc::at(4, 1), "fn a() {\n    "), Loc::at(4, 2));
+        assert_eq!(advance(Loc::at(4, 1), "ab"), Loc::at(6, 1));
+    }
+}
@@ -155,12 +155,12 @@ pub fn handle_multiple_cursors(
         }
         // Replay the event
         let old_loc = ged!(&editor).try_doc()
-            .map(|d| d.char_loc())
+            .map(kaolinite::Document::char_loc)
             .unwrap_or_default();
         handle_event(editor, event, lua)?;
         // Prevent any interference
         let char_loc = ged!(&editor).try_doc()
-            .map(|d| d.char_loc())
+            .map(kaolinite::Document::char_loc)
             .unwrap_or_default();
         cursor.loc = adjust_other_cursors(
             &mut secondary_cursors,
@@ -183,8 +183,7 @@ pub fn handle_multiple_cursors(
     // Restore back to the state of the document beforehand
     // Calculate the character pointer based on the cursor location
     let char_ptr = ged!(&editor).try_doc()
-        .map(|d| d.character_idx(&cursor.loc))
-        .unwrap_or(0);
+        .map_or(0, |d| d.character_idx(&cursor.loc));
     // Update all cursor state atomically
     if let Some(doc_mut) = ged!(mut &editor).try_doc_mut() {
         doc_mut.cursor = cursor;
@@ -1,7 +1,8 @@
 /// Tools for placing all information about open files into one place
-use crate::editor::{get_absolute_path, Editor, FileType};
+use crate::editor::{get_absolute_path, Editor, FileType, Folds, Formatting};
 use crate::pty_cross::Pty;
 use crate::Loc;
+use kaolinite::document::DiskState;
 use kaolinite::Document;
 use kaolinite::Size;
 use std::ops::Range;
@@ -12,6 +13,7 @@ pub type Span = Vec<(Vec<usize>, Range<usize>, Range<usize>)>;
 
 // File split structure
 #[derive(Debug)]
+#[derive(Default)]
 pub enum FileLayout {
     /// Side-by-side documents (with proportions)
     SideBySide(Vec<(FileLayout, f64)>),
@@ -20,18 +22,18 @@ pub enum FileLayout {
     /// Single file container (and pointer for tabs)
     Atom(Vec<FileContainer>, usize),
     /// Placeholder for an empty file split
+    #[default]
     None,
     /// Representing a file tree
     FileTree,
     /// Representing a terminal
     Terminal(Arc<Mutex<Pty>>),
+    /// Representing the results of a search across the project
+    SearchResults,
+    /// Representing the problems found by running a task
+    Problems,
 }
 
-impl Default for FileLayout {
-    fn default() -> Self {
-        Self::None
-    }
-}
 
 impl FileLayout {
     /// Will return file containers and what span of columns and rows they take up
@@ -45,7 +47,7 @@ impl FileLayout {
         match self {
             Self::None => vec![],
             // Atom file trees and terminals: stretch from starting position through to end of their containers
-            Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => {
+            Self::Atom(_, _) | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                 vec![(idx, at.y..at.y + size.h, at.x..at.x + size.w)]
             }
             // SideBySide: distributes available container space to each sub-layout
@@ -128,7 +130,7 @@ impl FileLayout {
                 }
             })
             .collect();
-        appropriate.sort_by(|a, b| a.2.start.cmp(&b.2.start));
+        appropriate.sort_by_key(|a| a.2.start);
         appropriate
     }
 
@@ -161,7 +163,7 @@ impl FileLayout {
     /// Work out how many files are currently open
     pub fn len(&self) -> usize {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => 0,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => 0,
             Self::Atom(containers, _) => containers.len(),
             Self::SideBySide(layouts) => layouts.iter().map(|(layout, _)| layout.len()).sum(),
             Self::TopToBottom(layouts) => layouts.iter().map(|(layout, _)| layout.len()).sum(),
@@ -171,17 +173,70 @@ impl FileLayout {
     /// Work out how many atoms are currently open
     pub fn n_atoms(&self) -> usize {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => 0,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => 0,
             Self::Atom(_, _) => 1,
             Self::SideBySide(layouts) => layouts.iter().map(|(layout, _)| layout.n_atoms()).sum(),
             Self::TopToBottom(layouts) => layouts.iter().map(|(layout, _)| layout.n_atoms()).sum(),
         }
     }
 
+    /// Find the indices of every atom in this layout
+    pub fn atoms(&self, idx: Vec<usize>) -> Vec<Vec<usize>> {
+        match self {
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => vec![],
+            Self::Atom(_, _) => vec![idx],
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => layouts
+                .iter()
+                .enumerate()
+                .flat_map(|(nth, (layout, _))| {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    layout.atoms(this_idx)
+                })
+                .collect(),
+        }
+    }
+
+    /// Find where the results of a project search are shown
+    pub fn search_results(&self, idx: Vec<usize>) -> Option<Vec<usize>> {
+        match self {
+            Self::SearchResults => Some(idx),
+            Self::None | Self::Atom(_, _) | Self::FileTree | Self::Problems | Self::Terminal(_) => {
+                None
+            }
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                layouts.iter().enumerate().find_map(|(nth, (layout, _))| {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    layout.search_results(this_idx)
+                })
+            }
+        }
+    }
+
+    /// Find where the problems found by a task are shown
+    pub fn problems(&self, idx: Vec<usize>) -> Option<Vec<usize>> {
+        match self {
+            Self::Problems => Some(idx),
+            Self::None
+            | Self::Atom(_, _)
+            | Self::FileTree
+            | Self::SearchResults
+            | Self::Terminal(_) => None,
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                layouts.iter().enumerate().find_map(|(nth, (layout, _))| {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    layout.problems(this_idx)
+                })
+            }
+        }
+    }
+
     /// Find a file container location from it's path
     pub fn find(&self, idx: Vec<usize>, path: &str) -> Option<(Vec<usize>, usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => None,
             Self::Atom(containers, _) => {
                 // Scan this atom for any documents
                 for (ptr, container) in containers.iter().enumerate() {
@@ -211,7 +266,7 @@ impl FileLayout {
     /// Get the `FileLayout` at a certain index
     pub fn get_raw(&self, mut idx: Vec<usize>) -> Option<&FileLayout> {
         match self {
-            Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => Some(self),
+            Self::None | Self::Atom(_, _) | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => Some(self),
             Self::SideBySide(layouts) => {
                 if idx.is_empty() {
                     Some(self)
@@ -237,7 +292,7 @@ impl FileLayout {
             Some(self)
         } else {
             match self {
-                Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => Some(self),
+                Self::None | Self::Atom(_, _) | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => Some(self),
                 Self::SideBySide(layouts) => {
                     let subidx = idx.remove(0);
                     layouts.get_mut(subidx)?.0.get_raw_mut(idx)
@@ -253,7 +308,7 @@ impl FileLayout {
     /// Get the `FileLayout` at a certain index
     pub fn set(&mut self, mut idx: Vec<usize>, fl: FileLayout) {
         match self {
-            Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => *self = fl,
+            Self::None | Self::Atom(_, _) | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => *self = fl,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 if idx.is_empty() {
                     *self = fl;
@@ -268,7 +323,7 @@ impl FileLayout {
     /// Given an index, find the file containers in the tree
     pub fn get_atom(&self, mut idx: Vec<usize>) -> Option<(&[FileContainer], usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => None,
             Self::Atom(containers, ptr) => Some((containers, *ptr)),
             Self::SideBySide(layouts) => {
                 let subidx = idx.remove(0);
@@ -287,7 +342,7 @@ impl FileLayout {
         mut idx: Vec<usize>,
     ) -> Option<(&mut Vec<FileContainer>, &mut usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => None,
             Self::Atom(ref mut containers, ref mut ptr) => Some((containers, ptr)),
             Self::SideBySide(layouts) => {
                 let subidx = idx.remove(0);
@@ -320,7 +375,7 @@ impl FileLayout {
     /// In the currently active atom, move to a different document
     pub fn move_to(&mut self, mut idx: Vec<usize>, ptr: usize) {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => (),
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => (),
             Self::Atom(_, ref mut old_ptr) => *old_ptr = ptr,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 let subidx = idx.remove(0);
@@ -366,7 +421,7 @@ impl FileLayout {
             // Determine behaviour based on parent
             if let Some(parent) = self.get_raw_mut(at_parent) {
                 match parent {
-                    Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => {
+                    Self::None | Self::Atom(_, _) | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                         unreachable!()
                     }
                     Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
@@ -392,7 +447,7 @@ impl FileLayout {
     /// Traverse the tree and return a list of indices to empty atoms
     pub fn empty_atoms(&self, at: Vec<usize>) -> Option<Vec<usize>> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Terminal(_) => None,
             Self::Atom(fcs, _) => {
                 if fcs.is_empty() {
                     Some(at)
@@ -420,7 +475,7 @@ impl FileLayout {
     /// Traverse the tree and return a list of indices to redundant sidebyside/toptobottom
     pub fn redundant_multis(&self, at: Vec<usize>) -> Option<Vec<usize>> {
         match self {
-            Self::None | Self::FileTree | Self::Atom(_, _) | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::Atom(_, _) | Self::SearchResults | Self::Problems | Self::Terminal(_) => None,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 if layouts.len() == 1 {
                     Some(at)
@@ -442,14 +497,14 @@ impl FileLayout {
     #[cfg(not(target_os = "windows"))]
     pub fn terminal_rerender(&mut self) -> bool {
         match self {
-            Self::None | Self::FileTree | Self::Atom(_, _) => false,
+            Self::None | Self::FileTree | Self::SearchResults | Self::Problems | Self::Atom(_, _) => false,
             Self::Terminal(term) => {
                 match term.lock() {
                     Ok(term) => {
                         term.check_force_rerender()
                     }
                     Err(e) => {
-                        eprintln!("Failed to lock terminal: {}", e);
+                        eprintln!("Failed to lock terminal: {e}");
                         false
                     }
                 }
@@ -493,7 +548,7 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                     new_ptr.push(0);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::TopToBottom(vec![(fl, 0.5), (old_fl, 0.5)])
@@ -514,7 +569,7 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                     new_ptr.push(1);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::TopToBottom(vec![(old_fl, 0.5), (fl, 0.5)])
@@ -535,7 +590,7 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                     new_ptr.push(0);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::SideBySide(vec![(fl, 0.5), (old_fl, 0.5)])
@@ -556,7 +611,7 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::SearchResults | Self::Problems | Self::Terminal(_) => {
                     new_ptr.push(1);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::SideBySide(vec![(old_fl, 0.5), (fl, 0.5)])
@@ -848,6 +903,12 @@ pub struct FileContainer {
     pub highlighter: Highlighter,
     /// File type (stores which file type this file is)
     pub file_type: Option<FileType>,
+    /// A change made to the file by another program, that the user chose not to load
+    pub dismissed: Option<DiskState>,
+    /// The regions of the document that can be folded away
+    pub folds: Folds,
+    /// How the document is indented and tidied up when saved
+    pub format: Formatting,
 }
 
 impl Default for FileContainer {
@@ -856,6 +917,9 @@ impl Default for FileContainer {
             doc: Document::new(Size { w: 10, h: 10 }),
             highlighter: Highlighter::new(4),
             file_type: None,
+            dismissed: None,
+            folds: Folds::default(),
+            format: Formatting::default(),
         }
     }
 }
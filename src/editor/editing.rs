@@ -1,7 +1,8 @@
 /// General functions for editing a document
-use crate::error::Result;
+use crate::error::{OxError, Result};
 use kaolinite::event::Event;
 use kaolinite::utils::Loc;
+use std::time::Duration;
 
 use super::Editor;
 
@@ -13,25 +14,16 @@ impl Editor {
             if !(self.plugin_active || self.pasting || self.macro_man.playing || multi_cursors) {
                 let last_ev = doc.event_mgmt.last_event.as_ref();
                 // If last event is present and the same as this one, commit
-                let event_type_differs = last_ev.map(|e1| e1.same_type(&ev)) != Some(true);
+                let event_type_differs = last_ev.is_none_or(|e1| !e1.same_type(&ev));
                 // If last event is present and on a different line from the previous, commit
                 let event_on_different_line =
-                    last_ev.map(|e| e.loc().y == ev.loc().y) != Some(true);
+                    last_ev.is_none_or(|e| e.loc().y != ev.loc().y);
                 // Commit if necessary
                 if event_type_differs || event_on_different_line {
                     if let Some(doc_mut) = self.try_doc_mut() {
                         doc_mut.commit();
                     }
                 }
-            } else if let Some(doc) = self.try_doc() {
-                if doc.event_mgmt.history.is_empty() {
-                    // If there is no initial commit and a plug-in changes things without commiting
-                    // It can cause the initial state of the document to be lost
-                    // This condition makes sure there is a copy to go back to if this is the case
-                    if let Some(doc_mut) = self.try_doc_mut() {
-                        doc_mut.commit();
-                    }
-                }
             }
             if let Some(doc_mut) = self.try_doc_mut() {
                 doc_mut.exe(ev)?;
@@ -55,14 +47,12 @@ impl Editor {
             // Handle the character insertion
             if ch == '\n' {
                 self.enter()?;
-            } else {
-                if let Some(doc) = self.try_doc() {
-                    let loc = doc.char_loc();
-                    self.exe(Event::Insert(loc, ch.to_string()))?;
-                    if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                        if !file.doc.info.read_only {
-                            file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
-                        }
+            } else if let Some(doc) = self.try_doc() {
+                let loc = doc.char_loc();
+                self.exe(Event::Insert(loc, ch.to_string()))?;
+                if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+                    if !file.doc.info.read_only {
+                        file.highlighter.edit(loc.y, &file.doc.line(loc.y).unwrap_or_default());
                     }
                 }
             }
@@ -86,9 +76,9 @@ impl Editor {
                 self.exe(Event::SplitDown(loc))?;
                 if let Some(file) = self.files.get_mut(self.ptr.clone()) {
                     if !file.doc.info.read_only {
-                        let line = &file.doc.lines[loc.y + 1];
+                        let line = &file.doc.line(loc.y + 1).unwrap_or_default();
                         file.highlighter.insert_line(loc.y + 1, line);
-                        let line = &file.doc.lines[loc.y];
+                        let line = &file.doc.line(loc.y).unwrap_or_default();
                         file.highlighter.edit(loc.y, line);
                     }
                 }
@@ -142,7 +132,7 @@ impl Editor {
                 
                 // Update highlighter after splice
                 if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                    let line = &file.doc.lines[loc.y];
+                    let line = &file.doc.line(loc.y).unwrap_or_default();
                     if !file.doc.info.read_only {
                         file.highlighter.edit(loc.y, line);
                     }
@@ -159,7 +149,7 @@ impl Editor {
                         self.exe(Event::Delete(loc, ch.to_string()))?;
                         if let Some(file) = self.files.get_mut(self.ptr.clone()) {
                             if !file.doc.info.read_only {
-                                file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
+                                file.highlighter.edit(loc.y, &file.doc.line(loc.y).unwrap_or_default());
                             }
                         }
                     }
@@ -182,7 +172,7 @@ impl Editor {
                     self.exe(Event::Delete(loc, ch.to_string()))?;
                     if let Some(file) = self.files.get_mut(self.ptr.clone()) {
                         if !file.doc.info.read_only {
-                            file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
+                            file.highlighter.edit(loc.y, &file.doc.line(loc.y).unwrap_or_default());
                         }
                     }
                 }
@@ -246,10 +236,68 @@ impl Editor {
         Ok(())
     }
 
+    /// Move back through the undo tree, by a number of revisions (e.g. "3") or time (e.g. "5m")
+    pub fn earlier(&mut self, spec: &str) -> Result<()> {
+        let travel = parse_time_travel(spec)?;
+        if let Some(doc) = self.try_doc_mut() {
+            match travel {
+                TimeTravel::Steps(steps) => doc.earlier(steps)?,
+                TimeTravel::Time(duration) => doc.earlier_by(duration)?,
+            }
+            self.reload_highlight();
+        }
+        Ok(())
+    }
+
+    /// Move forward through the undo tree, by a number of revisions (e.g. "3") or time (e.g. "5m")
+    pub fn later(&mut self, spec: &str) -> Result<()> {
+        let travel = parse_time_travel(spec)?;
+        if let Some(doc) = self.try_doc_mut() {
+            match travel {
+                TimeTravel::Steps(steps) => doc.later(steps)?,
+                TimeTravel::Time(duration) => doc.later_by(duration)?,
+            }
+            self.reload_highlight();
+        }
+        Ok(())
+    }
+
+    /// Move to a specific revision in the undo tree
+    pub fn goto_revision(&mut self, revision: usize) -> Result<()> {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.goto_revision(revision)?;
+            self.reload_highlight();
+        }
+        Ok(())
+    }
+
+    /// Switch to the next branch in the undo tree
+    pub fn next_branch(&mut self) -> Result<()> {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.next_branch()?;
+            self.reload_highlight();
+        }
+        Ok(())
+    }
+
+    /// Switch to the previous branch in the undo tree
+    pub fn previous_branch(&mut self) -> Result<()> {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.prev_branch()?;
+            self.reload_highlight();
+        }
+        Ok(())
+    }
+
     /// Copy the selected text
+    #[allow(clippy::unnecessary_wraps)]
     pub fn copy(&mut self) -> Result<()> {
-        if let Some(doc) = self.try_doc() {
-            let selected_text = doc.selection_text();
+        // Copy from the document, or from the output of a focused terminal
+        let selected_text = match self.try_doc() {
+            Some(doc) => Some(doc.selection_text()),
+            None => self.terminal_selection_text(),
+        };
+        if let Some(selected_text) = selected_text {
             match self.terminal.copy(&selected_text) {
                 Ok(method) => {
                     use crate::clipboard::ClipboardMethod;
@@ -264,7 +312,7 @@ impl Editor {
                 }
                 Err(e) => {
                     use crate::ui::Feedback;
-                    self.feedback = Feedback::Warning(format!("Clipboard warning: {}", e));
+                    self.feedback = Feedback::Warning(format!("Clipboard warning: {e}"));
                     Ok(()) // Don't fail the operation, just show warning
                 }
             }
@@ -295,3 +343,29 @@ impl Editor {
         }
     }
 }
+
+/// A distance to travel through the undo tree
+enum TimeTravel {
+    Steps(usize),
+    Time(Duration),
+}
+
+/// Parse a number of revisions (e.g. "3") or an amount of time (e.g. "30s", "5m", "2h", "1d")
+fn parse_time_travel(spec: &str) -> Result<TimeTravel> {
+    let spec = spec.trim();
+    let invalid = || OxError::Parse {
+        msg: format!("'{spec}' is not a number of revisions or an amount of time (e.g. 5m)"),
+    };
+    let split = spec.find(|c: char| !c.is_ascii_digit()).unwrap_or(spec.len());
+    let (amount, unit) = spec.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "" => return Ok(TimeTravel::Steps(usize::try_from(amount).map_err(|_| invalid())?)),
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => return Err(invalid()),
+    };
+    Ok(TimeTravel::Time(Duration::from_secs(amount.saturating_mul(seconds))))
+}
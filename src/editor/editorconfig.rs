@@ -0,0 +1,375 @@
+/// Support for `.editorconfig` files, which set up indentation and formatting for each project
+use crate::config;
+use crate::config::Indentation;
+use crate::editor::{Editor, FileContainer};
+use crate::error::Result;
+use globset::GlobBuilder;
+use kaolinite::document::LineEnding;
+use kaolinite::encoding::Encoding;
+use regex::{Captures, Regex};
+use std::path::Path;
+
+/// The name of the files that hold editor settings for a directory
+const FILE_NAME: &str = ".editorconfig";
+
+/// The largest numeric range (e.g. `{1..10}`) in a glob that will be matched
+const MAX_RANGE: i64 = 1000;
+
+/// How many columns one level of indentation takes up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentSize {
+    Columns(usize),
+    /// The same as the width of a tab character
+    Tab,
+}
+
+/// The `.editorconfig` properties that apply to a file (None where a property isn't set)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub indent_style: Option<Indentation>,
+    pub indent_size: Option<IndentSize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<Encoding>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl Properties {
+    /// Work out the properties for a file, from the `.editorconfig` files in its directory
+    /// and the directories above it (stopping at one marked as the root)
+    pub fn for_file(path: &Path) -> Self {
+        let Ok(path) = std::path::absolute(path) else {
+            return Self::default();
+        };
+        let mut files = vec![];
+        for dir in path.ancestors().skip(1) {
+            if let Ok(text) = std::fs::read_to_string(dir.join(FILE_NAME)) {
+                let file = ConfigFile::parse(&text);
+                let root = file.root;
+                files.push((dir, file));
+                if root {
+                    break;
+                }
+            }
+        }
+        // Files nearer to the file take precedence, as do later sections within a file
+        let mut properties = Self::default();
+        for (dir, file) in files.iter().rev() {
+            for (glob, pairs) in &file.sections {
+                if section_matches(glob, dir, &path) {
+                    for (key, value) in pairs {
+                        properties.set(key, value);
+                    }
+                }
+            }
+        }
+        properties
+    }
+
+    /// Apply a property from an `.editorconfig` file (unknown properties and values are ignored)
+    fn set(&mut self, key: &str, value: &str) {
+        let value = value.to_lowercase();
+        let unset = value == "unset";
+        let flag = match value.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        };
+        match key {
+            "indent_style" => update(
+                &mut self.indent_style,
+                unset,
+                match value.as_str() {
+                    "tab" => Some(Indentation::Tabs),
+                    "space" => Some(Indentation::Spaces),
+                    _ => None,
+                },
+            ),
+            "indent_size" => update(
+                &mut self.indent_size,
+                unset,
+                if value == "tab" {
+                    Some(IndentSize::Tab)
+                } else {
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .map(IndentSize::Columns)
+                },
+            ),
+            "tab_width" => update(
+                &mut self.tab_width,
+                unset,
+                value.parse().ok().filter(|n| *n > 0),
+            ),
+            "end_of_line" => update(&mut self.end_of_line, unset, LineEnding::from_name(&value)),
+            "charset" => update(&mut self.charset, unset, Encoding::from_name(&value)),
+            "trim_trailing_whitespace" => update(&mut self.trim_trailing_whitespace, unset, flag),
+            "insert_final_newline" => update(&mut self.insert_final_newline, unset, flag),
+            _ => (),
+        }
+    }
+
+    /// The width of a tab character (which falls back to the size of an indent)
+    pub fn tab_width(&self) -> Option<usize> {
+        self.tab_width.or(match self.indent_size {
+            Some(IndentSize::Columns(n)) => Some(n),
+            _ => None,
+        })
+    }
+
+    /// The number of columns in a level of indentation (which falls back to the tab width)
+    pub fn indent_size(&self) -> Option<usize> {
+        match self.indent_size {
+            Some(IndentSize::Columns(n)) => Some(n),
+            Some(IndentSize::Tab) => self.tab_width,
+            None if self.indent_style == Some(Indentation::Tabs) => self.tab_width,
+            None => None,
+        }
+    }
+}
+
+/// Change a property, following an "unset" value or ignoring a value that isn't valid
+fn update<T>(property: &mut Option<T>, unset: bool, value: Option<T>) {
+    if unset {
+        *property = None;
+    } else if value.is_some() {
+        *property = value;
+    }
+}
+
+/// The contents of an `.editorconfig` file
+#[derive(Debug, Default)]
+struct ConfigFile {
+    /// Whether files in directories above this one should be ignored
+    root: bool,
+    /// Each section's glob, along with its properties in the order they appear
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl ConfigFile {
+    /// Read an `.editorconfig` file (lines that can't be understood are skipped over)
+    fn parse(text: &str) -> Self {
+        let mut file = Self::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                file.sections.push((glob.to_string(), vec![]));
+            } else if let Some((key, value)) = line.split_once('=') {
+                let key = key.trim().to_lowercase();
+                let value = value.trim().to_string();
+                if let Some((_, pairs)) = file.sections.last_mut() {
+                    pairs.push((key, value));
+                } else if key == "root" {
+                    file.root = value.eq_ignore_ascii_case("true");
+                }
+            }
+        }
+        file
+    }
+}
+
+/// Check if a section's glob applies to a file, given the directory the `.editorconfig` file is in.
+/// Globs without a slash match files of that name in any directory beneath it.
+fn section_matches(glob: &str, dir: &Path, path: &Path) -> bool {
+    let dir = globset::escape(slashes(dir).trim_end_matches('/'));
+    let glob = expand_ranges(glob);
+    let pattern = if let Some(anchored) = glob.strip_prefix('/') {
+        format!("{dir}/{anchored}")
+    } else if glob.contains('/') {
+        format!("{dir}/{glob}")
+    } else {
+        format!("{dir}/**/{glob}")
+    };
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+        .is_ok_and(|glob| glob.compile_matcher().is_match(slashes(path)))
+}
+
+/// Write a path with forward slashes, as globs expect
+fn slashes(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Turn numeric ranges in a glob (e.g. `{1..3}`) into alternatives (e.g. `{1,2,3}`)
+fn expand_ranges(glob: &str) -> String {
+    let Ok(range) = Regex::new(r"\{(-?\d+)\.\.(-?\d+)\}") else {
+        return glob.to_string();
+    };
+    range
+        .replace_all(glob, |caps: &Captures| {
+            let bound = |i: usize| caps[i].parse::<i64>().unwrap_or(0);
+            let (start, end) = (bound(1).min(bound(2)), bound(1).max(bound(2)));
+            if end - start > MAX_RANGE {
+                return caps[0].to_string();
+            }
+            let numbers: Vec<String> = (start..=end).map(|n| n.to_string()).collect();
+            format!("{{{}}}", numbers.join(","))
+        })
+        .to_string()
+}
+
+/// How a document is indented, and tidied up when it is saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formatting {
+    pub indentation: Indentation,
+    /// The number of spaces in a level of indentation (when indenting with spaces)
+    pub indent_size: usize,
+    /// The line ending the document is converted to when it is saved
+    pub line_ending: Option<LineEnding>,
+    pub trim_trailing_whitespace: bool,
+    /// Whether the document should (or shouldn't) end with a line ending when it is saved
+    pub insert_final_newline: Option<bool>,
+}
+
+impl Default for Formatting {
+    fn default() -> Self {
+        Self {
+            indentation: Indentation::Tabs,
+            indent_size: 4,
+            line_ending: None,
+            trim_trailing_whitespace: false,
+            insert_final_newline: None,
+        }
+    }
+}
+
+impl FileContainer {
+    /// Tidy up the document the way its formatting asks for, ready to be saved
+    pub fn tidy(&mut self) -> Result<()> {
+        if self.doc.is_paged() || self.doc.info.read_only {
+            return Ok(());
+        }
+        if let Some(line_ending) = self.format.line_ending {
+            self.doc.set_line_ending(line_ending)?;
+        }
+        if self.format.trim_trailing_whitespace {
+            // Keep the tidying separate from other edits when undoing
+            self.doc.commit();
+            if self.doc.trim_trailing_whitespace()? {
+                self.doc.commit();
+                self.highlighter.run(&self.doc.lines_to_view());
+            }
+        }
+        if let Some(newline) = self.format.insert_final_newline {
+            self.doc.set_final_newline(newline)?;
+        }
+        Ok(())
+    }
+}
+
+impl Editor {
+    /// Work out the `.editorconfig` properties for a file (none if support for them is off)
+    pub fn editorconfig(&self, file_name: &str) -> Properties {
+        let mut properties = if config!(self.config, document).editorconfig {
+            Properties::for_file(Path::new(file_name))
+        } else {
+            Properties::default()
+        };
+        if self.encoding.is_some() {
+            properties.charset = self.encoding;
+        }
+        properties
+    }
+
+    /// Work out how wide a tab is in a document, from its properties and the document settings
+    pub fn tab_width_for(&self, properties: &Properties) -> usize {
+        properties
+            .tab_width()
+            .unwrap_or(config!(self.config, document).tab_width)
+    }
+
+    /// Work out how a document is formatted, from its properties and the document settings
+    pub fn formatting_for(&self, properties: &Properties) -> Formatting {
+        let document = config!(self.config, document);
+        Formatting {
+            indentation: properties.indent_style.unwrap_or(document.indentation),
+            indent_size: properties.indent_size().unwrap_or(document.tab_width),
+            line_ending: properties.end_of_line,
+            trim_trailing_whitespace: properties.trim_trailing_whitespace.unwrap_or(false),
+            insert_final_newline: properties.insert_final_newline,
+        }
+    }
+
+    /// Set up the document that is focused to be formatted as if it were a particular file
+    /// (for documents that have just been given a name)
+    pub fn reformat_for(&mut self, file_name: &str) {
+        let properties = self.editorconfig(file_name);
+        let format = self.formatting_for(&properties);
+        let tab_width = self.tab_width_for(&properties);
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.format = format;
+            file.doc.set_tab_width(tab_width);
+            file.highlighter.tab_width = tab_width;
+            if let Some(encoding) = properties.charset {
+                file.doc.info.encoding = encoding;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editorconfig_properties() {
+        let root = std::env::temp_dir().join(format!("ox-editorconfig-{}", std::process::id()));
+        let project = root.join("project");
+        std::fs::create_dir_all(project.join("src/deep")).unwrap();
+        std::fs::write(
+            root.join(FILE_NAME),
+            "[*]\nindent_style = tab\ntrim_trailing_whitespace = true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            project.join(FILE_NAME),
+            "; Project settings\nroot = true\n\n[*]\nend_of_line = crlf\ncharset = latin1\n\n\
+             [*.{rs,toml}]\nindent_style = space\nindent_size = 2\n\n\
+             [/src/*.rs]\nindent_size = 8\n\n[file{1..3}.txt]\nindent_size = tab\ntab_width = 3\n\n\
+             [Makefile]\nend_of_line = unset\ninsert_final_newline = false\n",
+        )
+        .unwrap();
+        // Nested globs, anchored globs and properties falling back to one another
+        let rs = Properties::for_file(&project.join("src/deep/main.rs"));
+        assert_eq!(rs.indent_style, Some(Indentation::Spaces));
+        assert_eq!((rs.indent_size(), rs.tab_width()), (Some(2), Some(2)));
+        assert_eq!(rs.end_of_line, Some(LineEnding::CrLf));
+        assert_eq!(rs.charset, Some(Encoding::Latin1));
+        // The root stops files further up from applying
+        assert_eq!(rs.trim_trailing_whitespace, None);
+        let anchored = Properties::for_file(&project.join("src/lib.rs"));
+        assert_eq!(anchored.indent_size(), Some(8));
+        let range = Properties::for_file(&project.join("file2.txt"));
+        assert_eq!(range.indent_size, Some(IndentSize::Tab));
+        assert_eq!((range.indent_size(), range.tab_width()), (Some(3), Some(3)));
+        assert_eq!(
+            Properties::for_file(&project.join("file4.txt")).indent_size,
+            None
+        );
+        let make = Properties::for_file(&project.join("sub/Makefile"));
+        assert_eq!(make.end_of_line, None);
+        assert_eq!(make.insert_final_newline, Some(false));
+        // Files outside the project only see the files above them
+        let outside = Properties::for_file(&root.join("notes.md"));
+        assert_eq!(outside.indent_style, Some(Indentation::Tabs));
+        assert_eq!(outside.trim_trailing_whitespace, Some(true));
+        assert_eq!(outside.end_of_line, None);
+        // An encoding given on the command line wins over the charset property
+        let lua = mlua::Lua::new();
+        let mut editor = crate::editor::Editor::new(&lua).unwrap();
+        let file = project.join("src/deep/main.rs");
+        assert_eq!(editor.editorconfig(&file.to_string_lossy()).charset, Some(Encoding::Latin1));
+        editor.encoding = Some(Encoding::Utf16Le);
+        let properties = editor.editorconfig(&file.to_string_lossy());
+        assert_eq!(properties.charset, Some(Encoding::Utf16Le));
+        assert_eq!(properties.indent_size(), Some(2));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -253,7 +253,7 @@ impl FileTree {
                 } else {
                     (String::new(), None)
                 };
-                let file_name = get_file_name(path).unwrap_or(path.to_string());
+                let file_name = get_file_name(path).unwrap_or(path.clone());
                 (
                     vec![(0, icon, icon_color, file_name)],
                     if self.is_selected(sel) { Some(0) } else { None },
@@ -268,7 +268,7 @@ impl FileTree {
                 } else {
                     (String::new(), None)
                 };
-                let file_name = get_file_name(path).unwrap_or(path.to_string());
+                let file_name = get_file_name(path).unwrap_or(path.clone());
                 result.push((0, icon, icon_color, file_name));
                 if self.is_selected(sel) {
                     at = Some(result.len().saturating_sub(1));
@@ -304,10 +304,10 @@ impl FileTree {
     /// Find the file path at a certain index (recursive)
     pub fn flatten_recursive(&self) -> Vec<String> {
         match self {
-            Self::File { path } => vec![path.to_string()],
+            Self::File { path } => vec![path.clone()],
             Self::Dir { path, files } => {
                 let mut result = vec![];
-                result.push(path.to_string());
+                result.push(path.clone());
                 if let Some(files) = files {
                     for file in files {
                         result.append(&mut file.flatten_recursive());
@@ -378,7 +378,7 @@ impl Editor {
     pub fn open_file_tree(&mut self) {
         if !self.file_tree_is_open() {
             // Calculate display proportions
-            let total_width = size().map(|s| s.w as f64).unwrap_or(1.0);
+            let total_width = size().map_or(1.0, |s| s.w as f64);
             let width = config!(self.config, file_tree).width as f64 / total_width;
             let other = 1.0 - width as f64;
             // Set up file tree values
@@ -14,7 +14,7 @@ pub struct FileTypes {
 }
 
 impl FileTypes {
-    pub fn identify(&self, doc: &mut Document) -> Option<FileType> {
+    pub fn identify(&self, doc: &Document) -> Option<FileType> {
         for t in &self.types {
             let mut extension = String::new();
             let mut file_name = String::new();
@@ -24,7 +24,6 @@ impl FileTypes {
                     extension = e.to_str().unwrap_or_default().to_string();
                 }
             }
-            doc.load_to(1);
             let first_line = doc.line(0).unwrap_or_default();
             if t.fits(&extension, &file_name, &first_line) {
                 return Some(t.clone());
@@ -66,6 +65,10 @@ pub struct FileType {
     pub modelines: Vec<String>,
     /// The colour associated with this file type
     pub color: String,
+    /// Whether long lines should be soft wrapped (None to use the document setting)
+    pub wrap: Option<bool>,
+    /// The command that starts a language server for this file type
+    pub lsp: Option<String>,
 }
 
 impl Default for FileType {
@@ -77,6 +80,8 @@ impl Default for FileType {
             extensions: vec![],
             modelines: vec![],
             color: "grey".to_string(),
+            wrap: None,
+            lsp: None,
         }
     }
 }
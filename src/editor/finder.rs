@@ -0,0 +1,621 @@
+/// Functions for finding files by typing any part of their names, in an overlay
+use crate::config;
+use crate::editor::FileLayout;
+use crate::error::{OxError, Result};
+use crate::events::wait_for_event_hog;
+use crate::ui::{key_event, size};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use crossterm::style::{
+    Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use kaolinite::utils::{get_absolute_path, get_cwd, width_char, Size};
+use mlua::Lua;
+use std::io::Read;
+use std::path::Path;
+
+use super::Editor;
+
+/// The score for each character that matches
+const SCORE_MATCH: i64 = 16;
+/// The bonus for matching the start of a word (or of the whole candidate)
+const BONUS_BOUNDARY: i64 = 10;
+/// The bonus for matching a capital letter that starts a word in camel case
+const BONUS_CAMEL: i64 = 8;
+/// The bonus for matching straight after the last match
+const BONUS_CONSECUTIVE: i64 = 12;
+/// The bonus for matching within the file name (rather than the folders leading to it)
+const BONUS_FILE_NAME: i64 = 6;
+/// The bonus for matching the case that was typed
+const BONUS_CASE: i64 = 1;
+/// The penalty for each character skipped between matches
+const PENALTY_GAP: i64 = 2;
+/// The penalty for each character skipped before the first match (up to a limit)
+const PENALTY_LEADING: i64 = 1;
+const MAX_LEADING_PENALTY: i64 = 10;
+/// The most files remembered as having been opened recently
+const RECENT_FILES: usize = 50;
+/// How much of a file is read to preview it (in bytes)
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// How well a candidate matches what was typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher scores are better matches
+    pub score: i64,
+    /// The character indices within the candidate that were matched
+    pub positions: Vec<usize>,
+}
+
+/// Determine if a character starts a word, given the character before it
+fn is_boundary(prev: Option<char>, c: char) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => {
+            matches!(prev, '/' | '\\' | '_' | '-' | '.' | ':' | ' ')
+                || (prev.is_lowercase() && c.is_uppercase())
+        }
+    }
+}
+
+/// Turn a number of characters into a score
+fn distance(n: usize) -> i64 {
+    i64::try_from(n).unwrap_or(i64::MAX / 4)
+}
+
+/// Work out the bonus for matching a character at a certain position
+fn position_bonus(chars: &[char], at: usize, file_name_start: usize) -> i64 {
+    let prev = at.checked_sub(1).map(|p| chars[p]);
+    let mut bonus = SCORE_MATCH;
+    if is_boundary(prev, chars[at]) {
+        bonus += if prev.is_some_and(char::is_lowercase) {
+            BONUS_CAMEL
+        } else {
+            BONUS_BOUNDARY
+        };
+    }
+    if at >= file_name_start {
+        bonus += BONUS_FILE_NAME;
+    }
+    bonus
+}
+
+/// Determine if two characters are the same, ignoring case
+fn same_letter(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Score how well a candidate matches a pattern, where the characters of the pattern have to
+/// appear in the candidate in order (but not necessarily next to each other).
+/// Matches at the start of words, in the file name, and runs of matching characters score higher.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: vec![],
+        });
+    }
+    // Quickly rule out candidates that don't contain the pattern at all
+    let mut rest = chars.iter();
+    if !pattern.iter().all(|p| rest.any(|c| same_letter(*p, *c))) {
+        return None;
+    }
+    let file_name_start = chars
+        .iter()
+        .rposition(|c| matches!(c, '/' | '\\'))
+        .map_or(0, |i| i + 1);
+    let (m, n) = (pattern.len(), chars.len());
+    // best[i][j] is the best score with the ith character of the pattern matched at j
+    let mut best: Vec<Vec<Option<i64>>> = vec![vec![None; n]; m];
+    let mut from: Vec<Vec<usize>> = vec![vec![0; n]; m];
+    for (i, p) in pattern.iter().enumerate() {
+        // The best score (and where it was) of the previous character that a gap could follow
+        let mut gap_best: Option<(i64, usize)> = None;
+        for j in i..n {
+            if i > 0 && j >= 2 {
+                if let Some(score) = best[i - 1][j - 2] {
+                    let score = score + PENALTY_GAP * distance(j - 2);
+                    if gap_best.is_none_or(|(s, _)| score > s) {
+                        gap_best = Some((score, j - 2));
+                    }
+                }
+            }
+            if !same_letter(*p, chars[j]) {
+                continue;
+            }
+            let mut bonus = position_bonus(&chars, j, file_name_start);
+            if *p == chars[j] {
+                bonus += BONUS_CASE;
+            }
+            if i == 0 {
+                let leading = (PENALTY_LEADING * distance(j)).min(MAX_LEADING_PENALTY);
+                best[i][j] = Some(bonus - leading);
+                continue;
+            }
+            let consecutive = best[i - 1][j - 1].map(|s| (s + BONUS_CONSECUTIVE, j - 1));
+            let gapped = gap_best.map(|(s, k)| (s - PENALTY_GAP * distance(j - 1), k));
+            let chosen = match (consecutive, gapped) {
+                (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                (c, g) => c.or(g),
+            };
+            if let Some((score, k)) = chosen {
+                best[i][j] = Some(score + bonus);
+                from[i][j] = k;
+            }
+        }
+    }
+    // Find the best place for the last character, then trace back to where the others went
+    let (score, mut at) = (0..n)
+        .filter_map(|j| best[m - 1][j].map(|s| (s, j)))
+        .max_by_key(|(s, j)| (*s, std::cmp::Reverse(*j)))?;
+    let mut positions = vec![at; m];
+    for i in (1..m).rev() {
+        at = from[i][at];
+        positions[i - 1] = at;
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Rank candidates by how well they match a pattern, best first (leaving out those that don't).
+/// Ties go to the shorter candidate, then to whichever came first.
+pub fn fuzzy_rank(pattern: &str, candidates: &[String]) -> Vec<(usize, FuzzyMatch)> {
+    let mut result: Vec<(usize, FuzzyMatch)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| Some((i, fuzzy_match(pattern, c)?)))
+        .collect();
+    result.sort_by_key(|(i, m)| {
+        (
+            std::cmp::Reverse(m.score),
+            candidates[*i].chars().count(),
+            *i,
+        )
+    });
+    result
+}
+
+/// List the files beneath a folder (relative to it), skipping those that are hidden,
+/// ignored (e.g. by `.gitignore`) or excluded by patterns (written like those in `.gitignore`)
+pub fn index_files(root: &Path, exclude: &[String], max: usize) -> Vec<String> {
+    let mut overrides = OverrideBuilder::new(root);
+    for pattern in exclude {
+        // Overrides include what they match unless they start with '!'
+        let _ = overrides.add(&format!("!{pattern}"));
+    }
+    let mut walker = WalkBuilder::new(root);
+    walker.require_git(false);
+    if let Ok(overrides) = overrides.build() {
+        walker.overrides(overrides);
+    }
+    let mut result: Vec<String> = walker
+        .build()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?;
+            Some(path.to_string_lossy().to_string())
+        })
+        .take(max)
+        .collect();
+    result.sort();
+    result
+}
+
+/// Where a file chosen in the finder is opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// In the atom that is focused
+    Here,
+    /// In a new split to the right
+    Right,
+    /// In a new split below
+    Below,
+}
+
+/// Something that can be chosen in the finder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FinderItem {
+    /// What is shown (and matched against)
+    pub label: String,
+    /// The absolute path of the file
+    pub path: String,
+}
+
+impl Editor {
+    /// Find a file beneath the working directory and open it
+    pub fn find_file(&mut self, lua: &Lua) -> Result<()> {
+        let Some(root) = get_cwd() else {
+            return Ok(());
+        };
+        let finder = config!(self.config, finder);
+        let files = index_files(Path::new(&root), &finder.exclude, finder.max_files);
+        drop(finder);
+        let items: Vec<FinderItem> = files
+            .into_iter()
+            .map(|label| {
+                let path = Path::new(&root).join(&label).to_string_lossy().to_string();
+                FinderItem { label, path }
+            })
+            .collect();
+        self.find_and_open(lua, "Find file", &items)
+    }
+
+    /// Find a document that is open and move to it
+    pub fn find_buffer(&mut self, lua: &Lua) -> Result<()> {
+        let root = get_cwd().unwrap_or_default();
+        let mut items = vec![];
+        for atom in self.files.atoms(vec![]) {
+            for fc in self.files.get_all(atom) {
+                if let Some(path) = fc.doc.file_name.as_deref().and_then(get_absolute_path) {
+                    items.push(FinderItem {
+                        label: relative_to(&path, &root),
+                        path,
+                    });
+                }
+            }
+        }
+        self.find_and_open(lua, "Find open file", &items)
+    }
+
+    /// Find a file that was opened recently and open it again
+    pub fn find_recent(&mut self, lua: &Lua) -> Result<()> {
+        let root = get_cwd().unwrap_or_default();
+        let items: Vec<FinderItem> = self
+            .recent_files
+            .iter()
+            .map(|path| FinderItem {
+                label: relative_to(path, &root),
+                path: path.clone(),
+            })
+            .collect();
+        self.find_and_open(lua, "Find recent file", &items)
+    }
+
+    /// Remember that a file has been opened
+    pub fn add_recent_file(&mut self, file_name: &str) {
+        if let Some(path) = get_absolute_path(file_name) {
+            self.recent_files.retain(|p| p != &path);
+            self.recent_files.insert(0, path);
+            self.recent_files.truncate(RECENT_FILES);
+        }
+    }
+
+    /// Let the user choose from some items in the finder, then open the one chosen
+    fn find_and_open(&mut self, lua: &Lua, title: &str, items: &[FinderItem]) -> Result<()> {
+        if items.is_empty() {
+            self.feedback = crate::ui::Feedback::Warning("Nothing to find".to_string());
+            return Ok(());
+        }
+        if let Some((item, placement)) = self.fuzzy_finder(lua, title, items)? {
+            self.open_placed(&item.path, placement)?;
+        }
+        Ok(())
+    }
+
+    /// Open a file in a certain place (moving to it instead if it is already open)
+    pub fn open_placed(&mut self, path: &str, placement: Placement) -> Result<()> {
+        if let Some((atom, idx)) = self.already_open(path) {
+            self.ptr = atom.clone();
+            self.files.move_to(atom, idx);
+        } else if placement == Placement::Here {
+            // Files open in an atom, so move out of anything else (like the file tree)
+            if self.files.get_atom(self.ptr.clone()).is_none() {
+                if let Some(atom) = self.files.atoms(vec![]).first() {
+                    self.ptr.clone_from(atom);
+                }
+            }
+            self.open(path)?;
+            self.next();
+        } else {
+            let fc = self.open_fc(path)?;
+            let fl = FileLayout::Atom(vec![fc], 0);
+            self.ptr = if placement == Placement::Right {
+                self.files.open_right(self.ptr.clone(), fl)
+            } else {
+                self.files.open_down(self.ptr.clone(), fl)
+            };
+            self.cache_old_ptr(&self.ptr.clone());
+        }
+        self.update_cwd();
+        self.update_highlighter();
+        Ok(())
+    }
+
+    /// Show the finder over the editor until an item is chosen (or the finder is cancelled)
+    pub fn fuzzy_finder(
+        &mut self,
+        lua: &Lua,
+        title: &str,
+        items: &[FinderItem],
+    ) -> Result<Option<(FinderItem, Placement)>> {
+        let labels: Vec<String> = items.iter().map(|i| i.label.clone()).collect();
+        let mut query = String::new();
+        let mut ranked = fuzzy_rank(&query, &labels);
+        let (mut selected, mut offset) = (0, 0);
+        let mut preview: Option<(String, Vec<String>)> = None;
+        loop {
+            // Rerender the editor with the finder over it
+            self.needs_rerender = true;
+            self.render(lua)?;
+            let path = ranked.get(selected).map(|(i, _)| items[*i].path.clone());
+            if config!(self.config, finder).preview {
+                if let Some(path) = path {
+                    if preview.as_ref().is_none_or(|(p, _)| *p != path) {
+                        let lines = self.preview_lines(&path);
+                        preview = Some((path, lines));
+                    }
+                }
+            }
+            let rows = self.render_finder(
+                title,
+                &query,
+                &ranked,
+                items,
+                selected,
+                &mut offset,
+                preview.as_ref(),
+            )?;
+            self.terminal.flush()?;
+            // Handle events
+            let Some((modifiers, code)) = key_event(&wait_for_event_hog(self), &mut self.macro_man)
+            else {
+                continue;
+            };
+            let placement = match (modifiers, code) {
+                (KMod::NONE, KCode::Enter) => Some(Placement::Here),
+                (KMod::CONTROL, KCode::Right) => Some(Placement::Right),
+                (KMod::CONTROL, KCode::Down) => Some(Placement::Below),
+                (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
+                (KMod::NONE, KCode::Up) => {
+                    selected = selected.saturating_sub(1);
+                    None
+                }
+                (KMod::NONE, KCode::Down) => {
+                    selected = (selected + 1).min(ranked.len().saturating_sub(1));
+                    None
+                }
+                (KMod::NONE, KCode::PageUp) => {
+                    selected = selected.saturating_sub(rows);
+                    None
+                }
+                (KMod::NONE, KCode::PageDown) => {
+                    selected = (selected + rows).min(ranked.len().saturating_sub(1));
+                    None
+                }
+                (KMod::NONE, KCode::Backspace) => {
+                    query.pop();
+                    ranked = fuzzy_rank(&query, &labels);
+                    (selected, offset) = (0, 0);
+                    None
+                }
+                (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
+                    query.push(c);
+                    ranked = fuzzy_rank(&query, &labels);
+                    (selected, offset) = (0, 0);
+                    None
+                }
+                _ => None,
+            };
+            if let Some(placement) = placement {
+                let chosen = ranked
+                    .get(selected)
+                    .map(|(i, _)| (items[*i].clone(), placement));
+                return Ok(chosen);
+            }
+        }
+    }
+
+    /// Read the start of a file to preview it (from its document, if it is open)
+    fn preview_lines(&mut self, path: &str) -> Vec<String> {
+        if let Some((atom, idx)) = self.already_open(path) {
+            if let Some(fc) = self.files.get_all(atom).get(idx) {
+                return (0..fc.doc.len_lines().min(200))
+                    .filter_map(|y| fc.doc.line(y))
+                    .collect();
+            }
+        }
+        let mut bytes = vec![];
+        if let Ok(file) = std::fs::File::open(path) {
+            let _ = file.take(PREVIEW_BYTES).read_to_end(&mut bytes);
+        }
+        if bytes.contains(&0) {
+            return vec!["(binary file)".to_string()];
+        }
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    /// Render the finder over the editor, returning how many items can be shown at once
+    #[allow(clippy::too_many_arguments, clippy::similar_names)]
+    fn render_finder(
+        &mut self,
+        title: &str,
+        query: &str,
+        ranked: &[(usize, FuzzyMatch)],
+        items: &[FinderItem],
+        selected: usize,
+        offset: &mut usize,
+        preview: Option<&(String, Vec<String>)>,
+    ) -> Result<usize> {
+        let colors = config!(self.config, colors);
+        let fg = Fg(colors.completion_fg.to_color()?);
+        let bg = Bg(colors.completion_bg.to_color()?);
+        let selection_fg = Fg(colors.completion_selection_fg.to_color()?);
+        let selection_bg = Bg(colors.completion_selection_bg.to_color()?);
+        let highlight = Fg(colors.highlight.to_color()?);
+        drop(colors);
+        let tab_width = config!(self.config, document).tab_width;
+        let Size { w, h } = size()?;
+        // The finder takes up most of the screen (leaving the feedback line free)
+        let width = (w * 4 / 5).max(60).min(w);
+        let height = (h * 7 / 10).max(10).min(h);
+        let (left, top) = ((w - width) / 2, (h - height) / 2);
+        let rows = height.saturating_sub(3).max(1);
+        let list_width = if preview.is_some() && width >= 70 {
+            width * 2 / 5
+        } else {
+            width
+        };
+        // Scroll so the selection is visible
+        if selected < *offset {
+            *offset = selected;
+        } else if selected >= *offset + rows {
+            *offset = selected + 1 - rows;
+        }
+        let mut lines = vec![];
+        let count = format!("{}/{} ", ranked.len(), items.len());
+        let input = format!(" {title}: {query}│");
+        let input = fit(
+            &[(String::new(), input)],
+            width.saturating_sub(count.len()),
+            tab_width,
+        );
+        lines.push(format!("{bg}{fg}{input}{count}"));
+        for row in 0..rows {
+            let mut line = String::new();
+            if let Some((i, mtch)) = ranked.get(*offset + row) {
+                let is_selected = *offset + row == selected;
+                let (fg, bg) = if is_selected {
+                    (selection_fg, selection_bg)
+                } else {
+                    (fg, bg)
+                };
+                let parts: Vec<(String, String)> = items[*i]
+                    .label
+                    .chars()
+                    .enumerate()
+                    .map(|(c, ch)| {
+                        let colour = if mtch.positions.contains(&c) {
+                            highlight
+                        } else {
+                            fg
+                        };
+                        (colour.to_string(), ch.to_string())
+                    })
+                    .collect();
+                let mut parts = parts;
+                parts.insert(0, (fg.to_string(), " ".to_string()));
+                line += &format!("{bg}{}", fit(&parts, list_width, tab_width));
+            } else {
+                line += &format!("{bg}{}", " ".repeat(list_width));
+            }
+            if list_width < width {
+                let text = preview
+                    .and_then(|(_, p)| p.get(row))
+                    .cloned()
+                    .unwrap_or_default();
+                let parts = [(fg.to_string(), format!("│ {text}"))];
+                line += &format!("{bg}{}", fit(&parts, width - list_width, tab_width));
+            }
+            lines.push(line);
+        }
+        let hint = " [Enter] Open | [Ctrl+→] Split right | [Ctrl+↓] Split below | [Esc] Cancel";
+        lines.push(format!(
+            "{bg}{}",
+            fit(&[(fg.to_string(), hint.to_string())], width, tab_width)
+        ));
+        for (y, line) in lines.into_iter().enumerate() {
+            self.terminal.goto(left, top + y);
+            self.terminal.cache += &SetAttribute(Attribute::NormalIntensity).to_string();
+            self.terminal.cache += &line;
+        }
+        self.terminal.hide_cursor();
+        Ok(rows)
+    }
+}
+
+/// Describe a path relative to a folder (if it is within it)
+fn relative_to(path: &str, root: &str) -> String {
+    Path::new(path)
+        .strip_prefix(root)
+        .map_or_else(|_| path.to_string(), |p| p.to_string_lossy().to_string())
+}
+
+/// Join coloured parts together, cut down (or padded) to fit a width
+pub fn fit(parts: &[(String, String)], length: usize, tab_width: usize) -> String {
+    let mut result = String::new();
+    let mut total = 0;
+    'parts: for (colour, text) in parts {
+        result += colour;
+        for c in text.chars() {
+            let c = if c == '\t' { ' ' } else { c };
+            let w = width_char(&c, tab_width);
+            if total + w > length {
+                break 'parts;
+            }
+            total += w;
+            result.push(c);
+        }
+    }
+    result + &" ".repeat(length.saturating_sub(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(pattern: &str, candidates: &[&str]) -> Vec<String> {
+        let candidates: Vec<String> = candidates.iter().map(ToString::to_string).collect();
+        fuzzy_rank(pattern, &candidates)
+            .into_iter()
+            .map(|(i, _)| candidates[i].clone())
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_matching() {
+        assert_eq!(
+            fuzzy_match("edmod", "src/editor/mod.rs").unwrap().positions,
+            vec![4, 5, 11, 12, 13]
+        );
+        assert!(fuzzy_match("xyz", "src/editor/mod.rs").is_none());
+        assert!(fuzzy_match("rs.mod", "src/editor/mod.rs").is_none());
+        // Runs of characters and the starts of words are preferred
+        let spread = fuzzy_match("main", "src/my_animation.rs").unwrap();
+        let together = fuzzy_match("main", "src/main.rs").unwrap();
+        assert!(together.score > spread.score);
+        assert_eq!(
+            fuzzy_match("FT", "src/FileTree.rs").unwrap().positions,
+            vec![4, 8]
+        );
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+    }
+
+    #[test]
+    fn fuzzy_ranking() {
+        let files = [
+            "src/editor/interface.rs",
+            "src/config/interface.rs",
+            "README.md",
+            "src/ui.rs",
+        ];
+        // File names are preferred to the folders leading to them
+        assert_eq!(names("ui", &files)[0], "src/ui.rs");
+        assert_eq!(names("cfgint", &files), vec!["src/config/interface.rs"]);
+        assert_eq!(names("", &files).len(), 4);
+        // Shorter candidates win ties
+        assert_eq!(names("a", &["ba", "a"]), vec!["a", "ba"]);
+    }
+
+    #[test]
+    fn finder_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/build")).unwrap();
+        std::fs::create_dir(root.join("vendor")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("src/build/out.rs"), "").unwrap();
+        std::fs::write(root.join("debug.log"), "").unwrap();
+        std::fs::write(root.join("vendor/lib.rs"), "").unwrap();
+        let exclude = vec!["vendor/".to_string(), "build".to_string()];
+        assert_eq!(index_files(root, &exclude, 100), vec!["src/main.rs"]);
+        assert_eq!(index_files(root, &[], 100).len(), 3);
+        assert_eq!(index_files(root, &[], 1).len(), 1);
+    }
+}
@@ -0,0 +1,230 @@
+/// Functions for working out which regions of a document can be folded away
+use kaolinite::Document;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use synoptic::{Highlighter, TokOpt, TokenRef};
+
+use super::{Editor, FileContainer};
+
+/// The regions of a document that can be folded away
+#[derive(Debug, Default, Clone)]
+pub struct Folds {
+    /// The document revision and number of highlighted lines these ranges were worked out for
+    key: Option<(usize, usize)>,
+    /// Ranges of lines that can be hidden (in order of the line above them)
+    ranges: Vec<Range<usize>>,
+}
+
+impl FileContainer {
+    /// Find the ranges of lines that can be folded away, working them out again
+    /// only when the document has changed since they were last asked for
+    pub fn fold_ranges(&mut self) -> &[Range<usize>] {
+        let key = (self.doc.revision, self.highlighter.line_ref.len());
+        if self.folds.key != Some(key) {
+            self.folds.ranges = fold_ranges(&self.doc, &self.highlighter);
+            self.folds.key = Some(key);
+        }
+        &self.folds.ranges
+    }
+}
+
+impl Editor {
+    /// Fold away the region a line starts (or else the innermost region the line is inside).
+    /// Returns true if a region was found to fold.
+    pub fn fold_at(&mut self, y: usize) -> bool {
+        let Some((fcs, ptr)) = self.files.get_atom_mut(self.ptr.clone()) else {
+            return false;
+        };
+        let fc = &mut fcs[*ptr];
+        let ranges = fc.fold_ranges();
+        let header = ranges.iter().find(|r| r.start == y + 1);
+        let inside = ranges.iter().filter(|r| r.contains(&y)).max_by_key(|r| r.start);
+        let Some(range) = header.or(inside).cloned() else {
+            return false;
+        };
+        fc.doc.fold(range);
+        true
+    }
+
+    /// Open up the fold a line represents (or the fold it is hidden within).
+    /// Returns true if a fold was opened.
+    pub fn unfold_at(&mut self, y: usize) -> bool {
+        self.try_doc_mut().is_some_and(|doc| doc.unfold(y))
+    }
+
+    /// Fold a region if it is open, or open it up if it is folded
+    pub fn toggle_fold(&mut self, y: usize) -> bool {
+        let folded = self
+            .try_doc()
+            .is_some_and(|doc| doc.fold_below(y).is_some() || doc.is_hidden(y));
+        if folded {
+            self.unfold_at(y)
+        } else {
+            self.fold_at(y)
+        }
+    }
+
+    /// Fold away every outermost region of the document
+    pub fn fold_all(&mut self) {
+        if let Some((fcs, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
+            let fc = &mut fcs[*ptr];
+            // Outer regions come before the regions within them, which they then swallow up
+            for range in fc.fold_ranges().to_vec() {
+                fc.doc.fold(range);
+            }
+        }
+    }
+
+    /// Open up every fold in the document
+    pub fn unfold_all(&mut self) {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.unfold_all();
+        }
+    }
+}
+
+/// Work out the ranges of lines that can be folded away in a document.
+/// Each range is found from (in order of preference for the line above it):
+/// - multi-line tokens, such as block comments and strings
+/// - brackets that are opened and closed on different lines
+/// - lines that are indented further than the line above them
+pub fn fold_ranges(doc: &Document, highlighter: &Highlighter) -> Vec<Range<usize>> {
+    // Paged documents are too large to look through
+    if doc.is_paged() {
+        return vec![];
+    }
+    let lines: Vec<String> = (0..doc.len_lines())
+        .map(|y| doc.line(y).unwrap_or_default())
+        .collect();
+    let mut ranges = BTreeMap::new();
+    for (header, range) in token_ranges(highlighter)
+        .into_iter()
+        .chain(bracket_ranges(&lines, highlighter))
+        .chain(indent_ranges(&lines, doc.tab_width))
+    {
+        ranges.entry(header).or_insert(range);
+    }
+    ranges.into_values().collect()
+}
+
+/// Find tokens (like block comments) that span several lines, hiding all but their first line
+fn token_ranges(highlighter: &Highlighter) -> Vec<(usize, Range<usize>)> {
+    // Tokens are referenced by every line they appear on
+    let mut spans: HashMap<usize, (usize, usize)> = HashMap::new();
+    for (y, refs) in highlighter.line_ref.iter().enumerate() {
+        for t in refs {
+            if let Some(TokenRef::Bounded { .. }) = highlighter.tokens.get(*t) {
+                spans.entry(*t).and_modify(|s| s.1 = y).or_insert((y, y));
+            }
+        }
+    }
+    spans
+        .into_values()
+        .filter(|(first, last)| last > first)
+        .map(|(first, last)| (first, first + 1..last + 1))
+        .collect()
+}
+
+/// Find brackets that are closed a few lines after they are opened,
+/// hiding the lines between them (brackets inside strings and comments are ignored)
+fn bracket_ranges(lines: &[String], highlighter: &Highlighter) -> Vec<(usize, Range<usize>)> {
+    let mut result = vec![];
+    let mut open: Vec<(char, usize)> = vec![];
+    for (y, line) in lines.iter().enumerate() {
+        // Only text outside of tokens is looked at (lines that haven't been highlighted yet are taken as they are)
+        let text: String = if y < highlighter.line_ref.len() {
+            highlighter
+                .line(y, line)
+                .into_iter()
+                .filter_map(|t| match t {
+                    TokOpt::None(text) => Some(text),
+                    TokOpt::Some(..) => None,
+                })
+                .collect()
+        } else {
+            line.clone()
+        };
+        for c in text.chars() {
+            match c {
+                '(' | '[' | '{' => open.push((c, y)),
+                ')' | ']' | '}' => {
+                    let opener = match c {
+                        ')' => '(',
+                        ']' => '[',
+                        _ => '{',
+                    };
+                    if let Some(at) = open.iter().rposition(|(o, _)| *o == opener) {
+                        let (_, start) = open[at];
+                        open.truncate(at);
+                        if y > start + 1 {
+                            result.push((start, start + 1..y));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+    result
+}
+
+/// Find lines that are followed by more indented lines, hiding the more indented lines
+fn indent_ranges(lines: &[String], tab_width: usize) -> Vec<(usize, Range<usize>)> {
+    let mut result = vec![];
+    // Lines that could have more indented lines after them (indent, line, last line inside)
+    let mut open: Vec<(usize, usize, usize)> = vec![];
+    let mut close = |open: &mut Vec<(usize, usize, usize)>| {
+        if let Some((_, header, last)) = open.pop() {
+            if last > header {
+                result.push((header, header + 1..last + 1));
+            }
+            if let Some(parent) = open.last_mut() {
+                parent.2 = parent.2.max(last);
+            }
+        }
+    };
+    for (y, line) in lines.iter().enumerate() {
+        // Blank lines belong to whichever region surrounds them
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { tab_width } else { 1 })
+            .sum();
+        while open.last().is_some_and(|(i, _, _)| *i >= indent) {
+            close(&mut open);
+        }
+        if let Some(parent) = open.last_mut() {
+            parent.2 = y;
+        }
+        open.push((indent, y, y));
+    }
+    while !open.is_empty() {
+        close(&mut open);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bracket_ranges, indent_ranges};
+    use synoptic::Highlighter;
+
+    fn lines(st: &str) -> Vec<String> {
+        st.lines().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn fold_ranges_from_text() {
+        let code = lines("fn main() {\n    let x = [\n        1,\n    ];\n\n    x\n}\nend");
+        assert_eq!(indent_ranges(&code, 4), vec![(1, 2..3), (0, 1..6)]);
+        assert_eq!(
+            bracket_ranges(&code, &Highlighter::new(4)),
+            vec![(1, 2..3), (0, 1..6)]
+        );
+        // Brackets that close on the very next line leave nothing to hide
+        assert!(bracket_ranges(&lines("f(\n)"), &Highlighter::new(4)).is_empty());
+    }
+}
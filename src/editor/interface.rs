@@ -5,17 +5,21 @@ use crate::error::{OxError, Result};
 use crate::events::wait_for_event_hog;
 use crate::ui::{key_event, size, Feedback};
 #[cfg(not(target_os = "windows"))]
-use crate::ui::{remove_ansi_codes, replace_reset, strip_escape_codes};
+use crate::vt::{Cell, Colour, Style};
+use crate::lsp::Severity;
 use crate::{config, display, handle_lua_error};
 use crossterm::{
     event::{KeyCode as KCode, KeyModifiers as KMod},
     style::{Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
 };
+use kaolinite::document::VisualRow;
 use kaolinite::utils::{file_or_dir, get_cwd, get_parent, list_dir, width, width_char, Loc, Size};
 use mlua::Lua;
+use std::collections::HashMap;
 use std::ops::Range;
 use synoptic::{trim_fit, Highlighter, TokOpt};
 
+use super::scanning::search_match_spans;
 use super::Editor;
 
 /// Render cache to store the results of any calculations during rendering
@@ -29,6 +33,8 @@ pub struct RenderCache {
     pub file_tree: FTParts,
     pub file_tree_selection: Option<usize>,
     pub term_cursor: Option<Loc>,
+    pub wrapped_rows: HashMap<Vec<usize>, Vec<VisualRow>>,
+    pub bracket_pair: Option<(Loc, Loc)>,
 }
 
 impl Editor {
@@ -41,6 +47,14 @@ impl Editor {
         }
         // Calculate span
         self.render_cache.span = self.files.span(vec![], size, Loc::at(0, 0));
+        // Keep the size of each terminal in line with the split it is shown in
+        for (ptr, rows, cols) in &self.render_cache.span {
+            if let Some(FileLayout::Terminal(term)) = self.files.get_raw(ptr.clone()) {
+                if let Ok(mut term) = term.lock() {
+                    let _ = term.resize(rows.len(), cols.len());
+                }
+            }
+        }
         // Calculate help message information
         let tab_width = config!(self.config, document).tab_width;
         self.render_cache.help_message = config!(self.config, help_message).render(lua);
@@ -70,10 +84,18 @@ impl Editor {
         }
         // Clear the terminal cursor position
         self.render_cache.term_cursor = None;
+        // Forget how documents were wrapped (they may have changed since)
+        self.render_cache.wrapped_rows.clear();
+        // Find the brackets to highlight
+        self.render_cache.bracket_pair = if config!(self.config, document).match_brackets {
+            self.focused_bracket_pair()
+        } else {
+            None
+        };
     }
 
     /// Render a specific line
-    #[allow(clippy::similar_names)]
+    #[allow(clippy::similar_names, clippy::too_many_lines)]
     pub fn render_line(&mut self, y: usize, size: Size, lua: &Lua, sh: &SH) -> Result<String> {
         let tab_line_enabled = config!(self.config, tab_line).enabled;
         let split_bg = Bg(config!(self.config, colors).split_bg.to_color()?);
@@ -92,6 +114,14 @@ impl Editor {
                 self.files.get_raw(fc.to_owned()),
                 Some(FileLayout::Terminal(_))
             );
+            let in_results = matches!(
+                self.files.get_raw(fc.to_owned()),
+                Some(FileLayout::SearchResults)
+            );
+            let in_problems = matches!(
+                self.files.get_raw(fc.to_owned()),
+                Some(FileLayout::Problems)
+            );
             // Check if we have encountered an area of discontinuity in the line
             if range.start != accounted_for {
                 // Discontinuity detected, fill with vertical bar!
@@ -126,6 +156,12 @@ impl Editor {
             } else if in_terminal {
                 // Part of terminal!
                 result += &self.render_terminal(fc, rel_y, length, height)?;
+            } else if in_results {
+                // Part of the project search results
+                result += &self.render_project_search(rel_y, length, height)?;
+            } else if in_problems {
+                // Part of the problems pane
+                result += &self.render_problems(rel_y, length, height)?;
             } else if y == rows.start && tab_line_enabled {
                 // Tab line
                 result += &self.render_tab_line(fc, lua, length)?;
@@ -212,7 +248,6 @@ impl Editor {
             if let Some(fc) = fcs.get_mut(doc_idx) {
                 let doc = &mut fc.doc;
                 doc.size = new_size;
-                doc.load_to(doc.offset.y + doc.size.h + 1);
             }
             self.update_highlighter_for(&ptr, doc_idx);
         }
@@ -227,6 +262,8 @@ impl Editor {
         }
         // Render the feedback line
         self.render_feedback_line(w, h)?;
+        // Render the completion popup over the document
+        self.render_completion()?;
         // Move cursor to the correct location and perform render
         if let Some(Loc { x, y }) = self.cursor_position() {
             self.terminal.show_cursor();
@@ -290,15 +327,20 @@ impl Editor {
         let line_number_fg = Fg(config!(self.config, colors).line_number_fg.to_color()?);
         let selection_bg = Bg(config!(self.config, colors).selection_bg.to_color()?);
         let selection_fg = Fg(config!(self.config, colors).selection_fg.to_color()?);
+        let search_match_bg = Bg(config!(self.config, colors).search_match_bg.to_color()?);
+        let search_match_fg = Fg(config!(self.config, colors).search_match_fg.to_color()?);
+        let bracket_match_bg = Bg(config!(self.config, colors).bracket_match_bg.to_color()?);
+        let bracket_match_fg = Fg(config!(self.config, colors).bracket_match_fg.to_color()?);
         let underline = SetAttribute(Attribute::Underlined);
         let no_underline = SetAttribute(Attribute::NoUnderline);
-        let tab_width = config!(self.config, document).tab_width;
         let line_numbers_enabled = config!(self.config, line_numbers).enabled;
         let ln_pad_left = config!(self.config, line_numbers).padding_left;
         let ln_pad_right = config!(self.config, line_numbers).padding_right;
+        let wrapped = self.wrapped_row(ptr, y);
         let fc = self.files.get(ptr.to_owned())
             .ok_or_else(|| crate::error::OxError::DocumentNotFound { index: 0 })?;
         let doc = &fc.doc;
+        let tab_width = doc.tab_width;
         let selection = doc.selection_loc_bound_disp();
         let has_file = doc.file_name.is_none();
         // Refuse to render help message on splits - awkward edge case
@@ -311,11 +353,40 @@ impl Editor {
         } else {
             0
         };
-        // Render the line numbers if enabled
+        // With soft wrapping and folding, rows and lines don't line up
+        let (at_line, start_x) = wrapped.map_or((y + doc.offset.y, doc.offset.x), |r| (r.y, r.start));
+        // Render the line numbers if enabled (only on the first row of wrapped lines)
         if line_numbers_enabled {
-            let num = doc.line_number(y + doc.offset.y);
-            let padding_left = " ".repeat(ln_pad_left);
-            let padding_right = " ".repeat(ln_pad_right);
+            let first_row = wrapped.is_none_or(|r| r.first);
+            let num = if first_row {
+                doc.line_number(at_line)
+            } else {
+                " ".repeat(doc.len_lines().to_string().len())
+            };
+            // Mark lines that language servers (or the last task) have found problems on
+            let severity = doc.file_name.as_ref().filter(|_| first_row && ln_pad_left > 0)
+                .and_then(|f| {
+                    self.lsp.worst_on_line(f, at_line).map(|d| d.severity)
+                        .or_else(|| self.problem_on_line(f, at_line))
+                });
+            let padding_left = if let Some(severity) = severity {
+                let colors = config!(self.config, colors);
+                let marker = match severity {
+                    Severity::Error => &colors.error_fg,
+                    Severity::Warning => &colors.warning_fg,
+                    _ => &colors.info_fg,
+                };
+                let marker = Fg(marker.to_color()?);
+                format!("{marker}●{line_number_fg}{}", " ".repeat(ln_pad_left - 1))
+            } else {
+                " ".repeat(ln_pad_left)
+            };
+            // Mark lines that have a fold hidden beneath them
+            let padding_right = if first_row && ln_pad_right > 0 && doc.fold_below(at_line).is_some() {
+                format!("▸{}", " ".repeat(ln_pad_right - 1))
+            } else {
+                " ".repeat(ln_pad_right)
+            };
             result += &format!("{line_number_bg}{line_number_fg}{padding_left}{num}{padding_right}│{editor_fg}{editor_bg}");
             total_width += ln_pad_left + ln_pad_right + width(&num, tab_width) + 1;
         } else {
@@ -323,19 +394,30 @@ impl Editor {
         }
         w = w.saturating_sub(total_width);
         // Render the body of the document if available
-        let at_line = y + doc.offset.y;
         if let Some(line) = doc.line(at_line) {
             // Reset the cache
             let mut cache_bg = editor_bg;
             let mut cache_fg = editor_fg;
             // Gather the tokens
-            let tokens = fc.highlighter.line(at_line, &line);
-            let tokens = trim_fit(&tokens, doc.offset.x, w, tab_width);
-            let mut x_disp = doc.offset.x;
-            let mut x_char = doc.character_idx(&doc.offset);
+            // Paged documents aren't given to the highlighter, so show them as plain text
+            let tokens = if at_line < fc.highlighter.line_ref.len() {
+                fc.highlighter.line(at_line, &line)
+            } else {
+                vec![TokOpt::None(line.clone())]
+            };
+            let length = wrapped.map_or(w, |r| w.min(r.end.saturating_sub(r.start)));
+            let tokens = trim_fit(&tokens, start_x, length, tab_width);
+            let mut x_disp = start_x;
+            let mut x_char = doc.character_idx(&Loc::at(start_x, at_line));
             // Run some more calcs
             let is_focus = self.ptr == ptr;
             let has_selection_somewhere = doc.cursor.selection_end != doc.cursor.loc;
+            let match_spans = if is_focus {
+                search_match_spans(&self.search_matches, at_line)
+            } else {
+                vec![]
+            };
+            let brackets = self.render_cache.bracket_pair.filter(|_| is_focus);
             for token in tokens {
                 // Find out the text (and colour of that text)
                 let (text, colour, feedback) = self.breakdown_token(token, sh)?;
@@ -350,6 +432,8 @@ impl Editor {
                     let is_selected = is_focus
                         && has_selection_somewhere
                         && doc.is_this_loc_selected_disp(disp_loc, selection);
+                    let is_match = match_spans.iter().any(|(start, end)| (*start..*end).contains(&x_char));
+                    let is_bracket = brackets.is_some_and(|(a, b)| a == char_loc || b == char_loc);
                     // Render the correct colour
                     if is_selected {
                         if cache_bg != selection_bg {
@@ -360,6 +444,24 @@ impl Editor {
                             result += &selection_fg.to_string();
                             cache_fg = selection_fg;
                         }
+                    } else if is_bracket {
+                        if cache_bg != bracket_match_bg {
+                            result += &bracket_match_bg.to_string();
+                            cache_bg = bracket_match_bg;
+                        }
+                        if cache_fg != bracket_match_fg {
+                            result += &bracket_match_fg.to_string();
+                            cache_fg = bracket_match_fg;
+                        }
+                    } else if is_match {
+                        if cache_bg != search_match_bg {
+                            result += &search_match_bg.to_string();
+                            cache_bg = search_match_bg;
+                        }
+                        if cache_fg != search_match_fg {
+                            result += &search_match_fg.to_string();
+                            cache_fg = search_match_fg;
+                        }
                     } else {
                         if cache_bg != editor_bg {
                             result += &editor_bg.to_string();
@@ -404,6 +506,28 @@ impl Editor {
         Ok(result)
     }
 
+    /// Find the part of a line shown on a row of a soft wrapped or folded document
+    /// (None if each line is simply shown on its own row)
+    fn wrapped_row(&mut self, ptr: &[usize], y: usize) -> Option<VisualRow> {
+        let doc = &self.files.get(ptr.to_owned())?.doc;
+        if doc.one_row_per_line() {
+            return None;
+        }
+        let rows = self
+            .render_cache
+            .wrapped_rows
+            .entry(ptr.to_vec())
+            .or_insert_with(|| doc.rows_in_view());
+        // Rows past the end of the document are left empty
+        let past_end = VisualRow {
+            y: doc.len_lines(),
+            start: 0,
+            end: 0,
+            first: true,
+        };
+        Some(rows.get(y).copied().unwrap_or(past_end))
+    }
+
     /// Render help message
     pub fn render_help_message(&self, y: usize) -> Result<String> {
         let tab_width = config!(self.config, document).tab_width;
@@ -416,7 +540,7 @@ impl Editor {
             .help_message
             .get(at)
             .map_or((false, " ".repeat(max_width)), |(hl, content)| {
-                (*hl, content.to_string())
+                (*hl, content.clone())
             });
         let extra_padding = " ".repeat(max_width.saturating_sub(width(&msg, tab_width)));
         if hl {
@@ -560,7 +684,12 @@ impl Editor {
     /// Render the feedback line
     pub fn render_feedback_line(&mut self, w: usize, h: usize) -> Result<()> {
         self.terminal.goto(0, h + 2);
-        let content = self.feedback.render(&config!(self.config, colors), w)?;
+        // With nothing else to say, describe any problem on the cursor's line
+        let diagnostic = matches!(self.feedback, Feedback::None)
+            .then(|| self.diagnostic_feedback())
+            .flatten();
+        let feedback = diagnostic.as_ref().unwrap_or(&self.feedback);
+        let content = feedback.render(&config!(self.config, colors), w)?;
         display!(self, content);
         Ok(())
     }
@@ -643,53 +772,84 @@ impl Editor {
         }
     }
 
-    /// Render the line of a terminal
+    /// Render the line of a terminal, cell by cell from its emulated screen
     #[allow(clippy::similar_names)]
     #[cfg(not(target_os = "windows"))]
-    fn render_terminal(&mut self, fc: &Vec<usize>, y: usize, l: usize, h: usize) -> Result<String> {
-        if let Some(FileLayout::Terminal(term)) = self.files.get_raw(fc.to_owned()) {
-            let term = match term.lock() {
-                Ok(guard) => guard,
-                Err(e) => {
-                    eprintln!("Failed to lock terminal: {}", e);
-                    return Ok(String::new());
+    fn render_terminal(&mut self, fc: &Vec<usize>, y: usize, l: usize, _: usize) -> Result<String> {
+        let Some(FileLayout::Terminal(term)) = self.files.get_raw(fc.to_owned()) else {
+            // Terminal was expected but not found - return empty line to avoid panic
+            // This can happen if the file layout changes between checking and rendering
+            return Ok(" ".repeat(l));
+        };
+        let term = match term.lock() {
+            Ok(guard) => guard,
+            Err(e) => {
+                eprintln!("Failed to lock terminal: {e}");
+                return Ok(String::new());
+            }
+        };
+        let colors = config!(self.config, colors);
+        let editor_fg = colors.editor_fg.to_color()?;
+        let editor_bg = colors.editor_bg.to_color()?;
+        let selection = (colors.selection_fg.to_color()?, colors.selection_bg.to_color()?);
+        let search_match = (
+            colors.search_match_fg.to_color()?,
+            colors.search_match_bg.to_color()?,
+        );
+        let screen = &term.screen;
+        let mut cells = screen.view_line(y).map(<[Cell]>::to_vec).unwrap_or_default();
+        cells.resize(l.max(cells.len()), Cell::default());
+        // Work out which cells are selected or part of a search match
+        let focused = self.ptr == *fc;
+        let buffer_y = screen.view_top() + y;
+        let highlights: Vec<Option<(Color, Color)>> = (0..cells.len())
+            .map(|x| {
+                let in_match = |(start, end): &(Loc, Loc)| {
+                    start.y == buffer_y && (start.x..end.x).contains(&x)
+                };
+                if screen.is_selected(x, buffer_y) {
+                    Some(selection)
+                } else if focused && self.search_matches.iter().any(in_match) {
+                    Some(search_match)
+                } else {
+                    None
                 }
-            };
-            let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?).to_string();
-            let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?).to_string();
-            let reset = SetAttribute(Attribute::NoBold);
-            let n_lines = term.output.matches('\n').count();
-            let shift_down = n_lines.saturating_sub(h.saturating_sub(1));
-            // Calculate the contents and amount of padding for this line of the terminal
-            let (line, pad) = if let Some(line) = term.output.split('\n').nth(shift_down + y) {
-                // Calculate line and padding
-                let line = line.replace(['\n', '\r'], "");
-                let mut visible_line = strip_escape_codes(&line);
-                // Replace resets with editor style
-                visible_line = replace_reset(&visible_line, &editor_bg, &editor_fg);
-                let mut w = width(&remove_ansi_codes(&line), 4);
-                // Work out if this is where the cursor should be
-                if n_lines.saturating_sub(shift_down) == y && self.ptr == *fc {
-                    visible_line += &format!("{editor_fg}{reset}{}", term.input);
-                    w += width(&term.input, 4);
-                    self.render_cache.term_cursor = Some(Loc { x: w, y });
+            })
+            .collect();
+        // Show the cursor of the terminal (unless scrolled back)
+        let cursor = screen.cursor();
+        if focused && cursor.y == y && screen.scrolled() == 0 && screen.cursor_visible {
+            self.render_cache.term_cursor = Some(Loc { x: cursor.x, y });
+        }
+        std::mem::drop(term);
+        // Draw the cells, only changing style where it needs to
+        let mut result = String::new();
+        let mut current = None;
+        let mut x = 0;
+        for (cell, highlight) in cells.into_iter().zip(highlights) {
+            if x >= l {
+                break;
+            }
+            if cell.ch == '\0' {
+                continue;
+            }
+            let w = width_char(&cell.ch, 1);
+            if current != Some((cell.style, highlight)) {
+                result += &terminal_style(&cell.style, editor_fg, editor_bg);
+                if let Some((fg, bg)) = highlight {
+                    result += &format!("{}{}", Fg(fg), Bg(bg));
                 }
-                // Return the result
-                (visible_line, l.saturating_sub(w))
+                current = Some((cell.style, highlight));
+            }
+            if cell.style.hidden || x + w > l {
+                result += &" ".repeat(w.min(l - x));
             } else {
-                (" ".repeat(l), 0)
-            };
-            std::mem::drop(term);
-            // Calculate the final result
-            Ok(format!(
-                "{reset}{editor_fg}{editor_bg}{line}{}",
-                " ".repeat(pad)
-            ))
-        } else {
-            // Terminal was expected but not found - return empty line to avoid panic
-            // This can happen if the file layout changes between checking and rendering
-            Ok(" ".repeat(l))
+                result.push(cell.ch);
+            }
+            x += w;
         }
+        result += &format!("{}{}", SetAttribute(Attribute::Reset), " ".repeat(l.saturating_sub(x)));
+        Ok(format!("{}{}{result}", Fg(editor_fg), Bg(editor_bg)))
     }
 
     /// Just render a blank space in place of terminal if on windows
@@ -763,7 +923,7 @@ impl Editor {
         while !done {
             // Find the suggested files and folders
             let parent = if input.ends_with('/') || input.ends_with('\\') {
-                input.to_string()
+                input.clone()
             } else {
                 get_parent(&input).unwrap_or_default()
             };
@@ -846,11 +1006,10 @@ impl Editor {
                     }
                     // Cycle through suggestions
                     (KMod::SHIFT, KCode::BackTab) => offset = offset.saturating_sub(1),
-                    (KMod::NONE, KCode::Tab) => {
-                        if offset + 1 < suggestions.len() {
+                    (KMod::NONE, KCode::Tab)
+                        if offset + 1 < suggestions.len() => {
                             offset += 1;
                         }
-                    }
                     _ => (),
                 }
             }
@@ -896,6 +1055,40 @@ impl Editor {
         Ok(result)
     }
 
+    /// Dialog for picking between several options, each chosen by pressing a key.
+    /// Returns None if the dialog was cancelled with the escape key.
+    pub fn choose(&mut self, msg: &str, choices: &[char]) -> Result<Option<char>> {
+        let mut result = None;
+        // Enter into the choice menu
+        self.terminal.hide_cursor();
+        loop {
+            let h = size()?.h;
+            let w = size()?.w;
+            // Render message
+            self.feedback = Feedback::Warning(msg.to_string());
+            self.render_feedback_line(w, h)?;
+            self.terminal.flush()?;
+            // Handle events
+            if let Some((modifiers, code)) =
+                key_event(&wait_for_event_hog(self), &mut self.macro_man)
+            {
+                match (modifiers, code) {
+                    (KMod::NONE, KCode::Esc) => break,
+                    (KMod::NONE | KMod::SHIFT, KCode::Char(c))
+                        if choices.contains(&c.to_ascii_lowercase()) =>
+                    {
+                        result = Some(c.to_ascii_lowercase());
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        self.feedback = Feedback::None;
+        self.terminal.show_cursor();
+        Ok(result)
+    }
+
     /// Append any missed lines to the syntax highlighter
     pub fn update_highlighter(&mut self) {
         if let Some((_, doc_idx)) = self.files.get_atom(self.ptr.clone()) {
@@ -906,17 +1099,19 @@ impl Editor {
     /// Update highlighter of a certain document
     pub fn update_highlighter_for(&mut self, ptr: &[usize], doc: usize) {
         let percieved = self.highlighter_for(ptr.to_owned(), doc)
-            .map(|h| h.line_ref.len())
-            .unwrap_or(0);
+            .map_or(0, |h| h.line_ref.len());
         if self.active {
             if let Some((ref mut fcs, _)) = self.files.get_atom_mut(ptr.to_owned()) {
-                let actual = fcs[doc].doc.info.loaded_to;
-                if percieved < actual {
-                    let diff = actual.saturating_sub(percieved);
-                    for i in 0..diff {
-                        let line = fcs[doc].doc.lines[percieved + i].clone();
-                        fcs[doc].highlighter.append(&line);
-                    }
+                // Highlight down to the bottom of the viewport (paged documents aren't highlighted)
+                let document = &fcs[doc].doc;
+                let actual = if document.is_paged() {
+                    0
+                } else {
+                    (document.offset.y + document.size.h + 1).min(document.file.len_lines())
+                };
+                for y in percieved..actual {
+                    let line = fcs[doc].doc.line(y).unwrap_or_default();
+                    fcs[doc].highlighter.append(&line);
                 }
             }
         }
@@ -945,7 +1140,9 @@ impl Editor {
     /// Reload the whole document in the highlighter
     pub fn reload_highlight(&mut self) {
         if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-            file.highlighter.run(&file.doc.lines);
+            if !file.doc.is_paged() {
+                file.highlighter.run(&file.doc.lines_to_view());
+            }
         }
     }
 
@@ -974,6 +1171,35 @@ impl Editor {
     }
 }
 
+/// Work out the escape codes to draw a cell of a terminal in a style
+#[allow(clippy::similar_names)]
+#[cfg(not(target_os = "windows"))]
+fn terminal_style(style: &Style, editor_fg: Color, editor_bg: Color) -> String {
+    let colour = |colour: Colour, default: Color| match colour {
+        Colour::Default => default,
+        Colour::Indexed(n) => Color::AnsiValue(n),
+        Colour::Rgb(r, g, b) => Color::Rgb { r, g, b },
+    };
+    let mut fg = colour(style.fg, editor_fg);
+    let mut bg = colour(style.bg, editor_bg);
+    if style.reverse {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    let mut result = format!("{}{}{}", SetAttribute(Attribute::Reset), Fg(fg), Bg(bg));
+    let attributes = [
+        (style.bold, Attribute::Bold),
+        (style.dim, Attribute::Dim),
+        (style.italic, Attribute::Italic),
+        (style.underline, Attribute::Underlined),
+    ];
+    for (on, attribute) in attributes {
+        if on {
+            result += &SetAttribute(attribute).to_string();
+        }
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -982,7 +1208,8 @@ mod tests {
     /// Create a test editor instance
     fn create_test_editor() -> Editor {
         use crate::config::Config;
-        use crate::editor::MacroMan;
+        use crate::editor::{CompletionPopup, LanguageServers, MacroMan};
+        use kaolinite::searching::SearchOptions;
         use mlua::Lua;
         use std::time::Instant;
         
@@ -1010,6 +1237,19 @@ mod tests {
             file_tree: None,
             file_tree_selection: None,
             old_ptr: vec![],
+            last_disk_check: Instant::now(),
+            search_options: SearchOptions::default(),
+            search_matches: vec![],
+            lsp: LanguageServers::default(),
+            completion: CompletionPopup::default(),
+            project_search: None,
+            problems: None,
+            recent_files: vec![],
+            recent_commands: vec![],
+            palette_choice: None,
+            session_file: None,
+            closing_session: None,
+            encoding: None,
         }
     }
 
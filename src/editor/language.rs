@@ -0,0 +1,498 @@
+/// Functions for working with language servers (for diagnostics, hover, go to definition and more)
+use crate::config;
+use crate::error::{OxError, Result};
+use crate::lsp::protocol::{
+    language_id, parse_completions, parse_hover, parse_locations, parse_workspace_edit,
+    path_to_uri, uri_to_path,
+};
+use crate::lsp::{
+    CompletionItem, Diagnostic, LanguageServer, Location, Notice, PositionEncoding, Severity,
+    TextEdit,
+};
+use crate::ui::Feedback;
+use kaolinite::document::DiskState;
+use kaolinite::event::Error as KError;
+use kaolinite::utils::get_absolute_path;
+use kaolinite::{Document, Loc};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::Editor;
+
+/// How long to wait for a language server to answer a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Files and folders that mark the root of a project (in order of preference)
+const ROOT_MARKERS: [&str; 6] = [
+    ".git",
+    "Cargo.toml",
+    "go.mod",
+    "package.json",
+    "pyproject.toml",
+    "Makefile",
+];
+
+/// The language servers that have been started, and what they have found
+#[derive(Default)]
+pub struct LanguageServers {
+    /// Running servers (by the command used to start them)
+    pub servers: HashMap<String, LanguageServer>,
+    /// Commands that couldn't be started, so they aren't tried again
+    pub failed: HashSet<String>,
+    /// The problems servers have found in each document (by URI)
+    pub diagnostics: HashMap<String, Vec<Diagnostic>>,
+    /// The URIs of the files that have been opened (by file name)
+    uris: HashMap<String, String>,
+    /// The state of each open document on the disk when it was last synced (by URI)
+    disk: HashMap<String, Option<DiskState>>,
+    /// The references found most recently (to move between them)
+    pub references: Vec<Location>,
+}
+
+impl LanguageServers {
+    /// Find the URI of a file
+    pub fn uri(&mut self, file_name: &str) -> Option<String> {
+        if let Some(uri) = self.uris.get(file_name) {
+            return Some(uri.clone());
+        }
+        let uri = path_to_uri(&get_absolute_path(file_name)?);
+        self.uris.insert(file_name.to_string(), uri.clone());
+        Some(uri)
+    }
+
+    /// Find the problems found in a file (its URI needs to have been found before)
+    #[must_use]
+    pub fn diagnostics_for(&self, file_name: &str) -> &[Diagnostic] {
+        self.uris
+            .get(file_name)
+            .and_then(|uri| self.diagnostics.get(uri))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Find the most serious problem found on a line of a file
+    #[must_use]
+    pub fn worst_on_line(&self, file_name: &str, y: usize) -> Option<&Diagnostic> {
+        self.diagnostics_for(file_name)
+            .iter()
+            .filter(|d| d.range.start.line == y)
+            .min_by_key(|d| d.severity)
+    }
+
+    /// Shut down every server
+    pub fn stop_all(&mut self) {
+        for server in self.servers.values_mut() {
+            server.stop();
+        }
+        self.servers.clear();
+        self.diagnostics.clear();
+    }
+}
+
+impl Editor {
+    /// Start language servers for open documents that need one, and tell them
+    /// about documents that have been opened, edited, saved or closed
+    pub fn sync_language_servers(&mut self) {
+        if !config!(self.config, document).language_servers {
+            return;
+        }
+        let lsp = &mut self.lsp;
+        let mut open: HashSet<(String, String)> = HashSet::new();
+        for atom in self.files.atoms(vec![]) {
+            let Some((fcs, _)) = self.files.get_atom_mut(atom) else {
+                continue;
+            };
+            for fc in fcs {
+                let Some(file_type) = &fc.file_type else {
+                    continue;
+                };
+                let (Some(command), Some(file_name)) = (&file_type.lsp, &fc.doc.file_name) else {
+                    continue;
+                };
+                if fc.doc.is_paged() || lsp.failed.contains(command) {
+                    continue;
+                }
+                let Some(uri) = lsp.uri(file_name) else {
+                    continue;
+                };
+                // The same file could be open in several places
+                if !open.insert((command.clone(), uri.clone())) {
+                    continue;
+                }
+                if !lsp.servers.contains_key(command) {
+                    let root = project_root(Path::new(file_name));
+                    if let Ok(server) = LanguageServer::start(command, &root) {
+                        lsp.servers.insert(command.clone(), server);
+                    } else {
+                        lsp.failed.insert(command.clone());
+                        continue;
+                    }
+                }
+                let Some(server) = lsp.servers.get_mut(command) else {
+                    continue;
+                };
+                if server.is_open(&uri) {
+                    server.change(&uri, &mut fc.doc);
+                    // Saving changes the state of the file on the disk
+                    let disk = lsp.disk.entry(uri.clone()).or_insert(fc.doc.info.disk);
+                    if *disk != fc.doc.info.disk {
+                        *disk = fc.doc.info.disk;
+                        server.save(&uri, &fc.doc);
+                    }
+                } else {
+                    server.open(&uri, &language_id(&file_type.name), &mut fc.doc);
+                    lsp.disk.insert(uri.clone(), fc.doc.info.disk);
+                }
+            }
+        }
+        // Close the documents that are no longer open
+        for (command, server) in &mut lsp.servers {
+            for uri in server.open_documents() {
+                if !open.contains(&(command.clone(), uri.clone())) {
+                    server.close(&uri);
+                    lsp.diagnostics.remove(&uri);
+                    lsp.disk.remove(&uri);
+                }
+            }
+        }
+    }
+
+    /// Pick up anything the language servers have said.
+    /// Returns true if anything changed that needs rendering.
+    pub fn poll_language_servers(&mut self) -> bool {
+        let mut changed = false;
+        let mut stopped = vec![];
+        for (command, server) in &mut self.lsp.servers {
+            for notice in server.poll() {
+                match notice {
+                    Notice::Diagnostics { uri, diagnostics } => {
+                        self.lsp.diagnostics.insert(uri, diagnostics);
+                        changed = true;
+                    }
+                    Notice::Message { severity, text } => {
+                        self.feedback = match severity {
+                            Severity::Error => Feedback::Error(text),
+                            Severity::Warning => Feedback::Warning(text),
+                            _ => Feedback::Info(text),
+                        };
+                        changed = true;
+                    }
+                    Notice::Stopped => stopped.push(command.clone()),
+                    Notice::Response { .. } => (),
+                }
+            }
+        }
+        // Servers that stop aren't started again until asked to
+        for command in stopped {
+            self.lsp.servers.remove(&command);
+            self.lsp.failed.insert(command.clone());
+            self.feedback = Feedback::Warning(format!("Language server '{command}' has stopped"));
+            changed = true;
+        }
+        changed
+    }
+
+    /// Start the language server for the current document again
+    pub fn restart_language_server(&mut self) {
+        let Some(command) = self.current_lsp_command() else {
+            self.feedback = Feedback::Error("No language server is set up for this file type".to_string());
+            return;
+        };
+        if let Some(mut server) = self.lsp.servers.remove(&command) {
+            server.stop();
+        }
+        self.lsp.failed.remove(&command);
+        self.sync_language_servers();
+        if self.lsp.servers.contains_key(&command) {
+            self.feedback = Feedback::Info(format!("Restarted language server '{command}'"));
+        } else {
+            self.feedback = Feedback::Error(format!("Couldn't start language server '{command}'"));
+        }
+    }
+
+    /// Find the command that starts the language server for the current document
+    fn current_lsp_command(&self) -> Option<String> {
+        let fc = self.files.get(self.ptr.clone())?;
+        fc.file_type.as_ref()?.lsp.clone()
+    }
+
+    /// Send a request about the cursor position in the current document and wait for the answer.
+    /// Returns the answer, along with how the server counts columns.
+    fn request_at_cursor(&mut self, method: &str, extra: Value) -> Result<(Value, PositionEncoding)> {
+        self.sync_language_servers();
+        let no_server = || OxError::LanguageServer {
+            msg: "no language server is running for this document".to_string(),
+        };
+        let command = self.current_lsp_command().ok_or_else(no_server)?;
+        let fc = self.files.get(self.ptr.clone()).ok_or_else(no_server)?;
+        let file_name = fc.doc.file_name.clone().ok_or_else(no_server)?;
+        let uri = self.lsp.uri(&file_name).ok_or_else(no_server)?;
+        let server = self.lsp.servers.get_mut(&command).ok_or_else(no_server)?;
+        let id = server.request_at(method, &uri, &fc.doc, &fc.doc.char_loc(), extra);
+        let encoding = server.encoding;
+        let result = server
+            .wait_for(id, REQUEST_TIMEOUT)
+            .map_err(|msg| OxError::LanguageServer { msg })?;
+        Ok((result, encoding))
+    }
+
+    /// Find out about the code under the cursor (None if there is nothing to say)
+    pub fn lsp_hover(&mut self) -> Result<Option<String>> {
+        let (result, _) = self.request_at_cursor("textDocument/hover", json!({}))?;
+        // Code blocks are shown as plain text
+        Ok(parse_hover(&result).map(|text| {
+            text.lines()
+                .filter(|l| !l.trim_start().starts_with("```"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        }))
+    }
+
+    /// Move to where the code under the cursor is defined.
+    /// Returns false if no definition was found.
+    pub fn lsp_definition(&mut self) -> Result<bool> {
+        let (result, encoding) = self.request_at_cursor("textDocument/definition", json!({}))?;
+        let Some(location) = parse_locations(&result).into_iter().next() else {
+            return Ok(false);
+        };
+        self.goto_location(&location, encoding)?;
+        Ok(true)
+    }
+
+    /// Find everywhere the code under the cursor is referred to
+    pub fn lsp_references(&mut self) -> Result<Vec<Location>> {
+        let context = json!({ "context": { "includeDeclaration": true } });
+        let (result, _) = self.request_at_cursor("textDocument/references", context)?;
+        let references = parse_locations(&result);
+        self.lsp.references.clone_from(&references);
+        Ok(references)
+    }
+
+    /// Move to the next reference to the code under the cursor (finding them if needed)
+    pub fn lsp_next_reference(&mut self) -> Result<()> {
+        let here = self.current_location();
+        let known = here
+            .as_ref()
+            .is_some_and(|(uri, _)| self.lsp.references.iter().any(|r| &r.uri == uri));
+        if !known {
+            self.lsp_references()?;
+        }
+        let references = self.lsp.references.clone();
+        if references.is_empty() {
+            self.feedback = Feedback::Warning("No references found".to_string());
+            return Ok(());
+        }
+        // Go to the first reference after the cursor (in this file), wrapping back around
+        let next = here.and_then(|(uri, loc)| {
+            references.iter().position(|r| {
+                let start = r.range.start;
+                r.uri == uri && (start.line, start.character) > (loc.y, loc.x)
+            })
+        });
+        let idx = next.unwrap_or(0);
+        let encoding = self.current_encoding();
+        self.goto_location(&references[idx], encoding)?;
+        self.feedback = Feedback::Info(format!("Reference {} of {}", idx + 1, references.len()));
+        Ok(())
+    }
+
+    /// Rename the code under the cursor everywhere it is used.
+    /// Returns the number of edits made.
+    pub fn lsp_rename(&mut self, new_name: &str) -> Result<usize> {
+        let params = json!({ "newName": new_name });
+        let (result, encoding) = self.request_at_cursor("textDocument/rename", params)?;
+        let mut count = 0;
+        for (uri, edits) in parse_workspace_edit(&result) {
+            let Some(path) = uri_to_path(&uri) else {
+                continue;
+            };
+            // Files that aren't open yet are opened (without moving to them)
+            let at = if let Some(at) = self.already_open(&path) {
+                at
+            } else {
+                self.open(&path)?;
+                self.already_open(&path).ok_or(OxError::InvalidPath)?
+            };
+            if let Some((fcs, _)) = self.files.get_atom_mut(at.0) {
+                count += edits.len();
+                apply_text_edits(&mut fcs[at.1].doc, edits, encoding)?;
+            }
+        }
+        self.update_highlighter();
+        Ok(count)
+    }
+
+    /// Ask for suggestions of what could be typed at the cursor
+    pub fn lsp_completion(&mut self) -> Result<Vec<CompletionItem>> {
+        let (result, _) = self.request_at_cursor("textDocument/completion", json!({}))?;
+        Ok(parse_completions(&result))
+    }
+
+    /// Send any request to the language server of the current document and wait for the answer
+    pub fn lsp_request(&mut self, method: &str, params: Value) -> Result<Value> {
+        self.sync_language_servers();
+        let command = self.current_lsp_command().ok_or(OxError::LanguageServer {
+            msg: "no language server is running for this document".to_string(),
+        })?;
+        let server = self.lsp.servers.get_mut(&command).ok_or(OxError::LanguageServer {
+            msg: format!("'{command}' isn't running"),
+        })?;
+        let id = server.request(method, params);
+        server
+            .wait_for(id, REQUEST_TIMEOUT)
+            .map_err(|msg| OxError::LanguageServer { msg })
+    }
+
+    /// Send any notification to the language server of the current document
+    pub fn lsp_notify(&mut self, method: &str, params: Value) -> Result<()> {
+        self.sync_language_servers();
+        let command = self.current_lsp_command().ok_or(OxError::LanguageServer {
+            msg: "no language server is running for this document".to_string(),
+        })?;
+        let server = self.lsp.servers.get_mut(&command).ok_or(OxError::LanguageServer {
+            msg: format!("'{command}' isn't running"),
+        })?;
+        server.notify(method, params);
+        Ok(())
+    }
+
+    /// Find the problems found in the current document
+    pub fn current_diagnostics(&self) -> &[Diagnostic] {
+        let file_name = self.try_doc().and_then(|doc| doc.file_name.as_ref());
+        file_name.map_or(&[], |f| self.lsp.diagnostics_for(f))
+    }
+
+    /// Describe the most serious problem on the cursor's line (for the feedback line)
+    pub fn diagnostic_feedback(&self) -> Option<Feedback> {
+        let doc = self.try_doc()?;
+        let diagnostic = self.lsp.worst_on_line(doc.file_name.as_ref()?, doc.loc().y)?;
+        let text = match &diagnostic.source {
+            Some(source) => format!("{source}: {}", diagnostic.message),
+            None => diagnostic.message.clone(),
+        };
+        let text = text.lines().next().unwrap_or_default().to_string();
+        Some(match diagnostic.severity {
+            Severity::Error => Feedback::Error(text),
+            Severity::Warning => Feedback::Warning(text),
+            _ => Feedback::Info(text),
+        })
+    }
+
+    /// Find the URI and cursor position (as the protocol counts it) of the current document
+    fn current_location(&mut self) -> Option<(String, Loc)> {
+        let doc = self.files.get(self.ptr.clone()).map(|fc| &fc.doc)?;
+        let loc = doc.char_loc();
+        let line = doc.line(loc.y).unwrap_or_default();
+        let file_name = doc.file_name.clone()?;
+        let x = self.current_encoding().to_column(&line, loc.x);
+        Some((self.lsp.uri(&file_name)?, Loc::at(x, loc.y)))
+    }
+
+    /// Find how the language server of the current document counts columns
+    fn current_encoding(&self) -> PositionEncoding {
+        self.current_lsp_command()
+            .and_then(|c| self.lsp.servers.get(&c))
+            .map_or(PositionEncoding::default(), |s| s.encoding)
+    }
+
+    /// Move to a location (opening the file it is in if needed)
+    fn goto_location(&mut self, location: &Location, encoding: PositionEncoding) -> Result<()> {
+        let path = uri_to_path(&location.uri).ok_or(OxError::InvalidPath)?;
+        if let Some((atom, idx)) = self.already_open(&path) {
+            self.ptr.clone_from(&atom);
+            self.files.move_to(atom, idx);
+            self.update_cwd();
+        } else {
+            self.open(&path)?;
+            self.next();
+        }
+        if let Some(doc) = self.try_doc_mut() {
+            let loc = location.range.start.to_loc(doc, encoding);
+            doc.move_to(&loc);
+        }
+        self.update_highlighter();
+        Ok(())
+    }
+}
+
+/// Make a set of edits to a document (as one undoable change)
+fn apply_text_edits(doc: &mut Document, mut edits: Vec<TextEdit>, encoding: PositionEncoding) -> Result<()> {
+    if doc.info.read_only {
+        return Err(KError::ReadOnlyFile.into());
+    }
+    // Work from the end of the document backwards, so earlier edits don't move later ones
+    edits.sort_by(|a, b| {
+        let start = |e: &TextEdit| (e.range.start.line, e.range.start.character);
+        start(b).cmp(&start(a))
+    });
+    // Keep the edits apart from anything typed before them
+    doc.commit();
+    for edit in edits {
+        let start = edit.range.start.to_loc(doc, encoding);
+        let end = edit.range.end.to_loc(doc, encoding);
+        let (from, to) = (doc.loc_to_file_pos(&start), doc.loc_to_file_pos(&end));
+        // Edits are taken exactly as they are (spanning lines, without any tab handling)
+        if from < to {
+            let removed = doc.file.slice(from..to).to_string();
+            doc.delete_text(&start, &removed)?;
+        }
+        if !edit.new_text.is_empty() {
+            doc.insert(&start, &edit.new_text)?;
+        }
+    }
+    doc.commit();
+    doc.cancel_selection();
+    Ok(())
+}
+
+/// Find the root of the project a file is in
+fn project_root(file: &Path) -> PathBuf {
+    let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let dir = file.parent().unwrap_or(&file).to_path_buf();
+    for marker in ROOT_MARKERS {
+        if let Some(root) = dir.ancestors().find(|d| d.join(marker).exists()) {
+            return root.to_path_buf();
+        }
+    }
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::protocol::{Position, Range};
+    use kaolinite::event::Event;
+    use kaolinite::utils::Size;
+
+    fn edit(from: (usize, usize), to: (usize, usize), new_text: &str) -> TextEdit {
+        let position = |(line, character)| Position { line, character };
+        TextEdit {
+            range: Range {
+                start: position(from),
+                end: position(to),
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn text_edits_across_lines() {
+        let mut doc = Document::new(Size::is(100, 10));
+        doc.exe(Event::Insert(Loc::at(0, 0), "fn main() {\n        call(a,\n    b);\n}".to_string()))
+            .unwrap();
+        doc.commit();
+        // Edits can span lines, and leading spaces aren't treated as tabs
+        let edits = vec![
+            edit((1, 0), (1, 4), ""),
+            edit((1, 15), (2, 4), " "),
+            edit((0, 3), (0, 7), "start"),
+        ];
+        apply_text_edits(&mut doc, edits, PositionEncoding::Utf32).unwrap();
+        assert_eq!(doc.file.to_string(), "fn start() {\n    call(a, b);\n}\n");
+        // All the edits are undone together
+        doc.undo().unwrap();
+        assert_eq!(doc.file.to_string(), "fn main() {\n        call(a,\n    b);\n}\n");
+        doc.redo().unwrap();
+        assert_eq!(doc.file.to_string(), "fn start() {\n    call(a, b);\n}\n");
+    }
+}
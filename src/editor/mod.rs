@@ -1,37 +1,64 @@
 /// Main functionality of the editor
 use crate::config;
-use crate::config::{Config, Indentation};
+use crate::config::{BackupMode, Config, Indentation};
 use crate::error::{OxError, Result};
 use crate::ui::{size, Feedback, Terminal};
 use crossterm::event::{
     Event as CEvent, KeyCode as KCode, KeyModifiers as KMod, MouseEvent, MouseEventKind,
 };
+use kaolinite::document::{Backup, DiskState};
+use kaolinite::encoding::Encoding;
 use kaolinite::event::Error as KError;
+use kaolinite::history::HistoryStore;
+use kaolinite::searching::SearchOptions;
 use kaolinite::utils::{file_or_dir, get_absolute_path, get_file_name};
 use kaolinite::{Document, Loc};
 use mlua::{Error as LuaError, Lua};
 use std::env;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use synoptic::Highlighter;
 
+/// The number of bytes in a mebibyte (used for file size limits)
+const MIB: u64 = 1024 * 1024;
+
+mod brackets;
+mod completion;
 mod cursor;
 mod documents;
 mod editing;
+mod editorconfig;
 mod filetree;
 mod filetypes;
+mod finder;
+mod folding;
 mod interface;
+mod language;
 mod macros;
 mod mouse;
+mod palette;
+mod problems;
+mod project;
 mod scanning;
+mod session;
+mod terminals;
+mod watching;
 
+pub use completion::{update_completion, CompletionPopup};
 pub use cursor::{allowed_by_multi_cursor, handle_multiple_cursors};
 pub use documents::{FileContainer, FileLayout};
+pub use editorconfig::Formatting;
 pub use filetree::{FTParts, FileTree};
 pub use filetypes::{FileType, FileTypes};
+pub use folding::Folds;
 pub use interface::RenderCache;
+pub use language::LanguageServers;
 pub use macros::MacroMan;
+pub use palette::{run_palette_choice, split_arguments, EntryKind, PaletteChoice};
+pub use problems::Problems;
+pub use project::ProjectSearch;
+pub use session::{auto_session_path, read_session, session_path};
 
 /// For managing all editing and rendering of cactus
 #[allow(clippy::struct_excessive_bools)]
@@ -78,6 +105,34 @@ pub struct Editor {
     pub file_tree_selection: Option<String>,
     /// For caching a pointer to go back to when in a file tree
     pub old_ptr: Vec<usize>,
+    /// When open files were last checked for changes made by other programs
+    pub last_disk_check: Instant,
+    /// How documents are searched (toggled from the search prompt)
+    pub search_options: SearchOptions,
+    /// Where the matches of the search in progress start and end, so they can be highlighted
+    pub search_matches: Vec<(Loc, Loc)>,
+    /// The language servers that are running, and the problems they have found
+    pub lsp: LanguageServers,
+    /// The completion popup and the suggestions within it
+    pub completion: CompletionPopup,
+    /// The results of the last search across the project
+    pub project_search: Option<ProjectSearch>,
+    /// The last task to be run, and the problems found in its output
+    pub problems: Option<Problems>,
+    /// Files that have been opened (as absolute paths, most recent first)
+    pub recent_files: Vec<String>,
+    /// Commands used from the palette or command line (most recent first)
+    pub recent_commands: Vec<String>,
+    /// What was chosen from the command palette, waiting to be run
+    pub palette_choice: Option<PaletteChoice>,
+    /// The session file the layout is saved to when the editor is closed (if any)
+    pub session_file: Option<PathBuf>,
+    /// The layout from before documents started being closed one after another
+    /// (and whether the last key pressed closed one)
+    pub closing_session: Option<(serde_json::Value, bool)>,
+    /// An encoding to open files with, in place of any editorconfig charset
+    /// (set while the files given on the command line are opened)
+    pub encoding: Option<Encoding>,
 }
 
 impl Editor {
@@ -106,6 +161,19 @@ impl Editor {
             file_tree: None,
             file_tree_selection: None,
             old_ptr: vec![],
+            last_disk_check: Instant::now(),
+            search_options: SearchOptions::default(),
+            search_matches: vec![],
+            lsp: LanguageServers::default(),
+            completion: CompletionPopup::default(),
+            project_search: None,
+            problems: None,
+            recent_files: vec![],
+            recent_commands: vec![],
+            palette_choice: None,
+            session_file: None,
+            closing_session: None,
+            encoding: None,
         })
     }
 
@@ -121,17 +189,19 @@ impl Editor {
         size.h = size.h.saturating_sub(1 + self.push_down);
         let mut doc = Document::new(size);
         doc.set_tab_width(config!(self.config, document).tab_width);
+        doc.set_wrap(config!(self.config, document).soft_wrap);
         doc.event_mgmt.force_not_with_disk = true;
-        // Load all the lines within viewport into the document
-        doc.load_to(size.h);
         // Update in the syntax highlighter
         let mut highlighter = Highlighter::new(4);
-        highlighter.run(&doc.lines);
+        highlighter.run(&doc.lines_to_view());
         // Add document to documents
         let file = FileContainer {
             highlighter,
             file_type: Some(FileType::default()),
             doc,
+            dismissed: None,
+            folds: Folds::default(),
+            format: self.formatting_for(&editorconfig::Properties::default()),
         };
         if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
             if *ptr + 1 >= files.len() {
@@ -204,27 +274,108 @@ impl Editor {
         }
         let mut size = size()?;
         size.h = size.h.saturating_sub(1 + self.push_down);
-        let mut doc = Document::open(size, file_name)?;
+        let len = std::fs::metadata(file_name).map_or(0, |m| m.len());
+        let properties = self.editorconfig(file_name);
+        let mut doc = if len > config!(self.config, document).large_file_size * MIB {
+            Document::open_paged_with_encoding(size, file_name, properties.charset)?
+        } else {
+            let mut doc = Document::open_with_encoding(size, file_name, properties.charset)?;
+            if let Some(store) = self.history_store() {
+                doc.attach_history(store);
+            }
+            doc
+        };
         // Collect various data from the document
-        let tab_width = config!(self.config, document).tab_width;
-        let file_type = config!(self.config, document).file_types.identify(&mut doc);
+        let tab_width = self.tab_width_for(&properties);
+        let file_type = config!(self.config, document).file_types.identify(&doc);
         // Set up the document
         doc.set_tab_width(tab_width);
-        doc.load_to(size.h);
+        doc.set_wrap(config!(self.config, document).soft_wrap_for(file_type.as_ref()));
+        if doc.info.lossy {
+            self.feedback = lossy_warning(file_name, doc.info.encoding);
+        } else if doc.is_paged() {
+            let file = get_file_name(file_name).unwrap_or_default();
+            self.feedback = Feedback::Info(format!("'{file}' is very large, opened read only"));
+        }
         // Update in the syntax highlighter
-        let mut highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
-            t.get_highlighter(&self.config, tab_width)
-        });
-        highlighter.run(&doc.lines);
+        let mut highlighter = self.highlighter_for_size(file_type.as_ref(), len);
+        highlighter.tab_width = tab_width;
+        if !doc.is_paged() {
+            highlighter.run(&doc.lines_to_view());
+        }
         // Add in the file
         let file = FileContainer {
             doc,
             highlighter,
             file_type,
+            dismissed: None,
+            folds: Folds::default(),
+            format: self.formatting_for(&properties),
         };
+        self.add_recent_file(file_name);
         Ok(file)
     }
 
+    /// Work out where undo history should be persisted (None if persistent undo is disabled)
+    pub fn history_store(&self) -> Option<HistoryStore> {
+        let document = config!(self.config, document);
+        if !document.persistent_undo {
+            return None;
+        }
+        let dir = crate::dirs::data_dir()?.join("undo");
+        Some(HistoryStore::new(dir, document.undo_history_size * 1024))
+    }
+
+    /// Create a syntax highlighter for a file, leaving out highlighting for very large files
+    pub fn highlighter_for_size(&self, file_type: Option<&FileType>, len: u64) -> Highlighter {
+        let tab_width = config!(self.config, document).tab_width;
+        match file_type {
+            Some(t) if len <= config!(self.config, document).highlight_size_limit * MIB => {
+                t.get_highlighter(&self.config, tab_width)
+            }
+            _ => Highlighter::new(tab_width),
+        }
+    }
+
+    /// Work out how files should be backed up when they are saved over
+    pub fn backup(&self) -> Backup {
+        let document = config!(self.config, document);
+        match document.backup {
+            BackupMode::None => Backup::None,
+            BackupMode::Tilde => Backup::Tilde,
+            BackupMode::Directory => crate::dirs::data_dir().map_or(Backup::None, |dir| {
+                Backup::Directory {
+                    dir: dir.join("backups"),
+                    keep: document.backup_count,
+                }
+            }),
+        }
+    }
+
+    /// Read a document from the disk again, interpreting it with a specific character encoding.
+    /// Files that don't exist on the disk yet will be saved with the encoding instead.
+    pub fn reopen_with_encoding(&mut self, idx: usize, encoding: Encoding) -> Result<()> {
+        let doc = self.get_doc(idx);
+        let file_name = doc.file_name.clone().unwrap_or_default();
+        if !Path::new(&file_name).exists() {
+            doc.info.encoding = encoding;
+            return Ok(());
+        } else if !doc.event_mgmt.with_disk() {
+            let file = get_file_name(&file_name).unwrap_or_default();
+            return Err(OxError::UnsavedChanges { file });
+        }
+        doc.reload_with_encoding(encoding)?;
+        let lossy = doc.info.lossy;
+        let lines = doc.lines_to_view();
+        if let Some(highlighter) = self.get_highlighter(idx) {
+            highlighter.run(&lines);
+        }
+        if lossy {
+            self.feedback = lossy_warning(&file_name, encoding);
+        }
+        Ok(())
+    }
+
     /// Function to ask the user for a file to open
     pub fn open_document(&mut self) -> Result<()> {
         let path = self.path_prompt()?;
@@ -241,23 +392,37 @@ impl Editor {
             if os.kind() == ErrorKind::NotFound || os.kind() == ErrorKind::IsADirectory {
                 // Create a new document if not found
                 self.blank()?;
+                let history_store = self.history_store();
+                let properties = self.editorconfig(&file_name);
+                let format = self.formatting_for(&properties);
+                let tab_width = self.tab_width_for(&properties);
                 if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
                     let file = files.last_mut().expect("Files list should not be empty after blank()");
                     file.doc.file_name = Some(file_name);
                     // Work out information for the document
-                    let tab_width = config!(self.config, document).tab_width;
                     let file_type = config!(self.config, document)
                         .file_types
-                        .identify(&mut file.doc);
+                        .identify(&file.doc);
+                    let wrap = config!(self.config, document).soft_wrap_for(file_type.as_ref());
                     // Set up the document
                     file.doc.set_tab_width(tab_width);
+                    file.doc.set_wrap(wrap);
                     // Attach the correct highlighter
                     let highlighter = file_type.clone().map_or(Highlighter::new(tab_width), |t| {
                         t.get_highlighter(&self.config, tab_width)
                     });
                     file.highlighter = highlighter;
-                    file.highlighter.run(&file.doc.lines);
+                    file.highlighter.tab_width = tab_width;
+                    file.highlighter.run(&file.doc.lines_to_view());
                     file.file_type = file_type;
+                    file.format = format;
+                    if let Some(encoding) = properties.charset {
+                        file.doc.info.encoding = encoding;
+                    }
+                    // Keep undo history once the file is saved
+                    if let Some(store) = history_store {
+                        file.doc.attach_history(store);
+                    }
                 }
                 Ok(())
             } else {
@@ -275,9 +440,15 @@ impl Editor {
 
     /// save the document to the disk
     pub fn save(&mut self) -> Result<()> {
-        if let Some(doc) = self.try_doc_mut() {
+        let backup = self.backup();
+        if let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) {
+            self.confirm_overwrite(&self.ptr.clone(), idx)?;
+        }
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
             // Perform the save
-            doc.save()?;
+            file.tidy()?;
+            file.doc.backup = backup;
+            file.doc.save()?;
             // All done
             self.feedback = Feedback::Info("Document saved successfully".to_string());
         }
@@ -288,21 +459,31 @@ impl Editor {
     pub fn save_as(&mut self) -> Result<()> {
         if self.try_doc().is_some() {
             let file_name = self.prompt("Save as")?;
-            let file_name_is_none = self.try_doc().map(|d| d.file_name.is_none()).unwrap_or(false);
-            if let Some(doc_mut) = self.try_doc_mut() {
-                doc_mut.save_as(&file_name)?;
+            let file_name_is_none = self.try_doc().is_some_and(|d| d.file_name.is_none());
+            let backup = self.backup();
+            // Newly named files are formatted the way files with that name should be
+            if file_name_is_none {
+                self.reformat_for(&file_name);
+            }
+            if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+                file.tidy()?;
+                file.doc.backup = backup;
+                file.doc.save_as(&file_name)?;
             }
             // If this file is currently unnamed, give it a name, syntax highlighting and a type
             if file_name_is_none {
-                let tab_width = config!(self.config, document).tab_width;
+                let history_store = self.history_store();
                 if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
                     let file = files.get_mut(*ptr).expect("File at pointer index should exist");
+                    let tab_width = file.doc.tab_width;
                     // Set the file name
                     file.doc.file_name = Some(file_name.clone());
                     // Update the file type
                     file.file_type = config!(self.config, document)
                         .file_types
-                        .identify(&mut file.doc);
+                        .identify(&file.doc);
+                    let wrap = config!(self.config, document).soft_wrap_for(file.file_type.as_ref());
+                    file.doc.set_wrap(wrap);
                     // Reattach an appropriate highlighter
                     let highlighter = file
                         .file_type
@@ -311,10 +492,16 @@ impl Editor {
                             t.get_highlighter(&self.config, tab_width)
                         });
                     file.highlighter = highlighter;
-                    file.highlighter.run(&file.doc.lines);
+                    file.highlighter.run(&file.doc.lines_to_view());
                     // Set up to date with disk
                     file.doc.event_mgmt.force_not_with_disk = false;
-                    file.doc.event_mgmt.disk_write(&file.doc.take_snapshot());
+                    file.doc.event_mgmt.disk_write(file.doc.cursor);
+                    file.doc.info.disk = DiskState::read(Path::new(&file_name));
+                    // Start keeping undo history for the newly named file
+                    if let Some(store) = history_store {
+                        file.doc.attach_history(store);
+                        let _ = file.doc.save_history();
+                    }
                 }
             }
             // Commit events to event manager (for undo / redo)
@@ -329,8 +516,14 @@ impl Editor {
 
     /// Save all the open documents to the disk
     pub fn save_all(&mut self) -> Result<()> {
-        if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
-            for file in files {
+        let backup = self.backup();
+        let len = self.files.get_atom(self.ptr.clone()).map_or(0, |(fcs, _)| fcs.len());
+        for idx in 0..len {
+            self.confirm_overwrite(&self.ptr.clone(), idx)?;
+            if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
+                let file = &mut files[idx];
+                file.tidy()?;
+                file.doc.backup = backup.clone();
                 file.doc.save()?;
                 // Commit events to event manager (for undo / redo)
                 file.doc.commit();
@@ -342,6 +535,12 @@ impl Editor {
 
     /// Quit the editor
     pub fn quit(&mut self) -> Result<()> {
+        // Describe the layout now, in case it is saved when the editor closes
+        let session = self.session_file.is_some().then(|| {
+            self.closing_session
+                .take()
+                .map_or_else(|| self.capture_session(), |(session, _)| session)
+        });
         match self.files.get_raw(self.ptr.clone()) {
             Some(FileLayout::Atom(fcs, ptr)) => {
                 let last_file = fcs.len() == 1;
@@ -349,7 +548,7 @@ impl Editor {
                 let msg =
                     "This document isn't saved, press Ctrl + Q to force quit or Esc to cancel";
                 let doc = &fcs[*ptr].doc;
-                if doc.event_mgmt.with_disk(&doc.take_snapshot()) || self.confirm(msg)? {
+                if doc.event_mgmt.with_disk() || self.confirm(msg)? {
                     let (fcs, ptr) = self.files.get_atom_mut(self.ptr.clone())
                         .expect("Files structure should still exist");
                     fcs.remove(*ptr);
@@ -365,6 +564,8 @@ impl Editor {
                     self.ptr = self.files.clean_up_multis(self.ptr.clone());
                 }
             }
+            Some(FileLayout::SearchResults) => self.close_project_search(),
+            Some(FileLayout::Problems) => self.close_problems(),
             Some(FileLayout::Terminal(_)) => {
                 self.files.remove(self.ptr.clone());
                 // Find a new pointer position
@@ -377,8 +578,19 @@ impl Editor {
         // If there are no longer any active atoms, quit the entire editor
         self.active = !matches!(
             self.files,
-            FileLayout::None | FileLayout::FileTree | FileLayout::Terminal(_)
+            FileLayout::None
+                | FileLayout::FileTree
+                | FileLayout::SearchResults
+                | FileLayout::Problems
+                | FileLayout::Terminal(_)
         );
+        if let Some(session) = session {
+            if self.active {
+                self.closing_session = Some((session, true));
+            } else {
+                self.save_session_on_exit(&session);
+            }
+        }
         Ok(())
     }
 
@@ -473,6 +685,9 @@ impl Editor {
         };
         // Pass event down to special handlers
         match event {
+            CEvent::Key(key) if self.terminal_focused() => {
+                self.terminal_key(lua, key.modifiers, key.code)?;
+            }
             CEvent::Key(key) => self.handle_key_event(key.modifiers, key.code)?,
             CEvent::Resize(_, _) => self.handle_resize(lua)?,
             CEvent::Mouse(mouse_event) => self.handle_mouse_event(lua, mouse_event)?,
@@ -499,44 +714,26 @@ impl Editor {
                 (KMod::NONE, KCode::Char('c')) => self.file_tree_copy()?,
                 _ => (),
             },
-            // Terminal behaviour
-            #[cfg(not(target_os = "windows"))]
-            Some(FileLayout::Terminal(term)) => match (modifiers, code) {
-                (KMod::NONE, KCode::Enter) => {
-                    match term.lock() {
-                        Ok(mut pty) => pty.char_input('\n')?,
-                        Err(e) => {
-                            eprintln!("Failed to lock terminal: {}", e);
-                            return Ok(());
-                        }
-                    }
-                }
-                (KMod::SHIFT | KMod::NONE, KCode::Char(ch)) => {
-                    match term.lock() {
-                        Ok(mut pty) => pty.char_input(ch)?,
-                        Err(e) => {
-                            eprintln!("Failed to lock terminal: {}", e);
-                            return Ok(());
-                        }
-                    }
-                }
-                (KMod::NONE, KCode::Backspace) => {
-                    match term.lock() {
-                        Ok(mut pty) => pty.char_pop(),
-                        Err(e) => {
-                            eprintln!("Failed to lock terminal: {}", e);
-                        }
-                    }
-                }
-                (KMod::CONTROL, KCode::Char('l')) => {
-                    match term.lock() {
-                        Ok(mut pty) => pty.clear()?,
-                        Err(e) => {
-                            eprintln!("Failed to lock terminal: {}", e);
-                            return Ok(());
-                        }
-                    }
-                }
+            // Project search results behaviour
+            Some(FileLayout::SearchResults) => match (modifiers, code) {
+                (KMod::NONE, KCode::Up) => self.project_select_up(),
+                (KMod::NONE, KCode::Down) => self.project_select_down(),
+                (KMod::CONTROL, KCode::Up) => self.project_select_top(),
+                (KMod::CONTROL, KCode::Down) => self.project_select_bottom(),
+                (KMod::NONE, KCode::Enter) => self.project_open_result()?,
+                (KMod::NONE, KCode::Char(' ')) => self.project_toggle_file(),
+                (KMod::NONE, KCode::Tab) => self.project_apply()?,
+                (KMod::NONE, KCode::Esc) => self.close_project_search(),
+                _ => (),
+            },
+            // Problems pane behaviour
+            Some(FileLayout::Problems) => match (modifiers, code) {
+                (KMod::NONE, KCode::Up) => self.problems_select_up(),
+                (KMod::NONE, KCode::Down) => self.problems_select_down(),
+                (KMod::CONTROL, KCode::Up) => self.problems_select_top(),
+                (KMod::CONTROL, KCode::Down) => self.problems_select_bottom(),
+                (KMod::NONE, KCode::Enter) => self.open_problem()?,
+                (KMod::NONE, KCode::Esc) => self.close_problems(),
                 _ => (),
             },
             // File behaviour
@@ -577,14 +774,17 @@ impl Editor {
     /// Handle paste
     pub fn handle_paste(&mut self, text: &str) -> Result<()> {
         // Take the text and ensure there are no nasty surprises when it comes to newlines
+        if self.terminal_focused() {
+            return self.terminal_paste(text);
+        }
         let text = text.replace("\r\n", "\n").replace('\r', "\n");
         if self.try_doc().is_some() {
             // If we're playing back a macro, use the last text the user copied
             // (to prevent hard-coded pasting)
             let text = if self.macro_man.playing {
-                self.terminal.last_copy.to_string()
+                self.terminal.last_copy.clone()
             } else {
-                text.to_string()
+                text.clone()
             };
             // Save state before paste
             if let Some(doc_mut) = self.try_doc_mut() {
@@ -606,14 +806,22 @@ impl Editor {
 
     /// Handle tab character being inserted
     pub fn handle_tab(&mut self) -> Result<()> {
-        if config!(self.config, document).indentation == Indentation::Tabs {
+        let format = self.files.get(self.ptr.clone()).map(|file| file.format).unwrap_or_default();
+        if format.indentation == Indentation::Tabs {
             self.character('\t')?;
         } else {
-            let tab_width = config!(self.config, document).tab_width;
-            for _ in 0..tab_width {
+            for _ in 0..format.indent_size {
                 self.character(' ')?;
             }
         }
         Ok(())
     }
 }
+
+/// Warn the user that a file couldn't be decoded properly, so was opened as read only
+fn lossy_warning(file_name: &str, encoding: Encoding) -> Feedback {
+    let file = get_file_name(file_name).unwrap_or_default();
+    Feedback::Warning(format!(
+        "'{file}' isn't valid {encoding}, so has been opened as read only to prevent corruption"
+    ))
+}
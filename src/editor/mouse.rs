@@ -16,8 +16,12 @@ enum MouseLocation {
     Tabs(Vec<usize>, usize),
     /// Where the mouse has clicked in the file tree
     FileTree(usize),
-    /// Where the mouse has clicked in the terminal
-    Terminal(Vec<usize>),
+    /// Where the mouse has clicked in the terminal (and where within its split)
+    Terminal(Vec<usize>, Loc),
+    /// Where the mouse has clicked in the project search results (and on which row)
+    SearchResults(Vec<usize>, usize),
+    /// Where the mouse has clicked in the problems pane (and on which row)
+    Problems(Vec<usize>, usize),
     /// Mouse has clicked nothing of importance
     Out,
 }
@@ -71,22 +75,31 @@ impl Editor {
                         // Clicked on line numbers
                         MouseLocation::Out
                     } else if let Some((fcs, ptr)) = self.files.get_atom(idx.clone()) {
-                        // Clicked on document
-                        let offset = fcs[ptr].doc.offset;
-                        MouseLocation::File(
-                            idx.clone(),
-                            Loc {
-                                x: clicked.x.saturating_sub(dent) + offset.x,
-                                y: clicked.y.saturating_sub(tab) + offset.y,
-                            },
-                        )
+                        // Clicked on document (which may have its lines wrapped onto several rows)
+                        let on_screen = Loc {
+                            x: clicked.x.saturating_sub(dent),
+                            y: clicked.y.saturating_sub(tab),
+                        };
+                        MouseLocation::File(idx.clone(), fcs[ptr].doc.screen_to_loc(&on_screen))
                     } else {
                         // We can't seem to get the atom for some reason, just default to Out
                         MouseLocation::Out
                     }
                 }
                 Some(FileLayout::FileTree) => MouseLocation::FileTree(row),
-                Some(FileLayout::Terminal(_)) => MouseLocation::Terminal(idx),
+                Some(FileLayout::Terminal(_)) => {
+                    let clicked = Loc {
+                        x: col.saturating_sub(cols.start),
+                        y: row.saturating_sub(rows.start),
+                    };
+                    MouseLocation::Terminal(idx, clicked)
+                }
+                Some(FileLayout::SearchResults) => {
+                    MouseLocation::SearchResults(idx, row.saturating_sub(rows.start))
+                }
+                Some(FileLayout::Problems) => {
+                    MouseLocation::Problems(idx, row.saturating_sub(rows.start))
+                }
                 _ => MouseLocation::Out,
             }
         } else {
@@ -138,16 +151,37 @@ impl Editor {
                             if let Some(ft) = &self.file_tree {
                                 // Move selection to where we clicked
                                 if let Some(item) = ft.flatten().get(y) {
-                                    self.file_tree_selection = Some(item.to_string());
+                                    self.file_tree_selection = Some(item.clone());
                                     // Toggle the node
                                     self.file_tree_open_node()?;
                                 }
                             }
                         }
-                        MouseLocation::Terminal(idx) => {
-                            // Move focus to the index
+                        MouseLocation::Terminal(idx, loc) => {
+                            // Move focus to the index and start selecting from here
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.terminal_select_from(loc);
+                        }
+                        MouseLocation::SearchResults(idx, y) => {
+                            // Move focus to the results and select the row that was clicked
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            if let Some(search) = &mut self.project_search {
+                                if y > 0 && search.offset + y - 1 < search.rows().len() {
+                                    search.selected = search.offset + y - 1;
+                                }
+                            }
+                        }
+                        MouseLocation::Problems(idx, y) => {
+                            // Move focus to the problems pane and select the row that was clicked
                             self.cache_old_ptr(&idx);
                             self.ptr.clone_from(&idx);
+                            if let Some(problems) = &mut self.problems {
+                                if y > 0 && problems.offset + y - 1 < problems.rows() {
+                                    problems.selected = problems.offset + y - 1;
+                                }
+                            }
                         }
                         MouseLocation::Out => (),
                     }
@@ -215,10 +249,16 @@ impl Editor {
                                 }
                             }
                         }
+                        MouseLocation::Terminal(idx, loc) => {
+                            if self.ptr == idx {
+                                self.terminal_select_to(loc);
+                            }
+                        }
                         MouseLocation::Tabs(_, _)
                         | MouseLocation::Out
                         | MouseLocation::FileTree(_)
-                        | MouseLocation::Terminal(_) => (),
+                        | MouseLocation::SearchResults(_, _)
+                        | MouseLocation::Problems(_, _) => (),
                     }
                 }
                 MouseEventKind::Drag(MouseButton::Right) => {
@@ -253,25 +293,41 @@ impl Editor {
                         MouseLocation::Tabs(_, _)
                         | MouseLocation::Out
                         | MouseLocation::FileTree(_)
-                        | MouseLocation::Terminal(_) => (),
+                        | MouseLocation::Terminal(_, _)
+                        | MouseLocation::SearchResults(_, _)
+                        | MouseLocation::Problems(_, _) => (),
                     }
                 }
                 // Mouse scroll behaviour
                 MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
                     let scroll_amount = config!(self.config, terminal).scroll_amount;
-                    if let MouseLocation::File(idx, _) = self.find_mouse_location(lua, event) {
-                        self.cache_old_ptr(&idx);
-                        self.ptr.clone_from(&idx);
-                        self.update_cwd();
-                        if let Some(doc) = self.try_doc_mut() {
-                            for _ in 0..scroll_amount {
-                                if event.kind == MouseEventKind::ScrollDown {
-                                    doc.scroll_down();
-                                } else {
-                                    doc.scroll_up();
+                    match self.find_mouse_location(lua, event) {
+                        MouseLocation::File(idx, _) => {
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.update_cwd();
+                            if let Some(doc) = self.try_doc_mut() {
+                                for _ in 0..scroll_amount {
+                                    if event.kind == MouseEventKind::ScrollDown {
+                                        doc.scroll_down();
+                                    } else {
+                                        doc.scroll_up();
+                                    }
                                 }
                             }
                         }
+                        MouseLocation::Terminal(idx, _) => {
+                            // Scroll through the terminal's output
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            let lines = isize::try_from(scroll_amount).unwrap_or(isize::MAX);
+                            if event.kind == MouseEventKind::ScrollDown {
+                                self.scroll_terminal(lines);
+                            } else {
+                                self.scroll_terminal(-lines);
+                            }
+                        }
+                        _ => (),
                     }
                 }
                 MouseEventKind::ScrollLeft => {
@@ -0,0 +1,634 @@
+/// Functions for finding and running commands, editor actions and key bindings from a palette
+use crate::config;
+use crate::config::{run_key, Description};
+use crate::error::{OxError, Result};
+use crate::events::wait_for_event_hog;
+use crate::ui::{key_event, size};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use crossterm::style::{
+    Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+};
+use kaolinite::utils::{width as text_width, Size};
+use mlua::prelude::*;
+
+use super::finder::{fit, fuzzy_match, FuzzyMatch};
+use super::Editor;
+
+/// The bonus given to recently used entries when ranking them
+const BONUS_RECENT: i64 = 8;
+
+/// The built-in editor actions that can be run from the palette (name, description, hint)
+const ACTIONS: &[(&str, &str, &str)] = &[
+    ("new", "Create a new document", ""),
+    ("open", "Open a file", ""),
+    ("find_file", "Find a file in the working directory", ""),
+    ("find_buffer", "Find an open file", ""),
+    ("find_recent", "Find a recently opened file", ""),
+    ("save", "Save the document", ""),
+    ("save_as", "Save the document under a new name", ""),
+    ("save_all", "Save all open documents", ""),
+    ("reload", "Reload the document from the disk", ""),
+    ("quit", "Close the document", ""),
+    ("next_tab", "Move to the next document", ""),
+    ("previous_tab", "Move to the previous document", ""),
+    ("undo", "Undo the last change", ""),
+    ("redo", "Redo the last change undone", ""),
+    (
+        "earlier",
+        "Go back in the undo history",
+        "<edits|time e.g. 5m>",
+    ),
+    (
+        "later",
+        "Go forward in the undo history",
+        "<edits|time e.g. 5m>",
+    ),
+    ("search", "Search the document", ""),
+    ("replace", "Replace in the document", ""),
+    (
+        "project_search",
+        "Search every file in the project",
+        "[target]",
+    ),
+    (
+        "project_replace",
+        "Replace in every file in the project",
+        "[target] [with]",
+    ),
+    ("close_project_search", "Close the search results", ""),
+    ("select_all", "Select the whole document", ""),
+    ("copy", "Copy the selection", ""),
+    ("cut", "Cut the selection", ""),
+    ("move_top", "Move to the start of the document", ""),
+    ("move_bottom", "Move to the end of the document", ""),
+    ("move_to", "Move the cursor", "<x> <y>"),
+    ("move_to_matching_bracket", "Jump to the bracket that pairs with this one", ""),
+    ("select_inside_brackets", "Select within the brackets around the cursor", ""),
+    ("select_around_brackets", "Select the brackets around the cursor", ""),
+    ("toggle_fold", "Fold or unfold the current line", "[line]"),
+    ("fold_all", "Fold everything that can be folded", ""),
+    ("unfold_all", "Unfold everything", ""),
+    (
+        "set_read_only",
+        "Make the document read only (or not)",
+        "<true|false>",
+    ),
+    (
+        "set_file_type",
+        "Change the file type of the document",
+        "<file type>",
+    ),
+    (
+        "reopen_with_encoding",
+        "Reopen the document in another encoding",
+        "<encoding>",
+    ),
+    ("toggle_file_tree", "Show or hide the file tree", ""),
+    (
+        "open_split_right",
+        "Open a file in a split to the right",
+        "<file>",
+    ),
+    ("open_split_down", "Open a file in a split below", "<file>"),
+    (
+        "open_terminal_right",
+        "Open a terminal to the right",
+        "[command]",
+    ),
+    ("open_terminal_down", "Open a terminal below", "[command]"),
+    ("run_file", "Run the document", ""),
+    (
+        "run_task",
+        "Run a task, listing the problems in its output",
+        "[task]",
+    ),
+    ("next_problem", "Jump to the next problem found by the task", ""),
+    (
+        "previous_problem",
+        "Jump to the previous problem found by the task",
+        "",
+    ),
+    ("close_problems", "Close the problems pane", ""),
+    (
+        "save_session",
+        "Save the splits and open documents to a session",
+        "[name]",
+    ),
+    (
+        "load_session",
+        "Restore the splits and documents saved in a session",
+        "[name]",
+    ),
+    ("complete", "Show completion suggestions", ""),
+    (
+        "lsp_hover",
+        "Show information about what is under the cursor",
+        "",
+    ),
+    (
+        "lsp_definition",
+        "Go to the definition of what is under the cursor",
+        "",
+    ),
+    (
+        "lsp_references",
+        "Find references to what is under the cursor",
+        "",
+    ),
+    (
+        "lsp_rename",
+        "Rename what is under the cursor",
+        "<new name>",
+    ),
+    ("lsp_restart", "Restart the language server", ""),
+    ("macro_record_start", "Start recording a macro", ""),
+    ("macro_record_stop", "Stop recording a macro", ""),
+    ("macro_play", "Play the recorded macro", "<times>"),
+    ("open_command_line", "Type in a command", ""),
+    ("reload_config", "Reload the configuration file", ""),
+    ("reload_plugins", "Reload plug-ins", ""),
+];
+
+/// Where something in the palette comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A function in the `commands` table
+    Command,
+    /// A built-in method on the editor
+    Action,
+    /// A key binding from `event_mapping`
+    Key,
+}
+
+/// Something that can be run from the palette
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub kind: EntryKind,
+    /// The name of the command, method or key
+    pub name: String,
+    /// What is shown (and matched against)
+    pub label: String,
+    pub description: Description,
+}
+
+/// What was chosen from the palette, to run once the editor is free
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteChoice {
+    pub kind: EntryKind,
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+/// Split text up into arguments by whitespace.
+/// Arguments can contain whitespace by putting them in single or double quotes,
+/// and a backslash makes the character after it literal (outside of single quotes)
+pub fn split_arguments(text: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None | Some('"'), '\\') => {
+                let escaped = chars.next().unwrap_or('\\');
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => {
+                if let Some(argument) = current.take() {
+                    result.push(argument);
+                }
+            }
+            (_, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    result.extend(current);
+    result
+}
+
+/// Quote an argument (if needed) so that it splits back into itself
+pub fn quote_argument(argument: &str) -> String {
+    let needs_quotes = argument.is_empty()
+        || argument
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\'));
+    if needs_quotes {
+        let escaped = argument.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{escaped}\"")
+    } else {
+        argument.to_string()
+    }
+}
+
+/// Determine if something in `event_mapping` is a key binding worth listing in the palette
+/// (rather than an event, or a key that types something)
+fn is_key_binding(name: &str) -> bool {
+    let key = name
+        .trim_start_matches("ctrl_")
+        .trim_start_matches("alt_")
+        .trim_start_matches("shift_");
+    let is_function_key =
+        key.len() > 1 && key.starts_with('f') && key.chars().skip(1).all(|c| c.is_ascii_digit());
+    !name.starts_with("before:")
+        && (name.starts_with("ctrl_") || name.starts_with("alt_") || is_function_key)
+}
+
+/// Gather up everything that can be run from the palette:
+/// the `commands` table, the built-in editor actions and the key bindings
+pub fn palette_entries(lua: &Lua, palette: &config::Palette) -> Vec<PaletteEntry> {
+    let describe = |label: &str, fallback: Description| {
+        palette.descriptions.get(label).cloned().unwrap_or(fallback)
+    };
+    let table_keys = |name: &str| -> Vec<String> {
+        let mut keys: Vec<String> = lua
+            .globals()
+            .get::<LuaTable>(name)
+            .map(|table| {
+                table
+                    .pairs::<String, LuaValue>()
+                    .filter_map(std::result::Result::ok)
+                    .map(|(key, _)| key)
+                    .collect()
+            })
+            .unwrap_or_default();
+        keys.sort();
+        keys
+    };
+    let mut result = vec![];
+    for name in table_keys("commands") {
+        let description = describe(&name, Description::default());
+        let label = name.clone();
+        result.push(PaletteEntry {
+            kind: EntryKind::Command,
+            name,
+            label,
+            description,
+        });
+    }
+    for (name, text, hint) in ACTIONS {
+        let label = format!("editor:{name}");
+        let fallback = Description {
+            text: (*text).to_string(),
+            hint: (*hint).to_string(),
+        };
+        let description = describe(&label, fallback);
+        let name = (*name).to_string();
+        result.push(PaletteEntry {
+            kind: EntryKind::Action,
+            name,
+            label,
+            description,
+        });
+    }
+    for name in table_keys("global_event_mapping") {
+        if is_key_binding(&name) {
+            let fallback = Description {
+                text: "Key binding".to_string(),
+                hint: String::new(),
+            };
+            let description = describe(&name, fallback);
+            let label = name.clone();
+            result.push(PaletteEntry {
+                kind: EntryKind::Key,
+                name,
+                label,
+                description,
+            });
+        }
+    }
+    result
+}
+
+/// Rank entries by how well they match what was typed, best first.
+/// Exact matches come first, and recently used entries are preferred.
+/// When nothing has been typed, recently used entries come first, in the order they were used.
+pub fn rank_entries(
+    query: &str,
+    entries: &[PaletteEntry],
+    recent: &[String],
+) -> Vec<(usize, FuzzyMatch)> {
+    let recency = |entry: &PaletteEntry| recent.iter().position(|r| *r == entry.label);
+    let mut result: Vec<(usize, FuzzyMatch)> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| {
+            let mut mtch = fuzzy_match(query, &entry.label)?;
+            if recency(entry).is_some() {
+                mtch.score += BONUS_RECENT;
+            }
+            Some((i, mtch))
+        })
+        .collect();
+    if query.is_empty() {
+        result.sort_by_key(|(i, _)| (recency(&entries[*i]).unwrap_or(usize::MAX), *i));
+    } else {
+        result.sort_by_key(|(i, m)| {
+            let entry = &entries[*i];
+            let exact = entry.name == query || entry.label == query;
+            (
+                !exact,
+                std::cmp::Reverse(m.score),
+                entry.label.chars().count(),
+                *i,
+            )
+        });
+    }
+    result
+}
+
+/// Turn an argument into a Lua value (numbers and booleans are passed as such)
+fn argument_value(lua: &Lua, argument: &str) -> LuaResult<LuaValue> {
+    Ok(match argument {
+        "true" => LuaValue::Boolean(true),
+        "false" => LuaValue::Boolean(false),
+        _ => match argument.parse::<i64>() {
+            Ok(n) => LuaValue::Integer(n),
+            Err(_) => match argument.parse::<f64>() {
+                Ok(n) => LuaValue::Number(n),
+                Err(_) => LuaValue::String(lua.create_string(argument)?),
+            },
+        },
+    })
+}
+
+/// Run something chosen from the palette (or typed into the command line).
+/// Commands are given their arguments as a table of strings,
+/// which holds a single empty string when there are no arguments.
+pub fn run_palette_choice(lua: &Lua, choice: &PaletteChoice) -> LuaResult<()> {
+    match choice.kind {
+        EntryKind::Command => {
+            let commands: LuaTable = lua.globals().get("commands")?;
+            let Some(command) = commands.get::<Option<LuaFunction>>(choice.name.as_str())? else {
+                return Err(LuaError::RuntimeError(format!(
+                    "command '{}' not found",
+                    choice.name
+                )));
+            };
+            let arguments = if choice.arguments.is_empty() {
+                vec![String::new()]
+            } else {
+                choice.arguments.clone()
+            };
+            command.call::<()>(arguments)
+        }
+        EntryKind::Action => {
+            let editor: LuaAnyUserData = lua.globals().get("editor")?;
+            let method: LuaFunction = lua.load(format!("return editor.{}", choice.name)).eval()?;
+            let mut arguments = vec![LuaValue::UserData(editor)];
+            for argument in &choice.arguments {
+                arguments.push(argument_value(lua, argument)?);
+            }
+            method.call::<LuaValue>(LuaMultiValue::from_vec(arguments))?;
+            Ok(())
+        }
+        EntryKind::Key => lua.load(run_key(&choice.name)).exec(),
+    }
+}
+
+impl Editor {
+    /// Remember that a command was used (by its label in the palette)
+    pub fn remember_command(&mut self, label: &str) {
+        let max = config!(self.config, palette).max_recent;
+        self.recent_commands.retain(|r| r != label);
+        self.recent_commands.insert(0, label.to_string());
+        self.recent_commands.truncate(max);
+    }
+
+    /// Let the user find something to run, which is run once the editor is free
+    pub fn command_palette(&mut self, lua: &Lua) -> Result<()> {
+        let entries = palette_entries(lua, &config!(self.config, palette));
+        let mut input = String::new();
+        let mut name = String::new();
+        let mut ranked = rank_entries(&name, &entries, &self.recent_commands);
+        let (mut selected, mut offset) = (0, 0);
+        loop {
+            // Rerender the editor with the palette over it
+            self.needs_rerender = true;
+            self.render(lua)?;
+            let rows = self.render_palette(&input, &ranked, &entries, selected, &mut offset)?;
+            self.terminal.flush()?;
+            // Handle events
+            let Some((modifiers, code)) = key_event(&wait_for_event_hog(self), &mut self.macro_man)
+            else {
+                continue;
+            };
+            match (modifiers, code) {
+                (KMod::NONE, KCode::Enter) => break,
+                (KMod::NONE, KCode::Esc) => return Err(OxError::Cancelled),
+                (KMod::NONE, KCode::Up) => selected = selected.saturating_sub(1),
+                (KMod::NONE, KCode::Down) => {
+                    selected = (selected + 1).min(ranked.len().saturating_sub(1));
+                }
+                (KMod::NONE, KCode::PageUp) => selected = selected.saturating_sub(rows),
+                (KMod::NONE, KCode::PageDown) => {
+                    selected = (selected + rows).min(ranked.len().saturating_sub(1));
+                }
+                // Fill in the selected entry, ready for arguments to be typed
+                (KMod::NONE, KCode::Tab) => {
+                    if let Some((i, _)) = ranked.get(selected) {
+                        let arguments = split_arguments(&input).into_iter().skip(1);
+                        let mut parts = vec![entries[*i].label.clone()];
+                        parts.extend(arguments.map(|a| quote_argument(&a)));
+                        input = parts.join(" ") + " ";
+                    }
+                }
+                (KMod::NONE, KCode::Backspace) => {
+                    input.pop();
+                }
+                (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => input.push(c),
+                _ => (),
+            }
+            // Only the first word picks what is run, the rest are arguments
+            let typed = split_arguments(&input)
+                .into_iter()
+                .next()
+                .unwrap_or_default();
+            if typed != name {
+                name = typed;
+                ranked = rank_entries(&name, &entries, &self.recent_commands);
+                (selected, offset) = (0, 0);
+            }
+        }
+        if let Some((i, _)) = ranked.get(selected) {
+            let entry = &entries[*i];
+            self.remember_command(&entry.label);
+            let arguments = split_arguments(&input).into_iter().skip(1).collect();
+            self.palette_choice = Some(PaletteChoice {
+                kind: entry.kind,
+                name: entry.name.clone(),
+                arguments,
+            });
+        }
+        Ok(())
+    }
+
+    /// Render the palette over the editor, returning how many entries can be shown at once
+    #[allow(clippy::similar_names)]
+    fn render_palette(
+        &mut self,
+        input: &str,
+        ranked: &[(usize, FuzzyMatch)],
+        entries: &[PaletteEntry],
+        selected: usize,
+        offset: &mut usize,
+    ) -> Result<usize> {
+        let colors = config!(self.config, colors);
+        let fg = Fg(colors.completion_fg.to_color()?);
+        let bg = Bg(colors.completion_bg.to_color()?);
+        let selection_fg = Fg(colors.completion_selection_fg.to_color()?);
+        let selection_bg = Bg(colors.completion_selection_bg.to_color()?);
+        let highlight = Fg(colors.highlight.to_color()?);
+        drop(colors);
+        let tab_width = config!(self.config, document).tab_width;
+        let Size { w, h } = size()?;
+        // The palette sits in the upper middle of the screen
+        let width = (w * 3 / 5).max(60).min(w);
+        let height = (h / 2).max(8).min(h);
+        let (left, top) = ((w - width) / 2, h / 8);
+        let top = top.min(h - height);
+        let rows = height.saturating_sub(3).max(1);
+        // Scroll so the selection is visible
+        if selected < *offset {
+            *offset = selected;
+        } else if selected >= *offset + rows {
+            *offset = selected + 1 - rows;
+        }
+        let mut lines = vec![];
+        let count = format!("{}/{} ", ranked.len(), entries.len());
+        let prompt = [(fg.to_string(), format!(" Command: {input}│"))];
+        let prompt = fit(&prompt, width.saturating_sub(count.len()), tab_width);
+        lines.push(format!("{bg}{prompt}{count}"));
+        for row in 0..rows {
+            let Some((i, mtch)) = ranked.get(*offset + row) else {
+                lines.push(format!("{bg}{}", " ".repeat(width)));
+                continue;
+            };
+            let entry = &entries[*i];
+            let (fg, bg) = if *offset + row == selected {
+                (selection_fg, selection_bg)
+            } else {
+                (fg, bg)
+            };
+            let mut parts = vec![(fg.to_string(), " ".to_string())];
+            for (c, ch) in entry.label.chars().enumerate() {
+                let colour = if mtch.positions.contains(&c) {
+                    highlight
+                } else {
+                    fg
+                };
+                parts.push((colour.to_string(), ch.to_string()));
+            }
+            if !entry.description.hint.is_empty() {
+                parts.push((fg.to_string(), format!(" {}", entry.description.hint)));
+            }
+            // Descriptions go on the right
+            let text = &entry.description.text;
+            let description_width = text_width(text, tab_width).min(width / 2);
+            let description = [(fg.to_string(), format!("{text} "))];
+            let label_width = width.saturating_sub(description_width + 1);
+            lines.push(format!(
+                "{bg}{}{}",
+                fit(&parts, label_width, tab_width),
+                fit(&description, width - label_width, tab_width)
+            ));
+        }
+        let hint = " [Enter] Run | [Tab] Fill in | [Esc] Cancel";
+        let hint = [(fg.to_string(), hint.to_string())];
+        lines.push(format!("{bg}{}", fit(&hint, width, tab_width)));
+        for (y, line) in lines.into_iter().enumerate() {
+            self.terminal.goto(left, top + y);
+            self.terminal.cache += &SetAttribute(Attribute::NormalIntensity).to_string();
+            self.terminal.cache += &line;
+        }
+        self.terminal.hide_cursor();
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_arguments() {
+        assert_eq!(split_arguments("fold all"), vec!["fold", "all"]);
+        assert_eq!(split_arguments("  split   right  "), vec!["split", "right"]);
+        assert_eq!(
+            split_arguments(r#"lsp rename "new name" 'it''s' a\ b"#),
+            vec!["lsp", "rename", "new name", "its", "a b"]
+        );
+        assert_eq!(
+            split_arguments(r#"say "\"hi\"" '\n' """#),
+            vec!["say", "\"hi\"", "\\n", ""]
+        );
+        assert_eq!(
+            split_arguments("open \"unfinished quote"),
+            vec!["open", "unfinished quote"]
+        );
+        assert!(split_arguments("   ").is_empty());
+        for argument in ["plain", "two words", "", "quote\"d", "back\\slash", "it's"] {
+            assert_eq!(split_arguments(&quote_argument(argument)), vec![argument]);
+        }
+    }
+
+    #[test]
+    fn palette_entries_and_ranking() {
+        let lua = Lua::new();
+        lua.load(
+            "commands = { fold = function() end, git = function() end }
+            global_event_mapping = { ctrl_s = {}, exit = {}, a = {}, ['before:ctrl_s'] = {}, f5 = {} }",
+        )
+        .exec()
+        .unwrap();
+        let mut palette = config::Palette::default();
+        let git = Description {
+            text: "Use git".to_string(),
+            hint: "<status|commit>".to_string(),
+        };
+        palette.descriptions.insert("git".to_string(), git.clone());
+        let entries = palette_entries(&lua, &palette);
+        let labels: Vec<&str> = entries.iter().map(|e| e.label.as_str()).collect();
+        assert_eq!(&labels[..3], &["fold", "git", "editor:new"]);
+        assert_eq!(&labels[labels.len() - 2..], &["ctrl_s", "f5"]);
+        assert_eq!(entries[1].description, git);
+        assert_eq!(entries[2].kind, EntryKind::Action);
+        // Exact names come first, even over better scoring matches
+        let ranked = rank_entries("save", &entries, &[]);
+        assert_eq!(entries[ranked[0].0].label, "editor:save");
+        let ranked = rank_entries("fold", &entries, &[]);
+        assert_eq!(entries[ranked[0].0].label, "fold");
+        // Recent entries are preferred, and come first when nothing has been typed
+        let recent = vec!["f5".to_string(), "git".to_string()];
+        let ranked = rank_entries("", &entries, &recent);
+        let first: Vec<&str> = ranked[..3]
+            .iter()
+            .map(|(i, _)| entries[*i].label.as_str())
+            .collect();
+        assert_eq!(first, vec!["f5", "git", "fold"]);
+    }
+
+    #[test]
+    fn palette_running() {
+        let lua = Lua::new();
+        lua.load(
+            "given = nil
+            commands = { echo = function(arguments) given = table.concat(arguments, ',') end }",
+        )
+        .exec()
+        .unwrap();
+        let choice = |name: &str, arguments: &[&str]| PaletteChoice {
+            kind: EntryKind::Command,
+            name: name.to_string(),
+            arguments: arguments.iter().map(ToString::to_string).collect(),
+        };
+        run_palette_choice(&lua, &choice("echo", &["a b", "c"])).unwrap();
+        assert_eq!(lua.globals().get::<String>("given").unwrap(), "a b,c");
+        run_palette_choice(&lua, &choice("echo", &[])).unwrap();
+        assert_eq!(lua.globals().get::<String>("given").unwrap(), "");
+        assert!(run_palette_choice(&lua, &choice("missing", &[])).is_err());
+    }
+}
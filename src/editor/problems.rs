@@ -0,0 +1,535 @@
+/// Functions for running tasks (like building, testing and linting) in the background
+/// and listing the problems found in their output
+use crate::config;
+use crate::config::TaskCommand;
+use crate::editor::FileLayout;
+use crate::error::Result;
+use crate::lsp::Severity;
+use crate::ui::Feedback;
+use crossterm::style::{SetBackgroundColor as Bg, SetForegroundColor as Fg};
+use kaolinite::utils::{get_absolute_path, get_cwd};
+use kaolinite::Loc;
+use mlua::Lua;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use super::project::fit;
+use super::Editor;
+
+/// How problems are found when none of a task's own error formats match a line:
+/// `file:line:col: severity: message`, where the column and severity are optional
+const DEFAULT_ERROR_FORMAT: &str = r"^(?P<file>[^\s:][^:]*):(?P<line>\d+):(?:(?P<col>\d+):)?\s*(?:(?P<severity>error|warning|note|help|info|hint)(?:\[[^\]]*\])?:\s*)?(?P<message>.*)$";
+
+/// A problem found in the output of a task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    /// The absolute path of the file the problem is in
+    pub path: String,
+    /// The file as the task named it (for display)
+    pub name: String,
+    /// Where in the file the problem is
+    pub loc: Loc,
+    /// How serious the problem is
+    pub severity: Severity,
+    /// What the problem is
+    pub message: String,
+}
+
+/// Where a task has got to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// The task is still running
+    Running,
+    /// The task has finished (with its exit status, if it has one)
+    Finished(Option<i32>),
+    /// The task couldn't be run (and why)
+    Failed(String),
+}
+
+/// The latest task to be run, along with the problems found in its output
+#[derive(Debug)]
+pub struct Problems {
+    /// The name of the task
+    pub task: String,
+    /// Where the task has got to
+    pub status: TaskStatus,
+    /// Everything the task printed (to stdout and stderr)
+    pub output: String,
+    /// The problems found in the output, in the order they were printed
+    pub found: Vec<Problem>,
+    /// The row of the problems pane that is selected
+    pub selected: usize,
+    /// The row of the problems pane at the top of the pane
+    pub offset: usize,
+    /// Where documents were shown before the problems pane was opened
+    pub origin: Vec<usize>,
+    /// Receives the output of the task once it finishes
+    pending: Option<Receiver<std::io::Result<Output>>>,
+    /// The expressions that find problems in the output
+    formats: Vec<Regex>,
+    /// The folder the task is run in (which file names are relative to)
+    root: PathBuf,
+}
+
+impl Problems {
+    /// Work out how many rows are shown in the problems pane.
+    /// When no problems were found, the output of the task is shown instead.
+    pub fn rows(&self) -> usize {
+        if self.found.is_empty() {
+            self.output.lines().count()
+        } else {
+            self.found.len()
+        }
+    }
+
+    /// Count the problems found of each severity, e.g. "2 errors, 1 warning"
+    pub fn counts(&self) -> String {
+        let count =
+            |severity: Severity| self.found.iter().filter(|p| p.severity == severity).count();
+        let mut parts = vec![];
+        for (severity, name) in [
+            (Severity::Error, "error"),
+            (Severity::Warning, "warning"),
+            (Severity::Information, "note"),
+            (Severity::Hint, "hint"),
+        ] {
+            match count(severity) {
+                0 => (),
+                1 => parts.push(format!("1 {name}")),
+                n => parts.push(format!("{n} {name}s")),
+            }
+        }
+        if parts.is_empty() {
+            "no problems".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Describe where the task has got to
+    pub fn summary(&self) -> String {
+        let task = &self.task;
+        match &self.status {
+            TaskStatus::Running => format!("{task}: running…"),
+            TaskStatus::Finished(Some(0)) => format!("{task}: finished, {}", self.counts()),
+            TaskStatus::Finished(Some(code)) => {
+                format!("{task}: failed with exit status {code}, {}", self.counts())
+            }
+            TaskStatus::Finished(None) => format!("{task}: stopped, {}", self.counts()),
+            TaskStatus::Failed(error) => format!("{task}: couldn't be run ({error})"),
+        }
+    }
+
+    /// Find the most serious problem found on a line of a file (given its absolute path)
+    pub fn worst_on_line(&self, path: &str, y: usize) -> Option<Severity> {
+        self.found
+            .iter()
+            .filter(|p| p.loc.y == y && p.path == path)
+            .map(|p| p.severity)
+            .min()
+    }
+
+    /// Take in the output of the task once it has finished
+    fn finish(&mut self, result: std::io::Result<Output>) {
+        match result {
+            Ok(output) => {
+                self.output = String::from_utf8_lossy(&output.stdout).to_string();
+                self.output += &String::from_utf8_lossy(&output.stderr);
+                self.found = parse_problems(&self.output, &self.formats, &self.root);
+                self.status = TaskStatus::Finished(output.status.code());
+            }
+            Err(error) => self.status = TaskStatus::Failed(error.to_string()),
+        }
+        self.selected = 0;
+        self.offset = 0;
+    }
+}
+
+/// Find the problems in the output of a task. Each line is tried against the error formats
+/// in turn, and lines naming files that don't exist (beneath the root) are left out.
+pub fn parse_problems(output: &str, formats: &[Regex], root: &Path) -> Vec<Problem> {
+    let mut result = vec![];
+    for line in output.lines() {
+        let Some(caps) = formats.iter().find_map(|format| format.captures(line)) else {
+            continue;
+        };
+        let Some(name) = caps.name("file").map(|n| n.as_str().trim()) else {
+            continue;
+        };
+        let number = |group: &str| {
+            caps.name(group)
+                .and_then(|n| n.as_str().parse::<usize>().ok())
+        };
+        let Some(y) = number("line") else {
+            continue;
+        };
+        let Some(path) = get_absolute_path(&root.join(name).to_string_lossy()) else {
+            continue;
+        };
+        if !Path::new(&path).is_file() {
+            continue;
+        }
+        let x = number("col").unwrap_or(1);
+        let severity = caps
+            .name("severity")
+            .map_or(Severity::Error, |s| severity(s.as_str()));
+        let message = caps.name("message").map_or("", |m| m.as_str().trim());
+        result.push(Problem {
+            path,
+            name: name.to_string(),
+            loc: Loc::at(x.saturating_sub(1), y.saturating_sub(1)),
+            severity,
+            message: message.to_string(),
+        });
+    }
+    result
+}
+
+/// Work out the severity of a problem from how a task describes it
+fn severity(name: &str) -> Severity {
+    match name.to_lowercase().as_str() {
+        "warning" | "warn" => Severity::Warning,
+        "note" | "info" | "information" => Severity::Information,
+        "help" | "hint" => Severity::Hint,
+        _ => Severity::Error,
+    }
+}
+
+/// Build the command that runs a task through the shell
+fn shell_command(command: &str) -> Command {
+    #[cfg(not(target_os = "windows"))]
+    let mut result = Command::new("sh");
+    #[cfg(not(target_os = "windows"))]
+    result.arg("-c");
+    #[cfg(target_os = "windows")]
+    let mut result = Command::new("cmd");
+    #[cfg(target_os = "windows")]
+    result.arg("/C");
+    result.arg(command);
+    result
+}
+
+impl Editor {
+    /// Run a task from the `tasks` table in the background, showing its problems once it finishes
+    pub fn run_task(&mut self, lua: &Lua, name: &str) -> Result<()> {
+        if self
+            .problems
+            .as_ref()
+            .is_some_and(|p| p.status == TaskStatus::Running)
+        {
+            self.feedback = Feedback::Warning("A task is already running".to_string());
+            return Ok(());
+        }
+        let tasks = lua.globals().get::<HashMap<String, TaskCommand>>("tasks")?;
+        let Some(task) = tasks.get(name) else {
+            self.feedback = Feedback::Error(format!("There is no task called '{name}'"));
+            return Ok(());
+        };
+        let mut formats = vec![];
+        for format in &task.error_format {
+            match Regex::new(format) {
+                Ok(format) => formats.push(format),
+                Err(error) => {
+                    self.feedback =
+                        Feedback::Error(format!("Invalid error format for task '{name}': {error}"));
+                    return Ok(());
+                }
+            }
+        }
+        formats.push(Regex::new(DEFAULT_ERROR_FORMAT).expect("default error format is valid"));
+        let root = PathBuf::from(get_cwd().unwrap_or_else(|| ".".to_string()));
+        // Run the task on another thread, so the editor can be used while it runs
+        let (sender, receiver) = channel();
+        let mut command = shell_command(&task.command);
+        command.current_dir(&root).stdin(Stdio::null());
+        std::thread::spawn(move || {
+            let _ = sender.send(command.output());
+        });
+        let origin = self.open_problems_pane();
+        self.problems = Some(Problems {
+            task: name.to_string(),
+            status: TaskStatus::Running,
+            output: String::new(),
+            found: vec![],
+            selected: 0,
+            offset: 0,
+            origin,
+            pending: Some(receiver),
+            formats,
+            root,
+        });
+        Ok(())
+    }
+
+    /// Pick up the output of a task that has finished.
+    /// Returns true if anything changed that needs rendering.
+    pub fn poll_tasks(&mut self) -> bool {
+        let Some(problems) = &mut self.problems else {
+            return false;
+        };
+        let result = match problems.pending.as_ref().map(Receiver::try_recv) {
+            Some(Ok(result)) => result,
+            Some(Err(TryRecvError::Disconnected)) => Err(std::io::Error::other("task was lost")),
+            Some(Err(TryRecvError::Empty)) | None => return false,
+        };
+        problems.pending = None;
+        problems.finish(result);
+        let summary = problems.summary();
+        self.feedback = match &problems.status {
+            TaskStatus::Finished(Some(0)) if problems.found.is_empty() => Feedback::Info(summary),
+            TaskStatus::Finished(_) if problems.found.is_empty() => Feedback::Warning(summary),
+            _ => Feedback::Error(summary),
+        };
+        true
+    }
+
+    /// Open the problems pane below the documents (if it isn't open), leaving the focus where it is.
+    /// Returns where documents are shown.
+    fn open_problems_pane(&mut self) -> Vec<usize> {
+        if self.files.problems(vec![]).is_some() {
+            return self
+                .problems
+                .as_ref()
+                .map(|p| p.origin.clone())
+                .unwrap_or_default();
+        }
+        // The file tree can't be split, so open the pane below the documents next to it instead
+        let mut at = self.ptr.clone();
+        if matches!(self.files.get_raw(at.clone()), Some(FileLayout::FileTree)) {
+            at = vec![1];
+        }
+        let had_documents = !matches!(self.files.get_raw(at.clone()), Some(FileLayout::None));
+        let pane = self.files.open_down(at.clone(), FileLayout::Problems);
+        at.push(0);
+        if had_documents {
+            if self.ptr.len() < at.len() && at.starts_with(&self.ptr) {
+                self.ptr.clone_from(&at);
+            }
+            at
+        } else {
+            self.ptr = pane;
+            vec![]
+        }
+    }
+
+    /// Close the problems pane (the problems are kept, so they can still be moved between)
+    pub fn close_problems(&mut self) {
+        if let Some(at) = self.files.problems(vec![]) {
+            let in_pane = self.ptr == at;
+            self.files.remove(at);
+            self.ptr = if in_pane {
+                self.files.new_pointer_position(&self.ptr)
+            } else {
+                self.ptr.clone()
+            };
+            self.ptr = self.files.clean_up_multis(self.ptr.clone());
+        }
+    }
+
+    /// Move the selection in the problems pane up
+    pub fn problems_select_up(&mut self) {
+        if let Some(problems) = &mut self.problems {
+            problems.selected = problems.selected.saturating_sub(1);
+        }
+    }
+
+    /// Move the selection in the problems pane down
+    pub fn problems_select_down(&mut self) {
+        if let Some(problems) = &mut self.problems {
+            if problems.selected + 1 < problems.rows() {
+                problems.selected += 1;
+            }
+        }
+    }
+
+    /// Move the selection to the top of the problems pane
+    pub fn problems_select_top(&mut self) {
+        if let Some(problems) = &mut self.problems {
+            problems.selected = 0;
+        }
+    }
+
+    /// Move the selection to the bottom of the problems pane
+    pub fn problems_select_bottom(&mut self) {
+        if let Some(problems) = &mut self.problems {
+            problems.selected = problems.rows().saturating_sub(1);
+        }
+    }
+
+    /// Jump to the selected problem
+    pub fn open_problem(&mut self) -> Result<()> {
+        let Some(problems) = &self.problems else {
+            return Ok(());
+        };
+        let Some(problem) = problems.found.get(problems.selected).cloned() else {
+            return Ok(());
+        };
+        let origin = problems.origin.clone();
+        self.open_from_pane(&problem.path, &origin)?;
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+            doc.move_to(&problem.loc);
+        }
+        self.update_highlighter();
+        self.feedback = match problem.severity {
+            Severity::Error => Feedback::Error(problem.message),
+            Severity::Warning => Feedback::Warning(problem.message),
+            _ => Feedback::Info(problem.message),
+        };
+        Ok(())
+    }
+
+    /// Jump to the next problem found by the last task
+    pub fn next_problem(&mut self) -> Result<()> {
+        self.step_problem(true)
+    }
+
+    /// Jump to the previous problem found by the last task
+    pub fn previous_problem(&mut self) -> Result<()> {
+        self.step_problem(false)
+    }
+
+    /// Select the next (or previous) problem, wrapping around, and jump to it
+    fn step_problem(&mut self, forward: bool) -> Result<()> {
+        let Some(problems) = &mut self.problems else {
+            self.feedback = Feedback::Warning("No task has been run".to_string());
+            return Ok(());
+        };
+        let total = problems.found.len();
+        if total == 0 {
+            self.feedback = Feedback::Info("No problems were found".to_string());
+            return Ok(());
+        }
+        problems.selected = if forward {
+            (problems.selected + 1) % total
+        } else {
+            (problems.selected + total - 1) % total
+        };
+        self.open_problem()
+    }
+
+    /// Find the most serious problem the last task found on a line of a document
+    pub fn problem_on_line(&self, file_name: &str, y: usize) -> Option<Severity> {
+        let problems = self.problems.as_ref().filter(|p| !p.found.is_empty())?;
+        problems.worst_on_line(&get_absolute_path(file_name)?, y)
+    }
+
+    /// Render a line of the problems pane
+    #[allow(clippy::similar_names)]
+    pub fn render_problems(&mut self, y: usize, length: usize, height: usize) -> Result<String> {
+        let colors = config!(self.config, colors);
+        let bg = Bg(colors.file_tree_bg.to_color()?);
+        let fg = Fg(colors.file_tree_fg.to_color()?);
+        let selection_bg = Bg(colors.file_tree_selection_bg.to_color()?);
+        let selection_fg = Fg(colors.file_tree_selection_fg.to_color()?);
+        let highlight = Fg(colors.highlight.to_color()?);
+        let error = Fg(colors.error_fg.to_color()?);
+        let warning = Fg(colors.warning_fg.to_color()?);
+        let info = Fg(colors.info_fg.to_color()?);
+        drop(colors);
+        let tab_width = config!(self.config, document).tab_width;
+        let Some(problems) = &mut self.problems else {
+            return Ok(format!("{bg}{}", " ".repeat(length)));
+        };
+        // The first row describes where the task has got to
+        if y == 0 {
+            // Scroll so that the selected row is visible
+            let visible = height.saturating_sub(1).max(1);
+            if problems.selected < problems.offset {
+                problems.offset = problems.selected;
+            } else if problems.selected >= problems.offset + visible {
+                problems.offset = problems.selected + 1 - visible;
+            }
+            let parts = vec![(highlight.to_string(), format!(" {}", problems.summary()))];
+            return Ok(format!("{bg}{}", fit(&parts, length, tab_width)));
+        }
+        let idx = problems.offset + y - 1;
+        if idx >= problems.rows() {
+            return Ok(format!("{bg}{}", " ".repeat(length)));
+        }
+        let (bg, fg) = if idx == problems.selected {
+            (selection_bg, selection_fg)
+        } else {
+            (bg, fg)
+        };
+        let parts = if let Some(problem) = problems.found.get(idx) {
+            let (colour, label) = match problem.severity {
+                Severity::Error => (error, "error"),
+                Severity::Warning => (warning, "warning"),
+                Severity::Information => (info, "note"),
+                Severity::Hint => (info, "hint"),
+            };
+            let Loc { x, y } = problem.loc;
+            vec![
+                (
+                    highlight.to_string(),
+                    format!(" {}:{}:{} ", problem.name, y + 1, x + 1),
+                ),
+                (colour.to_string(), format!("{label}: ")),
+                (fg.to_string(), problem.message.clone()),
+            ]
+        } else {
+            let line = problems.output.lines().nth(idx).unwrap_or_default();
+            vec![(fg.to_string(), format!(" {line}"))]
+        };
+        Ok(format!("{bg}{fg}{}", fit(&parts, length, tab_width)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problems_from_output() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        let output = "   Compiling demo v0.1.0\n\
+            src/main.rs:2:5: error[E0425]: cannot find value `x` in this scope\n\
+            src/main.rs:7: warning: unused variable\n\
+            src/missing.rs:1:1: error: not a file here\n\
+            12:30:45: also not a problem\n";
+        let formats = [Regex::new(DEFAULT_ERROR_FORMAT).unwrap()];
+        let problems = parse_problems(output, &formats, dir.path());
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].name, "src/main.rs");
+        assert_eq!(problems[0].loc, Loc::at(4, 1));
+        assert_eq!(problems[0].severity, Severity::Error);
+        assert_eq!(problems[0].message, "cannot find value `x` in this scope");
+        assert_eq!(problems[1].loc, Loc::at(0, 6));
+        assert_eq!(problems[1].severity, Severity::Warning);
+        // Tasks can give their own formats
+        let formats = [Regex::new(r"^\s*--> (?P<file>[^:]+):(?P<line>\d+):(?P<col>\d+)$").unwrap()];
+        let problems = parse_problems("  --> src/main.rs:1:4\n", &formats, dir.path());
+        assert_eq!(problems[0].loc, Loc::at(3, 0));
+        assert_eq!(problems[0].message, "");
+        // Problems are counted and looked up by line
+        let results = Problems {
+            task: "build".to_string(),
+            status: TaskStatus::Finished(Some(101)),
+            output: output.to_string(),
+            found: parse_problems(
+                output,
+                &[Regex::new(DEFAULT_ERROR_FORMAT).unwrap()],
+                dir.path(),
+            ),
+            selected: 0,
+            offset: 0,
+            origin: vec![],
+            pending: None,
+            formats: vec![],
+            root: dir.path().to_path_buf(),
+        };
+        assert_eq!(
+            results.summary(),
+            "build: failed with exit status 101, 1 error, 1 warning"
+        );
+        let path = results.found[0].path.clone();
+        assert_eq!(results.worst_on_line(&path, 1), Some(Severity::Error));
+        assert_eq!(results.worst_on_line(&path, 2), None);
+    }
+}
@@ -0,0 +1,633 @@
+/// Functions for searching and replacing across every file in the working directory
+use crate::config;
+use crate::editor::FileLayout;
+use crate::error::{OxError, Result};
+use crate::ui::{size, Feedback};
+use crossterm::style::{SetBackgroundColor as Bg, SetForegroundColor as Fg};
+use ignore::WalkBuilder;
+use kaolinite::document::Backup;
+use kaolinite::encoding::Encoding;
+use kaolinite::history::hash_rope;
+use kaolinite::searching::{Match, SearchText, Searcher};
+use kaolinite::utils::{get_absolute_path, get_cwd, width_char, Size};
+use kaolinite::Document;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::scanning::regex_problem;
+use super::Editor;
+
+/// Files larger than this (in bytes) are left out of searches
+const MAX_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The matches found within a single file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    /// The absolute path of the file
+    pub path: String,
+    /// The path of the file relative to where the search started (for display)
+    pub name: String,
+    /// Each match, along with the (first) line it is on
+    pub matches: Vec<(Match, String)>,
+    /// What each match would be replaced with (empty when only searching)
+    pub replacements: Vec<String>,
+    /// Whether the replacements should be made in this file
+    pub included: bool,
+    /// A hash of the text that was searched, to tell if the file has changed since
+    pub hash: u64,
+}
+
+impl FileMatches {
+    /// Work out what the (first) line of a match would look like once replaced
+    pub fn preview(&self, idx: usize) -> String {
+        let (mtch, line) = &self.matches[idx];
+        let into = self.replacements.get(idx).map_or("", String::as_str);
+        let before: String = line.chars().take(mtch.loc.x).collect();
+        let end = mtch.end();
+        let after: String = if end.y == mtch.loc.y {
+            line.chars().skip(end.x).collect()
+        } else {
+            String::new()
+        };
+        let into = into.split('\n').next().unwrap_or_default();
+        format!("{before}{into}{after}")
+    }
+
+    /// Pair each match up with what it is replaced with
+    pub fn edits(&self) -> Vec<(Match, String)> {
+        let matches = self.matches.iter().map(|(m, _)| m.clone());
+        matches.zip(self.replacements.iter().cloned()).collect()
+    }
+}
+
+/// A row in the results pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultRow {
+    /// The heading of a file (index of file)
+    File(usize),
+    /// A match within a file (index of file, index of match)
+    Match(usize, usize),
+    /// What a match would be replaced with (index of file, index of match)
+    Replacement(usize, usize),
+}
+
+/// The state of a search across the project
+#[derive(Debug, Clone, Default)]
+pub struct ProjectSearch {
+    /// What was searched for
+    pub target: String,
+    /// What the matches are being replaced with (None if only searching)
+    pub into: Option<String>,
+    /// The files that matched, in order of their name
+    pub files: Vec<FileMatches>,
+    /// The row of the results pane that is selected
+    pub selected: usize,
+    /// The row of the results pane at the top of the pane
+    pub offset: usize,
+    /// Where documents were shown before the results pane was opened
+    pub origin: Vec<usize>,
+}
+
+impl ProjectSearch {
+    /// Work out the rows shown in the results pane
+    pub fn rows(&self) -> Vec<ResultRow> {
+        let mut result = vec![];
+        for (f, file) in self.files.iter().enumerate() {
+            result.push(ResultRow::File(f));
+            for m in 0..file.matches.len() {
+                result.push(ResultRow::Match(f, m));
+                if self.into.is_some() {
+                    result.push(ResultRow::Replacement(f, m));
+                }
+            }
+        }
+        result
+    }
+
+    /// Count the total number of matches
+    pub fn total(&self) -> usize {
+        self.files.iter().map(|f| f.matches.len()).sum()
+    }
+
+    /// Describe the results
+    pub fn summary(&self) -> String {
+        let total = self.total();
+        let files = self.files.len();
+        let matches = if total == 1 { "match" } else { "matches" };
+        let files = if files == 1 { "1 file".to_string() } else { format!("{files} files") };
+        let mut result = format!("{total} {matches} in {files} for \"{}\"", self.target);
+        if let Some(into) = &self.into {
+            result += &format!(" → \"{into}\" | [Space] Toggle file | [Tab] Replace");
+        }
+        result
+    }
+}
+
+/// Search every file beneath a folder (skipping those that are hidden or ignored,
+/// e.g. by `.gitignore`). Files that are open are searched through `open` instead of the disk,
+/// so that changes that haven't been saved are included.
+pub fn search_files(
+    root: &Path,
+    searcher: &Searcher,
+    into: Option<&str>,
+    open: &HashMap<String, Rope>,
+) -> Vec<FileMatches> {
+    let mut result = vec![];
+    let walker = WalkBuilder::new(root).require_git(false).build();
+    for entry in walker.filter_map(std::result::Result::ok) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let absolute = get_absolute_path(&path.to_string_lossy()).unwrap_or_default();
+        let Some(rope) = open.get(&absolute).cloned().or_else(|| read_text(path)) else {
+            continue;
+        };
+        let text = SearchText::new(&rope);
+        let (matches, replacements): (Vec<Match>, Vec<String>) = match into {
+            Some(into) => searcher.replacements_in(&text, into).into_iter().unzip(),
+            None => (searcher.finds_in(&text), vec![]),
+        };
+        if matches.is_empty() {
+            continue;
+        }
+        let matches = matches
+            .into_iter()
+            .map(|m| {
+                let line = rope.line(m.loc.y).to_string();
+                let line = line.trim_end_matches(['\n', '\r']).to_string();
+                (m, line)
+            })
+            .collect();
+        let name = path.strip_prefix(root).unwrap_or(path);
+        result.push(FileMatches {
+            path: absolute,
+            name: name.to_string_lossy().to_string(),
+            matches,
+            replacements,
+            included: true,
+            hash: hash_rope(&rope),
+        });
+    }
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+}
+
+/// Read a file to search through, leaving out files that are too large or aren't text.
+/// Files are decoded the same way documents are, so the matches line up once they are opened.
+fn read_text(path: &Path) -> Option<Rope> {
+    if std::fs::metadata(path).ok()?.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    let encoding = Encoding::detect(&bytes);
+    let utf16 = matches!(encoding, Encoding::Utf16Le | Encoding::Utf16Be);
+    if bytes.contains(&0) && !utf16 {
+        return None;
+    }
+    let (text, lossless) = encoding.decode(&bytes);
+    lossless.then(|| Rope::from_str(&text))
+}
+
+/// Make the previewed replacements in a file that isn't open, returning how many were made.
+/// The file is loaded and saved as a document (keeping its encoding and line endings,
+/// saving it safely and backing it up), and is left alone if it has changed since it was searched.
+pub fn replace_in_file(file: &FileMatches, size: Size, backup: Backup) -> Result<usize> {
+    let mut doc = Document::open(size, &file.path)?;
+    if hash_rope(&doc.file) != file.hash {
+        return Err(OxError::ChangedSinceSearch);
+    }
+    let count = doc.replace_matches(&file.edits())?;
+    doc.backup = backup;
+    doc.save()?;
+    Ok(count)
+}
+
+impl Editor {
+    /// Search across every file in the working directory
+    pub fn project_search(&mut self) -> Result<()> {
+        let target = self.prompt(self.search_label("Search project"))?;
+        self.run_project_search(&target, None);
+        Ok(())
+    }
+
+    /// Replace across every file in the working directory (after previewing the changes)
+    pub fn project_replace(&mut self) -> Result<()> {
+        let target = self.prompt(self.search_label("Replace in project"))?;
+        if target.is_empty() {
+            return Ok(());
+        }
+        let into = self.prompt("With")?;
+        self.run_project_search(&target, Some(into));
+        Ok(())
+    }
+
+    /// Perform a search across the working directory, showing the results in the results pane
+    pub fn run_project_search(&mut self, target: &str, into: Option<String>) {
+        if target.is_empty() {
+            return;
+        }
+        let searcher = match Searcher::try_with_options(target, self.search_options) {
+            Ok(searcher) => searcher,
+            Err(err) => {
+                self.feedback = Feedback::Error(regex_problem(&err));
+                return;
+            }
+        };
+        let Some(root) = get_cwd() else {
+            return;
+        };
+        let files = search_files(Path::new(&root), &searcher, into.as_deref(), &self.open_ropes());
+        if files.is_empty() {
+            self.feedback = Feedback::Warning(format!("No matches found for \"{target}\""));
+            return;
+        }
+        let origin = self.open_results_pane();
+        self.project_search = Some(ProjectSearch {
+            target: target.to_string(),
+            into,
+            files,
+            origin,
+            ..ProjectSearch::default()
+        });
+    }
+
+    /// Gather the contents of every open document, by their absolute path
+    fn open_ropes(&self) -> HashMap<String, Rope> {
+        let mut result = HashMap::new();
+        for atom in self.files.atoms(vec![]) {
+            for fc in self.files.get_all(atom) {
+                // Paged documents aren't held in full, so they are read from the disk instead
+                if fc.doc.is_paged() {
+                    continue;
+                }
+                if let Some(path) = fc.doc.file_name.as_deref().and_then(get_absolute_path) {
+                    result.insert(path, fc.doc.file.clone());
+                }
+            }
+        }
+        result
+    }
+
+    /// Focus on the results pane (opening it below the current document if it isn't open).
+    /// Returns where documents were shown before it was opened.
+    fn open_results_pane(&mut self) -> Vec<usize> {
+        if let Some(at) = self.files.search_results(vec![]) {
+            let origin = self.project_search.as_ref().map(|s| s.origin.clone());
+            self.ptr = at;
+            return origin.unwrap_or_default();
+        }
+        // The file tree can't be split, so open the pane below the documents next to it instead
+        let mut at = self.ptr.clone();
+        if matches!(self.files.get_raw(at.clone()), Some(FileLayout::FileTree)) {
+            at = vec![1];
+        }
+        let had_documents = !matches!(self.files.get_raw(at.clone()), Some(FileLayout::None));
+        self.ptr = self.files.open_down(at.clone(), FileLayout::SearchResults);
+        at.push(0);
+        if had_documents {
+            at
+        } else {
+            vec![]
+        }
+    }
+
+    /// Close the results pane
+    pub fn close_project_search(&mut self) {
+        if let Some(at) = self.files.search_results(vec![]) {
+            let in_results = self.ptr == at;
+            self.files.remove(at);
+            self.ptr = if in_results {
+                self.files.new_pointer_position(&self.ptr)
+            } else {
+                self.ptr.clone()
+            };
+            self.ptr = self.files.clean_up_multis(self.ptr.clone());
+        }
+        self.project_search = None;
+    }
+
+    /// Move the selection in the results pane up
+    pub fn project_select_up(&mut self) {
+        if let Some(search) = &mut self.project_search {
+            search.selected = search.selected.saturating_sub(1);
+        }
+    }
+
+    /// Move the selection in the results pane down
+    pub fn project_select_down(&mut self) {
+        if let Some(search) = &mut self.project_search {
+            if search.selected + 1 < search.rows().len() {
+                search.selected += 1;
+            }
+        }
+    }
+
+    /// Move the selection to the top of the results pane
+    pub fn project_select_top(&mut self) {
+        if let Some(search) = &mut self.project_search {
+            search.selected = 0;
+        }
+    }
+
+    /// Move the selection to the bottom of the results pane
+    pub fn project_select_bottom(&mut self) {
+        if let Some(search) = &mut self.project_search {
+            search.selected = search.rows().len().saturating_sub(1);
+        }
+    }
+
+    /// Open the selected result (toggling whether a file is included when replacing)
+    pub fn project_open_result(&mut self) -> Result<()> {
+        let Some(search) = &self.project_search else {
+            return Ok(());
+        };
+        let (f, m) = match search.rows().get(search.selected) {
+            Some(ResultRow::File(_)) if search.into.is_some() => {
+                self.project_toggle_file();
+                return Ok(());
+            }
+            Some(ResultRow::File(f)) => (*f, 0),
+            Some(ResultRow::Match(f, m) | ResultRow::Replacement(f, m)) => (*f, *m),
+            None => return Ok(()),
+        };
+        let file = &search.files[f];
+        let (path, mtch) = (file.path.clone(), file.matches[m].0.clone());
+        let origin = search.origin.clone();
+        self.open_from_pane(&path, &origin)?;
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+            doc.move_to(&mtch.end());
+            doc.select_to(&mtch.loc);
+        }
+        self.update_highlighter();
+        Ok(())
+    }
+
+    /// Open a file from a pane (like the results pane), where documents were shown
+    /// before the pane was opened (or move to it if it is already open)
+    pub fn open_from_pane(&mut self, path: &str, origin: &[usize]) -> Result<()> {
+        if let Some((atom, idx)) = self.already_open(path) {
+            self.ptr = atom.clone();
+            self.files.move_to(atom, idx);
+        } else {
+            let atoms = self.files.atoms(vec![]);
+            if let Some(atom) = atoms.iter().find(|a| **a == origin).or(atoms.first()) {
+                self.ptr.clone_from(atom);
+                self.open(path)?;
+                self.next();
+            } else {
+                let fc = self.open_fc(path)?;
+                self.ptr = self.files.open_up(self.ptr.clone(), FileLayout::Atom(vec![fc], 0));
+            }
+        }
+        self.update_cwd();
+        Ok(())
+    }
+
+    /// Toggle whether the selected file has its replacements made
+    pub fn project_toggle_file(&mut self) {
+        if let Some(search) = &mut self.project_search {
+            if let Some(
+                ResultRow::File(f) | ResultRow::Match(f, _) | ResultRow::Replacement(f, _),
+            ) = search.rows().get(search.selected)
+            {
+                search.files[*f].included = !search.files[*f].included;
+            }
+        }
+    }
+
+    /// Make the previewed replacements in every included file.
+    /// Open documents are changed (so the replacements can be undone), other files are saved.
+    /// Files that can't be changed are reported, without stopping the others from being changed.
+    pub fn project_apply(&mut self) -> Result<()> {
+        let Some(search) = self.project_search.clone() else {
+            return Ok(());
+        };
+        if search.into.is_none() {
+            return Ok(());
+        }
+        let (size, backup) = (size()?, self.backup());
+        let (mut count, mut files, mut failed) = (0, 0, vec![]);
+        for file in search.files.iter().filter(|f| f.included) {
+            let result = if let Some((atom, idx)) = self.already_open(&file.path) {
+                self.replace_in_open(atom, idx, file)
+            } else {
+                replace_in_file(file, size, backup.clone())
+            };
+            match result {
+                Ok(n) => {
+                    count += n;
+                    files += 1;
+                }
+                Err(err) => failed.push(format!("{} ({err})", file.name)),
+            }
+        }
+        self.close_project_search();
+        let replaced = format!("Replaced {count} occurrences in {files} files");
+        self.feedback = if failed.is_empty() {
+            Feedback::Info(replaced)
+        } else {
+            Feedback::Error(format!("{replaced}, couldn't replace in {}", failed.join(", ")))
+        };
+        Ok(())
+    }
+
+    /// Make the previewed replacements in an open document, returning how many were made
+    fn replace_in_open(&mut self, atom: Vec<usize>, idx: usize, file: &FileMatches) -> Result<usize> {
+        let Some((fcs, _)) = self.files.get_atom_mut(atom) else {
+            return Ok(0);
+        };
+        let fc = &mut fcs[idx];
+        if hash_rope(&fc.doc.file) != file.hash {
+            return Err(OxError::ChangedSinceSearch);
+        }
+        let count = fc.doc.replace_matches(&file.edits())?;
+        fc.highlighter.run(&fc.doc.lines_to_view());
+        Ok(count)
+    }
+
+    /// Render a line of the results pane
+    #[allow(clippy::similar_names)]
+    pub fn render_project_search(&mut self, y: usize, length: usize, height: usize) -> Result<String> {
+        let colors = config!(self.config, colors);
+        let bg = Bg(colors.file_tree_bg.to_color()?);
+        let fg = Fg(colors.file_tree_fg.to_color()?);
+        let selection_bg = Bg(colors.file_tree_selection_bg.to_color()?);
+        let selection_fg = Fg(colors.file_tree_selection_fg.to_color()?);
+        let highlight = Fg(colors.highlight.to_color()?);
+        let removed = Fg(colors.file_tree_red.to_color()?);
+        let added = Fg(colors.file_tree_green.to_color()?);
+        drop(colors);
+        let tab_width = config!(self.config, document).tab_width;
+        let Some(search) = &mut self.project_search else {
+            return Ok(format!("{bg}{}", " ".repeat(length)));
+        };
+        // The first row describes the results
+        if y == 0 {
+            // Scroll so that the selected row is visible
+            let visible = height.saturating_sub(1).max(1);
+            if search.selected < search.offset {
+                search.offset = search.selected;
+            } else if search.selected >= search.offset + visible {
+                search.offset = search.selected + 1 - visible;
+            }
+            let parts = vec![(highlight.to_string(), format!(" {}", search.summary()))];
+            return Ok(format!("{bg}{}", fit(&parts, length, tab_width)));
+        }
+        let rows = search.rows();
+        let idx = search.offset + y - 1;
+        let Some(row) = rows.get(idx) else {
+            return Ok(format!("{bg}{}", " ".repeat(length)));
+        };
+        let selected = idx == search.selected;
+        let (bg, fg) = if selected {
+            (selection_bg, selection_fg)
+        } else {
+            (bg, fg)
+        };
+        let parts = match *row {
+            ResultRow::File(f) => {
+                let file = &search.files[f];
+                let check = match (&search.into, file.included) {
+                    (None, _) => "",
+                    (Some(_), true) => "[x] ",
+                    (Some(_), false) => "[ ] ",
+                };
+                let count = file.matches.len();
+                vec![
+                    (fg.to_string(), format!(" {check}")),
+                    (highlight.to_string(), file.name.clone()),
+                    (fg.to_string(), format!(" ({count})")),
+                ]
+            }
+            ResultRow::Match(f, m) => {
+                let (mtch, line) = &search.files[f].matches[m];
+                let (sign, colour) = if search.into.is_some() {
+                    ("-", removed.to_string())
+                } else {
+                    (":", fg.to_string())
+                };
+                let before: String = line.chars().take(mtch.loc.x).collect();
+                let text = mtch.text.split('\n').next().unwrap_or_default().to_string();
+                let after: String = line.chars().skip(mtch.loc.x + text.chars().count()).collect();
+                vec![
+                    (colour.clone(), format!("   {}{sign} ", mtch.loc.y + 1)),
+                    (fg.to_string(), before.trim_start().to_string()),
+                    (highlight.to_string(), text),
+                    (fg.to_string(), after),
+                ]
+            }
+            ResultRow::Replacement(f, m) => {
+                let file = &search.files[f];
+                let number = file.matches[m].0.loc.y + 1;
+                vec![
+                    (added.to_string(), format!("   {number}+ ")),
+                    (fg.to_string(), file.preview(m).trim_start().to_string()),
+                ]
+            }
+        };
+        Ok(format!("{bg}{fg}{}", fit(&parts, length, tab_width)))
+    }
+}
+
+/// Join coloured parts of a row together, cut down (or padded) to fit a width
+pub fn fit(parts: &[(String, String)], length: usize, tab_width: usize) -> String {
+    let mut result = String::new();
+    let mut total = 0;
+    'parts: for (colour, text) in parts {
+        result += colour;
+        for c in text.chars() {
+            let c = if c == '\t' { ' ' } else { c };
+            let w = width_char(&c, tab_width);
+            if total + w > length {
+                break 'parts;
+            }
+            total += w;
+            result.push(c);
+        }
+    }
+    result + &" ".repeat(length.saturating_sub(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::create_dir(root.join("target")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\r\n    greet();\r\n}\r\n").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub fn greet() {}\n").unwrap();
+        std::fs::write(root.join("target/out.rs"), "greet();\n").unwrap();
+        std::fs::write(root.join("binary"), b"greet\0").unwrap();
+        dir
+    }
+
+    #[test]
+    fn project_search_files() {
+        let dir = project();
+        let searcher = Searcher::new("gre+t");
+        let files = search_files(dir.path(), &searcher, None, &HashMap::new());
+        // Ignored and binary files are left out
+        let names: Vec<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, vec!["src/lib.rs", "src/main.rs"]);
+        assert_eq!(files[1].matches[0].0.loc, kaolinite::Loc::at(4, 1));
+        assert_eq!(files[1].matches[0].1, "    greet();");
+        // Open documents are searched instead of the disk
+        let mut open = HashMap::new();
+        let main = get_absolute_path(&dir.path().join("src/main.rs").to_string_lossy()).unwrap();
+        open.insert(main, Rope::from_str("greet(); greet();"));
+        let files = search_files(dir.path(), &searcher, None, &open);
+        assert_eq!(files[1].matches.len(), 2);
+        let search = ProjectSearch {
+            files,
+            ..ProjectSearch::default()
+        };
+        assert_eq!(search.total(), 3);
+        assert_eq!(search.rows().len(), 5);
+    }
+
+    #[test]
+    fn project_replace_files() {
+        let dir = project();
+        let searcher = Searcher::new(r"(\w+)\(\)");
+        let files = search_files(dir.path(), &searcher, Some("$1(name)"), &HashMap::new());
+        assert_eq!(files[1].replacements, vec!["main(name)", "greet(name)"]);
+        assert_eq!(files[1].preview(1), "    greet(name);");
+        let search = ProjectSearch {
+            files,
+            into: Some("$1(name)".to_string()),
+            ..ProjectSearch::default()
+        };
+        assert_eq!(search.rows()[1..3], [ResultRow::Match(0, 0), ResultRow::Replacement(0, 0)]);
+        // Replacing makes the previewed replacements and keeps the line endings of the file
+        let size = Size { w: 80, h: 24 };
+        let main = &search.files[1];
+        assert_eq!(replace_in_file(main, size, Backup::None).unwrap(), 2);
+        let text = std::fs::read_to_string(dir.path().join("src/main.rs")).unwrap();
+        assert_eq!(text, "fn main(name) {\r\n    greet(name);\r\n}\r\n");
+        // Files that changed since they were searched are left alone
+        let lib = dir.path().join("src/lib.rs");
+        std::fs::write(&lib, "fn greet() {}\n").unwrap();
+        let result = replace_in_file(&search.files[0], size, Backup::None);
+        assert!(matches!(result, Err(OxError::ChangedSinceSearch)));
+        assert_eq!(std::fs::read_to_string(&lib).unwrap(), "fn greet() {}\n");
+    }
+
+    #[test]
+    fn project_replace_encodings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin.txt");
+        std::fs::write(&path, b"caf\xe9 caf\xe9\n").unwrap();
+        let searcher = Searcher::new("caf\u{e9}");
+        let files = search_files(dir.path(), &searcher, Some("th\u{e9}"), &HashMap::new());
+        assert_eq!(files[0].matches[1].0.loc, kaolinite::Loc::at(5, 0));
+        assert_eq!(replace_in_file(&files[0], Size { w: 80, h: 24 }, Backup::None).unwrap(), 2);
+        assert_eq!(std::fs::read(&path).unwrap(), b"th\xe9 th\xe9\n");
+    }
+}
@@ -1,27 +1,43 @@
 /// Functions for searching and replacing
 use crate::error::{OxError, Result};
 use crate::events::wait_for_event_hog;
-use crate::ui::{key_event, size};
+use crate::ui::{key_event, size, Feedback};
 use crate::{config, display};
 use crossterm::{
     event::{KeyCode as KCode, KeyModifiers as KMod},
-    style::{Attribute, Print, SetAttribute, SetBackgroundColor as Bg},
+    style::{Attribute, Print, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
 };
+use kaolinite::searching::{Match, Searcher};
 use kaolinite::utils::{Loc, Size};
+use kaolinite::Document;
 use mlua::Lua;
 
 use super::Editor;
 
+/// The most matches a document is searched for (any more aren't highlighted or counted)
+const MAX_MATCHES: usize = 10_000;
+
 impl Editor {
     /// Use search feature
     pub fn search(&mut self, lua: &Lua) -> Result<()> {
-        // Block any non-documents from activating search
-        if self.try_doc().is_none() {
+        let result = self.run_search(lua);
+        self.search_matches.clear();
+        result
+    }
+
+    /// Search the document (or the output of a terminal), highlighting every match while searching
+    #[allow(clippy::similar_names, clippy::too_many_lines)]
+    fn run_search(&mut self, lua: &Lua) -> Result<()> {
+        // Block anything other than documents and terminals from activating search
+        if self.try_doc().is_none() && !self.terminal_focused() {
             return Ok(());
         }
         // Gather data
         let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
-        let cache = self.try_doc().unwrap().char_loc();
+        let error_fg = Fg(config!(self.config, colors).error_fg.to_color()?);
+        let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
+        let cache = self.try_doc().map(Document::char_loc).unwrap_or_default();
+        let mut error: Option<String> = None;
         // Prompt for a search term
         let mut target = String::new();
         let mut done = false;
@@ -30,14 +46,22 @@ impl Editor {
             // Rerender the editor
             self.needs_rerender = true;
             self.render(lua)?;
-            // Render prompt message
+            // Render prompt message (along with how many matches there are)
             self.terminal.prepare_line(h);
+            let (status, status_fg) = match &error {
+                Some(error) => (error.clone(), error_fg),
+                None => (self.match_count(&target), editor_fg),
+            };
             display!(
                 self,
                 editor_bg,
-                "Search: ",
+                self.search_label("Search"),
+                ": ",
                 target.clone(),
-                "│",
+                "│  ",
+                status_fg,
+                status,
+                editor_fg,
                 " ".to_string().repeat(w)
             );
             // Move back to correct cursor position
@@ -56,20 +80,31 @@ impl Editor {
                     (KMod::NONE, KCode::Enter) => done = true,
                     // Cancel operation
                     (KMod::NONE, KCode::Esc) => {
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.try_doc_mut().unwrap().cancel_selection();
+                        self.return_to(&cache);
+                        if let Some(doc) = self.try_doc_mut() {
+                            doc.cancel_selection();
+                        }
                         return Err(OxError::Cancelled);
                     }
                     // Remove from the input string if the user presses backspace
                     (KMod::NONE, KCode::Backspace) => {
                         target.pop();
-                        self.try_doc_mut().unwrap().move_to(&cache);
+                        self.return_to(&cache);
+                        error = self.find_matches(&target);
                         self.next_match(&target);
                     }
                     // Add to the input string if the user presses a character
                     (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
                         target.push(c);
-                        self.try_doc_mut().unwrap().move_to(&cache);
+                        self.return_to(&cache);
+                        error = self.find_matches(&target);
+                        self.next_match(&target);
+                    }
+                    // Toggle case insensitive, whole word and regular expression searching
+                    (KMod::ALT, KCode::Char(c @ ('c' | 'w' | 'r'))) => {
+                        self.toggle_search_option(c);
+                        self.return_to(&cache);
+                        error = self.find_matches(&target);
                         self.next_match(&target);
                     }
                     _ => (),
@@ -92,11 +127,13 @@ impl Editor {
             self.render(lua)?;
             // Render custom status line with mode information
             self.terminal.prepare_line(h);
+            let count = self.match_count(&target);
             display!(
                 self,
                 editor_bg,
                 Print("[<-]: Search previous | [->]: Search next | [Enter] Finish | [Esc] Cancel"),
-                Print(" ".repeat(w.saturating_sub(73)))
+                Print(format!("  {count}")),
+                Print(" ".repeat(w.saturating_sub(75 + count.len())))
             );
             // Move back to correct cursor position
             if let Some(Loc { x, y }) = self.cursor_position() {
@@ -114,7 +151,7 @@ impl Editor {
                     // On return or escape key, exit menu
                     (KMod::NONE, KCode::Enter) => done = true,
                     (KMod::NONE, KCode::Esc) => {
-                        self.try_doc_mut().unwrap().move_to(&cache);
+                        self.return_to(&cache);
                         done = true;
                     }
                     // On left key, move to the previous match in the document
@@ -126,57 +163,211 @@ impl Editor {
             }
             self.update_highlighter();
         }
-        self.try_doc_mut().unwrap().cancel_selection();
+        // Matches in terminals stay selected so they can be copied
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+        }
         Ok(())
     }
 
-    /// Move to the next match
-    pub fn next_match(&mut self, target: &str) -> Option<String> {
+    /// Go back to where a search started from: the cursor location in a document,
+    /// or the bottom of a terminal's output
+    fn return_to(&mut self, cache: &Loc) {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.move_to(cache);
+        } else {
+            self.with_terminal(|pty| {
+                pty.screen.clear_selection();
+                pty.screen.scroll_to_bottom();
+            });
+        }
+    }
+
+    /// Describe a search prompt, along with any search options that are turned on
+    pub fn search_label(&self, name: &str) -> String {
+        let mut options = vec![];
+        if self.search_options.case_insensitive {
+            options.push("case insensitive");
+        }
+        if self.search_options.whole_word {
+            options.push("whole word");
+        }
+        if self.search_options.literal {
+            options.push("plain text");
+        }
+        if options.is_empty() {
+            name.to_string()
+        } else {
+            format!("{name} ({})", options.join(", "))
+        }
+    }
+
+    /// Toggle a search option: 'c' for case insensitivity, 'w' for whole words
+    /// or 'r' for regular expressions (rather than plain text)
+    fn toggle_search_option(&mut self, option: char) {
+        let options = &mut self.search_options;
+        match option {
+            'c' => options.case_insensitive = !options.case_insensitive,
+            'w' => options.whole_word = !options.whole_word,
+            _ => options.literal = !options.literal,
+        }
+    }
+
+    /// Find every match in the document so they can be highlighted and counted,
+    /// returning a description of the problem if the target isn't a valid expression
+    fn find_matches(&mut self, target: &str) -> Option<String> {
+        self.search_matches.clear();
         if target.is_empty() {
             return None;
         }
+        let mut searcher = match Searcher::try_with_options(target, self.search_options) {
+            Ok(searcher) => searcher,
+            Err(err) => return Some(regex_problem(&err)),
+        };
+        if let Some(doc) = self.try_doc() {
+            // Paged documents aren't held in a rope, so only the lines on screen are searched
+            let matches = if doc.is_paged() {
+                doc.find_in_lines(&searcher, doc.offset.y..doc.offset.y + doc.size.h)
+            } else {
+                doc.find_upto(&searcher, MAX_MATCHES)
+            };
+            self.search_matches = matches.iter().map(|m| (m.loc, m.end())).collect();
+        } else if let Some(matches) = self.with_terminal(|pty| pty.screen.find_all(&mut searcher)) {
+            self.search_matches = matches;
+        }
+        None
+    }
+
+    /// Describe where the selected match is amongst all the matches, e.g. "3 of 17"
+    /// (or "3 of 5 on screen" in paged documents, where only the lines on screen are counted)
+    fn match_count(&self, target: &str) -> String {
+        let count = self.count_matches(target);
+        if !count.is_empty() && self.try_doc().is_some_and(Document::is_paged) {
+            format!("{count} on screen")
+        } else {
+            count
+        }
+    }
+
+    /// Describe where the selected match is amongst the matches that were found
+    fn count_matches(&self, target: &str) -> String {
+        let total = self.search_matches.len();
+        // Documents stop being searched once there are too many matches to be worth counting
+        let total = if total >= MAX_MATCHES && self.try_doc().is_some() {
+            format!("{total}+")
+        } else {
+            total.to_string()
+        };
+        if target.is_empty() {
+            String::new()
+        } else if self.search_matches.is_empty() {
+            "No matches".to_string()
+        } else if let Some(at) = self
+            .try_doc()
+            .map(|doc| {
+                // Matches are in character indices, rather than display columns
+                let start = doc.cursor.selection_end.min(doc.cursor.loc);
+                Loc::at(doc.character_idx(&start), start.y)
+            })
+            .or_else(|| self.with_terminal(|pty| pty.screen.selection().map(|(start, _)| start))?)
+            .and_then(|start| {
+                self.search_matches
+                    .iter()
+                    .position(|(loc, _)| *loc == start)
+            })
+        {
+            format!("{} of {total}", at + 1)
+        } else {
+            format!("{total} matches")
+        }
+    }
 
+    /// Move to the next match (matches can span several lines)
+    pub fn next_match(&mut self, target: &str) -> Option<Match> {
+        if target.is_empty() {
+            return None;
+        }
+        let searcher = Searcher::with_options(target, self.search_options);
         if let Some(doc) = self.try_doc_mut() {
-            let mtch = doc.next_match(target, 1)?;
+            let mtch = doc.find_next(&searcher, 1)?;
             // Select match
             doc.cancel_selection();
-            let mut move_to = mtch.loc;
-            move_to.x += mtch.text.chars().count();
-            doc.move_to(&move_to);
+            doc.move_to(&mtch.end());
             doc.select_to(&mtch.loc);
             // Update highlighting
             self.update_highlighter();
-            Some(mtch.text)
+            self.refresh_paged_matches(target);
+            Some(mtch)
         } else {
-            None
+            self.terminal_match(true)
         }
     }
 
-    /// Move to the previous match
-    pub fn prev_match(&mut self, target: &str) -> Option<String> {
+    /// Move to the previous match (matches can span several lines)
+    pub fn prev_match(&mut self, target: &str) -> Option<Match> {
         if target.is_empty() {
             return None;
         }
-
+        let searcher = Searcher::with_options(target, self.search_options);
         if let Some(doc) = self.try_doc_mut() {
-            let mtch = doc.prev_match(target)?;
-            doc.move_to(&mtch.loc);
+            let mtch = doc.find_prev(&searcher)?;
             // Select match
             doc.cancel_selection();
-            let mut move_to = mtch.loc;
-            move_to.x += mtch.text.chars().count();
-            doc.move_to(&move_to);
+            doc.move_to(&mtch.end());
             doc.select_to(&mtch.loc);
             // Update highlighting
             self.update_highlighter();
-            Some(mtch.text)
+            self.refresh_paged_matches(target);
+            Some(mtch)
         } else {
-            None
+            self.terminal_match(false)
         }
     }
 
+    /// Only the lines on screen are searched in paged documents, so search again once moved
+    fn refresh_paged_matches(&mut self, target: &str) {
+        if self.try_doc().is_some_and(Document::is_paged) {
+            self.find_matches(target);
+        }
+    }
+
+    /// Select the next (or previous) match in the output of the focused terminal,
+    /// starting from the match nearest the bottom when nothing is selected yet
+    fn terminal_match(&mut self, forward: bool) -> Option<Match> {
+        let matches = &self.search_matches;
+        let last = matches.len().checked_sub(1)?;
+        let mtch = self.with_terminal(|pty| {
+            let screen = &mut pty.screen;
+            let idx = match screen.selection() {
+                None => last,
+                Some((from, _)) if forward => {
+                    matches.iter().position(|(loc, _)| *loc > from).unwrap_or(0)
+                }
+                Some((from, _)) => matches
+                    .iter()
+                    .rposition(|(loc, _)| *loc < from)
+                    .unwrap_or(last),
+            };
+            let (start, end) = matches[idx];
+            screen.select(start, end);
+            screen.scroll_to_line(start.y);
+            Match {
+                loc: start,
+                text: screen.selected_text(),
+            }
+        });
+        mtch
+    }
+
     /// Use replace feature
     pub fn replace(&mut self, lua: &Lua) -> Result<()> {
+        let result = self.run_replace(lua);
+        self.search_matches.clear();
+        result
+    }
+
+    /// Replace in the document, highlighting every match while replacing
+    fn run_replace(&mut self, lua: &Lua) -> Result<()> {
         // Block any non-documents from activating replace
         if self.try_doc().is_none() {
             return Ok(());
@@ -184,11 +375,15 @@ impl Editor {
         // Gather data
         let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         // Request replace information
-        let target = self.prompt("Replace")?;
+        let target = self.prompt(self.search_label("Replace"))?;
         // If no target is given, do nothing
         if target.is_empty() {
             return Ok(());
         }
+        if let Some(error) = self.find_matches(&target) {
+            self.feedback = Feedback::Error(error);
+            return Ok(());
+        }
         let into = self.prompt("With")?;
         let mut done = false;
         let Size { w, h } = size()?;
@@ -240,10 +435,21 @@ impl Editor {
                     (KMod::NONE, KCode::Left) => mtch = self.prev_match(&target).unwrap_or(mtch),
                     // On left key, move to the next match, keeping note of what that match is
                     (KMod::NONE, KCode::Right) => mtch = self.next_match(&target).unwrap_or(mtch),
-                    // On return key, perform replacement
-                    (KMod::NONE, KCode::Enter) => self.do_replace(&into, &mtch)?,
+                    // On return key, perform replacement and move on to the next match
+                    (KMod::NONE, KCode::Enter) => {
+                        self.do_replace(&target, &into, &mtch)?;
+                        self.find_matches(&target);
+                        if let Some(m) = self.next_match(&target).or_else(|| self.prev_match(&target)) {
+                            mtch = m;
+                        } else {
+                            done = true;
+                        }
+                    }
                     // On tab key, replace all instances within the document
-                    (KMod::NONE, KCode::Tab) => self.do_replace_all(&target, &into),
+                    (KMod::NONE, KCode::Tab) => {
+                        self.do_replace_all(&target, &into)?;
+                        done = true;
+                    }
                     _ => (),
                 }
             }
@@ -254,43 +460,100 @@ impl Editor {
         Ok(())
     }
 
-    /// Replace an instance in a document
-    fn do_replace(&mut self, into: &str, text: &str) -> Result<()> {
+    /// Replace an instance in a document (filling in any capture groups, such as `$1`)
+    fn do_replace(&mut self, target: &str, into: &str, mtch: &Match) -> Result<()> {
+        let searcher = Searcher::with_options(target, self.search_options);
         if let Some(doc) = self.try_doc_mut() {
-            // Commit events to event manager (for undo / redo)
-            doc.commit();
-            // Do the replacement
-            let loc = doc.char_loc();
-            doc.replace(loc, text, into)?;
-            doc.move_to(&loc);
-            // Update syntax highlighter
-            self.update_highlighter();
-            if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
-            }
+            doc.replace_match(&searcher, mtch, into)?;
+            doc.move_to(&mtch.loc);
         }
+        // Replacements can add and remove lines, so update the whole highlighter
+        self.reload_highlight();
         Ok(())
     }
 
-    /// Replace all instances in a document
-    fn do_replace_all(&mut self, target: &str, into: &str) {
-        if self.try_doc().is_some() {
-            // Commit events to event manager (for undo / redo)
-            self.try_doc_mut().unwrap().commit();
-            // Replace everything top to bottom
-            self.try_doc_mut().unwrap().move_to(&Loc::at(0, 0));
-            while let Some(mtch) = self.try_doc_mut().unwrap().next_match(target, 1) {
-                drop(
-                    self.try_doc_mut()
-                        .unwrap()
-                        .replace(mtch.loc, &mtch.text, into),
-                );
-                self.update_highlighter();
-                if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                    file.highlighter
-                        .edit(mtch.loc.y, &file.doc.lines[mtch.loc.y]);
-                }
-            }
+    /// Replace all instances in a document, as a single change that can be undone
+    fn do_replace_all(&mut self, target: &str, into: &str) -> Result<()> {
+        let searcher = Searcher::with_options(target, self.search_options);
+        if let Some(doc) = self.try_doc_mut() {
+            let count = doc.replace_all_matches(&searcher, into)?;
+            self.feedback = Feedback::Info(format!("Replaced {count} occurrences"));
         }
+        self.reload_highlight();
+        Ok(())
+    }
+}
+
+/// Describe what is wrong with a regular expression (without repeating the expression itself)
+pub fn regex_problem(err: &regex::Error) -> String {
+    let message = err.to_string();
+    let problem = message
+        .lines()
+        .find_map(|line| line.strip_prefix("error: "))
+        .unwrap_or(&message);
+    format!("Invalid expression: {problem}")
+}
+
+/// Work out which characters of a line are within search matches (as ranges of character indices)
+pub fn search_match_spans(matches: &[(Loc, Loc)], y: usize) -> Vec<(usize, usize)> {
+    // Matches are in order and don't overlap, so skip those ending before this line
+    let first = matches.partition_point(|(_, end)| end.y < y);
+    matches[first..]
+        .iter()
+        .take_while(|(start, _)| start.y <= y)
+        .map(|(start, end)| {
+            let from = if start.y == y { start.x } else { 0 };
+            let to = if end.y == y { end.x } else { usize::MAX };
+            (from, to)
+        })
+        .filter(|(from, to)| from < to)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{FileContainer, FileLayout};
+    use kaolinite::searching::SearchOptions;
+
+    #[test]
+    fn search_match_highlighting() {
+        let matches = vec![
+            (Loc::at(2, 0), Loc::at(5, 0)),
+            (Loc::at(7, 0), Loc::at(1, 2)),
+            (Loc::at(3, 2), Loc::at(4, 2)),
+        ];
+        assert_eq!(search_match_spans(&matches, 0), vec![(2, 5), (7, usize::MAX)]);
+        assert_eq!(search_match_spans(&matches, 1), vec![(0, usize::MAX)]);
+        assert_eq!(search_match_spans(&matches, 2), vec![(0, 1), (3, 4)]);
+        assert!(search_match_spans(&matches, 3).is_empty());
+        let problem = Searcher::try_with_options("(a", SearchOptions::default()).err().unwrap();
+        assert_eq!(regex_problem(&problem), "Invalid expression: unclosed group");
+    }
+
+    #[test]
+    fn search_paged_counting() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.log");
+        let contents: Vec<String> = (0..1000).map(|n| format!("entry {n}")).collect();
+        std::fs::write(&path, contents.join("\n")).unwrap();
+        let doc = Document::open_paged(Size { w: 80, h: 10 }, path.to_str().unwrap()).unwrap();
+        assert!(doc.is_paged());
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let file = FileContainer {
+            doc,
+            ..FileContainer::default()
+        };
+        editor.files = FileLayout::Atom(vec![file], 0);
+        // The lines on screen are counted, rather than reporting no matches
+        assert_eq!(editor.find_matches("entry"), None);
+        assert_eq!(editor.match_count("entry"), "1 of 10 on screen");
+        // Moving to a match off screen counts the matches around it instead
+        assert!(editor.next_match("entry 99").is_some());
+        assert_eq!(editor.try_doc().unwrap().loc().y, 99);
+        assert_eq!(editor.match_count("entry 99"), "1 of 1 on screen");
+        editor.find_matches("entry 5000");
+        assert_eq!(editor.match_count("entry 5000"), "No matches on screen");
     }
 }
@@ -0,0 +1,377 @@
+/// Functions for saving the layout of splits, the documents open in them and where their
+/// cursors are to a session file, and restoring them from it again later
+use crate::editor::{FileContainer, FileLayout};
+use crate::error::{OxError, Result};
+use crate::pty_cross::Shell;
+use crate::ui::Feedback;
+use kaolinite::history::hash;
+use kaolinite::utils::{get_absolute_path, get_cwd, get_file_name};
+use kaolinite::Loc;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use super::Editor;
+
+/// The directory sessions are kept in
+pub fn sessions_dir() -> Option<PathBuf> {
+    Some(crate::dirs::config_dir()?.join("sessions"))
+}
+
+/// The file a named session is kept in
+pub fn session_path(name: &str) -> Option<PathBuf> {
+    Some(sessions_dir()?.join(format!("{}.json", session_name(name))))
+}
+
+/// The file the automatic session of a directory is kept in
+pub fn auto_session_path(dir: &str) -> Option<PathBuf> {
+    let file = format!("{:016x}.json", hash(dir.bytes()));
+    Some(sessions_dir()?.join("auto").join(file))
+}
+
+/// Turn the name of a session into one that can safely be used as a file name
+pub fn session_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Write a session to a file
+pub fn write_session(path: &Path, session: &Value) -> Result<()> {
+    crate::dirs::ensure_parent_dirs(path)?;
+    std::fs::write(path, format!("{session:#}"))?;
+    Ok(())
+}
+
+/// Read a session from a file
+pub fn read_session(path: &Path) -> Result<Value> {
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(|e| OxError::Parse { msg: e.to_string() })
+}
+
+/// Describe part of the layout, leaving out what can't be restored
+/// (the file tree, search results, problems and documents that were never saved)
+pub fn layout_to_json(layout: &FileLayout, idx: &[usize], ptr: &[usize]) -> Value {
+    match layout {
+        FileLayout::SideBySide(layouts) | FileLayout::TopToBottom(layouts) => {
+            let mut children = vec![];
+            for (c, (layout, size)) in layouts.iter().enumerate() {
+                let child = layout_to_json(layout, &[idx, &[c]].concat(), ptr);
+                if !child.is_null() {
+                    children.push((child, *size));
+                }
+            }
+            // Share out the space of anything left out between the rest
+            let total: f64 = children.iter().map(|(_, size)| size).sum();
+            let split = if matches!(layout, FileLayout::SideBySide(_)) {
+                "side_by_side"
+            } else {
+                "top_to_bottom"
+            };
+            match children.len() {
+                0 => Value::Null,
+                1 => children.remove(0).0,
+                _ => json!({
+                    "split": split,
+                    "children": children
+                        .into_iter()
+                        .map(|(layout, size)| json!({ "layout": layout, "size": size / total }))
+                        .collect::<Vec<_>>(),
+                }),
+            }
+        }
+        FileLayout::Atom(fcs, tab) => {
+            let mut documents = vec![];
+            let mut new_tab = 0;
+            for (c, fc) in fcs.iter().enumerate() {
+                let Some(path) = fc.doc.file_name.as_deref().and_then(get_absolute_path) else {
+                    continue;
+                };
+                if c == *tab {
+                    new_tab = documents.len();
+                }
+                let cursor = fc.doc.char_loc();
+                documents.push(json!({
+                    "path": path,
+                    "cursor": [cursor.x, cursor.y],
+                    "offset": [fc.doc.offset.x, fc.doc.offset.y],
+                }));
+            }
+            if documents.is_empty() {
+                Value::Null
+            } else {
+                json!({ "documents": documents, "tab": new_tab, "focus": idx == ptr })
+            }
+        }
+        FileLayout::Terminal(term) => match term.lock() {
+            Ok(pty) => json!({ "terminal": pty.shell.command(), "focus": idx == ptr }),
+            Err(_) => Value::Null,
+        },
+        FileLayout::None
+        | FileLayout::FileTree
+        | FileLayout::SearchResults
+        | FileLayout::Problems => Value::Null,
+    }
+}
+
+/// Read a location stored as `[x, y]`
+fn loc_from_json(value: &Value) -> Loc {
+    let at = |i: usize| {
+        value[i]
+            .as_u64()
+            .and_then(|n| usize::try_from(n).ok())
+            .unwrap_or(0)
+    };
+    Loc::at(at(0), at(1))
+}
+
+impl Editor {
+    /// Describe the layout of splits, the documents open in them and where their cursors are
+    pub fn capture_session(&self) -> Value {
+        json!({
+            "cwd": get_cwd(),
+            "file_tree": self.file_tree_is_open(),
+            "layout": layout_to_json(&self.files, &[], &self.ptr),
+        })
+    }
+
+    /// Replace the layout with one from a session, reopening its documents and terminals.
+    /// Nothing is replaced if any of the documents open have unsaved changes.
+    pub fn restore_session(&mut self, session: &Value) -> Result<()> {
+        // Make sure no changes are thrown away
+        for atom in self.files.atoms(vec![]) {
+            for fc in self.files.get_all(atom) {
+                let untouched = fc.doc.file_name.is_none() && fc.doc.file.len_chars() == 0;
+                if !untouched && !fc.doc.event_mgmt.with_disk() {
+                    let file = fc.doc.file_name.as_deref().and_then(get_file_name);
+                    let file = file.unwrap_or_else(|| "[No Name]".to_string());
+                    return Err(OxError::UnsavedChanges { file });
+                }
+            }
+        }
+        // Clear away the old layout
+        self.close_project_search();
+        self.close_problems();
+        self.close_file_tree();
+        if let Some(cwd) = session["cwd"].as_str() {
+            let _ = std::env::set_current_dir(cwd);
+        }
+        self.files = FileLayout::Atom(vec![], 0);
+        self.ptr = vec![];
+        // Build the new layout
+        let mut focus = None;
+        if let Some(layout) = self.layout_from_json(&session["layout"], vec![], &mut focus) {
+            self.files = layout;
+        }
+        let first_atom = self.files.atoms(vec![]).into_iter().next();
+        self.ptr = focus.or(first_atom).unwrap_or_default();
+        if self.files.n_atoms() == 0 {
+            // Make sure there is somewhere for documents to go
+            self.ptr = self.files.open_up(vec![], FileLayout::Atom(vec![], 0));
+        }
+        self.new_if_empty()?;
+        if session["file_tree"].as_bool() == Some(true) {
+            let ptr = self.ptr.clone();
+            self.open_file_tree();
+            self.ptr = [&[1], ptr.as_slice()].concat();
+        }
+        self.update_cwd();
+        self.needs_rerender = true;
+        Ok(())
+    }
+
+    /// Build part of a layout from its description in a session, noting down where focus goes.
+    /// Parts that can't be restored (like documents that no longer exist) are left out.
+    fn layout_from_json(
+        &mut self,
+        value: &Value,
+        idx: Vec<usize>,
+        focus: &mut Option<Vec<usize>>,
+    ) -> Option<FileLayout> {
+        let layout = if let Some(children) = value["children"].as_array() {
+            let mut layouts = vec![];
+            for child in children {
+                let at = [idx.as_slice(), &[layouts.len()]].concat();
+                if let Some(layout) = self.layout_from_json(&child["layout"], at, focus) {
+                    layouts.push((layout, child["size"].as_f64().unwrap_or(1.0)));
+                }
+            }
+            let total: f64 = layouts.iter().map(|(_, size)| size).sum();
+            for (_, size) in &mut layouts {
+                *size /= total;
+            }
+            match layouts.len() {
+                0 => return None,
+                1 => {
+                    // A split with one thing left in it is just that thing
+                    if let Some(focus) = focus.as_mut().filter(|f| f.starts_with(&idx)) {
+                        focus.remove(idx.len());
+                    }
+                    return Some(layouts.remove(0).0);
+                }
+                _ if value["split"] == "top_to_bottom" => FileLayout::TopToBottom(layouts),
+                _ => FileLayout::SideBySide(layouts),
+            }
+        } else if let Some(documents) = value["documents"].as_array() {
+            let mut fcs = vec![];
+            for document in documents {
+                if let Some(path) = document["path"].as_str() {
+                    match self.reopen_document(path, document) {
+                        Ok(fc) => fcs.push(fc),
+                        Err(err) => {
+                            let file = get_file_name(path).unwrap_or_default();
+                            self.feedback =
+                                Feedback::Warning(format!("Couldn't reopen '{file}': {err}"));
+                        }
+                    }
+                }
+            }
+            if fcs.is_empty() {
+                return None;
+            }
+            let tab = value["tab"]
+                .as_u64()
+                .and_then(|n| usize::try_from(n).ok())
+                .unwrap_or(0)
+                .min(fcs.len() - 1);
+            FileLayout::Atom(fcs, tab)
+        } else if let Some(shell) = value["terminal"].as_str() {
+            match self.new_terminal_with(Shell::from(shell.to_string())) {
+                Ok(term) => FileLayout::Terminal(term),
+                Err(err) => {
+                    self.feedback = Feedback::Warning(format!("Couldn't start terminal: {err}"));
+                    return None;
+                }
+            }
+        } else {
+            return None;
+        };
+        if value["focus"].as_bool() == Some(true) {
+            *focus = Some(idx);
+        }
+        Some(layout)
+    }
+
+    /// Open a document from a session, with its cursor and scroll position restored
+    fn reopen_document(&mut self, path: &str, document: &Value) -> Result<FileContainer> {
+        let mut fc = self.open_fc(path)?;
+        fc.doc.move_to(&loc_from_json(&document["cursor"]));
+        fc.doc.offset = loc_from_json(&document["offset"]);
+        fc.doc.bring_cursor_in_viewport();
+        if !fc.doc.is_paged() {
+            fc.highlighter.run(&fc.doc.lines_to_view());
+        }
+        Ok(fc)
+    }
+
+    /// Save the layout to a named session
+    pub fn save_session(&mut self, name: &str) -> Result<()> {
+        if session_name(name).is_empty() {
+            self.feedback = Feedback::Error("Sessions need a name".to_string());
+            return Ok(());
+        }
+        let Some(path) = session_path(name) else {
+            self.feedback =
+                Feedback::Error("Couldn't find the configuration directory".to_string());
+            return Ok(());
+        };
+        write_session(&path, &self.capture_session())?;
+        self.feedback = Feedback::Info(format!("Saved session '{name}'"));
+        Ok(())
+    }
+
+    /// Replace the layout with the one saved in a named session
+    pub fn load_session(&mut self, name: &str) -> Result<()> {
+        let Some(path) = session_path(name).filter(|path| path.exists()) else {
+            self.feedback = Feedback::Error(format!("There is no session called '{name}'"));
+            return Ok(());
+        };
+        self.restore_session(&read_session(&path)?)?;
+        if matches!(self.feedback, Feedback::None) {
+            self.feedback = Feedback::Info(format!("Loaded session '{name}'"));
+        }
+        Ok(())
+    }
+
+    /// Forget the layout from before documents started being closed, unless the last key
+    /// pressed closed one (so quitting documents one after another saves all of them)
+    pub fn settle_session(&mut self) {
+        match &mut self.closing_session {
+            Some((_, closed @ true)) => *closed = false,
+            _ => self.closing_session = None,
+        }
+    }
+
+    /// Save the layout to the session file it should be kept in when the editor is closed
+    pub fn save_session_on_exit(&self, session: &Value) {
+        if let Some(path) = &self.session_file {
+            if let Err(err) = write_session(path, session) {
+                eprintln!("Failed to save session: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kaolinite::Document;
+    use kaolinite::Size;
+
+    #[test]
+    fn sessions_from_layouts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "first\nsecond line\nthird\n").unwrap();
+        let mut doc = Document::open(Size { w: 10, h: 10 }, path.to_str().unwrap()).unwrap();
+        doc.move_to(&Loc::at(3, 1));
+        let named = FileContainer {
+            doc,
+            ..FileContainer::default()
+        };
+        let unnamed = FileContainer::default();
+        // Unnamed documents are left out (moving the tab along), as are panes
+        let layout = FileLayout::SideBySide(vec![
+            (FileLayout::FileTree, 0.2),
+            (
+                FileLayout::Atom(vec![unnamed.clone(), named.clone()], 1),
+                0.4,
+            ),
+            (FileLayout::SearchResults, 0.4),
+        ]);
+        let json = layout_to_json(&layout, &[], &[1]);
+        assert_eq!(json["tab"], 0);
+        assert_eq!(json["focus"], true);
+        assert_eq!(json["documents"][0]["cursor"], json!([3, 1]));
+        assert!(json["documents"][0]["path"]
+            .as_str()
+            .unwrap()
+            .ends_with("notes.txt"));
+        // Splits keep the proportions of what is left in them
+        let layout = FileLayout::TopToBottom(vec![
+            (layout, 0.5),
+            (FileLayout::Problems, 0.25),
+            (FileLayout::Atom(vec![unnamed], 0), 0.25),
+        ]);
+        assert_eq!(layout_to_json(&layout, &[], &[0, 1]), json);
+        let layout = FileLayout::SideBySide(vec![
+            (layout, 0.2),
+            (FileLayout::FileTree, 0.2),
+            (FileLayout::Atom(vec![named], 0), 0.6),
+        ]);
+        let json = layout_to_json(&layout, &[], &[2]);
+        assert_eq!(json["split"], "side_by_side");
+        assert_eq!(json["children"][0]["size"], json!(0.25));
+        assert_eq!(json["children"][0]["layout"]["focus"], false);
+        assert_eq!(json["children"][1]["layout"]["focus"], true);
+        // Session names are made safe to use as file names
+        assert_eq!(session_name(" my project "), "my_project");
+        assert_eq!(session_name("../../etc/passwd"), "______etc_passwd");
+    }
+}
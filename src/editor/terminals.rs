@@ -0,0 +1,153 @@
+/// Functions for terminal splits: passing keys straight through to the program running in them,
+/// and scrolling back through, selecting and copying their output
+use crate::config;
+use crate::config::key_to_string;
+use crate::editor::FileLayout;
+use crate::error::Result;
+use crate::pty_cross::{Pty, Shell};
+use crate::pty_error::PtyResult;
+use crate::ui::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::Loc;
+use mlua::Lua;
+use std::sync::{Arc, Mutex};
+
+use super::Editor;
+
+impl Editor {
+    /// Start a new terminal, ready to be opened in a split
+    pub fn new_terminal(&self) -> PtyResult<Arc<Mutex<Pty>>> {
+        self.new_terminal_with(config!(self.config, terminal).shell)
+    }
+
+    /// Start a new terminal running a particular shell
+    pub fn new_terminal_with(&self, shell: Shell) -> PtyResult<Arc<Mutex<Pty>>> {
+        let term = Pty::new(shell)?;
+        if let Ok(mut pty) = term.lock() {
+            pty.set_scrollback(config!(self.config, terminal).scrollback);
+        }
+        Ok(term)
+    }
+
+    /// Get the terminal in a split
+    pub fn terminal_at(&self, idx: &[usize]) -> Option<Arc<Mutex<Pty>>> {
+        match self.files.get_raw(idx.to_vec()) {
+            Some(FileLayout::Terminal(term)) => Some(Arc::clone(term)),
+            _ => None,
+        }
+    }
+
+    /// Determine if a terminal split is focused (in which case keys go to it)
+    #[cfg(not(target_os = "windows"))]
+    pub fn terminal_focused(&self) -> bool {
+        self.terminal_at(&self.ptr).is_some()
+    }
+
+    /// Terminal splits aren't drawn on windows, so keys never go to them
+    #[cfg(target_os = "windows")]
+    pub fn terminal_focused(&self) -> bool {
+        false
+    }
+
+    /// Run something on the focused terminal (if there is one)
+    pub fn with_terminal<T>(&self, action: impl FnOnce(&mut Pty) -> T) -> Option<T> {
+        let term = self.terminal_at(&self.ptr)?;
+        let result = match term.lock() {
+            Ok(mut pty) => Some(action(&mut pty)),
+            Err(e) => {
+                eprintln!("Failed to lock terminal: {e}");
+                None
+            }
+        };
+        result
+    }
+
+    /// Handle a key press in the focused terminal. Keys for leaving the terminal, scrolling,
+    /// copying and searching are handled by the editor, and the rest go to the terminal.
+    pub fn terminal_key(&mut self, lua: &Lua, modifiers: KMod, code: KCode) -> Result<()> {
+        if key_to_string(modifiers, code) == config!(self.config, terminal).escape_key {
+            self.leave_terminal();
+            return Ok(());
+        }
+        let page = self
+            .with_terminal(|pty| pty.screen.rows.saturating_sub(1))
+            .unwrap_or(1)
+            .max(1);
+        let page = isize::try_from(page).unwrap_or(isize::MAX);
+        let ctrl_shift = KMod::CONTROL | KMod::SHIFT;
+        match (modifiers, code) {
+            (KMod::SHIFT, KCode::PageUp) => self.scroll_terminal(-page),
+            (KMod::SHIFT, KCode::PageDown) => self.scroll_terminal(page),
+            (KMod::SHIFT, KCode::Up) => self.scroll_terminal(-1),
+            (KMod::SHIFT, KCode::Down) => self.scroll_terminal(1),
+            (m, KCode::Char('c' | 'C')) if m == ctrl_shift => self.copy()?,
+            (m, KCode::Char('f' | 'F')) if m == ctrl_shift => self.search(lua)?,
+            _ => {
+                if let Some(result) = self.with_terminal(|pty| pty.send_key(modifiers, code)) {
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Paste text into the focused terminal
+    pub fn terminal_paste(&mut self, text: &str) -> Result<()> {
+        if let Some(result) = self.with_terminal(|pty| pty.paste(text)) {
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Scroll the focused terminal back (negative) or forward (positive) through its output.
+    /// Programs using the alternate screen have no scrollback, so they get cursor keys instead.
+    pub fn scroll_terminal(&mut self, lines: isize) {
+        let result = self.with_terminal(|pty| {
+            if pty.screen.is_alternate() {
+                let key = if lines < 0 { KCode::Up } else { KCode::Down };
+                for _ in 0..lines.unsigned_abs() {
+                    pty.send_key(KMod::NONE, key)?;
+                }
+            } else if lines < 0 {
+                pty.screen.scroll_back(lines.unsigned_abs());
+            } else {
+                pty.screen.scroll_forward(lines.unsigned_abs());
+            }
+            PtyResult::Ok(())
+        });
+        if let Some(Err(e)) = result {
+            self.feedback = Feedback::Error(e.to_string());
+        }
+    }
+
+    /// Start selecting the output of the focused terminal from a location in its split
+    pub fn terminal_select_from(&mut self, at: Loc) {
+        self.with_terminal(|pty| {
+            let y = pty.screen.view_top() + at.y;
+            pty.screen.select(Loc::at(at.x, y), Loc::at(at.x, y));
+        });
+    }
+
+    /// Extend the selection of the focused terminal to a location in its split
+    pub fn terminal_select_to(&mut self, at: Loc) {
+        self.with_terminal(|pty| {
+            let y = pty.screen.view_top() + at.y;
+            pty.screen.select_to(Loc::at(at.x, y));
+        });
+    }
+
+    /// The text selected in the focused terminal
+    pub fn terminal_selection_text(&self) -> Option<String> {
+        self.with_terminal(|pty| pty.screen.selected_text())
+    }
+
+    /// Move focus out of a terminal split, to the first split with documents in it
+    pub fn leave_terminal(&mut self) {
+        if let Some(atom) = self.files.atoms(vec![]).into_iter().next() {
+            self.ptr = atom;
+            self.update_cwd();
+        } else {
+            self.feedback = Feedback::Warning("There are no other splits to move to".to_string());
+        }
+    }
+}
@@ -0,0 +1,285 @@
+/// Functions for noticing when other programs change the files that are open
+use crate::config;
+use crate::error::{OxError, Result};
+use crate::ui::Feedback;
+use kaolinite::document::DiskState;
+use kaolinite::event::Event;
+use kaolinite::utils::get_file_name;
+use kaolinite::{Document, Loc, Size};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::{Editor, MIB};
+
+/// How often to look at open files on the disk
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// The number of unchanged lines to show around each change in a diff
+const CONTEXT: usize = 3;
+/// The largest region of changes (in old lines multiplied by new lines) to find a minimal diff for
+const MAX_DIFF_WORK: usize = 4_000_000;
+
+impl Editor {
+    /// Look for open files that other programs have changed (at most once a second).
+    /// Files without unsaved changes are reloaded, otherwise the user is asked what to do.
+    /// Returns true if anything changed that needs rendering.
+    pub fn check_external_changes(&mut self) -> Result<bool> {
+        if self.last_disk_check.elapsed() < CHECK_INTERVAL {
+            return Ok(false);
+        }
+        let mut changed = false;
+        for atom in self.files.atoms(vec![]) {
+            let len = self.files.get_atom(atom.clone()).map_or(0, |(fcs, _)| fcs.len());
+            for idx in 0..len {
+                changed |= self.check_external_change(&atom, idx)?;
+            }
+        }
+        // Start the interval after any prompts, so the user isn't asked again straight away
+        self.last_disk_check = Instant::now();
+        Ok(changed)
+    }
+
+    /// Look for changes made to a particular file by another program
+    fn check_external_change(&mut self, atom: &[usize], idx: usize) -> Result<bool> {
+        let Some((fcs, _)) = self.files.get_atom_mut(atom.to_vec()) else {
+            return Ok(false);
+        };
+        let fc = &mut fcs[idx];
+        let Some(file_name) = fc.doc.file_name.clone() else {
+            return Ok(false);
+        };
+        if fc.dismissed.is_some_and(|d| d.unchanged(Path::new(&file_name))) {
+            return Ok(false);
+        }
+        let Some(state) = fc.doc.external_change() else {
+            return Ok(false);
+        };
+        let name = get_file_name(&file_name).unwrap_or(file_name);
+        if fc.doc.event_mgmt.with_disk() {
+            self.reload_file(atom, idx)?;
+            self.feedback = Feedback::Info(format!("Reloaded '{name}' after it changed on disk"));
+            return Ok(true);
+        }
+        let msg = format!(
+            "'{name}' changed on disk but has unsaved changes: [r]eload, [k]eep yours or [d]iff"
+        );
+        match self.choose(&msg, &['r', 'k', 'd'])? {
+            Some('r') => {
+                self.reload_file(atom, idx)?;
+                let msg = format!("Reloaded '{name}' from disk (undo to get your changes back)");
+                self.feedback = Feedback::Info(msg);
+            }
+            Some('d') => {
+                self.dismiss_change(atom, idx, state);
+                self.show_external_diff(atom, idx)?;
+            }
+            _ => self.dismiss_change(atom, idx, state),
+        }
+        Ok(true)
+    }
+
+    /// Remember that the user wants to keep their version of a file over a change on disk
+    fn dismiss_change(&mut self, atom: &[usize], idx: usize, state: DiskState) {
+        if let Some((fcs, _)) = self.files.get_atom_mut(atom.to_vec()) {
+            fcs[idx].dismissed = Some(state);
+        }
+    }
+
+    /// Make sure the user is happy to save over changes another program made to a file
+    pub fn confirm_overwrite(&mut self, atom: &[usize], idx: usize) -> Result<()> {
+        let changed = self
+            .files
+            .get_atom_mut(atom.to_vec())
+            .and_then(|(fcs, _)| fcs[idx].doc.external_change())
+            .is_some();
+        let msg = "This file was changed by another program, press Ctrl + Q to overwrite it or Esc to cancel";
+        if changed && !self.confirm(msg)? {
+            return Err(OxError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Read a file from the disk again, in place of any unsaved changes
+    /// (the reload is an edit, so undoing it brings the unsaved changes back).
+    /// Large files that have become small enough to edit are loaded fully.
+    pub fn reload_file(&mut self, atom: &[usize], idx: usize) -> Result<()> {
+        let large_file_size = config!(self.config, document).large_file_size * MIB;
+        let Some((fcs, _)) = self.files.get_atom_mut(atom.to_vec()) else {
+            return Ok(());
+        };
+        let fc = &mut fcs[idx];
+        fc.doc.reload()?;
+        fc.dismissed = None;
+        let len = fc.doc.info.disk.map_or(0, |d| d.len);
+        if fc.doc.is_paged() && len <= large_file_size {
+            fc.doc.load_fully()?;
+            let file_type = fc.file_type.clone();
+            let highlighter = self.highlighter_for_size(file_type.as_ref(), len);
+            if let Some((fcs, _)) = self.files.get_atom_mut(atom.to_vec()) {
+                fcs[idx].highlighter = highlighter;
+            }
+        }
+        if let Some((fcs, _)) = self.files.get_atom_mut(atom.to_vec()) {
+            let fc = &mut fcs[idx];
+            if !fc.doc.is_paged() {
+                fc.highlighter.run(&fc.doc.lines_to_view());
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a new document showing how a file on disk differs from its open document
+    fn show_external_diff(&mut self, atom: &[usize], idx: usize) -> Result<()> {
+        let Some((fcs, _)) = self.files.get_atom(atom.to_vec()) else {
+            return Ok(());
+        };
+        let doc = &fcs[idx].doc;
+        let Some(file_name) = doc.file_name.clone() else {
+            return Ok(());
+        };
+        let encoding = Some(doc.info.encoding);
+        let on_disk = Document::open_with_encoding(Size::is(0, 0), &file_name, encoding)?;
+        let name = get_file_name(&file_name).unwrap_or_else(|| file_name.clone());
+        let mut text = format!("--- {name} (unsaved changes)\n+++ {name} (on disk)\n");
+        for line in diff(&rope_lines(doc), &rope_lines(&on_disk)) {
+            text.push_str(&line);
+            text.push('\n');
+        }
+        // Show the diff in a new tab next to the current one
+        self.blank()?;
+        self.next();
+        let diff_type = config!(self.config, document).file_types.get_name("Diff");
+        let tab_width = config!(self.config, document).tab_width;
+        if let Some(doc) = self.try_doc_mut() {
+            doc.exe(Event::Insert(Loc::at(0, 0), text))?;
+            doc.move_to(&Loc::at(0, 0));
+        }
+        if let Some(file_type) = diff_type {
+            let highlighter = file_type.get_highlighter(&self.config, tab_width);
+            if let Some((fcs, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
+                let fc = &mut fcs[*ptr];
+                fc.highlighter = highlighter;
+                fc.highlighter.run(&fc.doc.lines_to_view());
+                fc.file_type = Some(file_type);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collect the lines of a document (without their line endings)
+fn rope_lines(doc: &Document) -> Vec<String> {
+    doc.file
+        .lines()
+        .map(|l| l.to_string().trim_end_matches(['\n', '\r']).to_string())
+        .collect()
+}
+
+/// Produce a unified diff that turns one list of lines into another (without file headers)
+pub fn diff(old: &[String], new: &[String]) -> Vec<String> {
+    // Lines that are shared at the start and end don't need comparing
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    let mut ops: Vec<(char, &str)> = old[..prefix].iter().map(|l| (' ', l.as_str())).collect();
+    if a.len().saturating_mul(b.len()) <= MAX_DIFF_WORK {
+        // Find the longest common subsequence of the remaining lines
+        let mut lcs = vec![vec![0_usize; b.len() + 1]; a.len() + 1];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if a[i] == b[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push((' ', &a[i]));
+                i += 1;
+                j += 1;
+            } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                ops.push(('-', &a[i]));
+                i += 1;
+            } else {
+                ops.push(('+', &b[j]));
+                j += 1;
+            }
+        }
+    } else {
+        // Too large to compare line by line, so treat the whole region as replaced
+        ops.extend(a.iter().map(|l| ('-', l.as_str())));
+        ops.extend(b.iter().map(|l| ('+', l.as_str())));
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|l| (' ', l.as_str())));
+    // Work out where each operation sits in the old and new lines
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for (kind, _) in &ops {
+        positions.push((o, n));
+        o += usize::from(*kind != '+');
+        n += usize::from(*kind != '-');
+    }
+    positions.push((o, n));
+    // Group the changes into hunks, with some unchanged lines around them
+    let changes: Vec<usize> = (0..ops.len()).filter(|i| ops[*i].0 != ' ').collect();
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for change in changes {
+        let start = change.saturating_sub(CONTEXT);
+        let end = (change + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut result = vec![];
+    for (start, end) in hunks {
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        result.push(format!(
+            "@@ -{},{} +{},{} @@",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        ));
+        result.extend(ops[start..end].iter().map(|(kind, line)| format!("{kind}{line}")));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+
+    fn lines(st: &str) -> Vec<String> {
+        st.lines().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn diff_hunks() {
+        assert!(diff(&lines("a\nb"), &lines("a\nb")).is_empty());
+        assert_eq!(
+            diff(&lines("a\nb\nc"), &lines("a\nx\nc\nd")),
+            lines("@@ -1,3 +1,4 @@\n a\n-b\n+x\n c\n+d")
+        );
+        // Changes far apart are given their own hunks
+        let old: Vec<String> = (0..20).map(|n| n.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "one".to_string();
+        new.remove(15);
+        assert_eq!(
+            diff(&old, &new),
+            lines(
+                "@@ -1,5 +1,5 @@\n 0\n-1\n+one\n 2\n 3\n 4\n\
+                 @@ -13,7 +13,6 @@\n 12\n 13\n 14\n-15\n 16\n 17\n 18"
+            )
+        );
+    }
+}
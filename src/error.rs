@@ -13,6 +13,11 @@ error_set! {
                 KError::ReadOnlyFile => "This file is read only and can't be saved or edited".to_string(),
                 KError::Rope(rerr) => format!("Backend had an issue processing text: {rerr}"),
                 KError::Io(ioerr) => format!("I/O Error: {ioerr}"),
+                KError::Unencodable { encoding } => format!("This document contains characters that can't be saved as {encoding}"),
+                KError::TempFile { reason } => format!("Couldn't save, failed to write a temporary file: {reason}"),
+                KError::Sync { reason } => format!("Couldn't save, failed to flush to the disk: {reason}"),
+                KError::Rename { reason } => format!("Couldn't save, failed to replace the original file: {reason}"),
+                KError::Backup { reason } => format!("Couldn't save, failed to back up the original file: {reason}"),
             }
         )]
         Kaolinite(KError),
@@ -28,6 +33,12 @@ error_set! {
         AlreadyOpen {
             file: String,
         },
+        #[display("File '{}' has unsaved changes, save them first", file)]
+        UnsavedChanges {
+            file: String,
+        },
+        #[display("File has changed since it was searched")]
+        ChangedSinceSearch,
         #[cfg(not(target_os = "windows"))]
         #[display("PTY error: {}", msg)]
         Pty {
@@ -58,6 +69,10 @@ error_set! {
         Clipboard {
             msg: String
         },
+        #[display("Language server error: {}", msg)]
+        LanguageServer {
+            msg: String
+        },
         #[display("Internal error: {}", msg)]
         Internal {
             msg: String
@@ -48,6 +48,22 @@ pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
                             Feedback::Warning(format!("Function '{task}' was not found"));
                     }
                 }
+                // Pick up any changes other programs have made to open files
+                if ged!(mut &editor).check_external_changes()? {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Keep language servers up to date, and show what they have found
+                ged!(mut &editor).sync_language_servers();
+                if ged!(mut &editor).poll_language_servers() {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Show the problems found by a task once it finishes
+                if ged!(mut &editor).poll_tasks() {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
                 // If a terminal dictates, force a rerender
                 #[cfg(not(target_os = "windows"))]
                 if was_term {
@@ -104,12 +120,8 @@ pub fn get_event(editor: &mut Editor) -> Option<CEvent> {
         // Take from macro man
         Some(ev)
     } else if let Ok(true) = poll(Duration::from_millis(50)) {
-        if let Ok(ev) = read() {
-            // Use standard crossterm event
-            Some(ev)
-        } else {
-            None
-        }
+        // Use standard crossterm event
+        read().ok()
     } else {
         None
     }
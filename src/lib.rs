@@ -3,8 +3,10 @@
 //! This library provides the core functionality of the Ox text editor.
 
 pub mod clipboard;
+pub mod lsp;
 pub mod pty_cross;
 pub mod pty_error;
+pub mod vt;
 #[cfg(target_os = "windows")]
 pub mod conpty_windows;
 
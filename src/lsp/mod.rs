@@ -0,0 +1,486 @@
+//! Language Server Protocol client
+//!
+//! Language servers are started as child processes and spoken to with JSON-RPC over their
+//! standard input and output. Messages from a server are read on a background thread and
+//! picked up with [`LanguageServer::poll`], so the editor doesn't wait on a server unless
+//! it asks to (with [`LanguageServer::wait_for`]).
+//!
+//! Open documents are kept in sync incrementally, from the events kaolinite records as
+//! documents are edited (see [`kaolinite::Document::follow_edits`]).
+
+pub mod protocol;
+
+use kaolinite::{Document, Loc};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub use protocol::{
+    CompletionItem, Diagnostic, Location, Position, PositionEncoding, Severity, TextEdit,
+};
+
+/// How long to wait for a server to shut down before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where a language server is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerState {
+    /// Waiting for the server to answer the initialize request
+    Starting,
+    /// Ready to use
+    Running,
+    /// The server has exited (or was stopped)
+    Stopped,
+}
+
+/// Something a language server has told the editor
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notice {
+    /// The problems found in a document (replacing any found before)
+    Diagnostics {
+        uri: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    /// A message the server would like the user to see
+    Message { severity: Severity, text: String },
+    /// The answer to a request that wasn't waited for
+    Response {
+        id: u64,
+        result: Result<Value, String>,
+    },
+    /// The server has exited
+    Stopped,
+}
+
+/// A document open on a server
+#[derive(Debug, Clone, Copy)]
+struct OpenDocument {
+    /// The version last sent to the server
+    version: i64,
+    /// The revision of the document that version was taken from
+    revision: usize,
+}
+
+/// A connection to a running language server
+pub struct LanguageServer {
+    /// The command the server was started with
+    pub command: String,
+    /// Where the server is in its life
+    pub state: ServerState,
+    /// What the server said it can do when it was initialised
+    pub capabilities: Value,
+    /// How the server counts the columns of positions
+    pub encoding: PositionEncoding,
+    child: Child,
+    stdin: ChildStdin,
+    incoming: Receiver<Value>,
+    next_id: u64,
+    /// The id of the initialize request (the server can't be used until it is answered)
+    init_id: u64,
+    /// Messages held back until the server has been initialised
+    held: Vec<Value>,
+    /// Notices picked up while waiting for a response, to be handed out on the next poll
+    backlog: Vec<Notice>,
+    /// The documents open on the server (by URI)
+    documents: HashMap<String, OpenDocument>,
+}
+
+impl LanguageServer {
+    /// Start a language server (the command can include arguments) for a project
+    /// # Errors
+    /// Returns an error if the server couldn't be started
+    pub fn start(command: &str, root: &Path) -> io::Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no command given"))?;
+        let mut child = Command::new(program)
+            .args(parts)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or_else(|| io::Error::other("no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| io::Error::other("no stdout"))?;
+        // Read messages on another thread, the channel disconnects when the server exits
+        let (sender, incoming) = channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut server = Self {
+            command: command.to_string(),
+            state: ServerState::Starting,
+            capabilities: Value::Null,
+            encoding: PositionEncoding::default(),
+            child,
+            stdin,
+            incoming,
+            next_id: 0,
+            init_id: 0,
+            held: vec![],
+            backlog: vec![],
+            documents: HashMap::new(),
+        };
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+        let root_uri = protocol::path_to_uri(&root.to_string_lossy());
+        let name = root.file_name().map_or(String::new(), |n| n.to_string_lossy().to_string());
+        server.init_id = server.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "clientInfo": { "name": "ox" },
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": name }],
+                "capabilities": client_capabilities(),
+            }),
+        );
+        Ok(server)
+    }
+
+    /// Send a request to the server, returning its id (to match up with the response)
+    pub fn request(&mut self, method: &str, params: Value) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut message = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        message["params"] = params;
+        self.send(message);
+        id
+    }
+
+    /// Send a notification to the server
+    pub fn notify(&mut self, method: &str, params: Value) {
+        let mut message = json!({ "jsonrpc": "2.0", "method": method });
+        message["params"] = params;
+        self.send(message);
+    }
+
+    /// Send a message, holding it back if the server hasn't been initialised yet
+    fn send(&mut self, message: Value) {
+        let is_init = message["method"] == "initialize";
+        match self.state {
+            ServerState::Starting if !is_init => self.held.push(message),
+            ServerState::Stopped => (),
+            _ => {
+                if write_message(&mut self.stdin, &message).is_err() {
+                    self.state = ServerState::Stopped;
+                }
+            }
+        }
+    }
+
+    /// Pick up everything the server has said since it was last polled
+    pub fn poll(&mut self) -> Vec<Notice> {
+        let mut notices = std::mem::take(&mut self.backlog);
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => notices.extend(self.handle(&message)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if self.state != ServerState::Stopped {
+                        self.state = ServerState::Stopped;
+                        notices.push(Notice::Stopped);
+                    }
+                    break;
+                }
+            }
+        }
+        notices
+    }
+
+    /// Wait for the response to a request (anything else the server says is kept for the next poll)
+    /// # Errors
+    /// Returns an error if the server responded with an error, stopped or took too long
+    pub fn wait_for(&mut self, id: u64, timeout: Duration) -> Result<Value, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let message = match self.incoming.recv_timeout(left) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err("The language server took too long to respond".to_string())
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    if self.state != ServerState::Stopped {
+                        self.state = ServerState::Stopped;
+                        self.backlog.push(Notice::Stopped);
+                    }
+                    return Err("The language server has stopped".to_string());
+                }
+            };
+            match self.handle(&message) {
+                Some(Notice::Response { id: got, result }) if got == id => return result,
+                Some(notice) => self.backlog.push(notice),
+                None => (),
+            }
+        }
+    }
+
+    /// Deal with a message from the server
+    fn handle(&mut self, message: &Value) -> Option<Notice> {
+        let method = message["method"].as_str();
+        let id = &message["id"];
+        match (method, id) {
+            // A request from the server, which must be answered
+            (Some(method), id) if !id.is_null() => {
+                let result = match method {
+                    // No settings are given, so servers use their defaults
+                    "workspace/configuration" => {
+                        let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; items])
+                    }
+                    "workspace/applyEdit" => json!({ "applied": false }),
+                    _ => Value::Null,
+                };
+                let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                if write_message(&mut self.stdin, &response).is_err() {
+                    self.state = ServerState::Stopped;
+                }
+                None
+            }
+            // A notification from the server
+            (Some("textDocument/publishDiagnostics"), _) => {
+                let params = &message["params"];
+                let diagnostics = params["diagnostics"]
+                    .as_array()
+                    .map_or(vec![], |d| d.iter().filter_map(Diagnostic::parse).collect());
+                Some(Notice::Diagnostics {
+                    uri: params["uri"].as_str()?.to_string(),
+                    diagnostics,
+                })
+            }
+            (Some("window/showMessage"), _) => {
+                let params = &message["params"];
+                let severity = match params["type"].as_u64() {
+                    Some(1) => Severity::Error,
+                    Some(2) => Severity::Warning,
+                    _ => Severity::Information,
+                };
+                Some(Notice::Message {
+                    severity,
+                    text: params["message"].as_str()?.to_string(),
+                })
+            }
+            (Some(_), _) => None,
+            // A response to one of our requests
+            (None, id) => {
+                let id = id.as_u64()?;
+                let result = match message.get("error") {
+                    Some(error) => Err(error["message"].as_str().unwrap_or("Unknown error").to_string()),
+                    None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+                };
+                if id == self.init_id && self.state == ServerState::Starting {
+                    self.initialised(result);
+                    return None;
+                }
+                Some(Notice::Response { id, result })
+            }
+        }
+    }
+
+    /// Finish starting up once the server has answered the initialize request
+    fn initialised(&mut self, result: Result<Value, String>) {
+        let Ok(result) = result else {
+            self.state = ServerState::Stopped;
+            self.backlog.push(Notice::Stopped);
+            return;
+        };
+        self.capabilities = result["capabilities"].clone();
+        self.encoding = PositionEncoding::from_capabilities(&self.capabilities);
+        self.state = ServerState::Running;
+        self.notify("initialized", json!({}));
+        for message in std::mem::take(&mut self.held) {
+            self.send(message);
+        }
+    }
+
+    /// Determine if a document is open on this server
+    #[must_use]
+    pub fn is_open(&self, uri: &str) -> bool {
+        self.documents.contains_key(uri)
+    }
+
+    /// The URIs of the documents open on this server
+    #[must_use]
+    pub fn open_documents(&self) -> Vec<String> {
+        self.documents.keys().cloned().collect()
+    }
+
+    /// Tell the server about a document that has been opened
+    pub fn open(&mut self, uri: &str, language_id: &str, doc: &mut Document) {
+        doc.follow_edits();
+        doc.take_edits();
+        let state = OpenDocument {
+            version: 0,
+            revision: doc.revision,
+        };
+        self.documents.insert(uri.to_string(), state);
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language_id,
+                    "version": state.version,
+                    "text": doc.file.to_string(),
+                }
+            }),
+        );
+    }
+
+    /// Tell the server about any changes made to a document since it was last synced.
+    /// The changes are sent incrementally where possible, otherwise the whole document is sent.
+    pub fn change(&mut self, uri: &str, doc: &mut Document) {
+        let Some(state) = self.documents.get_mut(uri) else {
+            return;
+        };
+        let edits = doc.take_edits();
+        if state.revision == doc.revision {
+            return;
+        }
+        // Every edit bumps the revision, so anything else means the rope was changed directly
+        let complete = doc.revision.wrapping_sub(state.revision) == edits.len();
+        let incremental = matches!(sync_kind(&self.capabilities), Some(2));
+        let changes = if complete && incremental {
+            protocol::content_changes(&edits, doc, self.encoding)
+        } else {
+            None
+        };
+        let changes = changes.unwrap_or_else(|| vec![json!({ "text": doc.file.to_string() })]);
+        state.version += 1;
+        state.revision = doc.revision;
+        let version = state.version;
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": changes,
+            }),
+        );
+    }
+
+    /// Tell the server a document has been saved
+    pub fn save(&mut self, uri: &str, doc: &Document) {
+        if self.is_open(uri) {
+            self.notify(
+                "textDocument/didSave",
+                json!({ "textDocument": { "uri": uri }, "text": doc.file.to_string() }),
+            );
+        }
+    }
+
+    /// Tell the server a document has been closed
+    pub fn close(&mut self, uri: &str) {
+        if self.documents.remove(uri).is_some() {
+            self.notify("textDocument/didClose", json!({ "textDocument": { "uri": uri } }));
+        }
+    }
+
+    /// Ask for a request to be carried out at a location in a document
+    pub fn request_at(&mut self, method: &str, uri: &str, doc: &Document, loc: &Loc, extra: Value) -> u64 {
+        let mut params = json!({
+            "textDocument": { "uri": uri },
+            "position": Position::from_loc(doc, loc, self.encoding).to_json(),
+        });
+        if let (Some(params), Value::Object(extra)) = (params.as_object_mut(), extra) {
+            params.extend(extra);
+        }
+        self.request(method, params)
+    }
+
+    /// Ask the server to shut down, killing it if it doesn't in time
+    pub fn stop(&mut self) {
+        if self.state == ServerState::Running {
+            let id = self.request("shutdown", Value::Null);
+            let _ = self.wait_for(id, SHUTDOWN_TIMEOUT);
+            self.notify("exit", Value::Null);
+        }
+        self.state = ServerState::Stopped;
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while matches!(self.child.try_wait(), Ok(None)) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        if matches!(self.child.try_wait(), Ok(None)) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+/// Find out how a server would like documents to be synced (1 for whole, 2 for incremental)
+fn sync_kind(capabilities: &Value) -> Option<u64> {
+    let sync = &capabilities["textDocumentSync"];
+    sync.as_u64().or_else(|| sync["change"].as_u64())
+}
+
+/// What the editor can do, as told to servers when they start
+fn client_capabilities() -> Value {
+    json!({
+        "general": { "positionEncodings": ["utf-32", "utf-16"] },
+        "textDocument": {
+            "synchronization": { "didSave": true },
+            "publishDiagnostics": {},
+            "hover": { "contentFormat": ["plaintext", "markdown"] },
+            "definition": { "linkSupport": true },
+            "references": {},
+            "rename": {},
+            "completion": { "completionItem": { "snippetSupport": false } },
+        },
+        "workspace": { "workspaceFolders": true, "configuration": true },
+        "window": { "showMessage": {} },
+    })
+}
+
+/// Write a message, with the header the protocol puts before each one
+/// # Errors
+/// Returns an error if the message couldn't be written
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// Read the next message (None once there are no more to read)
+/// # Errors
+/// Returns an error if the message couldn't be read or wasn't valid
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
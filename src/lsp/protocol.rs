@@ -0,0 +1,481 @@
+//! Conversions between the editor and the JSON structures of the Language Server Protocol
+//!
+//! Positions in the protocol count columns in UTF-16 code units unless the server agrees
+//! to count them in characters (UTF-32), whereas kaolinite counts them in characters.
+//! [`PositionEncoding`] converts between the two, given the text of the line.
+
+use kaolinite::event::Event;
+use kaolinite::{Document, Loc};
+use serde_json::{json, Value};
+
+/// How the columns of positions are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// In UTF-16 code units (what every server supports)
+    #[default]
+    Utf16,
+    /// In characters (matching kaolinite's character indices)
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Find the encoding a server chose from its capabilities
+    #[must_use]
+    pub fn from_capabilities(capabilities: &Value) -> Self {
+        match capabilities["positionEncoding"].as_str() {
+            Some("utf-32") => Self::Utf32,
+            _ => Self::Utf16,
+        }
+    }
+
+    /// Convert a character index on a line into a protocol column
+    #[must_use]
+    pub fn to_column(self, line: &str, x: usize) -> usize {
+        match self {
+            Self::Utf32 => x,
+            Self::Utf16 => line.chars().take(x).map(char::len_utf16).sum(),
+        }
+    }
+
+    /// Convert a protocol column on a line into a character index
+    #[must_use]
+    pub fn to_char_index(self, line: &str, column: usize) -> usize {
+        match self {
+            Self::Utf32 => column,
+            Self::Utf16 => {
+                let mut units = 0;
+                let mut x = 0;
+                for c in line.chars() {
+                    if units >= column {
+                        break;
+                    }
+                    units += c.len_utf16();
+                    x += 1;
+                }
+                x
+            }
+        }
+    }
+}
+
+/// A position in a document, as the protocol counts it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub line: usize,
+    pub character: usize,
+}
+
+impl Position {
+    /// Find the position of a location in a document
+    #[must_use]
+    pub fn from_loc(doc: &Document, loc: &Loc, encoding: PositionEncoding) -> Self {
+        let line = doc.line(loc.y).unwrap_or_default();
+        Self {
+            line: loc.y,
+            character: encoding.to_column(&line, loc.x),
+        }
+    }
+
+    /// Find the location (with a character index) of this position in a document
+    #[must_use]
+    pub fn to_loc(self, doc: &Document, encoding: PositionEncoding) -> Loc {
+        let line = doc.line(self.line).unwrap_or_default();
+        let x = encoding.to_char_index(&line, self.character);
+        Loc::at(x.min(line.chars().count()), self.line)
+    }
+
+    /// Read a position from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        Some(Self {
+            line: usize::try_from(value["line"].as_u64()?).ok()?,
+            character: usize::try_from(value["character"].as_u64()?).ok()?,
+        })
+    }
+
+    /// Write this position as JSON
+    #[must_use]
+    pub fn to_json(self) -> Value {
+        json!({ "line": self.line, "character": self.character })
+    }
+}
+
+/// A range between two positions in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    /// Read a range from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        Some(Self {
+            start: Position::parse(&value["start"])?,
+            end: Position::parse(&value["end"])?,
+        })
+    }
+
+    /// Write this range as JSON
+    #[must_use]
+    pub fn to_json(self) -> Value {
+        json!({ "start": self.start.to_json(), "end": self.end.to_json() })
+    }
+}
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    /// The name of this severity (as used by plug-ins)
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Information => "info",
+            Self::Hint => "hint",
+        }
+    }
+}
+
+/// A problem a server found in a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range,
+    pub severity: Severity,
+    pub message: String,
+    /// What found the problem (such as the compiler or a linter)
+    pub source: Option<String>,
+}
+
+impl Diagnostic {
+    /// Read a diagnostic from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        let severity = match value["severity"].as_u64() {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            // Servers that don't say are taken to mean errors
+            _ => Severity::Error,
+        };
+        Some(Self {
+            range: Range::parse(&value["range"])?,
+            severity,
+            message: value["message"].as_str()?.to_string(),
+            source: value["source"].as_str().map(ToString::to_string),
+        })
+    }
+}
+
+/// A range within a particular document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+impl Location {
+    /// Read a location (or a location link) from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        if let Some(uri) = value["targetUri"].as_str() {
+            let range = &value["targetSelectionRange"];
+            return Some(Self {
+                uri: uri.to_string(),
+                range: Range::parse(range).or_else(|| Range::parse(&value["targetRange"]))?,
+            });
+        }
+        Some(Self {
+            uri: value["uri"].as_str()?.to_string(),
+            range: Range::parse(&value["range"])?,
+        })
+    }
+}
+
+/// Read the locations given in response to a definition or references request
+#[must_use]
+pub fn parse_locations(value: &Value) -> Vec<Location> {
+    match value {
+        Value::Array(items) => items.iter().filter_map(Location::parse).collect(),
+        Value::Null => vec![],
+        single => Location::parse(single).into_iter().collect(),
+    }
+}
+
+/// Read the text of a hover response (None if there is nothing to show)
+#[must_use]
+pub fn parse_hover(value: &Value) -> Option<String> {
+    fn marked(value: &Value) -> Option<String> {
+        match value {
+            Value::String(text) => Some(text.clone()),
+            Value::Object(_) => value["value"].as_str().map(ToString::to_string),
+            _ => None,
+        }
+    }
+    let contents = &value["contents"];
+    let text = match contents {
+        Value::Array(items) => items
+            .iter()
+            .filter_map(marked)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => marked(contents)?,
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// A replacement of a range of a document with some new text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    /// Read a text edit from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        // Insert and replace edits are given with two ranges, the insert range is used
+        let range = Range::parse(&value["range"]).or_else(|| Range::parse(&value["insert"]))?;
+        Some(Self {
+            range,
+            new_text: value["newText"].as_str()?.to_string(),
+        })
+    }
+}
+
+/// Read the edits to make to each document from a workspace edit (such as from a rename)
+#[must_use]
+pub fn parse_workspace_edit(value: &Value) -> Vec<(String, Vec<TextEdit>)> {
+    let mut result = vec![];
+    if let Some(changes) = value["documentChanges"].as_array() {
+        for change in changes {
+            // Creating, renaming and deleting files aren't supported
+            let Some(uri) = change["textDocument"]["uri"].as_str() else {
+                continue;
+            };
+            let edits = change["edits"].as_array().map_or(vec![], |edits| {
+                edits.iter().filter_map(TextEdit::parse).collect()
+            });
+            result.push((uri.to_string(), edits));
+        }
+    } else if let Some(changes) = value["changes"].as_object() {
+        for (uri, edits) in changes {
+            let edits = edits.as_array().map_or(vec![], |edits| {
+                edits.iter().filter_map(TextEdit::parse).collect()
+            });
+            result.push((uri.clone(), edits));
+        }
+    }
+    result
+}
+
+/// A suggestion of what could be typed at the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionItem {
+    /// What to show in the list of suggestions
+    pub label: String,
+    /// Extra information about the suggestion (such as its type)
+    pub detail: Option<String>,
+    /// The kind of suggestion (such as a function or a variable), as a protocol number
+    pub kind: Option<u64>,
+    /// The text to insert
+    pub insert_text: String,
+    /// The exact edit to make, when the server gives one
+    pub edit: Option<TextEdit>,
+    /// What to sort the suggestion by
+    pub sort_text: String,
+}
+
+impl CompletionItem {
+    /// Read a completion item from JSON
+    #[must_use]
+    pub fn parse(value: &Value) -> Option<Self> {
+        let label = value["label"].as_str()?.to_string();
+        let edit = TextEdit::parse(&value["textEdit"]);
+        let insert_text = edit.as_ref().map_or_else(
+            || value["insertText"].as_str().unwrap_or(&label).to_string(),
+            |e| e.new_text.clone(),
+        );
+        Some(Self {
+            detail: value["detail"].as_str().map(ToString::to_string),
+            kind: value["kind"].as_u64(),
+            insert_text,
+            edit,
+            sort_text: value["sortText"].as_str().unwrap_or(&label).to_string(),
+            label,
+        })
+    }
+}
+
+/// Read the suggestions given in response to a completion request (in the order to show them)
+#[must_use]
+pub fn parse_completions(value: &Value) -> Vec<CompletionItem> {
+    let items = match value {
+        Value::Array(items) => items,
+        _ => match value["items"].as_array() {
+            Some(items) => items,
+            None => return vec![],
+        },
+    };
+    let mut result: Vec<CompletionItem> = items.iter().filter_map(CompletionItem::parse).collect();
+    result.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+    result
+}
+
+/// Describe the edits made to a document as incremental content changes.
+/// None is given if they can't be described like this (and the whole document should be sent).
+#[must_use]
+pub fn content_changes(edits: &[Event], doc: &Document, encoding: PositionEncoding) -> Option<Vec<Value>> {
+    // Character indices and UTF-16 columns are only the same without characters outside the BMP
+    if encoding == PositionEncoding::Utf16 {
+        let wide = |st: &str| st.chars().any(|c| c.len_utf16() > 1);
+        let wide_edit = edits.iter().any(|e| match e {
+            Event::Insert(_, st) | Event::Delete(_, st) | Event::InsertLine(_, st) | Event::DeleteLine(_, st) => wide(st),
+            _ => false,
+        });
+        if wide_edit || doc.file.len_utf16_cu() != doc.file.len_chars() {
+            return None;
+        }
+    }
+    let change = |start: Position, end: Position, text: &str| {
+        json!({ "range": Range { start, end }.to_json(), "text": text })
+    };
+    let at = |x: usize, y: usize| Position { line: y, character: x };
+    edits
+        .iter()
+        .map(|edit| match edit {
+            Event::Insert(loc, st) => Some(change(at(loc.x, loc.y), at(loc.x, loc.y), st)),
+            Event::Delete(loc, st) => {
+                let breaks = line_breaks(st);
+                let end = if breaks == 0 {
+                    at(loc.x + st.chars().count(), loc.y)
+                } else {
+                    let last = st.rsplit(['\n', '\r']).next().unwrap_or_default();
+                    at(last.chars().count(), loc.y + breaks)
+                };
+                Some(change(at(loc.x, loc.y), end, ""))
+            }
+            Event::InsertLine(y, st) => {
+                let text = format!("{st}{}", doc.info.line_ending.as_str());
+                Some(change(at(0, *y), at(0, *y), &text))
+            }
+            Event::DeleteLine(y, _) => Some(change(at(0, *y), at(0, y + 1), "")),
+            // These are made up of the events above, so are never recorded themselves
+            Event::SplitDown(_) | Event::SpliceUp(_) => None,
+        })
+        .collect()
+}
+
+/// Count the line breaks in some text (a carriage return and line feed together count as one)
+fn line_breaks(st: &str) -> usize {
+    let mut breaks = 0;
+    let mut chars = st.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => breaks += 1,
+            '\r' if chars.peek() != Some(&'\n') => breaks += 1,
+            _ => (),
+        }
+    }
+    breaks
+}
+
+/// Turn a path to a file into a `file://` URI
+#[must_use]
+pub fn path_to_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut uri = String::from("file://");
+    // Windows paths start with a drive letter rather than a slash
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// Turn a `file://` URI back into a path (None for URIs that aren't files)
+#[must_use]
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    let encoded = uri.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut iter = encoded.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex: String = iter.by_ref().take(2).map(char::from).collect();
+            bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // Drive letters on Windows don't need the leading slash
+    let is_drive = path.len() > 2 && path.as_bytes()[2] == b':' && path.starts_with('/');
+    Some(if is_drive { path[1..].to_string() } else { path })
+}
+
+/// The language identifier the protocol uses for a file type
+#[must_use]
+pub fn language_id(file_type: &str) -> String {
+    match file_type {
+        "C++" => "cpp".to_string(),
+        "C#" => "csharp".to_string(),
+        "Shell" => "shellscript".to_string(),
+        other => other.to_lowercase().replace(' ', ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_encodings() {
+        let line = "a😀b";
+        assert_eq!(PositionEncoding::Utf16.to_column(line, 2), 3);
+        assert_eq!(PositionEncoding::Utf16.to_char_index(line, 3), 2);
+        assert_eq!(PositionEncoding::Utf32.to_column(line, 2), 2);
+        assert_eq!(PositionEncoding::Utf16.to_char_index(line, 10), 3);
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(path_to_uri("/home/me/my file.rs"), "file:///home/me/my%20file.rs");
+        assert_eq!(uri_to_path("file:///home/me/my%20file.rs").unwrap(), "/home/me/my file.rs");
+        assert_eq!(path_to_uri("C:\\code\\main.rs"), "file:///C:/code/main.rs");
+        assert_eq!(uri_to_path("file:///C:/code/main.rs").unwrap(), "C:/code/main.rs");
+        assert_eq!(uri_to_path("https://example.com"), None);
+    }
+
+    #[test]
+    fn responses() {
+        let hover = json!({ "contents": { "kind": "markdown", "value": "fn main()" } });
+        assert_eq!(parse_hover(&hover).unwrap(), "fn main()");
+        assert_eq!(parse_hover(&json!({ "contents": [] })), None);
+        let range = json!({ "start": { "line": 1, "character": 2 }, "end": { "line": 1, "character": 5 } });
+        let locations = parse_locations(&json!([{ "uri": "file:///a.rs", "range": range }]));
+        assert_eq!(locations[0].range.start, Position { line: 1, character: 2 });
+        let edit = json!({ "changes": { "file:///a.rs": [{ "range": range, "newText": "x" }] } });
+        assert_eq!(parse_workspace_edit(&edit)[0].1[0].new_text, "x");
+        let items = json!({ "isIncomplete": false, "items": [{ "label": "b" }, { "label": "a", "insertText": "a()" }] });
+        let items = parse_completions(&items);
+        assert_eq!(items[0].insert_text, "a()");
+        assert_eq!(items[1].label, "b");
+    }
+}
@@ -1,21 +1,34 @@
 #![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::format_push_string, clippy::assigning_clones, clippy::match_same_arms)]
 
 mod cli;
+// Modules below marked with dead_code are shared with the library crate,
+// which exposes parts of their API that the binary doesn't use itself
+#[allow(dead_code)]
 mod clipboard;
 mod config;
+#[allow(dead_code)]
 mod dirs;
 mod editor;
 mod error;
 mod events;
+#[allow(dead_code)]
+mod lsp;
 #[cfg(not(target_os = "windows"))]
+#[allow(dead_code)]
 mod pty;
 mod pty_error;
 #[cfg(target_os = "windows")]
 mod conpty_windows;
+#[allow(dead_code)]
 mod pty_cross;
+#[allow(dead_code)]
 mod regex_cache;
+#[allow(dead_code)]
 mod terminal;
 mod ui;
+#[allow(dead_code)]
+mod vt;
 
 use cli::CommandLineInterface;
 use config::{
@@ -23,9 +36,14 @@ use config::{
     PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN,
 };
 use crossterm::event::{Event as CEvent, KeyEvent, KeyEventKind};
-use editor::{allowed_by_multi_cursor, handle_multiple_cursors, Editor, FileTypes};
+use editor::{
+    allowed_by_multi_cursor, auto_session_path, handle_multiple_cursors, read_session,
+    run_palette_choice, session_path, split_arguments, update_completion, Editor, EntryKind,
+    FileTypes, PaletteChoice,
+};
 use error::{OxError, Result};
 use events::wait_for_event;
+use kaolinite::encoding::Encoding;
 use kaolinite::event::{Error as KError, Event};
 use kaolinite::searching::Searcher;
 use kaolinite::utils::{file_or_dir, get_cwd};
@@ -55,9 +73,9 @@ const EXIT_STARTUP_ERROR: i32 = 3;
 
 /// Report an error to stderr and exit with appropriate code
 fn report_error_and_exit(context: &str, error: impl std::fmt::Debug, exit_code: i32) -> ! {
-    eprintln!("Error: {}", context);
+    eprintln!("Error: {context}");
     if std::env::var("OX_DEBUG").is_ok() {
-        eprintln!("Debug details: {:?}", error);
+        eprintln!("Debug details: {error:?}");
     } else {
         eprintln!("Run with OX_DEBUG=1 for more details");
     }
@@ -115,7 +133,7 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
             eprintln!("  - Missing dependencies");
             eprintln!("  - Corrupted configuration files");
             if std::env::var("OX_DEBUG").is_ok() {
-                eprintln!("\nDebug details: {:?}", error);
+                eprintln!("\nDebug details: {error:?}");
             } else {
                 eprintln!("\nRun with OX_DEBUG=1 for detailed error information");
             }
@@ -143,7 +161,7 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
     if let Some(err) = result {
         // Handle error if available
         handle_lua_error("configuration", Err(err), &mut ged!(mut &editor).feedback);
-    };
+    }
 
     // Run plug-ins
     handle_lua_error(
@@ -171,12 +189,21 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         .unwrap()
         .file_types = file_types;
     // Open files user has asked to open
+    let encoding = cli.encoding.as_ref().and_then(|name| {
+        let encoding = Encoding::from_name(name);
+        if encoding.is_none() {
+            fatal_error(&format!("'{name}' is not a supported encoding"));
+        }
+        encoding
+    });
     let cwd = get_cwd().unwrap_or(".".to_string());
+    // Files are decoded with the encoding given (if any) as they are opened
+    ged!(mut &editor).encoding = encoding;
     for (c, file) in cli.to_open.iter().enumerate() {
         // Reset cwd
         let _ = std::env::set_current_dir(&cwd);
         // Open the file
-        let result = ged!(mut &editor).open_or_new(file.to_string());
+        let result = ged!(mut &editor).open_or_new(file.clone());
         handle_file_opening(&editor, result, file);
         // Set read only if applicable
         if cli.flags.read_only {
@@ -190,16 +217,19 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
                 .get_name(file_type)
                 .unwrap_or_default();
             let mut highlighter = file_type.get_highlighter(&ged!(&editor).config, tab_width);
-            highlighter.run(&ged!(mut &editor).get_doc(c).lines);
+            highlighter.run(&ged!(mut &editor).get_doc(c).lines_to_view());
+            let wrap = config!(ged!(&editor).config, document).soft_wrap_for(Some(&file_type));
             let mut editor = ged!(mut &editor);
             let current_ptr = editor.ptr.clone();
             let file = &mut editor.files.get_atom_mut(current_ptr).unwrap().0[c];
+            file.doc.set_wrap(wrap);
             file.highlighter = highlighter;
             file.file_type = Some(file_type);
         }
         // Move the pointer to the file we just created
         ged!(mut &editor).next();
     }
+    ged!(mut &editor).encoding = None;
     // Reset the pointer back to the first document
     let current_ptr = ged!(mut &editor).ptr.clone();
     ged!(mut &editor).files.move_to(current_ptr, 0);
@@ -217,8 +247,7 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
             .ok_or(OxError::DocumentNotFound { index: this_doc })?
             .doc;
         doc.exe(Event::Insert(Loc { x: 0, y: 0 }, stdin))?;
-        doc.load_to(doc.size.h);
-        let lines = doc.lines.clone();
+        let lines = doc.lines_to_view();
         if let Some(hl) = holder.get_highlighter(this_doc) {
             hl.run(&lines);
         }
@@ -227,6 +256,26 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         }
     }
 
+    // Restore the session asked for (or the automatic session of this directory)
+    let session_file = match &cli.session {
+        Some(name) => session_path(name),
+        None if config!(ged!(&editor).config, document).auto_session => auto_session_path(&cwd),
+        None => None,
+    };
+    if let (Some(path), true) = (session_file, cli.to_open.is_empty() && !cli.flags.stdin) {
+        if path.exists() {
+            let result = read_session(&path).and_then(|s| ged!(mut &editor).restore_session(&s));
+            if let Err(err) = result {
+                ged!(mut &editor).feedback =
+                    Feedback::Error(format!("Failed to restore session: {err}"));
+            }
+        }
+        ged!(mut &editor).session_file = Some(path);
+    } else if cli.session.is_some() {
+        ged!(mut &editor).feedback =
+            Feedback::Warning("Sessions aren't restored when files are opened".to_string());
+    }
+
     // Create a blank document if none are opened
     ged!(mut &editor).new_if_empty()?;
 
@@ -249,6 +298,16 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         // Wait for an event
         let event = wait_for_event(&editor, &lua)?;
 
+        // Let the completion popup have the keys it uses
+        if let CEvent::Key(key) = event {
+            let used = ged!(mut &editor).completion_key(key.modifiers, key.code);
+            match used {
+                Ok(true) => continue,
+                Ok(false) => (),
+                Err(err) => ged!(mut &editor).feedback = Feedback::Error(err.to_string()),
+            }
+        }
+
         // Handle the event
         let original_loc = ged!(&editor)
             .try_doc()
@@ -260,12 +319,15 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
         if let CEvent::Key(_) = event {
             let has_multicursors = !ged!(&editor)
                 .try_doc()
-                .map_or(true, |doc| doc.secondary_cursors.is_empty());
+                .is_none_or(|doc| doc.secondary_cursors.is_empty());
             if ged!(&editor).active && allowed_by_multi_cursor(&event) && has_multicursors {
                 handle_multiple_cursors(&editor, &event, &lua, &original_loc)?;
             }
         }
 
+        // Open, filter or close the completion popup
+        update_completion(&editor, &event, &lua)?;
+
         ged!(mut &editor).update_highlighter();
 
         // Check for any commands to run
@@ -274,12 +336,27 @@ fn run(cli: &CommandLineInterface) -> Result<()> {
             run_editor_command(&editor, &command, &lua);
         }
         ged!(mut &editor).command = None;
+
+        // Run anything chosen from the command palette
+        let choice = ged!(mut &editor).palette_choice.take();
+        if let Some(choice) = choice {
+            let result = run_palette_choice(&lua, &choice);
+            handle_lua_error(&choice.name, result, &mut ged!(mut &editor).feedback);
+        }
+
+        // Keep hold of the session while documents are being closed one after another
+        if let CEvent::Key(_) = event {
+            ged!(mut &editor).settle_session();
+        }
     }
 
     // Run any plugin cleanup operations
     let result = lua.load(run_key("exit")).exec();
     handle_lua_error("exit", result, &mut ged!(mut &editor).feedback);
 
+    // Give language servers the chance to shut down cleanly
+    ged!(mut &editor).lsp.stop_all();
+
     ged!(mut &editor).terminal.end()?;
     Ok(())
 }
@@ -291,8 +368,11 @@ fn handle_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
         ged!(mut &editor).feedback = Feedback::None;
     }
 
+    // Keys pressed in a terminal go to the program running in it, rather than key bindings
+    let to_terminal = matches!(event, CEvent::Key(_)) && ged!(&editor).terminal_focused();
+
     // Handle plug-in before key press mappings
-    if let CEvent::Key(key) = event {
+    if let (CEvent::Key(key), false) = (event, to_terminal) {
         let key_str = key_to_string(key.modifiers, key.code);
         let code = run_key_before(&key_str);
         let result = lua.load(&code).exec();
@@ -340,7 +420,7 @@ fn handle_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
     }
 
     // Handle plug-in after key press mappings (if no errors occured)
-    if let CEvent::Key(key) = event {
+    if let (CEvent::Key(key), false) = (event, to_terminal) {
         let key_str = key_to_string(key.modifiers, key.code);
         let code = run_key(&key_str);
         let result = lua.load(&code).exec();
@@ -395,7 +475,7 @@ fn handle_lua_error(key_str: &str, error: RResult<(), mlua::Error>, feedback: &m
                 *feedback = Feedback::Error(format!("The command '{key_str}' is not defined"));
             } else {
                 // Some other runtime error
-                *feedback = Feedback::Error(msg.to_string());
+                *feedback = Feedback::Error(msg.clone());
             }
         }
         // Handle a syntax error
@@ -452,17 +532,19 @@ fn handle_file_opening(editor: &AnyUserData, result: Result<()>, name: &str) {
 
 /// Run a command in the editor
 fn run_editor_command(editor: &AnyUserData, cmd: &str, lua: &Lua) {
-    let cmd = cmd.replace('\'', "\\'").to_string();
-    if let [subcmd, arguments @ ..] = cmd.split(' ').collect::<Vec<&str>>().as_slice() {
-        let arguments = arguments.join("', '");
-        let code =
-            format!("(commands['{subcmd}'] or error('command not found'))({{'{arguments}'}})");
-        handle_lua_error(
-            subcmd,
-            lua.load(code).exec(),
-            &mut ged!(mut &editor).feedback,
-        );
+    let mut arguments = split_arguments(cmd);
+    if arguments.is_empty() {
+        return;
     }
+    let name = arguments.remove(0);
+    ged!(mut &editor).remember_command(&name);
+    let choice = PaletteChoice {
+        kind: EntryKind::Command,
+        name,
+        arguments,
+    };
+    let result = run_palette_choice(lua, &choice);
+    handle_lua_error(&choice.name, result, &mut ged!(mut &editor).feedback);
 }
 
 #[cfg(test)]
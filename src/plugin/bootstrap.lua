@@ -348,6 +348,24 @@ runner = {
     },
 }
 
+-- Tasks that run in the background (with editor:run_task), listing the problems in their output
+-- Each error format is an expression with file, line and (optionally) col, severity and message groups
+-- They are tried before the built-in one, which finds lines like `file:line:col: error: message`
+tasks = {
+    ["build"] = {
+        command = "cargo build --message-format=short",
+    },
+    ["test"] = {
+        command = "cargo test --message-format=short",
+        error_format = {
+            [[^thread '(?P<message>.*)' panicked at (?P<file>[^:]+):(?P<line>\d+):(?P<col>\d+):$]],
+        },
+    },
+    ["lint"] = {
+        command = "cargo clippy --message-format=short",
+    },
+}
+
 -- Add types for built-in file type detection
 -- Colours are in the format of a string of:
 file_types = {
@@ -441,6 +459,7 @@ file_types = {
         extensions = {"c"},
         modelines = {},
         color = "lightblue",
+        lsp = "clangd",
     },
     ["CMake"] = {
         icon = " ",
@@ -644,6 +663,7 @@ file_types = {
         extensions = {"go"},
         modelines = {},
         color = "lightblue",
+        lsp = "gopls",
     },
     ["Groovy"] = {
         icon = " ",
@@ -742,6 +762,7 @@ file_types = {
         extensions = {"js"},
         modelines = {"#!\\s*/usr/bin/(env )?node"},
         color = "yellow",
+        lsp = "typescript-language-server --stdio",
     },
     ["Julia"] = {
         icon = " ",
@@ -924,6 +945,7 @@ file_types = {
         extensions = {"py", "pyw"},
         modelines = {"#!\\s*/usr/bin/(env )?python3?"},
         color = "lightblue",
+        lsp = "pylsp",
     },
     ["Cython"] = {
         icon = " ",
@@ -966,6 +988,7 @@ file_types = {
         extensions = {"rs"},
         modelines = {"#!\\s*/usr/bin/(env )?rust"},
         color = "orange",
+        lsp = "rust-analyzer",
     },
     ["Shell"] = {
         icon = " ",
@@ -1053,6 +1076,7 @@ file_types = {
         extensions = {"ts", "tsx"},
         modelines = {},
         color = "darkblue",
+        lsp = "typescript-language-server --stdio",
     },
     ["Plain Text"] = {
         icon = " ",
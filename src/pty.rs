@@ -92,7 +92,7 @@ impl Pty {
             let mut pty_guard = pty.lock()
                 .unwrap_or_else(recover_lock_poisoned);
             pty_guard.process.set_echo(false, None)
-                .map_err(|e| PtyError::InitializationFailed(format!("Failed to set PTY echo mode: {}", e)))?;
+                .map_err(|e| PtyError::InitializationFailed(format!("Failed to set PTY echo mode: {e}")))?;
         }
         
         std::thread::sleep(Duration::from_millis(100));
@@ -101,7 +101,7 @@ impl Pty {
             let mut pty_guard = pty.lock()
                 .unwrap_or_else(recover_lock_poisoned);
             pty_guard.run_command("")
-                .map_err(|e| PtyError::InitializationFailed(format!("Failed to run initial command: {:?}", e)))?;
+                .map_err(|e| PtyError::InitializationFailed(format!("Failed to run initial command: {e:?}")))?;
         }
         
         // Spawn reader thread with proper lifecycle management
@@ -136,12 +136,9 @@ impl Pty {
                         Err(std::sync::TryLockError::Poisoned(err)) => {
                             // Recover from poisoned lock
                             let mut pty = recover_lock_poisoned(err);
-                            match pty.catch_up() {
-                                Ok(true) => {
-                                    force_rerender_clone.store(true, Ordering::Relaxed);
-                                    let _ = update_sender.send(true);
-                                }
-                                _ => {}
+                            if let Ok(true) = pty.catch_up() {
+                                force_rerender_clone.store(true, Ordering::Relaxed);
+                                let _ = update_sender.send(true);
                             }
                         }
                         Err(std::sync::TryLockError::WouldBlock) => {
@@ -150,7 +147,7 @@ impl Pty {
                     }
                 }
             })
-            .map_err(|e| PtyError::InitializationFailed(format!("Failed to spawn PTY reader thread: {}", e)))?;
+            .map_err(|e| PtyError::InitializationFailed(format!("Failed to spawn PTY reader thread: {e}")))?;
         
         // Store the thread handle
         pty.lock()
@@ -162,10 +159,10 @@ impl Pty {
 
     pub fn run_command(&mut self, cmd: &str) -> PtyResult<()> {
         let mut stream = self.process.get_raw_handle()
-            .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {}", e)))?;
+            .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
         // Write the command
         write!(stream, "{cmd}")
-            .map_err(|e| PtyError::CommunicationError(format!("Failed to write command to PTY: {}", e)))?;
+            .map_err(|e| PtyError::CommunicationError(format!("Failed to write command to PTY: {e}")))?;
         std::thread::sleep(Duration::from_millis(100));
         if self.shell.manual_input_echo() {
             // println!("Adding (pre-cmd) {:?}", cmd);
@@ -175,7 +172,7 @@ impl Pty {
         let mut reader = BufReader::new(stream);
         let mut buf = [0u8; 10240];
         let bytes_read = reader.read(&mut buf)
-            .map_err(|e| PtyError::CommunicationError(format!("Failed to read PTY output: {}", e)))?;
+            .map_err(|e| PtyError::CommunicationError(format!("Failed to read PTY output: {e}")))?;
         let mut output = String::from_utf8_lossy(&buf[..bytes_read]).to_string();
         // Add on the output
         if self.shell.inserts_extra_newline() {
@@ -199,7 +196,7 @@ impl Pty {
         self.input.push(c);
         if c == '\n' {
             // Return key pressed, send the input
-            self.run_command(&self.input.to_string())?;
+            self.run_command(&self.input.clone())?;
             self.input.clear();
         }
         Ok(())
@@ -218,25 +215,25 @@ impl Pty {
 
     pub fn catch_up(&mut self) -> PtyResult<bool> {
         let stream = self.process.get_raw_handle()
-            .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {}", e)))?;
+            .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
         let raw_fd = stream.as_raw_fd();
         
         let flags = fcntl(raw_fd, FcntlArg::F_GETFL)
-            .map_err(|e| PtyError::PlatformError(format!("Failed to get file flags: {}", e)))?;
+            .map_err(|e| PtyError::PlatformError(format!("Failed to get file flags: {e}")))?;
         fcntl(
             raw_fd,
             FcntlArg::F_SETFL(OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK),
         )
-        .map_err(|e| PtyError::PlatformError(format!("Failed to set non-blocking mode: {}", e)))?;
+        .map_err(|e| PtyError::PlatformError(format!("Failed to set non-blocking mode: {e}")))?;
         
         let mut source = SourceFd(&raw_fd);
         // Set up mio Poll and register the raw_fd
         let mut poll = Poll::new()
-            .map_err(|e| PtyError::PlatformError(format!("Failed to create poll instance: {}", e)))?;
+            .map_err(|e| PtyError::PlatformError(format!("Failed to create poll instance: {e}")))?;
         let mut events = Events::with_capacity(128);
         poll.registry()
             .register(&mut source, Token(0), Interest::READABLE)
-            .map_err(|e| PtyError::PlatformError(format!("Failed to register poll interest: {}", e)))?;
+            .map_err(|e| PtyError::PlatformError(format!("Failed to register poll interest: {e}")))?;
             
         match poll.poll(&mut events, Some(Duration::from_millis(100))) {
             Ok(()) => {
@@ -244,7 +241,7 @@ impl Pty {
                 let mut reader = BufReader::new(stream);
                 let mut buf = [0u8; 10240];
                 let bytes_read = reader.read(&mut buf)
-                    .map_err(|e| PtyError::CommunicationError(format!("Failed to read from PTY: {}", e)))?;
+                    .map_err(|e| PtyError::CommunicationError(format!("Failed to read from PTY: {e}")))?;
 
                 // Process the read data
                 let mut output = String::from_utf8_lossy(&buf[..bytes_read]).to_string();
@@ -276,6 +273,7 @@ impl Pty {
     }
 }
 
+#[allow(clippy::missing_fields_in_debug)]
 impl std::fmt::Debug for Pty {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Pty")
@@ -2,18 +2,18 @@
 //! 
 //! This module provides a unified interface for pseudo-terminal (PTY) operations
 //! across different platforms. On Unix-like systems, it uses the traditional PTY
-//! interface via ptyprocess. On Windows, it uses the ConPTY API through the
+//! interface via ptyprocess. On Windows, it uses the `ConPTY` API through the
 //! portable-pty crate.
 //! 
 //! # Windows Terminal Support
 //! 
 //! The Windows implementation supports various terminal emulators:
-//! - **Windows Terminal**: Full ConPTY support with all features
-//! - **PowerShell/PowerShell Core**: Native ConPTY integration
+//! - **Windows Terminal**: Full `ConPTY` support with all features
+//! - **PowerShell/PowerShell Core**: Native `ConPTY` integration
 //! - **Command Prompt (cmd.exe)**: Basic PTY functionality
-//! - **ConEmu/Cmder**: ConPTY support on Windows 10+
-//! - **MinTTY (Git Bash/MSYS2)**: May require special handling
-//! - **VS Code Terminal**: Full ConPTY support
+//! - **ConEmu/Cmder**: `ConPTY` support on Windows 10+
+//! - **`MinTTY` (Git Bash/MSYS2)**: May require special handling
+//! - **VS Code Terminal**: Full `ConPTY` support
 //! 
 //! # Shell Detection
 //! 
@@ -36,6 +36,8 @@ use std::thread::JoinHandle;
 use std::time::Duration;
 
 use crate::pty_error::{PtyError, PtyResult, PtyErrorContext, recover_lock_poisoned};
+use crate::vt::{encode_key, encode_paste, Screen};
+use crossterm::event::{KeyCode, KeyModifiers};
 
 // Module implementations are defined inline below
 
@@ -156,6 +158,15 @@ impl Shell {
     }
 }
 
+/// Signals that can be sent to the program running in a terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Interrupt the program (ctrl + c)
+    Interrupt,
+    /// Suspend the program (ctrl + z)
+    Suspend,
+}
+
 impl Default for Shell {
     fn default() -> Self {
         Self::detect()
@@ -195,8 +206,8 @@ impl From<String> for Shell {
 }
 
 impl IntoLua for Shell {
-    fn into_lua(self, _lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
-        Ok(mlua::Value::String(_lua.create_string(self.command())?))
+    fn into_lua(self, lua: &mlua::Lua) -> mlua::Result<mlua::Value> {
+        Ok(mlua::Value::String(lua.create_string(self.command())?))
     }
 }
 
@@ -216,9 +227,11 @@ impl FromLua for Shell {
 #[derive(Debug)]
 pub struct Pty {
     inner: platform::PtyImpl,
-    pub output: String,
-    pub input: String,
     pub shell: Shell,
+    /// The emulated terminal screen that output is drawn onto
+    pub screen: Screen,
+    /// Bytes of a UTF-8 character that was split between reads
+    pending: Vec<u8>,
     force_rerender: Arc<AtomicBool>,
     shutdown_flag: Arc<AtomicBool>,
     reader_thread: Option<JoinHandle<()>>,
@@ -228,16 +241,16 @@ pub struct Pty {
 impl Pty {
     pub fn new(shell: Shell) -> PtyResult<Arc<Mutex<Self>>> {
         let inner = platform::PtyImpl::new(shell)
-            .map_err(|e| PtyError::InitializationFailed(format!("Failed to create PTY: {}", e)))?;
+            .map_err(|e| PtyError::InitializationFailed(format!("Failed to create PTY: {e}")))?;
         let shutdown_flag = Arc::new(AtomicBool::new(false));
         let force_rerender = Arc::new(AtomicBool::new(false));
         let (update_sender, update_receiver) = channel::<bool>();
         
         let pty = Arc::new(Mutex::new(Self {
             inner,
-            output: String::new(),
-            input: String::new(),
             shell,
+            screen: Screen::new(24, 80),
+            pending: vec![],
             force_rerender: Arc::clone(&force_rerender),
             shutdown_flag: Arc::clone(&shutdown_flag),
             reader_thread: None,
@@ -249,7 +262,7 @@ impl Pty {
             let mut pty_guard = pty.lock()
                 .unwrap_or_else(recover_lock_poisoned);
             pty_guard.initialize()
-                .map_err(|e| PtyError::InitializationFailed(format!("Failed to initialize PTY: {}", e)))?;
+                .map_err(|e| PtyError::InitializationFailed(format!("Failed to initialize PTY: {e}")))?;
         }
         
         // Spawn reader thread with proper lifecycle management
@@ -284,12 +297,9 @@ impl Pty {
                         Err(std::sync::TryLockError::Poisoned(err)) => {
                             // Recover from poisoned lock
                             let mut pty = recover_lock_poisoned(err);
-                            match pty.catch_up() {
-                                Ok(true) => {
-                                    force_rerender_clone.store(true, Ordering::Relaxed);
-                                    let _ = update_sender.send(true);
-                                }
-                                _ => {}
+                            if let Ok(true) = pty.catch_up() {
+                                force_rerender_clone.store(true, Ordering::Relaxed);
+                                let _ = update_sender.send(true);
                             }
                         }
                         Err(std::sync::TryLockError::WouldBlock) => {
@@ -298,7 +308,7 @@ impl Pty {
                     }
                 }
             })
-            .map_err(|e| PtyError::InitializationFailed(format!("Failed to spawn PTY reader thread: {}", e)))?;
+            .map_err(|e| PtyError::InitializationFailed(format!("Failed to spawn PTY reader thread: {e}")))?;
         
         // Store the thread handle
         pty.lock()
@@ -309,7 +319,8 @@ impl Pty {
     }
 
     fn initialize(&mut self) -> PtyResult<()> {
-        self.inner.set_echo(false)
+        // Keystrokes are passed straight through, so the terminal echoes them like any other
+        self.inner.set_echo(true)
             .context("Failed to set echo mode")?;
         std::thread::sleep(Duration::from_millis(100));
         self.run_command("")
@@ -320,64 +331,88 @@ impl Pty {
     pub fn run_command(&mut self, cmd: &str) -> PtyResult<()> {
         self.inner.write_input(cmd)?;
         std::thread::sleep(std::time::Duration::from_millis(100));
-        
-        if self.shell.manual_input_echo() {
-            self.output += cmd;
-        }
-        
-        let mut output = self.inner.read_output()?;
+        let output = self.inner.read_output()?;
+        self.receive(&output)
+    }
+
+    /// Take in output from the PTY, drawing it onto the screen and replying to any queries
+    fn receive(&mut self, bytes: &[u8]) -> PtyResult<()> {
+        let mut output = decode_utf8(&mut self.pending, bytes);
         
         // Clean up shell-specific output quirks
         if self.shell.inserts_extra_newline() {
             output = output.replace("\u{1b}[?2004l\r\r\n", "");
         }
         
-        self.output += &output;
+        self.screen.feed(&output);
+        let responses = self.screen.take_responses();
+        if !responses.is_empty() {
+            self.inner.write_input(&responses)?;
+        }
         Ok(())
     }
 
-    pub fn silent_run_command(&mut self, cmd: &str) -> PtyResult<()> {
-        self.output.clear();
-        self.run_command(cmd)?;
-        if self.output.starts_with(cmd) {
-            self.output = self.output.chars().skip(cmd.chars().count()).collect();
-        }
-        Ok(())
+    /// Change how many lines of output are kept once they scroll off the screen
+    pub fn set_scrollback(&mut self, lines: usize) {
+        self.screen.set_max_scrollback(lines);
     }
 
-    pub fn char_input(&mut self, c: char) -> PtyResult<()> {
-        self.input.push(c);
-        if c == '\n' {
-            self.run_command(&self.input.to_string())?;
-            self.input.clear();
+    /// Write input straight to the program running in the terminal
+    /// (output is picked up by the reader thread)
+    pub fn send(&mut self, input: &str) -> PtyResult<()> {
+        self.inner.write_input(input)
+    }
+
+    /// Send a key press to the program running in the terminal
+    pub fn send_key(&mut self, modifiers: KeyModifiers, code: KeyCode) -> PtyResult<()> {
+        self.screen.scroll_to_bottom();
+        match (modifiers, code) {
+            (KeyModifiers::CONTROL, KeyCode::Char('c')) => self.signal(Signal::Interrupt),
+            (KeyModifiers::CONTROL, KeyCode::Char('z')) => self.signal(Signal::Suspend),
+            _ => match encode_key(modifiers, code, self.screen.application_cursor) {
+                Some(input) => self.send(&input),
+                None => Ok(()),
+            },
         }
-        Ok(())
     }
 
-    pub fn char_pop(&mut self) {
-        self.input.pop();
+    /// Paste text into the program running in the terminal
+    pub fn paste(&mut self, text: &str) -> PtyResult<()> {
+        self.screen.scroll_to_bottom();
+        let input = encode_paste(text, self.screen.bracketed_paste);
+        self.send(&input)
+    }
+
+    /// Send a signal to the foreground process group of the terminal
+    pub fn signal(&mut self, signal: Signal) -> PtyResult<()> {
+        self.inner.send_signal(signal)
     }
 
     pub fn clear(&mut self) -> PtyResult<()> {
-        self.output.clear();
-        self.run_command("\n")?;
-        self.output = self.output.trim_start_matches('\n').to_string();
-        Ok(())
+        self.screen.clear();
+        self.run_command("\n")
     }
 
     pub fn catch_up(&mut self) -> PtyResult<bool> {
         let output = self.inner.try_read_output()?;
-        if !output.is_empty() {
-            let mut processed = output;
-            if self.shell.inserts_extra_newline() {
-                processed = processed.replace("\u{1b}[?2004l\r\r\n", "");
-            }
-            self.output += &processed;
-            Ok(true)
-        } else {
+        if output.is_empty() {
             Ok(false)
+        } else {
+            self.receive(&output)?;
+            Ok(true)
         }
     }
+
+    /// Change the size of the terminal (does nothing if it is already this size)
+    pub fn resize(&mut self, rows: usize, cols: usize) -> PtyResult<()> {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        if (rows, cols) == (self.screen.rows, self.screen.cols) {
+            return Ok(());
+        }
+        self.screen.resize(rows, cols);
+        let size = |n: usize| u16::try_from(n).unwrap_or(u16::MAX);
+        self.inner.resize(size(rows), size(cols))
+    }
     
     /// Check if there's a pending rerender request
     pub fn check_force_rerender(&self) -> bool {
@@ -394,6 +429,37 @@ impl Pty {
     }
 }
 
+/// Decode output from the PTY, holding back the bytes of a character that was split
+/// between reads until the rest of it arrives
+pub fn decode_utf8(pending: &mut Vec<u8>, bytes: &[u8]) -> String {
+    pending.extend_from_slice(bytes);
+    let mut result = String::new();
+    let mut rest = pending.as_slice();
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => {
+                result.push_str(text);
+                rest = &[];
+                break;
+            }
+            Err(e) => {
+                let (valid, after) = rest.split_at(e.valid_up_to());
+                result.push_str(&String::from_utf8_lossy(valid));
+                // Invalid bytes are replaced, but an incomplete character waits for the rest of it
+                if let Some(len) = e.error_len() {
+                    result.push(char::REPLACEMENT_CHARACTER);
+                    rest = &after[len..];
+                } else {
+                    rest = after;
+                    break;
+                }
+            }
+        }
+    }
+    *pending = rest.to_vec();
+    result
+}
+
 impl Drop for Pty {
     fn drop(&mut self) {
         // Signal the reader thread to shutdown
@@ -414,10 +480,13 @@ impl Drop for Pty {
 
 #[cfg(not(target_os = "windows"))]
 mod unix_impl {
-    use super::{Shell, PtyError, PtyResult};
+    use super::{Shell, PtyError, PtyResult, Signal};
     use mio::unix::SourceFd;
     use mio::{Events, Interest, Poll, Token};
     use nix::fcntl::{fcntl, FcntlArg, OFlag};
+    use nix::sys::signal::{killpg, Signal as UnixSignal};
+    use nix::sys::termios::{tcgetattr, LocalFlags, SpecialCharacterIndices};
+    use nix::unistd::tcgetpgrp;
     use ptyprocess::PtyProcess;
     use std::io::{BufReader, Read, Write};
     use std::os::unix::io::AsRawFd;
@@ -427,12 +496,15 @@ mod unix_impl {
     #[derive(Debug)]
     pub struct PtyImpl {
         process: PtyProcess,
+        #[allow(dead_code)]
         shell: Shell,
     }
 
     impl PtyImpl {
         pub fn new(shell: Shell) -> PtyResult<Self> {
-            let process = PtyProcess::spawn(Command::new(shell.command()))
+            let mut command = Command::new(shell.command());
+            command.env("TERM", "xterm-256color");
+            let process = PtyProcess::spawn(command)
                 .map_err(|e| PtyError::SpawnFailed(format!("Failed to spawn {}: {}", shell.command(), e)))?;
             Ok(Self {
                 process,
@@ -442,58 +514,87 @@ mod unix_impl {
 
         pub fn set_echo(&mut self, echo: bool) -> PtyResult<()> {
             self.process.set_echo(echo, None)
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to set PTY echo mode: {}", e)))?;
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to set PTY echo mode: {e}")))?;
             Ok(())
         }
 
         pub fn write_input(&mut self, input: &str) -> PtyResult<()> {
             let mut stream = self.process.get_raw_handle()
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {}", e)))?;
-            write!(stream, "{}", input)
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to write to PTY: {}", e)))?;
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
+            write!(stream, "{input}")
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to write to PTY: {e}")))?;
             Ok(())
         }
 
-        pub fn read_output(&mut self) -> PtyResult<String> {
+        pub fn read_output(&mut self) -> PtyResult<Vec<u8>> {
             let stream = self.process.get_raw_handle()
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {}", e)))?;
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
             let mut reader = BufReader::new(stream);
             let mut buf = [0u8; 10240];
             let bytes_read = reader.read(&mut buf)
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to read from PTY: {}", e)))?;
-            Ok(String::from_utf8_lossy(&buf[..bytes_read]).to_string())
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to read from PTY: {e}")))?;
+            Ok(buf[..bytes_read].to_vec())
+        }
+
+        pub fn resize(&mut self, rows: u16, cols: u16) -> PtyResult<()> {
+            self.process.set_window_size(cols, rows)
+                .map_err(|e| PtyError::PlatformError(format!("Failed to resize PTY: {e}")))
+        }
+
+        pub fn send_signal(&mut self, signal: Signal) -> PtyResult<()> {
+            let stream = self.process.get_raw_handle()
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
+            let termios = tcgetattr(&stream)
+                .map_err(|e| PtyError::PlatformError(format!("Failed to get terminal attributes: {e}")))?;
+            let (index, unix_signal) = match signal {
+                Signal::Interrupt => (SpecialCharacterIndices::VINTR, UnixSignal::SIGINT),
+                Signal::Suspend => (SpecialCharacterIndices::VSUSP, UnixSignal::SIGTSTP),
+            };
+            // Programs that read keys themselves (e.g. editors) turn signals off and get the key instead
+            if !termios.local_flags.contains(LocalFlags::ISIG) {
+                let key = termios.control_chars[index as usize];
+                return self.write_input(&char::from(key).to_string());
+            }
+            let group = tcgetpgrp(&stream)
+                .map_err(|e| PtyError::PlatformError(format!("Failed to find foreground process group: {e}")))?;
+            killpg(group, unix_signal)
+                .map_err(|e| PtyError::PlatformError(format!("Failed to send signal: {e}")))
         }
 
-        pub fn try_read_output(&mut self) -> PtyResult<String> {
+        pub fn try_read_output(&mut self) -> PtyResult<Vec<u8>> {
             let stream = self.process.get_raw_handle()
-                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {}", e)))?;
+                .map_err(|e| PtyError::CommunicationError(format!("Failed to get PTY handle: {e}")))?;
             let raw_fd = stream.as_raw_fd();
             
             // Set non-blocking mode
             let flags = fcntl(raw_fd, FcntlArg::F_GETFL)
-                .map_err(|e| PtyError::PlatformError(format!("Failed to get file flags: {}", e)))?;
+                .map_err(|e| PtyError::PlatformError(format!("Failed to get file flags: {e}")))?;
             fcntl(
                 raw_fd,
                 FcntlArg::F_SETFL(OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK),
             )
-            .map_err(|e| PtyError::PlatformError(format!("Failed to set non-blocking mode: {}", e)))?;
+            .map_err(|e| PtyError::PlatformError(format!("Failed to set non-blocking mode: {e}")))?;
             
             let mut source = SourceFd(&raw_fd);
             let mut poll = Poll::new()
-                .map_err(|e| PtyError::PlatformError(format!("Failed to create poll instance: {}", e)))?;
+                .map_err(|e| PtyError::PlatformError(format!("Failed to create poll instance: {e}")))?;
             let mut events = Events::with_capacity(128);
             
             poll.registry()
                 .register(&mut source, Token(0), Interest::READABLE)
-                .map_err(|e| PtyError::PlatformError(format!("Failed to register poll interest: {}", e)))?;
+                .map_err(|e| PtyError::PlatformError(format!("Failed to register poll interest: {e}")))?;
             
             match poll.poll(&mut events, Some(Duration::from_millis(100))) {
+                // Poll timed out, there is nothing to read yet
+                Ok(()) if events.is_empty() => Ok(vec![]),
                 Ok(()) => {
                     let mut reader = BufReader::new(stream);
                     let mut buf = [0u8; 10240];
-                    let bytes_read = reader.read(&mut buf)
-                        .map_err(|e| PtyError::CommunicationError(format!("Failed to read from PTY: {}", e)))?;
-                    Ok(String::from_utf8_lossy(&buf[..bytes_read]).to_string())
+                    match reader.read(&mut buf) {
+                        Ok(bytes_read) => Ok(buf[..bytes_read].to_vec()),
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(vec![]),
+                        Err(e) => Err(PtyError::CommunicationError(format!("Failed to read from PTY: {e}"))),
+                    }
                 }
                 Err(e) => Err(PtyError::from(e)),
             }
@@ -503,7 +604,7 @@ mod unix_impl {
 
 #[cfg(target_os = "windows")]
 mod windows_impl {
-    use super::{Shell, PtyError, PtyResult, PtyErrorContext, recover_lock_poisoned};
+    use super::{Shell, Signal, PtyError, PtyResult, PtyErrorContext, recover_lock_poisoned};
     use std::io::{Error, ErrorKind, Read, Write};
     use std::sync::{Arc, Mutex};
     use std::time::Duration;
@@ -666,12 +767,12 @@ mod windows_impl {
             }
         }
 
-        pub fn read_output(&mut self) -> PtyResult<String> {
+        pub fn read_output(&mut self) -> PtyResult<Vec<u8>> {
             match &mut self.backend {
                 PtyBackend::ConPty(conpty) => {
                     let data = conpty.read()
                         .map_err(|e| PtyError::CommunicationError(format!("ConPTY read failed: {}", e)))?;
-                    Ok(String::from_utf8_lossy(&data).to_string())
+                    Ok(data)
                 }
                 PtyBackend::PortablePty { reader, .. } => {
                     let mut buffer = vec![0u8; 10240];
@@ -679,22 +780,22 @@ mod windows_impl {
                     
                     match reader.read(&mut buffer) {
                         Ok(n) if n > 0 => {
-                            Ok(String::from_utf8_lossy(&buffer[..n]).to_string())
+                            Ok(buffer[..n].to_vec())
                         }
-                        Ok(_) => Ok(String::new()),
-                        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(String::new()),
+                        Ok(_) => Ok(vec![]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(vec![]),
                         Err(e) => Err(PtyError::from(e)),
                     }
                 }
             }
         }
 
-        pub fn try_read_output(&mut self) -> PtyResult<String> {
+        pub fn try_read_output(&mut self) -> PtyResult<Vec<u8>> {
             match &mut self.backend {
                 PtyBackend::ConPty(conpty) => {
                     let data = conpty.try_read()
                         .map_err(|e| PtyError::CommunicationError(format!("ConPTY try_read failed: {}", e)))?;
-                    Ok(String::from_utf8_lossy(&data).to_string())
+                    Ok(data)
                 }
                 PtyBackend::PortablePty { reader, child, .. } => {
                     // Check if the child process is still alive
@@ -711,15 +812,15 @@ mod windows_impl {
                         Ok(mut reader) => {
                             match reader.read(&mut buffer) {
                                 Ok(n) if n > 0 => {
-                                    Ok(String::from_utf8_lossy(&buffer[..n]).to_string())
+                                    Ok(buffer[..n].to_vec())
                                 }
-                                Ok(_) => Ok(String::new()),
-                                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(String::new()),
+                                Ok(_) => Ok(vec![]),
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(vec![]),
                                 Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
                                     if child.try_wait().is_some() {
                                         Err(PtyError::ProcessTerminated)
                                     } else {
-                                        Ok(String::new())
+                                        Ok(vec![])
                                     }
                                 }
                                 Err(e) => Err(PtyError::from(e)),
@@ -728,13 +829,13 @@ mod windows_impl {
                         Err(std::sync::TryLockError::Poisoned(err)) => {
                             let mut reader = recover_lock_poisoned(err);
                             match reader.read(&mut buffer) {
-                                Ok(n) if n > 0 => Ok(String::from_utf8_lossy(&buffer[..n]).to_string()),
-                                Ok(_) => Ok(String::new()),
-                                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(String::new()),
+                                Ok(n) if n > 0 => Ok(buffer[..n].to_vec()),
+                                Ok(_) => Ok(vec![]),
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(vec![]),
                                 Err(e) => Err(PtyError::from(e)),
                             }
                         }
-                        Err(std::sync::TryLockError::WouldBlock) => Ok(String::new()),
+                        Err(std::sync::TryLockError::WouldBlock) => Ok(vec![]),
                     }
                 }
             }
@@ -770,7 +871,11 @@ mod windows_impl {
             }
         }
         
-        pub fn send_signal(&mut self, signal: WindowsSignal) -> PtyResult<()> {
+        pub fn send_signal(&mut self, signal: Signal) -> PtyResult<()> {
+            let signal = match signal {
+                Signal::Interrupt => WindowsSignal::CtrlC,
+                Signal::Suspend => WindowsSignal::CtrlZ,
+            };
             match &mut self.backend {
                 PtyBackend::ConPty(conpty) => {
                     let conpty_signal = match signal {
@@ -901,9 +1006,31 @@ mod tests {
         }
     }
     
+    #[test]
+    fn test_decode_split_utf8() {
+        let mut pending = vec![];
+        let bytes = "a£€".as_bytes();
+        assert_eq!(decode_utf8(&mut pending, &bytes[..2]), "a");
+        assert_eq!(decode_utf8(&mut pending, &bytes[2..4]), "£");
+        assert_eq!(decode_utf8(&mut pending, &bytes[4..]), "€");
+        assert!(pending.is_empty());
+        assert_eq!(decode_utf8(&mut pending, b"a\xffb"), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn test_output_is_bounded() {
+        if let Ok(pty) = Pty::new(Shell::detect()) {
+            let mut pty = pty.lock().unwrap();
+            pty.set_scrollback(2);
+            pty.screen.feed(&"1\r\n".repeat(100));
+            assert_eq!(pty.screen.total_lines(), 2 + pty.screen.rows);
+            assert_eq!(pty.screen.text().split('\n').count(), 2 + pty.screen.rows);
+        }
+    }
+
     #[test]
     fn test_pty_thread_lifecycle() {
-        use std::sync::Arc;
+        
         use std::thread;
         use std::time::Duration;
         
@@ -934,7 +1061,7 @@ mod tests {
     
     #[test]
     fn test_pty_force_rerender_synchronization() {
-        use std::sync::Arc;
+        
         use std::thread;
         use std::time::Duration;
         
@@ -963,7 +1090,7 @@ mod tests {
     
     #[test]
     fn test_pty_shutdown_flag() {
-        use std::sync::Arc;
+        
         use std::thread;
         use std::time::Duration;
         
@@ -984,7 +1111,7 @@ mod tests {
     
     #[test]
     fn test_pty_multiple_instances() {
-        use std::sync::Arc;
+        
         use std::thread;
         use std::time::Duration;
         
@@ -1018,7 +1145,7 @@ mod tests {
     
     #[test]
     fn test_pty_reader_thread_naming() {
-        use std::sync::Arc;
+        
         use std::thread;
         use std::time::Duration;
         
@@ -44,16 +44,16 @@ pub enum PtyError {
 impl fmt::Display for PtyError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            PtyError::Io(err) => write!(f, "PTY I/O error: {}", err),
-            PtyError::LockPoisoned(msg) => write!(f, "PTY lock poisoned: {}", msg),
+            PtyError::Io(err) => write!(f, "PTY I/O error: {err}"),
+            PtyError::LockPoisoned(msg) => write!(f, "PTY lock poisoned: {msg}"),
             PtyError::LockTimeout => write!(f, "PTY lock acquisition timed out"),
-            PtyError::InitializationFailed(msg) => write!(f, "PTY initialization failed: {}", msg),
+            PtyError::InitializationFailed(msg) => write!(f, "PTY initialization failed: {msg}"),
             PtyError::ProcessTerminated => write!(f, "PTY child process has terminated"),
-            PtyError::SpawnFailed(msg) => write!(f, "Failed to spawn shell: {}", msg),
-            PtyError::CommunicationError(msg) => write!(f, "PTY communication error: {}", msg),
-            PtyError::CommandFailed(msg) => write!(f, "Shell command failed: {}", msg),
-            PtyError::InvalidShell(msg) => write!(f, "Invalid shell: {}", msg),
-            PtyError::PlatformError(msg) => write!(f, "Platform-specific PTY error: {}", msg),
+            PtyError::SpawnFailed(msg) => write!(f, "Failed to spawn shell: {msg}"),
+            PtyError::CommunicationError(msg) => write!(f, "PTY communication error: {msg}"),
+            PtyError::CommandFailed(msg) => write!(f, "Shell command failed: {msg}"),
+            PtyError::InvalidShell(msg) => write!(f, "Invalid shell: {msg}"),
+            PtyError::PlatformError(msg) => write!(f, "Platform-specific PTY error: {msg}"),
         }
     }
 }
@@ -79,7 +79,7 @@ impl From<io::Error> for PtyError {
 
 impl<T> From<PoisonError<T>> for PtyError {
     fn from(err: PoisonError<T>) -> Self {
-        PtyError::LockPoisoned(format!("Mutex guard poisoned: {}", err))
+        PtyError::LockPoisoned(format!("Mutex guard poisoned: {err}"))
     }
 }
 
@@ -95,7 +95,7 @@ pub type PtyResult<T> = Result<T, PtyError>;
 
 /// Helper function to recover from a poisoned lock
 pub fn recover_lock_poisoned<T>(err: PoisonError<T>) -> T {
-    eprintln!("Warning: Recovering from poisoned lock: {}", err);
+    eprintln!("Warning: Recovering from poisoned lock: {err}");
     err.into_inner()
 }
 
@@ -109,7 +109,7 @@ impl<T> PtyErrorContext<T> for PtyResult<T> {
     fn context(self, msg: &str) -> PtyResult<T> {
         self.map_err(|e| match e {
             PtyError::Io(io_err) => PtyError::CommunicationError(
-                format!("{}: {}", msg, io_err)
+                format!("{msg}: {io_err}")
             ),
             other => other,
         })
@@ -126,13 +126,13 @@ mod tests {
     #[test]
     fn test_pty_error_display() {
         let err = PtyError::ProcessTerminated;
-        assert_eq!(format!("{}", err), "PTY child process has terminated");
+        assert_eq!(format!("{err}"), "PTY child process has terminated");
         
         let err = PtyError::LockPoisoned("test lock".to_string());
-        assert_eq!(format!("{}", err), "PTY lock poisoned: test lock");
+        assert_eq!(format!("{err}"), "PTY lock poisoned: test lock");
         
         let err = PtyError::InitializationFailed("init failed".to_string());
-        assert_eq!(format!("{}", err), "PTY initialization failed: init failed");
+        assert_eq!(format!("{err}"), "PTY initialization failed: init failed");
     }
     
     #[test]
@@ -145,7 +145,7 @@ mod tests {
         let pty_err: PtyError = io_err.into();
         assert!(matches!(pty_err, PtyError::ProcessTerminated));
         
-        let io_err = io::Error::new(io::ErrorKind::Other, "other error");
+        let io_err = io::Error::other("other error");
         let pty_err: PtyError = io_err.into();
         assert!(matches!(pty_err, PtyError::Io(_)));
     }
@@ -1,5 +1,3 @@
-/// Lazy-compiled regex patterns for better performance and error handling
-use once_cell::sync::Lazy;
 use regex::Regex;
 use std::sync::Arc;
 
@@ -21,12 +19,12 @@ impl std::error::Error for RegexError {}
 /// Macro to create lazy regex with proper error handling
 macro_rules! lazy_regex {
     ($name:ident, $pattern:expr) => {
-        pub static $name: Lazy<Arc<Regex>> = Lazy::new(|| {
+        pub static $name: std::sync::LazyLock<Arc<Regex>> = std::sync::LazyLock::new(|| {
             Arc::new(Regex::new($pattern).unwrap_or_else(|e| {
                 eprintln!("WARNING: Failed to compile regex '{}': {}", $pattern, e);
                 eprintln!("Using fallback regex that matches nothing");
                 // Return a regex that never matches as a safe fallback
-                Regex::new("(?!)").expect("Fallback regex should always compile")
+                Regex::new(r"[^\s\S]").expect("Fallback regex should always compile")
             }))
         });
     };
@@ -34,7 +32,7 @@ macro_rules! lazy_regex {
 
 #[cfg(not(target_os = "windows"))]
 pub mod ansi {
-    use super::*;
+    use super::{Arc, Regex};
     
     // ANSI pattern constants
     const CURSORS: &str = r"\x1b\[(s|u|H|\d+;?\d*[A-J])";
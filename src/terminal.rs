@@ -176,8 +176,7 @@ impl TerminalType {
         // Check LANG/LC_ALL for UTF-8 support
         let is_utf8 = env::var("LANG")
             .or_else(|_| env::var("LC_ALL"))
-            .map(|v| v.to_lowercase().contains("utf-8") || v.to_lowercase().contains("utf8"))
-            .unwrap_or(false);
+            .is_ok_and(|v| v.to_lowercase().contains("utf-8") || v.to_lowercase().contains("utf8"));
         
         match self {
             // Modern terminals generally support Unicode
@@ -277,6 +276,7 @@ impl TerminalType {
 }
 
 /// Terminal capabilities
+#[allow(clippy::struct_excessive_bools)]
 pub struct TerminalCapabilities {
     pub terminal_type: TerminalType,
     pub true_color: bool,
@@ -311,7 +311,7 @@ mod tests {
     #[test]
     fn test_terminal_detection() {
         let terminal = TerminalType::detect();
-        println!("Detected terminal: {:?}", terminal);
+        println!("Detected terminal: {terminal:?}");
         println!("Terminal name: {}", terminal.name());
     }
     
@@ -3,9 +3,7 @@ use crate::clipboard::{Clipboard, ClipboardMethod, ClipboardStatus};
 use crate::config::{Colors, Terminal as TerminalConfig};
 use crate::editor::MacroMan;
 use crate::error::Result;
-use crate::regex_cache;
 use crate::terminal::TerminalCapabilities;
-use base64::prelude::*;
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
     event::{
@@ -22,10 +20,8 @@ use crossterm::{
 };
 use kaolinite::utils::{width, Size};
 use mlua::AnyUserData;
-use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::io::{stdout, Stdout, Write};
-#[cfg(not(target_os = "windows"))]
 
 /// Printing macro
 #[macro_export]
@@ -173,7 +169,7 @@ impl Terminal {
         let (use_osc52, max_retries, verbose_logging) = {
             let cfg = config.borrow::<crate::config::Terminal>()
                 .expect("Failed to borrow terminal config");
-            (cfg.clipboard_use_osc52, cfg.clipboard_max_retries as u32, cfg.clipboard_verbose_logging)
+            (cfg.clipboard_use_osc52, u32::try_from(cfg.clipboard_max_retries).unwrap_or(u32::MAX), cfg.clipboard_verbose_logging)
         };
         
         // Build clipboard with configuration
@@ -190,7 +186,7 @@ impl Terminal {
         
         Terminal {
             stdout: stdout(),
-            cache: String::with_capacity(size().map(|s| s.w * s.h).unwrap_or(1000)),
+            cache: String::with_capacity(size().map_or(1000, |s| s.w * s.h)),
             config,
             last_copy: String::new(),
             clipboard,
@@ -298,6 +294,7 @@ impl Terminal {
     }
 
     /// Put text into the clipboard and return status
+    #[allow(clippy::unnecessary_wraps)]
     pub fn copy(&mut self, text: &str) -> Result<ClipboardMethod> {
         self.last_copy = text.to_string();
         
@@ -329,7 +326,7 @@ pub fn rgb_to_xterm256(r: u8, g: u8, b: u8) -> u8 {
     let lookup = get_xterm_lookup();
     let mut min_distance = f64::INFINITY;
     let mut closest_index = 0;
-    for (&index, &(xr, xg, xb)) in lookup.iter() {
+    for (&index, &(xr, xg, xb)) in lookup {
         // Calculate the Euclidean distance in RGB space
         let distance = ((f64::from(r) - f64::from(xr)).powi(2)
             + (f64::from(g) - f64::from(xg)).powi(2)
@@ -392,82 +389,9 @@ fn parse_xterm_lookup() -> HashMap<u8, (u8, u8, u8)> {
 }
 
 /// Lazy-loaded XTERM color lookup table
-static XTERM_LOOKUP: Lazy<HashMap<u8, (u8, u8, u8)>> = Lazy::new(parse_xterm_lookup);
+static XTERM_LOOKUP: std::sync::LazyLock<HashMap<u8, (u8, u8, u8)>> = std::sync::LazyLock::new(parse_xterm_lookup);
 
 /// Get the XTERM lookup table
 pub fn get_xterm_lookup() -> &'static HashMap<u8, (u8, u8, u8)> {
     &XTERM_LOOKUP
 }
-
-/// Define various ANSI code regex patterns for matching
-#[cfg(not(target_os = "windows"))]
-pub const CURSORS: &str = r"\x1b(?:\[(?:\d+(?:;\d+)?(?:A|B|C|D|F|f|G|H)|(?:#[A-G]|6n|s|u|H)|[0-6] q|(?:[0-9]{1,3};)*[0-9]{1,3}t)|[7-8])";
-#[cfg(not(target_os = "windows"))]
-pub const ERASING: &str = r"\x1b\[[0-3]?(?:J|K)";
-#[cfg(not(target_os = "windows"))]
-pub const DISPLAY: &str =
-    r"\x1b\[(?:(?:[0-9]|22|23|24|25|27|28|29)(?:t|m)|(?:[0-9]{1,3};)*[0-9]{1,3}m)";
-#[cfg(not(target_os = "windows"))]
-pub const SC_MODE: &str = r"\x1b\[(?:=|\?)[0-9]{1,4}(?:h|l)";
-#[cfg(not(target_os = "windows"))]
-pub const AC_JUNK: &str = r"(\a|\b|\n|\v|\f|\r)";
-
-/// Global ANSI removal
-#[cfg(not(target_os = "windows"))]
-fn global() -> String {
-    format!("({CURSORS}|{ERASING}|{DISPLAY}|{SC_MODE}|{AC_JUNK})")
-}
-
-#[cfg(not(target_os = "windows"))]
-/// Remove ANSI codes from a string
-pub fn remove_ansi_codes(input: &str) -> String {
-    use regex_cache::ansi::*;
-    // Replace all matches with an empty string
-    let result = GLOBAL_ANSI.replace_all(input, "").to_string();
-    // Replace weird new line stuff
-    let result = WEIRD_NEWLINE.replace_all(&result, "").to_string();
-    // Replace long spaces
-    let result = LONG_SPACES.replace_all(&result, "").to_string();
-    // Return result
-    result
-}
-
-#[cfg(not(target_os = "windows"))]
-/// Remove all ANSI codes outside of color and attribute codes
-pub fn strip_escape_codes(input: &str) -> String {
-    use regex_cache::ansi::*;
-    // Replace escape sequences, keeping those for attributes and colors
-    let result = GLOBAL_ANSI
-        .replace_all(input, |caps: &regex::Captures| {
-            let code = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-            if DISPLAY_ONLY.is_match(code) {
-                // Return the escape code unchanged
-                code.to_string()
-            } else {
-                // Return an empty string for non-allowed escape codes (including cursor styling)
-                String::new()
-            }
-        })
-        .to_string();
-    // Replace weird new line stuff
-    let result = WEIRD_NEWLINE.replace_all(&result, "").to_string();
-    // Replace long spaces
-    let result = LONG_SPACES.replace_all(&result, "").to_string();
-    // Return result
-    result
-}
-
-#[cfg(not(target_os = "windows"))]
-#[allow(clippy::similar_names)]
-/// Replace reset colour ANSI codes with a custom background color.
-pub fn replace_reset(input: &str, custom_bg: &str, custom_fg: &str) -> String {
-    use regex_cache::ansi::*;
-    // Replace reset background with the custom background color
-    let attr_full_reset =
-        "\u{1b}[22m\u{1b}[23m\u{1b}[24m\u{1b}[25m\u{1b}[27m\u{1b}[28m\u{1b}[29m".to_string();
-    let full_reset = format!("{custom_bg}{custom_fg}{attr_full_reset}");
-    let input = TOTAL_RESET.replace_all(input, full_reset).to_string();
-    let input = RESET_BG.replace_all(&input, custom_bg).to_string();
-    let input = RESET_FG.replace_all(&input, custom_fg).to_string();
-    input
-}
@@ -0,0 +1,1346 @@
+//! Terminal emulation for the integrated terminal
+//!
+//! Programs running in a pseudo-terminal write text mixed with escape sequences that move
+//! the cursor, change colours and switch screens. This module interprets that output the way
+//! a VT100 / xterm compatible terminal would, into a grid of styled cells that can be drawn
+//! into a split.
+//!
+//! Supported features include:
+//! - Cursor addressing and movement, saving and restoring the cursor
+//! - Erasing, inserting and deleting characters and lines, and scroll regions
+//! - SGR attributes with 16, 256 and true colour
+//! - The alternate screen (used by full-screen programs like htop, less and vim)
+//! - A scrollback buffer of lines that have scrolled off the top of the main screen
+//! - Replies to device status and attribute queries
+//!
+//! It also encodes keys and pasted text into the bytes a program expects to read from its terminal.
+
+use crossterm::event::{KeyCode, KeyModifiers};
+use kaolinite::searching::Searcher;
+use kaolinite::utils::width_char;
+use kaolinite::Loc;
+use std::collections::VecDeque;
+
+/// How many lines of scrollback each terminal keeps by default
+pub const MAX_SCROLLBACK: usize = 10_000;
+
+/// Tab stops are placed every this many columns
+const TAB_STOP: usize = 8;
+
+/// The longest control sequence or string that will be collected (longer ones are cut short)
+const MAX_SEQUENCE: usize = 4096;
+
+/// The largest number a parameter of a control sequence can be (larger ones are cut down)
+const MAX_PARAM: usize = 65_535;
+
+/// A colour a cell can be drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Colour {
+    /// The terminal's default foreground or background colour
+    #[default]
+    Default,
+    /// A colour from the 256 colour palette (the first 16 are the standard ANSI colours)
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// How a cell is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub fg: Colour,
+    pub bg: Colour,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    /// Whether the foreground and background colours are swapped
+    pub reverse: bool,
+    pub hidden: bool,
+}
+
+/// A single character position on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// The character in this cell (`'\0'` for the second half of a wide character)
+    pub ch: char,
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            style: Style::default(),
+        }
+    }
+}
+
+/// Where the parser is up to in a piece of output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Plain text
+    Ground,
+    /// Just after an escape character
+    Escape,
+    /// Choosing the character set for G0 or G1
+    Charset(usize),
+    /// Skipping the single character that ends an escape sequence
+    Skip,
+    /// Within a control sequence (`ESC [`)
+    Csi,
+    /// Within an operating system command (`ESC ]`), e.g. setting the title
+    Osc,
+    /// Within another kind of string (e.g. a device control string), which is ignored
+    Str,
+    /// Just after an escape character within a string, which may end it
+    StrEscape,
+}
+
+/// A terminal screen, which output from a program is fed into
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Screen {
+    /// The number of rows on the screen
+    pub rows: usize,
+    /// The number of columns on the screen
+    pub cols: usize,
+    /// The lines currently on the screen
+    lines: Vec<Vec<Cell>>,
+    /// The main screen's lines, kept aside while the alternate screen is shown
+    main_lines: Option<Vec<Vec<Cell>>>,
+    /// Lines that have scrolled off the top of the main screen (oldest first)
+    pub scrollback: VecDeque<Vec<Cell>>,
+    /// The most lines of scrollback kept
+    max_scrollback: usize,
+    /// How many lines the view is scrolled back into the scrollback (0 shows the screen)
+    scroll: usize,
+    /// The selected text, from one location to another (in lines counted from the start of the
+    /// scrollback, and columns)
+    selection: Option<(Loc, Loc)>,
+    cursor: Loc,
+    /// The cursor and style saved by `ESC 7` or `CSI s`
+    saved_cursor: Option<(Loc, Style)>,
+    /// The style newly written characters are given
+    style: Style,
+    /// Set when a character was written in the last column, so the next one wraps
+    wrap_pending: bool,
+    /// The first and last rows of the scroll region
+    top: usize,
+    bottom: usize,
+    /// Whether the cursor should be shown
+    pub cursor_visible: bool,
+    auto_wrap: bool,
+    insert_mode: bool,
+    /// Whether cursor addressing is relative to the scroll region
+    origin_mode: bool,
+    /// Whether the cursor keys should send application sequences (`ESC O A` rather than `ESC [ A`)
+    pub application_cursor: bool,
+    /// Whether pasted text should be wrapped in bracketed paste sequences
+    pub bracketed_paste: bool,
+    /// Whether G0 and G1 are the DEC line drawing character set
+    line_drawing: [bool; 2],
+    /// Whether G1 is in use rather than G0
+    shifted: bool,
+    /// The title set by the program running in the terminal
+    pub title: String,
+    /// The last character written (for repeating it)
+    last_char: Option<char>,
+    state: State,
+    /// The control sequence or string being collected
+    sequence: String,
+    /// Replies to queries, waiting to be sent back to the program
+    responses: String,
+}
+
+impl Screen {
+    /// Create a blank screen
+    pub fn new(rows: usize, cols: usize) -> Self {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        Self {
+            rows,
+            cols,
+            lines: vec![vec![Cell::default(); cols]; rows],
+            main_lines: None,
+            scrollback: VecDeque::new(),
+            max_scrollback: MAX_SCROLLBACK,
+            scroll: 0,
+            selection: None,
+            cursor: Loc::default(),
+            saved_cursor: None,
+            style: Style::default(),
+            wrap_pending: false,
+            top: 0,
+            bottom: rows - 1,
+            cursor_visible: true,
+            auto_wrap: true,
+            insert_mode: false,
+            origin_mode: false,
+            application_cursor: false,
+            bracketed_paste: false,
+            line_drawing: [false; 2],
+            shifted: false,
+            title: String::new(),
+            last_char: None,
+            state: State::Ground,
+            sequence: String::new(),
+            responses: String::new(),
+        }
+    }
+
+    /// Interpret some output from a program
+    pub fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            self.advance(c);
+        }
+    }
+
+    /// Take the replies to queries the program has made, which should be written back to it
+    pub fn take_responses(&mut self) -> String {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// A line of the screen
+    pub fn line(&self, y: usize) -> Option<&[Cell]> {
+        self.lines.get(y).map(Vec::as_slice)
+    }
+
+    /// The text on a line of the screen, without trailing spaces
+    pub fn line_text(&self, y: usize) -> String {
+        self.line(y).map_or(String::new(), cells_text)
+    }
+
+    /// Where the cursor is on the screen
+    pub fn cursor(&self) -> Loc {
+        self.cursor
+    }
+
+    /// Whether the alternate screen is being shown
+    pub fn is_alternate(&self) -> bool {
+        self.main_lines.is_some()
+    }
+
+    /// How many lines of scrollback are kept
+    pub fn max_scrollback(&self) -> usize {
+        self.max_scrollback
+    }
+
+    /// Change how many lines of scrollback are kept
+    pub fn set_max_scrollback(&mut self, lines: usize) {
+        self.max_scrollback = lines;
+        self.trim_scrollback();
+    }
+
+    /// The number of lines in the scrollback and on the screen
+    pub fn total_lines(&self) -> usize {
+        self.scrollback.len() + self.rows
+    }
+
+    /// A line of the scrollback or screen (counted from the start of the scrollback)
+    pub fn buffer_line(&self, y: usize) -> Option<&[Cell]> {
+        match y.checked_sub(self.scrollback.len()) {
+            Some(y) => self.line(y),
+            None => self.scrollback.get(y).map(Vec::as_slice),
+        }
+    }
+
+    /// Where the top of the view is (in lines counted from the start of the scrollback)
+    pub fn view_top(&self) -> usize {
+        self.scrollback.len() - self.scroll
+    }
+
+    /// A line of the view, which is the screen unless it has been scrolled back
+    pub fn view_line(&self, y: usize) -> Option<&[Cell]> {
+        if y < self.rows {
+            self.buffer_line(self.view_top() + y)
+        } else {
+            None
+        }
+    }
+
+    /// How many lines the view has been scrolled back
+    pub fn scrolled(&self) -> usize {
+        self.scroll
+    }
+
+    /// Scroll the view back into the scrollback
+    pub fn scroll_back(&mut self, lines: usize) {
+        self.scroll = (self.scroll + lines).min(self.scrollback.len());
+    }
+
+    /// Scroll the view forward, towards the screen
+    pub fn scroll_forward(&mut self, lines: usize) {
+        self.scroll = self.scroll.saturating_sub(lines);
+    }
+
+    /// Scroll the view back to the screen
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Scroll the view (if needed) so that a line is in it
+    pub fn scroll_to_line(&mut self, y: usize) {
+        let top = self.view_top();
+        if y < top {
+            self.scroll_back(top - y);
+        } else if y >= top + self.rows {
+            self.scroll_forward(y + 1 - top - self.rows);
+        }
+    }
+
+    /// The selected text, from one location to another (the end isn't included)
+    pub fn selection(&self) -> Option<(Loc, Loc)> {
+        let (start, end) = self.selection?;
+        let (start, end) = if (start.y, start.x) <= (end.y, end.x) {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        (start != end).then_some((start, end))
+    }
+
+    /// Select from one location to another (in lines counted from the start of the scrollback)
+    pub fn select(&mut self, start: Loc, end: Loc) {
+        self.selection = Some((start, end));
+    }
+
+    /// Move the end of the selection, starting one if there isn't one
+    pub fn select_to(&mut self, end: Loc) {
+        let start = self.selection.map_or(end, |(start, _)| start);
+        self.selection = Some((start, end));
+    }
+
+    /// Remove the selection
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    /// Determine if a cell of a line (counted from the start of the scrollback) is selected
+    pub fn is_selected(&self, x: usize, y: usize) -> bool {
+        self.selection()
+            .is_some_and(|(start, end)| (start.y, start.x) <= (y, x) && (y, x) < (end.y, end.x))
+    }
+
+    /// The text of the scrollback and screen (each line without trailing spaces)
+    pub fn text(&self) -> String {
+        let lines: Vec<String> = (0..self.total_lines())
+            .filter_map(|y| self.buffer_line(y).map(cells_text))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// The text that is selected (each line without trailing spaces)
+    pub fn selected_text(&self) -> String {
+        let Some((start, end)) = self.selection() else {
+            return String::new();
+        };
+        let mut lines = vec![];
+        for y in start.y..=end.y {
+            let line = self.buffer_line(y).unwrap_or_default();
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { line.len() };
+            lines.push(cells_text(&line[from.min(line.len())..to.min(line.len())]));
+        }
+        lines.join("\n")
+    }
+
+    /// Find every match of a search in the scrollback and screen, as the locations it starts
+    /// and ends at (in lines counted from the start of the scrollback, and columns)
+    pub fn find_all(&self, searcher: &mut Searcher) -> Vec<(Loc, Loc)> {
+        let mut result = vec![];
+        for y in 0..self.total_lines() {
+            let line = self.buffer_line(y).unwrap_or_default();
+            let text = cells_text(line);
+            // Find the column that each character is in (and the column after the last one)
+            let mut columns: Vec<usize> = line
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| cell.ch != '\0')
+                .map(|(x, _)| x)
+                .take(text.chars().count())
+                .collect();
+            columns.push(
+                columns
+                    .last()
+                    .map_or(0, |x| x + width_char(&line[*x].ch, 1)),
+            );
+            for found in searcher.lfinds(&text) {
+                let length = found.text.chars().count();
+                if length > 0 {
+                    let start = Loc::at(columns[found.loc.x], y);
+                    let end = Loc::at(columns[found.loc.x + length], y);
+                    result.push((start, end));
+                }
+            }
+        }
+        result
+    }
+
+    /// Erase the screen and move the cursor to the top left
+    pub fn clear(&mut self) {
+        self.erase_display(2);
+        self.goto(0, 0);
+    }
+
+    /// Change the size of the screen. Lines aren't rewrapped, but lines pushed off the top
+    /// of the main screen go into the scrollback.
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        let (rows, cols) = (rows.max(1), cols.max(1));
+        if rows == self.rows && cols == self.cols {
+            return;
+        }
+        for line in self
+            .lines
+            .iter_mut()
+            .chain(self.main_lines.iter_mut().flatten())
+        {
+            line.resize(cols, Cell::default());
+        }
+        // Prefer dropping lines below the cursor, then lines from the top
+        let below = self.rows.saturating_sub(self.cursor.y + 1);
+        let drop_below = self.rows.saturating_sub(rows).min(below);
+        self.lines.truncate(self.rows - drop_below);
+        let alternate = self.is_alternate();
+        while self.lines.len() > rows {
+            let line = self.lines.remove(0);
+            if !alternate {
+                self.push_scrollback(line);
+            }
+            self.cursor.y = self.cursor.y.saturating_sub(1);
+        }
+        self.lines.resize(rows, vec![Cell::default(); cols]);
+        if let Some(mut main) = self.main_lines.take() {
+            while main.len() > rows {
+                self.push_scrollback(main.remove(0));
+            }
+            main.resize(rows, vec![Cell::default(); cols]);
+            self.main_lines = Some(main);
+        }
+        self.rows = rows;
+        self.cols = cols;
+        self.top = 0;
+        self.bottom = rows - 1;
+        self.cursor.x = self.cursor.x.min(cols - 1);
+        self.cursor.y = self.cursor.y.min(rows - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Interpret a single character of output
+    fn advance(&mut self, c: char) {
+        match self.state {
+            State::Ground => {
+                if is_control(c) {
+                    self.control(c);
+                } else {
+                    self.put(c);
+                }
+            }
+            State::Escape => {
+                self.state = State::Ground;
+                self.escape(c);
+            }
+            State::Charset(set) => {
+                if let Some(drawing) = self.line_drawing.get_mut(set) {
+                    *drawing = c == '0';
+                }
+                self.state = State::Ground;
+            }
+            State::Skip => self.state = State::Ground,
+            State::Csi => match c {
+                // Cancel the sequence
+                '\x18' | '\x1a' => self.state = State::Ground,
+                '\x40'..='\x7e' => {
+                    self.state = State::Ground;
+                    let sequence = std::mem::take(&mut self.sequence);
+                    self.csi(c, &sequence);
+                }
+                _ if is_control(c) => self.control(c),
+                _ => self.collect(c),
+            },
+            State::Osc | State::Str => match c {
+                '\x07' => self.end_string(),
+                '\x1b' => {
+                    // Remember the kind of string in the sequence until it is known to have ended
+                    if self.state == State::Osc {
+                        self.sequence.insert(0, ']');
+                    }
+                    self.state = State::StrEscape;
+                }
+                '\x18' | '\x1a' => self.state = State::Ground,
+                _ if self.state == State::Osc => self.collect(c),
+                _ => (),
+            },
+            State::StrEscape => {
+                let osc = self.sequence.starts_with(']');
+                if osc {
+                    self.sequence.remove(0);
+                    self.state = State::Osc;
+                }
+                self.end_string();
+                // Anything other than `ESC \` starts a new escape sequence
+                if c != '\\' {
+                    self.escape(c);
+                }
+            }
+        }
+    }
+
+    /// Add a character to the sequence being collected
+    fn collect(&mut self, c: char) {
+        if self.sequence.len() < MAX_SEQUENCE {
+            self.sequence.push(c);
+        }
+    }
+
+    /// Carry out a control character
+    fn control(&mut self, c: char) {
+        match c {
+            '\x08' => {
+                self.cursor.x = self.cursor.x.saturating_sub(1);
+                self.wrap_pending = false;
+            }
+            '\t' => {
+                let next = (self.cursor.x / TAB_STOP + 1) * TAB_STOP;
+                self.cursor.x = next.min(self.cols - 1);
+                self.wrap_pending = false;
+            }
+            '\n' | '\x0b' | '\x0c' => self.linefeed(),
+            '\r' => {
+                self.cursor.x = 0;
+                self.wrap_pending = false;
+            }
+            '\x0e' => self.shifted = true,
+            '\x0f' => self.shifted = false,
+            '\x1b' => {
+                self.sequence.clear();
+                self.state = State::Escape;
+            }
+            // Bells and other controls are ignored
+            _ => (),
+        }
+    }
+
+    /// Carry out the character following an escape character
+    fn escape(&mut self, c: char) {
+        self.sequence.clear();
+        match c {
+            '[' => self.state = State::Csi,
+            ']' => self.state = State::Osc,
+            'P' | 'X' | '^' | '_' => self.state = State::Str,
+            '(' => self.state = State::Charset(0),
+            ')' => self.state = State::Charset(1),
+            '*' | '+' | '#' | '%' | ' ' => self.state = State::Skip,
+            '7' => self.save_cursor(),
+            '8' => self.restore_cursor(),
+            'D' => self.linefeed(),
+            'E' => {
+                self.cursor.x = 0;
+                self.linefeed();
+            }
+            'M' => self.reverse_index(),
+            'c' => self.reset(),
+            '\x1b' => self.state = State::Escape,
+            // Keypad modes and anything unknown are ignored
+            _ => (),
+        }
+    }
+
+    /// Finish a string, carrying it out if it is an operating system command
+    fn end_string(&mut self) {
+        let sequence = std::mem::take(&mut self.sequence);
+        if self.state == State::Osc {
+            // Only setting the title is supported
+            if let Some((kind, title)) = sequence.split_once(';') {
+                if kind == "0" || kind == "2" {
+                    self.title = title.to_string();
+                }
+            }
+        }
+        self.state = State::Ground;
+    }
+
+    /// Carry out a control sequence, given its final character and what came before it
+    #[allow(clippy::too_many_lines)]
+    fn csi(&mut self, action: char, sequence: &str) {
+        let private = sequence.chars().next().filter(|c| "?<=>".contains(*c));
+        let body = &sequence[private.map_or(0, char::len_utf8)..];
+        // Sequences with intermediate characters (e.g. cursor shapes) aren't supported
+        if body.chars().any(|c| (' '..='/').contains(&c)) {
+            return;
+        }
+        let params: Vec<usize> = body
+            .split([';', ':'])
+            .map(|p| p.parse().map_or(0, |n: usize| n.min(MAX_PARAM)))
+            .collect();
+        let arg = |i: usize| params.get(i).copied().unwrap_or(0);
+        let count = |i: usize| arg(i).max(1);
+        match (private, action) {
+            (None, '@') => self.insert_blanks(count(0)),
+            (None, 'A') => {
+                let limit = if self.cursor.y >= self.top {
+                    self.top
+                } else {
+                    0
+                };
+                let y = self.cursor.y.saturating_sub(count(0)).max(limit);
+                self.move_to(self.cursor.x, y);
+            }
+            (None, 'B' | 'e') => {
+                let limit = if self.cursor.y <= self.bottom {
+                    self.bottom
+                } else {
+                    self.rows - 1
+                };
+                let y = self.cursor.y.saturating_add(count(0)).min(limit);
+                self.move_to(self.cursor.x, y);
+            }
+            (None, 'C' | 'a') => {
+                self.move_to(self.cursor.x.saturating_add(count(0)), self.cursor.y);
+            }
+            (None, 'D') => self.move_to(self.cursor.x.saturating_sub(count(0)), self.cursor.y),
+            (None, 'E') => {
+                let y = self
+                    .cursor
+                    .y
+                    .saturating_add(count(0))
+                    .min(self.bottom.max(self.cursor.y));
+                self.move_to(0, y);
+            }
+            (None, 'F') => self.move_to(0, self.cursor.y.saturating_sub(count(0))),
+            (None, 'G' | '`') => self.move_to(count(0) - 1, self.cursor.y),
+            (None, 'H' | 'f') => self.goto(count(1) - 1, count(0) - 1),
+            (None, 'd') => self.goto(self.cursor.x, count(0) - 1),
+            (None, 'J') => self.erase_display(arg(0)),
+            (None, 'K') => self.erase_line(arg(0)),
+            (None, 'L') => self.insert_lines(count(0)),
+            (None, 'M') => self.delete_lines(count(0)),
+            (None, 'P') => self.delete_chars(count(0)),
+            (None, 'X') => self.erase_chars(count(0)),
+            (None, 'S') => self.scroll_up(count(0)),
+            (None, 'T') => self.scroll_down(count(0)),
+            (None, 'b') => {
+                if let Some(c) = self.last_char {
+                    for _ in 0..count(0).min(self.cols * self.rows) {
+                        self.put(c);
+                    }
+                }
+            }
+            (None, 'c') => self.responses.push_str("\x1b[?1;2c"),
+            (Some('>'), 'c') => self.responses.push_str("\x1b[>0;0;0c"),
+            (None, 'n') if arg(0) == 5 => self.responses.push_str("\x1b[0n"),
+            (None, 'n') if arg(0) == 6 => {
+                let y = self.cursor.y - if self.origin_mode { self.top } else { 0 };
+                self.responses
+                    .push_str(&format!("\x1b[{};{}R", y + 1, self.cursor.x + 1));
+            }
+            (None, 'm') => self.sgr(&params),
+            (None, 'r') => {
+                let top = count(0) - 1;
+                let bottom = if arg(1) == 0 { self.rows } else { arg(1) }.min(self.rows) - 1;
+                if top < bottom {
+                    self.top = top;
+                    self.bottom = bottom;
+                    self.goto(0, 0);
+                }
+            }
+            (None, 's') => self.save_cursor(),
+            (None, 'u') => self.restore_cursor(),
+            (_, 'h') => self.set_modes(private, &params, true),
+            (_, 'l') => self.set_modes(private, &params, false),
+            _ => (),
+        }
+    }
+
+    /// Turn terminal modes on or off
+    fn set_modes(&mut self, private: Option<char>, params: &[usize], on: bool) {
+        for mode in params {
+            match (private, mode) {
+                (Some('?'), 1) => self.application_cursor = on,
+                (Some('?'), 6) => {
+                    self.origin_mode = on;
+                    self.goto(0, 0);
+                }
+                (Some('?'), 7) => self.auto_wrap = on,
+                (Some('?'), 25) => self.cursor_visible = on,
+                (Some('?'), 47 | 1047) => self.alternate_screen(on, false),
+                (Some('?'), 1049) => self.alternate_screen(on, true),
+                (Some('?'), 2004) => self.bracketed_paste = on,
+                (None, 4) => self.insert_mode = on,
+                _ => (),
+            }
+        }
+    }
+
+    /// Change the style of newly written characters
+    fn sgr(&mut self, params: &[usize]) {
+        let mut i = 0;
+        while i < params.len() {
+            let style = &mut self.style;
+            match params[i] {
+                0 => *style = Style::default(),
+                1 => style.bold = true,
+                2 => style.dim = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                7 => style.reverse = true,
+                8 => style.hidden = true,
+                21 | 22 => {
+                    style.bold = false;
+                    style.dim = false;
+                }
+                23 => style.italic = false,
+                24 => style.underline = false,
+                27 => style.reverse = false,
+                28 => style.hidden = false,
+                n @ 30..=37 => style.fg = Colour::Indexed(palette(n - 30)),
+                39 => style.fg = Colour::Default,
+                n @ 40..=47 => style.bg = Colour::Indexed(palette(n - 40)),
+                49 => style.bg = Colour::Default,
+                n @ 90..=97 => style.fg = Colour::Indexed(palette(n - 90 + 8)),
+                n @ 100..=107 => style.bg = Colour::Indexed(palette(n - 100 + 8)),
+                n @ (38 | 48) => {
+                    let (colour, used) = extended_colour(&params[i + 1..]);
+                    if let Some(colour) = colour {
+                        if n == 38 {
+                            style.fg = colour;
+                        } else {
+                            style.bg = colour;
+                        }
+                    }
+                    i += used;
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    /// Write a character at the cursor
+    fn put(&mut self, c: char) {
+        let c = if self.line_drawing[usize::from(self.shifted)] {
+            line_drawing(c)
+        } else {
+            c
+        };
+        let w = width_char(&c, 1);
+        // Combining characters aren't supported
+        if w == 0 {
+            return;
+        }
+        if self.wrap_pending && self.auto_wrap {
+            self.cursor.x = 0;
+            self.linefeed();
+        }
+        self.wrap_pending = false;
+        // Wide characters that don't fit at the end of a line go on the next
+        if w > 1 && self.cursor.x + w > self.cols {
+            if !self.auto_wrap || self.cols < w {
+                return;
+            }
+            self.erase_chars(self.cols - self.cursor.x);
+            self.cursor.x = 0;
+            self.linefeed();
+        }
+        if self.insert_mode {
+            self.insert_blanks(w);
+        }
+        let Loc { x, y } = self.cursor;
+        let blank = self.blank();
+        let style = self.style;
+        let line = &mut self.lines[y];
+        // Overwriting half of a wide character leaves the other half blank
+        if line[x].ch == '\0' && x > 0 {
+            line[x - 1] = blank;
+        }
+        if line.get(x + w).is_some_and(|cell| cell.ch == '\0') {
+            line[x + w] = blank;
+        }
+        line[x] = Cell { ch: c, style };
+        if w > 1 {
+            line[x + 1] = Cell { ch: '\0', style };
+        }
+        self.last_char = Some(c);
+        if x + w >= self.cols {
+            self.cursor.x = self.cols - 1;
+            self.wrap_pending = true;
+        } else {
+            self.cursor.x = x + w;
+        }
+    }
+
+    /// An empty cell, in the current background colour
+    fn blank(&self) -> Cell {
+        Cell {
+            ch: ' ',
+            style: Style {
+                bg: self.style.bg,
+                ..Style::default()
+            },
+        }
+    }
+
+    /// An empty line, in the current background colour
+    fn blank_line(&self) -> Vec<Cell> {
+        vec![self.blank(); self.cols]
+    }
+
+    /// Move the cursor to a location on the screen
+    fn move_to(&mut self, x: usize, y: usize) {
+        self.cursor = Loc::at(x.min(self.cols - 1), y.min(self.rows - 1));
+        self.wrap_pending = false;
+    }
+
+    /// Move the cursor to a location, which is within the scroll region in origin mode
+    fn goto(&mut self, x: usize, y: usize) {
+        if self.origin_mode {
+            self.move_to(x, (y + self.top).min(self.bottom));
+        } else {
+            self.move_to(x, y);
+        }
+    }
+
+    /// Move the cursor down a line, scrolling at the bottom of the scroll region
+    fn linefeed(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.y == self.bottom {
+            self.scroll_up(1);
+        } else if self.cursor.y + 1 < self.rows {
+            self.cursor.y += 1;
+        }
+    }
+
+    /// Move the cursor up a line, scrolling at the top of the scroll region
+    fn reverse_index(&mut self) {
+        self.wrap_pending = false;
+        if self.cursor.y == self.top {
+            self.scroll_down(1);
+        } else {
+            self.cursor.y = self.cursor.y.saturating_sub(1);
+        }
+    }
+
+    /// Scroll the scroll region up, keeping lines from the top of the main screen as scrollback
+    fn scroll_up(&mut self, n: usize) {
+        let keep = self.top == 0 && !self.is_alternate();
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            let line = self.lines.remove(self.top);
+            if keep {
+                self.push_scrollback(line);
+            }
+            self.lines.insert(self.bottom, self.blank_line());
+        }
+    }
+
+    /// Scroll the scroll region down
+    fn scroll_down(&mut self, n: usize) {
+        for _ in 0..n.min(self.bottom - self.top + 1) {
+            self.lines.remove(self.bottom);
+            self.lines.insert(self.top, self.blank_line());
+        }
+    }
+
+    /// Keep a line in the scrollback, forgetting the oldest lines beyond the limit
+    fn push_scrollback(&mut self, line: Vec<Cell>) {
+        self.scrollback.push_back(line);
+        // Keep what is in view still while scrolled back
+        if self.scroll > 0 {
+            self.scroll += 1;
+        }
+        self.trim_scrollback();
+    }
+
+    /// Forget the oldest lines of scrollback beyond the limit
+    fn trim_scrollback(&mut self) {
+        while self.scrollback.len() > self.max_scrollback {
+            self.scrollback.pop_front();
+            // The selection moves up along with the lines it is on
+            self.selection = self.selection.and_then(|(start, end)| {
+                Some((
+                    Loc::at(start.x, start.y.checked_sub(1)?),
+                    Loc::at(end.x, end.y.checked_sub(1)?),
+                ))
+            });
+        }
+        self.scroll = self.scroll.min(self.scrollback.len());
+    }
+
+    /// Insert blank lines at the cursor, pushing lines below down
+    fn insert_lines(&mut self, n: usize) {
+        if (self.top..=self.bottom).contains(&self.cursor.y) {
+            for _ in 0..n.min(self.bottom - self.cursor.y + 1) {
+                self.lines.remove(self.bottom);
+                self.lines.insert(self.cursor.y, self.blank_line());
+            }
+            self.move_to(0, self.cursor.y);
+        }
+    }
+
+    /// Delete lines at the cursor, pulling lines below up
+    fn delete_lines(&mut self, n: usize) {
+        if (self.top..=self.bottom).contains(&self.cursor.y) {
+            for _ in 0..n.min(self.bottom - self.cursor.y + 1) {
+                self.lines.remove(self.cursor.y);
+                self.lines.insert(self.bottom, self.blank_line());
+            }
+            self.move_to(0, self.cursor.y);
+        }
+    }
+
+    /// Insert blank cells at the cursor, pushing the rest of the line right
+    fn insert_blanks(&mut self, n: usize) {
+        let Loc { x, y } = self.cursor;
+        let blank = self.blank();
+        let line = &mut self.lines[y];
+        for _ in 0..n.min(self.cols - x) {
+            line.insert(x, blank);
+            line.pop();
+        }
+    }
+
+    /// Delete cells at the cursor, pulling the rest of the line left
+    fn delete_chars(&mut self, n: usize) {
+        let Loc { x, y } = self.cursor;
+        let blank = self.blank();
+        let line = &mut self.lines[y];
+        for _ in 0..n.min(self.cols - x) {
+            line.remove(x);
+            line.push(blank);
+        }
+    }
+
+    /// Blank out cells from the cursor onwards
+    fn erase_chars(&mut self, n: usize) {
+        let Loc { x, y } = self.cursor;
+        let blank = self.blank();
+        let end = (x + n).min(self.cols);
+        self.lines[y][x..end].fill(blank);
+    }
+
+    /// Erase part of the line the cursor is on (0: to the end, 1: to the start, 2: all of it)
+    fn erase_line(&mut self, mode: usize) {
+        let Loc { x, y } = self.cursor;
+        let blank = self.blank();
+        let line = &mut self.lines[y];
+        match mode {
+            0 => line[x..].fill(blank),
+            1 => line[..=x].fill(blank),
+            2 => line.fill(blank),
+            _ => (),
+        }
+    }
+
+    /// Erase part of the screen (0: after the cursor, 1: before it, 2: all of it,
+    /// 3: the scrollback)
+    fn erase_display(&mut self, mode: usize) {
+        let y = self.cursor.y;
+        let blank = self.blank_line();
+        match mode {
+            0 => {
+                self.erase_line(0);
+                self.lines[y + 1..].fill(blank);
+            }
+            1 => {
+                self.erase_line(1);
+                self.lines[..y].fill(blank);
+            }
+            2 => self.lines.fill(blank),
+            3 => {
+                self.scrollback.clear();
+                self.scroll = 0;
+                self.selection = None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Remember where the cursor is, along with the current style
+    fn save_cursor(&mut self) {
+        self.saved_cursor = Some((self.cursor, self.style));
+    }
+
+    /// Go back to where the cursor was saved
+    fn restore_cursor(&mut self) {
+        let (cursor, style) = self.saved_cursor.unwrap_or_default();
+        self.style = style;
+        self.move_to(cursor.x, cursor.y);
+    }
+
+    /// Switch to or from the alternate screen (optionally saving and restoring the cursor)
+    fn alternate_screen(&mut self, on: bool, save_cursor: bool) {
+        if on == self.is_alternate() {
+            return;
+        }
+        if on {
+            if save_cursor {
+                self.save_cursor();
+            }
+            let blank = vec![vec![Cell::default(); self.cols]; self.rows];
+            self.main_lines = Some(std::mem::replace(&mut self.lines, blank));
+        } else {
+            if let Some(lines) = self.main_lines.take() {
+                self.lines = lines;
+            }
+            if save_cursor {
+                self.restore_cursor();
+            }
+        }
+    }
+
+    /// Reset the terminal to how it started (keeping the scrollback)
+    fn reset(&mut self) {
+        let mut fresh = Self::new(self.rows, self.cols);
+        fresh.scrollback = std::mem::take(&mut self.scrollback);
+        fresh.max_scrollback = self.max_scrollback;
+        *self = fresh;
+    }
+}
+
+/// The text in a line of cells, without trailing spaces
+pub fn cells_text(cells: &[Cell]) -> String {
+    let text: String = cells.iter().map(|c| c.ch).filter(|c| *c != '\0').collect();
+    text.trim_end_matches(' ').to_string()
+}
+
+/// Determine if a character is a C0 control character (or delete)
+fn is_control(c: char) -> bool {
+    c < ' ' || c == '\x7f'
+}
+
+/// Convert the number of a palette colour
+fn palette(n: usize) -> u8 {
+    u8::try_from(n).unwrap_or(u8::MAX)
+}
+
+/// Read an extended colour (after 38 or 48 in an SGR sequence), returning it along with
+/// how many parameters it took up
+fn extended_colour(params: &[usize]) -> (Option<Colour>, usize) {
+    match params {
+        [5, n, ..] => (Some(Colour::Indexed(palette(*n))), 2),
+        [2, r, g, b, ..] => (Some(Colour::Rgb(palette(*r), palette(*g), palette(*b))), 4),
+        [5 | 2, ..] => (None, params.len()),
+        _ => (None, 0),
+    }
+}
+
+/// Convert a character into the DEC line drawing character set
+fn line_drawing(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'f' => '°',
+        'g' => '±',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+/// Encode a key press into what a terminal sends to the program running in it
+/// (None for keys that send nothing)
+pub fn encode_key(
+    modifiers: KeyModifiers,
+    code: KeyCode,
+    application_cursor: bool,
+) -> Option<String> {
+    let alt = modifiers.contains(KeyModifiers::ALT);
+    let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+    // Modifiers of special keys are sent as a parameter (e.g. `ESC [ 1 ; 5 A` for ctrl + up)
+    let modifier = 1
+        + u8::from(modifiers.contains(KeyModifiers::SHIFT))
+        + 2 * u8::from(alt)
+        + 4 * u8::from(ctrl);
+    let cursor = |c: char| {
+        if modifier > 1 {
+            format!("\x1b[1;{modifier}{c}")
+        } else if application_cursor {
+            format!("\x1bO{c}")
+        } else {
+            format!("\x1b[{c}")
+        }
+    };
+    let tilde = |n: u8| {
+        if modifier > 1 {
+            format!("\x1b[{n};{modifier}~")
+        } else {
+            format!("\x1b[{n}~")
+        }
+    };
+    let (text, alt_prefix) = match code {
+        KeyCode::Char(c) if ctrl => (control_char(c)?.to_string(), alt),
+        KeyCode::Char(c) => (c.to_string(), alt),
+        KeyCode::Enter => ("\r".to_string(), alt),
+        KeyCode::Tab => ("\t".to_string(), alt),
+        KeyCode::BackTab => ("\x1b[Z".to_string(), false),
+        KeyCode::Backspace if ctrl => ("\x08".to_string(), alt),
+        KeyCode::Backspace => ("\x7f".to_string(), alt),
+        KeyCode::Esc => ("\x1b".to_string(), alt),
+        KeyCode::Up => (cursor('A'), false),
+        KeyCode::Down => (cursor('B'), false),
+        KeyCode::Right => (cursor('C'), false),
+        KeyCode::Left => (cursor('D'), false),
+        KeyCode::Home => (cursor('H'), false),
+        KeyCode::End => (cursor('F'), false),
+        KeyCode::Insert => (tilde(2), false),
+        KeyCode::Delete => (tilde(3), false),
+        KeyCode::PageUp => (tilde(5), false),
+        KeyCode::PageDown => (tilde(6), false),
+        KeyCode::F(n @ 1..=4) => {
+            let c = char::from(b'P' + n - 1);
+            if modifier > 1 {
+                (format!("\x1b[1;{modifier}{c}"), false)
+            } else {
+                (format!("\x1bO{c}"), false)
+            }
+        }
+        KeyCode::F(n @ 5..=12) => (
+            tilde([15, 17, 18, 19, 20, 21, 23, 24][usize::from(n - 5)]),
+            false,
+        ),
+        _ => return None,
+    };
+    Some(if alt_prefix {
+        format!("\x1b{text}")
+    } else {
+        text
+    })
+}
+
+/// Work out the control character sent when ctrl is held with a key
+fn control_char(c: char) -> Option<char> {
+    match c.to_ascii_lowercase() {
+        c @ 'a'..='z' => Some(char::from(c as u8 - b'a' + 1)),
+        ' ' | '@' | '2' => Some('\0'),
+        '[' | '3' => Some('\x1b'),
+        '\\' | '4' => Some('\x1c'),
+        ']' | '5' => Some('\x1d'),
+        '^' | '6' => Some('\x1e'),
+        '_' | '-' | '7' => Some('\x1f'),
+        '?' | '8' => Some('\x7f'),
+        _ => None,
+    }
+}
+
+/// Encode pasted text, wrapping it in bracketed paste sequences if the program asked for them
+pub fn encode_paste(text: &str, bracketed: bool) -> String {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if bracketed {
+        // Stop the pasted text from ending the paste early
+        let text = text.replace("\x1b[201~", "");
+        format!("\x1b[200~{text}\x1b[201~")
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(rows: usize, cols: usize, output: &str) -> Screen {
+        let mut screen = Screen::new(rows, cols);
+        screen.feed(output);
+        screen
+    }
+
+    #[test]
+    fn text_and_wrapping() {
+        let s = screen(4, 5, "hello world\r\nab\tc");
+        assert_eq!(s.line_text(0), "hello");
+        assert_eq!(s.line_text(1), " worl");
+        assert_eq!(s.line_text(2), "d");
+        // Tabs stop at the last column if there are no more tab stops
+        assert_eq!(s.line_text(3), "ab  c");
+        assert_eq!(s.cursor(), Loc::at(4, 3));
+        let s = screen(2, 4, "ab\r\n12345678");
+        assert_eq!(s.scrollback.len(), 1);
+        assert_eq!(cells_text(&s.scrollback[0]), "ab");
+        assert_eq!(
+            (s.line_text(0), s.line_text(1)),
+            ("1234".into(), "5678".into())
+        );
+        // Wide characters move to the next line rather than being split
+        let s = screen(2, 5, "abcd你好");
+        assert_eq!(s.line_text(1), "你好");
+        assert_eq!(s.line(1).unwrap()[1].ch, '\0');
+    }
+
+    #[test]
+    fn cursor_addressing_and_erasing() {
+        let mut s = screen(4, 10, "aaaaaaaaaa\r\nbbbbbbbbbb\r\ncccccccccc");
+        s.feed("\x1b[2;3H\x1b[K\x1b[1;5H\x1b[1K\x1b[3;4H\x1b[2P\x1b[2@");
+        assert_eq!(s.line_text(0), "     aaaaa");
+        assert_eq!(s.line_text(1), "bb");
+        assert_eq!(s.line_text(2), "ccc  ccccc");
+        s.feed("\x1b[5C\x1b[Ax\x1b[10;10Hz");
+        assert_eq!(s.line_text(1), "bb      x");
+        assert_eq!(s.cursor(), Loc::at(9, 3));
+        s.feed("\x1b[2J");
+        assert_eq!((0..4).map(|y| s.line_text(y)).collect::<String>(), "");
+        // Replies to queries
+        s.feed("\x1b[2;3H\x1b[6n\x1b[c");
+        assert_eq!(s.take_responses(), "\x1b[2;3R\x1b[?1;2c");
+        assert_eq!(s.take_responses(), "");
+        // Oversized parameters move as far as they can, rather than overflowing
+        s.feed("\x1b[2;3H\x1b[18446744073709551615C\x1b[18446744073709551615B");
+        assert_eq!(s.cursor(), Loc::at(9, 3));
+        s.feed("\x1b[1;1H\x1b[99999999999999999999999E\x1b[?6h\x1b[18446744073709551615;1H");
+        assert_eq!(s.cursor(), Loc::at(0, 3));
+        s.feed("\x1b[?6l\x1b[1;1H\x1b[18446744073709551615X\x1b[18446744073709551615e");
+        assert_eq!(s.cursor(), Loc::at(0, 3));
+    }
+
+    #[test]
+    fn scroll_regions_and_lines() {
+        let mut s = screen(4, 3, "1\r\n2\r\n3\r\n4");
+        s.feed("\x1b[2;3r\x1b[3;1H\n");
+        assert_eq!(
+            (0..4).map(|y| s.line_text(y)).collect::<Vec<_>>(),
+            ["1", "3", "", "4"]
+        );
+        // Lines scrolled within a region don't go into the scrollback
+        assert!(s.scrollback.is_empty());
+        s.feed("\x1b[2;1H\x1bM\x1b[L");
+        assert_eq!(
+            (0..4).map(|y| s.line_text(y)).collect::<Vec<_>>(),
+            ["1", "", "", "4"]
+        );
+        s.feed("\x1b[r\x1b[1;1H\x1b[M");
+        assert_eq!(
+            (0..4).map(|y| s.line_text(y)).collect::<Vec<_>>(),
+            ["", "", "4", ""]
+        );
+    }
+
+    #[test]
+    fn styles() {
+        let s = screen(
+            1,
+            10,
+            "\x1b[1;31ma\x1b[38;5;200;48;2;1;2;3mb\x1b[0;7;94mc\x1b[mde",
+        );
+        let style = |x: usize| s.line(0).unwrap()[x].style;
+        assert!(style(0).bold);
+        assert_eq!(style(0).fg, Colour::Indexed(1));
+        assert_eq!(style(1).fg, Colour::Indexed(200));
+        assert_eq!(style(1).bg, Colour::Rgb(1, 2, 3));
+        assert!(!style(2).bold && style(2).reverse);
+        assert_eq!(style(2).fg, Colour::Indexed(12));
+        assert_eq!(style(3), Style::default());
+        // Line drawing characters
+        let s = screen(1, 10, "\x1b(0lqk\x1b(Bq");
+        assert_eq!(s.line_text(0), "┌─┐q");
+        // Titles, and strings that are ignored
+        let s = screen(1, 10, "\x1b]0;my title\x07\x1bPignored\x1b\\ok");
+        assert_eq!(s.title, "my title");
+        assert_eq!(s.line_text(0), "ok");
+    }
+
+    #[test]
+    fn alternate_screen_and_resizing() {
+        let mut s = screen(3, 5, "main\r\nabc");
+        s.feed("\x1b[?1049h\x1b[H\x1b[2Jfull");
+        assert!(s.is_alternate());
+        assert_eq!(
+            (s.line_text(0), s.line_text(1)),
+            ("full".into(), String::new())
+        );
+        s.feed("\x1b[?1049l");
+        assert!(!s.is_alternate());
+        assert_eq!(
+            (s.line_text(0), s.line_text(1)),
+            ("main".into(), "abc".into())
+        );
+        assert_eq!(s.cursor(), Loc::at(3, 1));
+        // Shrinking drops blank lines below the cursor, then pushes lines into the scrollback
+        s.resize(2, 3);
+        assert_eq!(
+            (s.line_text(0), s.line_text(1)),
+            ("mai".into(), "abc".into())
+        );
+        s.resize(1, 3);
+        assert_eq!(s.line_text(0), "abc");
+        assert_eq!(cells_text(&s.scrollback[0]), "mai");
+        assert_eq!(s.cursor(), Loc::at(2, 0));
+        s.resize(3, 6);
+        assert_eq!((s.rows, s.cols, s.line_text(0)), (3, 6, "abc".into()));
+    }
+
+    #[test]
+    fn key_encoding() {
+        let key = |modifiers, code| encode_key(modifiers, code, false).unwrap();
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::Char('a')), "a");
+        assert_eq!(key(KeyModifiers::SHIFT, KeyCode::Char('A')), "A");
+        assert_eq!(key(KeyModifiers::CONTROL, KeyCode::Char('c')), "\x03");
+        assert_eq!(key(KeyModifiers::CONTROL, KeyCode::Char('z')), "\x1a");
+        assert_eq!(key(KeyModifiers::ALT, KeyCode::Char('b')), "\x1bb");
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::Enter), "\r");
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::Backspace), "\x7f");
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::Up), "\x1b[A");
+        assert_eq!(key(KeyModifiers::CONTROL, KeyCode::Right), "\x1b[1;5C");
+        assert_eq!(key(KeyModifiers::SHIFT, KeyCode::Delete), "\x1b[3;2~");
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::F(1)), "\x1bOP");
+        assert_eq!(key(KeyModifiers::NONE, KeyCode::F(12)), "\x1b[24~");
+        // Programs can ask for application cursor keys
+        let up = encode_key(KeyModifiers::NONE, KeyCode::Up, true);
+        assert_eq!(up.as_deref(), Some("\x1bOA"));
+        assert_eq!(
+            encode_key(KeyModifiers::NONE, KeyCode::CapsLock, false),
+            None
+        );
+        // Pasting
+        assert_eq!(encode_paste("a\nb", false), "a\rb");
+        assert_eq!(encode_paste("a\x1b[201~b", true), "\x1b[200~ab\x1b[201~");
+    }
+
+    #[test]
+    fn scrollback_selection_and_searching() {
+        let mut s = screen(2, 6, "one\r\ntwo\r\nthree\r\nfour");
+        s.set_max_scrollback(1);
+        assert_eq!(s.scrollback.len(), 1);
+        assert_eq!(s.total_lines(), 3);
+        assert_eq!(cells_text(s.buffer_line(0).unwrap()), "two");
+        // Scrolling back keeps the view still as more lines arrive
+        s.scroll_back(5);
+        assert_eq!(s.scrolled(), 1);
+        assert_eq!(cells_text(s.view_line(0).unwrap()), "two");
+        s.set_max_scrollback(10);
+        s.feed("\r\nfive");
+        assert_eq!(cells_text(s.view_line(0).unwrap()), "two");
+        s.scroll_to_bottom();
+        assert_eq!(cells_text(s.view_line(1).unwrap()), "five");
+        // Selections can span lines, and are copied without trailing spaces
+        s.select(Loc::at(1, 1), Loc::at(0, 0));
+        assert_eq!(s.selection(), Some((Loc::at(0, 0), Loc::at(1, 1))));
+        assert_eq!(s.selected_text(), "two\nt");
+        assert!(s.is_selected(5, 0) && !s.is_selected(1, 1));
+        // Matches are found across the scrollback and screen
+        let mut searcher = Searcher::new("o");
+        let matches = s.find_all(&mut searcher);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0], (Loc::at(2, 0), Loc::at(3, 0)));
+        s.scroll_to_line(0);
+        assert_eq!(s.view_top(), 0);
+        let s = screen(1, 6, "a你b");
+        let matches = s.find_all(&mut Searcher::new("b"));
+        assert_eq!(matches, vec![(Loc::at(3, 0), Loc::at(4, 0))]);
+    }
+}
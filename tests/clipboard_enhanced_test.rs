@@ -1,6 +1,6 @@
 // Enhanced clipboard tests with error handling and retry logic
 
-use ox::clipboard::{Clipboard, ClipboardError, ClipboardMethod, ClipboardStatus};
+use ox::clipboard::{Clipboard, ClipboardError, ClipboardMethod};
 use std::time::{Duration, Instant};
 
 #[test]
@@ -30,7 +30,7 @@ fn test_clipboard_error_types() {
 
 #[test]
 fn test_clipboard_status_tracking() {
-    let mut clipboard = Clipboard::new();
+    let clipboard = Clipboard::new();
     let status = clipboard.get_status();
     
     // Check that status contains expected fields
@@ -39,7 +39,7 @@ fn test_clipboard_status_tracking() {
     assert!(status.last_error.is_none());
     
     // Test with OSC52 fallback enabled
-    let mut clipboard_osc = Clipboard::new().with_osc52_fallback();
+    let clipboard_osc = Clipboard::new().with_osc52_fallback();
     let status_osc = clipboard_osc.get_status();
     assert!(status_osc.osc52_enabled);
 }
@@ -265,6 +265,6 @@ fn test_concurrent_clipboard_access() {
     }
     
     // Verify clipboard is still in valid state
-    let clipboard_final = Arc::try_unwrap(clipboard).unwrap().into_inner().unwrap();
+    let clipboard_final = Arc::try_unwrap(clipboard).ok().unwrap().into_inner().unwrap();
     assert!(!clipboard_final.last_copied().is_empty());
 }
\ No newline at end of file
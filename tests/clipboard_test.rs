@@ -86,7 +86,7 @@ mod linux_clipboard_tests {
     #[test]
     #[ignore] // Run with --ignored flag as this requires clipboard tools
     fn test_clipboard_operations() {
-        use ox::clipboard::{Clipboard, Selection};
+        use ox::clipboard::Clipboard;
         
         let mut clipboard = Clipboard::new();
         
@@ -0,0 +1,289 @@
+//! Tests for the language server client, run against a mock language server.
+//! This test binary is its own mock server: when started with `OX_MOCK_LSP` set,
+//! it answers requests over its standard input and output instead of running the tests.
+
+use kaolinite::event::Event;
+use kaolinite::{Document, Loc, Size};
+use ox::lsp::protocol::{parse_completions, parse_hover, parse_locations, parse_workspace_edit};
+use ox::lsp::{read_message, write_message, LanguageServer, Notice, ServerState};
+use serde_json::{json, Value};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const URI: &str = "file:///project/main.rs";
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn main() {
+    if std::env::var_os("OX_MOCK_LSP").is_some() {
+        mock_server();
+        return;
+    }
+    std::env::set_var("OX_MOCK_LSP", "1");
+    let tests: [(&str, fn()); 4] = [
+        ("lsp_sync", lsp_sync),
+        ("lsp_diagnostics", lsp_diagnostics),
+        ("lsp_requests", lsp_requests),
+        ("lsp_stop", lsp_stop),
+    ];
+    for (name, test) in tests {
+        print!("test {name} ... ");
+        test();
+        println!("ok");
+    }
+}
+
+/// Start the mock server, with a document open on it
+fn start(text: &str) -> (LanguageServer, Document) {
+    let exe = std::env::current_exe().unwrap();
+    let mut server = LanguageServer::start(&exe.to_string_lossy(), Path::new(".")).unwrap();
+    let mut doc = Document::new(Size { w: 80, h: 20 });
+    doc.exe(Event::Insert(Loc::at(0, 0), text.to_string())).unwrap();
+    server.open(URI, "rust", &mut doc);
+    (server, doc)
+}
+
+/// Ask the mock server what it thinks the document contains
+fn server_text(server: &mut LanguageServer) -> String {
+    let id = server.request("mock/text", Value::Null);
+    server.wait_for(id, TIMEOUT).unwrap().as_str().unwrap().to_string()
+}
+
+/// Ask the mock server how many times it has been sent the whole document in a change
+fn full_syncs(server: &mut LanguageServer) -> u64 {
+    let id = server.request("mock/full_syncs", Value::Null);
+    server.wait_for(id, TIMEOUT).unwrap().as_u64().unwrap()
+}
+
+/// Poll the server until it publishes diagnostics
+fn wait_for_diagnostics(server: &mut LanguageServer) -> Vec<ox::lsp::Diagnostic> {
+    let deadline = Instant::now() + TIMEOUT;
+    while Instant::now() < deadline {
+        let found = server.poll().into_iter().rev().find_map(|n| match n {
+            Notice::Diagnostics { diagnostics, .. } => Some(diagnostics),
+            _ => None,
+        });
+        if let Some(diagnostics) = found {
+            return diagnostics;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("no diagnostics were published");
+}
+
+fn lsp_sync() {
+    let (mut server, mut doc) = start("fn main() {\n    println!(\"hi\");\n}");
+    assert_eq!(server_text(&mut server), doc.file.to_string());
+    assert_eq!(server.state, ServerState::Running);
+    // Edits are sent incrementally
+    doc.exe(Event::Insert(Loc::at(3, 0), "über_".to_string())).unwrap();
+    doc.exe(Event::InsertLine(1, "    let x = 1;".to_string())).unwrap();
+    doc.exe(Event::Delete(Loc::at(4, 2), "println".to_string())).unwrap();
+    doc.exe(Event::Insert(Loc::at(4, 2), "print".to_string())).unwrap();
+    doc.exe(Event::DeleteLine(3, "}".to_string())).unwrap();
+    doc.exe(Event::Insert(Loc::at(0, 1), "// a\n// b\n".to_string())).unwrap();
+    server.change(URI, &mut doc);
+    assert_eq!(server_text(&mut server), doc.file.to_string());
+    assert_eq!(full_syncs(&mut server), 0);
+    // Changing the rope directly means the whole document is sent instead
+    doc.file.insert(0, "// direct\n");
+    doc.revision += 1;
+    server.change(URI, &mut doc);
+    assert_eq!(server_text(&mut server), doc.file.to_string());
+    assert_eq!(full_syncs(&mut server), 1);
+    // Nothing is sent when nothing has changed
+    server.change(URI, &mut doc);
+    assert_eq!(server_text(&mut server), doc.file.to_string());
+    server.close(URI);
+    assert!(!server.is_open(URI));
+    server.stop();
+}
+
+fn lsp_diagnostics() {
+    let (mut server, mut doc) = start("fn main() {\n    // TODO\n}");
+    let diagnostics = wait_for_diagnostics(&mut server);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].range.start.line, 1);
+    assert_eq!(diagnostics[0].range.start.character, 7);
+    assert_eq!(diagnostics[0].message, "Unfinished");
+    // Fixing the problem clears the diagnostics
+    doc.exe(Event::Delete(Loc::at(7, 1), "TODO".to_string())).unwrap();
+    server.change(URI, &mut doc);
+    assert!(wait_for_diagnostics(&mut server).is_empty());
+    server.stop();
+}
+
+fn lsp_requests() {
+    let (mut server, doc) = start("fn main() {\n    main();\n}");
+    let at = Loc::at(6, 1);
+    // Hover
+    let id = server.request_at("textDocument/hover", URI, &doc, &at, json!({}));
+    let hover = parse_hover(&server.wait_for(id, TIMEOUT).unwrap());
+    assert_eq!(hover, Some("```rust\nfn main()\n```\nThe entry point".to_string()));
+    // Definition
+    let id = server.request_at("textDocument/definition", URI, &doc, &at, json!({}));
+    let definition = parse_locations(&server.wait_for(id, TIMEOUT).unwrap());
+    assert_eq!(definition.len(), 1);
+    assert_eq!(definition[0].uri, URI);
+    assert_eq!((definition[0].range.start.line, definition[0].range.start.character), (0, 3));
+    // References
+    let context = json!({ "context": { "includeDeclaration": true } });
+    let id = server.request_at("textDocument/references", URI, &doc, &at, context);
+    let references = parse_locations(&server.wait_for(id, TIMEOUT).unwrap());
+    assert_eq!(references.len(), 2);
+    assert_eq!(references[1].range.start.line, 1);
+    // Rename
+    let params = json!({ "newName": "start" });
+    let id = server.request_at("textDocument/rename", URI, &doc, &at, params);
+    let edits = parse_workspace_edit(&server.wait_for(id, TIMEOUT).unwrap());
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].0, URI);
+    assert_eq!(edits[0].1.len(), 2);
+    assert_eq!(edits[0].1[0].new_text, "start");
+    // Completion (sorted as the server asks)
+    let id = server.request_at("textDocument/completion", URI, &doc, &at, json!({}));
+    let completions = parse_completions(&server.wait_for(id, TIMEOUT).unwrap());
+    let labels: Vec<&str> = completions.iter().map(|c| c.label.as_str()).collect();
+    assert_eq!(labels, vec!["print!", "println!"]);
+    assert_eq!(completions[1].detail.as_deref(), Some("macro"));
+    // Unknown requests give errors
+    let id = server.request("mock/unknown", Value::Null);
+    assert!(server.wait_for(id, TIMEOUT).is_err());
+    server.stop();
+}
+
+fn lsp_stop() {
+    let (mut server, _) = start("");
+    server.stop();
+    assert_eq!(server.state, ServerState::Stopped);
+    // Requests made once the server has stopped fail straight away
+    let id = server.request("mock/text", Value::Null);
+    assert!(server.wait_for(id, TIMEOUT).is_err());
+}
+
+/// Answer requests like a (very simple) language server would
+fn mock_server() {
+    let mut reader = BufReader::new(io::stdin());
+    let mut stdout = io::stdout();
+    let mut text = String::new();
+    let mut full_syncs = 0;
+    while let Ok(Some(message)) = read_message(&mut reader) {
+        let params = &message["params"];
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "positionEncoding": "utf-32",
+                    "textDocumentSync": { "openClose": true, "change": 2 },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "completionProvider": {},
+                }
+            }),
+            "textDocument/didOpen" => {
+                text = params["textDocument"]["text"].as_str().unwrap().to_string();
+                publish_diagnostics(&mut stdout, &text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                for change in params["contentChanges"].as_array().unwrap() {
+                    if change.get("range").is_none() {
+                        full_syncs += 1;
+                    }
+                    apply_change(&mut text, change);
+                }
+                publish_diagnostics(&mut stdout, &text);
+                continue;
+            }
+            "textDocument/hover" => json!({
+                "contents": { "kind": "markdown", "value": "```rust\nfn main()\n```\nThe entry point" }
+            }),
+            "textDocument/definition" => json!([location(0, 3)]),
+            "textDocument/references" => json!([location(0, 3), location(1, 4)]),
+            "textDocument/rename" => {
+                let new_text = &params["newName"];
+                json!({ "changes": { URI: [
+                    { "range": range(0, 3), "newText": new_text },
+                    { "range": range(1, 4), "newText": new_text },
+                ]}})
+            }
+            "textDocument/completion" => json!({
+                "isIncomplete": false,
+                "items": [
+                    { "label": "println!", "detail": "macro", "sortText": "b" },
+                    { "label": "print!", "detail": "macro", "sortText": "a" },
+                ]
+            }),
+            "mock/text" => json!(text),
+            "mock/full_syncs" => json!(full_syncs),
+            "shutdown" => Value::Null,
+            "exit" => return,
+            _ if message["id"].is_null() => continue,
+            _ => {
+                let error = json!({ "code": -32601, "message": "Method not found" });
+                let response = json!({ "jsonrpc": "2.0", "id": message["id"], "error": error });
+                write_message(&mut stdout, &response).unwrap();
+                continue;
+            }
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": message["id"], "result": result });
+        write_message(&mut stdout, &response).unwrap();
+    }
+}
+
+fn range(line: usize, character: usize) -> Value {
+    json!({
+        "start": { "line": line, "character": character },
+        "end": { "line": line, "character": character + 4 },
+    })
+}
+
+fn location(line: usize, character: usize) -> Value {
+    json!({ "uri": URI, "range": range(line, character) })
+}
+
+/// Report every "TODO" in the document as a problem
+fn publish_diagnostics(stdout: &mut io::Stdout, text: &str) {
+    let diagnostics: Vec<Value> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(y, line)| {
+            let x = line.find("TODO")?;
+            let x = line[..x].chars().count();
+            Some(json!({ "range": range(y, x), "severity": 2, "message": "Unfinished" }))
+        })
+        .collect();
+    let params = json!({ "uri": URI, "diagnostics": diagnostics });
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": params,
+    });
+    write_message(stdout, &notification).unwrap();
+}
+
+/// Apply a content change (with utf-32 positions) to the mock server's copy of the document
+fn apply_change(text: &mut String, change: &Value) {
+    let new_text = change["text"].as_str().unwrap();
+    let Some(range) = change.get("range") else {
+        *text = new_text.to_string();
+        return;
+    };
+    let index = |position: &Value| {
+        let line = position["line"].as_u64().unwrap() as usize;
+        let character = position["character"].as_u64().unwrap() as usize;
+        let mut chars = 0;
+        for (y, l) in text.split_inclusive('\n').enumerate() {
+            if y == line {
+                return chars + character;
+            }
+            chars += l.chars().count();
+        }
+        chars + character
+    };
+    let (start, end) = (index(&range["start"]), index(&range["end"]));
+    let mut chars: Vec<char> = text.chars().collect();
+    chars.splice(start..end, new_text.chars());
+    *text = chars.into_iter().collect();
+}
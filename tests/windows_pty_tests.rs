@@ -251,8 +251,8 @@ fn test_cross_platform_pty_abstraction() {
                 assert!(result.is_ok(), "Failed to run command: {:?}", result.err());
                 
                 // Check output
-                assert!(!pty_lock.output.is_empty(), "Should have output");
-                println!("Cross-platform PTY output: {}", pty_lock.output);
+                assert!(!pty_lock.screen.text().is_empty(), "Should have output");
+                println!("Cross-platform PTY output: {}", pty_lock.screen.text());
             }
         }
         Err(e) => {
@@ -300,8 +300,8 @@ fn test_pty_thread_safety() {
             
             // Check final output
             let pty_lock = pty.lock().unwrap();
-            println!("Final output length: {}", pty_lock.output.len());
-            assert!(!pty_lock.output.is_empty(), "Should have accumulated output");
+            println!("Final output length: {}", pty_lock.screen.text().len());
+            assert!(!pty_lock.screen.text().is_empty(), "Should have accumulated output");
         }
         Err(e) => {
             println!("Failed to create PTY for thread safety test: {:?}", e);